@@ -0,0 +1,544 @@
+use async_trait::async_trait;
+use ethers::utils::hex;
+use hmac::{Hmac, Mac};
+use reqwest::Client;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::time::{SystemTime, UNIX_EPOCH};
+use teloxide::{
+    prelude::*,
+    types::{ParseMode, Recipient},
+};
+
+use crate::chains::{
+    get_alert_webhook_bearer_token, get_alert_webhook_secret, get_alert_webhook_url,
+    get_discord_webhook_url, get_slack_webhook_url, get_telegram_bot_token, get_telegram_chat_id,
+};
+use crate::error::MonitorError;
+use crate::stats::STATS;
+
+// Embed colour used for Discord alerts (red)
+const DISCORD_ALERT_COLOR: u32 = 0xE74C3C;
+
+// Slack's section blocks hold at most this many fields
+const SLACK_FIELDS_PER_SECTION: usize = 10;
+
+/// Version of the generic webhook payload, bumped on incompatible changes
+pub const WEBHOOK_SCHEMA_VERSION: u32 = 1;
+
+// Header carrying the HMAC-SHA256 of the webhook body when ALERT_WEBHOOK_SECRET is set
+pub const WEBHOOK_SIGNATURE_HEADER: &str = "X-Signature-256";
+
+/// How urgent an alert is, for channels that colour-code their messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AlertSeverity {
+    /// Good news: a recovery or a restored feed
+    Resolved,
+    #[default]
+    Warning,
+    Critical,
+}
+
+impl AlertSeverity {
+    /// Green, yellow or red, as a Slack attachment colour
+    pub fn slack_color(&self) -> &'static str {
+        match self {
+            AlertSeverity::Resolved => "#2EB67D",
+            AlertSeverity::Warning => "#ECB22E",
+            AlertSeverity::Critical => "#E01E5A",
+        }
+    }
+
+    /// Lowercase name used in the generic webhook payload
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AlertSeverity::Resolved => "resolved",
+            AlertSeverity::Warning => "warning",
+            AlertSeverity::Critical => "critical",
+        }
+    }
+}
+
+/// The position an alert is about, for channels that take structured data
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AlertPosition {
+    pub chain: String,
+    pub user_address: String,
+    // `None` when the alert has no priced reading, or the health factor is infinite
+    pub health_factor: Option<f64>,
+    pub collateral_usd: Option<f64>,
+    pub debt_usd: Option<f64>,
+    pub tokens: Vec<AlertToken>,
+}
+
+/// One tracked reserve of an `AlertPosition`
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlertToken {
+    // "supplied" or "borrowed"
+    pub side: &'static str,
+    pub address: String,
+    pub symbol: Option<String>,
+    // Raw token amount, as a decimal string since it outgrows JSON numbers
+    pub amount: String,
+}
+
+/// Channel-agnostic alert content; every notification channel formats from this
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlertMessage {
+    pub emoji: String,
+    pub title: String,
+    pub severity: AlertSeverity,
+    // Labelled values such as the tracked address or token details
+    pub fields: Vec<(String, String)>,
+    // Free-form plain-text explanation shown after the fields
+    pub body: String,
+    pub position: Option<AlertPosition>,
+}
+
+impl AlertMessage {
+    pub fn new(emoji: &str, title: &str) -> Self {
+        Self {
+            emoji: emoji.to_string(),
+            title: title.to_string(),
+            severity: AlertSeverity::default(),
+            fields: Vec::new(),
+            body: String::new(),
+            position: None,
+        }
+    }
+
+    pub fn severity(mut self, severity: AlertSeverity) -> Self {
+        self.severity = severity;
+        self
+    }
+
+    pub fn field(mut self, label: &str, value: impl ToString) -> Self {
+        self.fields.push((label.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn body(mut self, body: impl ToString) -> Self {
+        self.body = body.to_string();
+        self
+    }
+
+    pub fn position(mut self, position: AlertPosition) -> Self {
+        self.position = Some(position);
+        self
+    }
+
+    /// Render for Telegram with MarkdownV2 formatting
+    pub fn to_markdown_v2(&self) -> String {
+        // Every dynamic value is escaped: one reserved character makes Telegram
+        // reject the whole message and the alert would be lost
+        let emoji = escape_markdown_v2(&self.emoji);
+        let mut text = format!("{} *{}* {}", emoji, escape_markdown_v2(&self.title), emoji);
+        if !self.fields.is_empty() {
+            text.push('\n');
+        }
+        for (label, value) in &self.fields {
+            text.push_str(&format!(
+                "\n*{}:* `{}`",
+                escape_markdown_v2(label),
+                escape_markdown_v2_code(value)
+            ));
+        }
+        if !self.body.is_empty() {
+            text.push_str("\n\n");
+            text.push_str(&escape_markdown_v2(&self.body));
+        }
+        text
+    }
+
+    /// Render as the versioned JSON object documented in the README for generic
+    /// webhooks. Position keys are `null` when the alert isn't about a priced position.
+    pub fn to_webhook_payload(&self, timestamp: u64) -> Value {
+        let fields: serde_json::Map<String, Value> = self
+            .fields
+            .iter()
+            .map(|(label, value)| (label.clone(), Value::String(value.clone())))
+            .collect();
+        let position = self.position.clone().unwrap_or_default();
+        let tokens: Vec<Value> = position
+            .tokens
+            .iter()
+            .map(|token| {
+                json!({
+                    "side": token.side,
+                    "address": token.address,
+                    "symbol": token.symbol,
+                    "amount": token.amount,
+                })
+            })
+            .collect();
+        let non_empty = |text: String| (!text.is_empty()).then_some(text);
+        json!({
+            "schema_version": WEBHOOK_SCHEMA_VERSION,
+            "timestamp": timestamp,
+            "severity": self.severity.as_str(),
+            "title": self.title,
+            "chain": non_empty(position.chain),
+            "user_address": non_empty(position.user_address),
+            // serde_json has no infinity: an unbounded health factor is sent as null
+            "health_factor": position.health_factor.filter(|hf| hf.is_finite()),
+            "collateral_usd": position.collateral_usd,
+            "debt_usd": position.debt_usd,
+            "tokens": tokens,
+            "fields": fields,
+            "body": self.body,
+        })
+    }
+
+    /// Render as a Discord webhook payload with a single embed
+    pub fn to_discord_payload(&self) -> Value {
+        let fields: Vec<Value> = self
+            .fields
+            .iter()
+            .map(|(label, value)| json!({ "name": label, "value": format!("`{}`", value) }))
+            .collect();
+        json!({
+            "embeds": [{
+                "title": format!("{} {} {}", self.emoji, self.title, self.emoji),
+                "description": self.body,
+                "color": DISCORD_ALERT_COLOR,
+                "fields": fields,
+            }]
+        })
+    }
+
+    /// Render as a Slack incoming-webhook payload: one attachment coloured by
+    /// severity, holding a header, the fields and the body as Block Kit blocks
+    pub fn to_slack_payload(&self) -> Value {
+        let title = format!("{} {} {}", self.emoji, self.title, self.emoji);
+        let mut blocks = vec![json!({
+            "type": "header",
+            "text": { "type": "plain_text", "text": title, "emoji": true },
+        })];
+        for fields in self.fields.chunks(SLACK_FIELDS_PER_SECTION) {
+            let fields: Vec<Value> = fields
+                .iter()
+                .map(|(label, value)| {
+                    json!({
+                        "type": "mrkdwn",
+                        "text": format!("*{}:*\n`{}`", escape_slack(label), escape_slack(value)),
+                    })
+                })
+                .collect();
+            blocks.push(json!({ "type": "section", "fields": fields }));
+        }
+        // Slack rejects a section with empty text
+        if !self.body.is_empty() {
+            blocks.push(json!({
+                "type": "section",
+                "text": { "type": "mrkdwn", "text": escape_slack(&self.body) },
+            }));
+        }
+        json!({
+            // Shown in notifications, where blocks aren't rendered
+            "text": title,
+            "attachments": [{
+                "color": self.severity.slack_color(),
+                "blocks": blocks,
+            }]
+        })
+    }
+}
+
+// Slack only reserves these three characters in mrkdwn text
+fn escape_slack(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Escape text for Telegram MarkdownV2
+pub fn escape_markdown_v2(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(
+            c,
+            '_' | '*'
+                | '['
+                | ']'
+                | '('
+                | ')'
+                | '~'
+                | '`'
+                | '>'
+                | '#'
+                | '+'
+                | '-'
+                | '='
+                | '|'
+                | '{'
+                | '}'
+                | '.'
+                | '!'
+                | '\\'
+        ) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// Inside MarkdownV2 code spans only backticks and backslashes need escaping
+fn escape_markdown_v2_code(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('`', "\\`")
+}
+
+/// A notification channel alerts can be delivered to
+#[async_trait]
+pub trait Alerter: Send + Sync {
+    /// Channel name used in logs
+    fn name(&self) -> &str;
+
+    async fn send(&self, message: &AlertMessage) -> Result<(), MonitorError>;
+}
+
+/// Build an alerter for every channel that has configuration, skipping the rest
+pub fn configured_alerters() -> Vec<Box<dyn Alerter>> {
+    let mut alerters: Vec<Box<dyn Alerter>> = Vec::new();
+
+    match (get_telegram_bot_token(), get_telegram_chat_id()) {
+        (Some(bot_token), Some(chat_id)) => {
+            alerters.push(Box::new(TelegramAlerter { bot_token, chat_id }))
+        }
+        _ => tracing::info!(
+            "TELEGRAM_BOT_TOKEN or TELEGRAM_CHAT_ID is not set, skipping Telegram alerts"
+        ),
+    }
+
+    match get_discord_webhook_url() {
+        Some(webhook_url) => alerters.push(Box::new(DiscordAlerter { webhook_url })),
+        None => tracing::debug!("DISCORD_WEBHOOK_URL is not set, skipping Discord alerts"),
+    }
+
+    match get_slack_webhook_url() {
+        Some(webhook_url) => alerters.push(Box::new(SlackAlerter { webhook_url })),
+        None => tracing::debug!("SLACK_WEBHOOK_URL is not set, skipping Slack alerts"),
+    }
+
+    match get_alert_webhook_url() {
+        Some(url) => alerters.push(Box::new(WebhookAlerter {
+            url,
+            bearer_token: get_alert_webhook_bearer_token(),
+            secret: get_alert_webhook_secret(),
+        })),
+        None => tracing::debug!("ALERT_WEBHOOK_URL is not set, skipping webhook alerts"),
+    }
+
+    alerters
+}
+
+/// Send an alert through every alerter. A failing channel doesn't stop the others;
+/// the failures are returned with the name of the channel that raised them.
+pub async fn send_to_all(
+    alerters: &[Box<dyn Alerter>],
+    message: &AlertMessage,
+) -> Vec<(String, MonitorError)> {
+    tracing::info!(
+        title = %message.title,
+        channels = alerters.len(),
+        fields = ?message.fields,
+        "Sending alert"
+    );
+    STATS.record_alert();
+    let mut failures = Vec::new();
+    for alerter in alerters {
+        if let Err(e) = alerter.send(message).await {
+            failures.push((alerter.name().to_string(), e));
+        }
+    }
+    failures
+}
+
+/// Benign message sent by `--test-alerts` and `/testalert` to check that every
+/// channel delivers
+pub fn test_alert_message() -> AlertMessage {
+    AlertMessage::new("✅", "MONITORING STARTED")
+        .severity(AlertSeverity::Resolved)
+        .body("Test alert: this channel receives the monitor's alerts. No action needed.")
+}
+
+/// Send the test message through every channel, returning each channel's result.
+/// Not counted in the alert statistics.
+pub async fn send_test_alert(
+    alerters: &[Box<dyn Alerter>],
+) -> Vec<(String, Result<(), MonitorError>)> {
+    let message = test_alert_message();
+    let mut results = Vec::new();
+    for alerter in alerters {
+        let result = alerter.send(&message).await;
+        match &result {
+            Ok(_) => tracing::info!(channel = alerter.name(), "Test alert delivered"),
+            Err(e) => tracing::warn!(channel = alerter.name(), "Test alert failed: {}", e),
+        }
+        results.push((alerter.name().to_string(), result));
+    }
+    results
+}
+
+/// One line per channel for a test alert's results
+pub fn test_alert_report(results: &[(String, Result<(), MonitorError>)]) -> Vec<String> {
+    if results.is_empty() {
+        return vec!["No alert channels configured".to_string()];
+    }
+    results
+        .iter()
+        .map(|(channel, result)| match result {
+            Ok(_) => format!("{}: ok", channel),
+            Err(e) => format!("{}: failed ({})", channel, e),
+        })
+        .collect()
+}
+
+/// Sends alerts to a Telegram chat
+pub struct TelegramAlerter {
+    bot_token: String,
+    chat_id: String,
+}
+
+#[async_trait]
+impl Alerter for TelegramAlerter {
+    fn name(&self) -> &str {
+        "Telegram"
+    }
+
+    async fn send(&self, message: &AlertMessage) -> Result<(), MonitorError> {
+        let bot = Bot::new(&self.bot_token);
+        let recipient = telegram_recipient(&self.chat_id)?;
+
+        bot.send_message(recipient, message.to_markdown_v2())
+            .parse_mode(ParseMode::MarkdownV2)
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// Resolve a configured chat id: positive ids are users, negative ids are
+/// groups, supergroups and channels (e.g. `-1001234567890`)
+pub fn telegram_recipient(chat_id: &str) -> Result<Recipient, MonitorError> {
+    let id = chat_id
+        .trim()
+        .parse::<i64>()
+        .map_err(|e| MonitorError::Config(format!("Invalid TELEGRAM_CHAT_ID: {}", e)))?;
+    if id < 0 {
+        Ok(Recipient::from(ChatId(id)))
+    } else {
+        Ok(Recipient::from(UserId(id as u64)))
+    }
+}
+
+/// Posts alerts as embeds to a Discord webhook
+pub struct DiscordAlerter {
+    webhook_url: String,
+}
+
+#[async_trait]
+impl Alerter for DiscordAlerter {
+    fn name(&self) -> &str {
+        "Discord"
+    }
+
+    async fn send(&self, message: &AlertMessage) -> Result<(), MonitorError> {
+        post_json(&self.webhook_url, &message.to_discord_payload()).await
+    }
+}
+
+/// Posts alerts to a Slack incoming webhook
+pub struct SlackAlerter {
+    webhook_url: String,
+}
+
+#[async_trait]
+impl Alerter for SlackAlerter {
+    fn name(&self) -> &str {
+        "Slack"
+    }
+
+    async fn send(&self, message: &AlertMessage) -> Result<(), MonitorError> {
+        let response = Client::new()
+            .post(&self.webhook_url)
+            .json(&message.to_slack_payload())
+            .send()
+            .await
+            .map_err(|e| MonitorError::Webhook(format!("Failed to post to Slack: {}", e)))?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+        // Slack explains rejections in a short plain-text body, e.g. `invalid_blocks`
+        let body = response.text().await.unwrap_or_default();
+        tracing::warn!(status = %status, body = %body, "Slack rejected alert");
+        Err(MonitorError::Webhook(format!(
+            "Slack rejected alert: {} {}",
+            status, body
+        )))
+    }
+}
+
+/// Posts alerts as plain JSON to any HTTP endpoint, optionally authenticated with a
+/// bearer token and signed with an HMAC of the body
+pub struct WebhookAlerter {
+    url: String,
+    bearer_token: Option<String>,
+    secret: Option<String>,
+}
+
+#[async_trait]
+impl Alerter for WebhookAlerter {
+    fn name(&self) -> &str {
+        "Webhook"
+    }
+
+    async fn send(&self, message: &AlertMessage) -> Result<(), MonitorError> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+        // Serialized once so the signature covers exactly the bytes sent
+        let body = serde_json::to_vec(&message.to_webhook_payload(timestamp))
+            .map_err(|e| MonitorError::Webhook(format!("Failed to encode webhook: {}", e)))?;
+        let mut request = Client::new()
+            .post(&self.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json");
+        if let Some(token) = &self.bearer_token {
+            request = request.bearer_auth(token);
+        }
+        if let Some(secret) = &self.secret {
+            request = request.header(WEBHOOK_SIGNATURE_HEADER, webhook_signature(secret, &body));
+        }
+        request
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| MonitorError::Webhook(format!("Failed to post webhook: {}", e)))?
+            .error_for_status()
+            .map_err(|e| MonitorError::Webhook(format!("Webhook rejected alert: {}", e)))?;
+        Ok(())
+    }
+}
+
+/// `sha256=<hex>` HMAC-SHA256 of a webhook body under ALERT_WEBHOOK_SECRET, so the
+/// receiver can check an alert came from us and wasn't altered
+pub fn webhook_signature(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+async fn post_json(url: &str, payload: &Value) -> Result<(), MonitorError> {
+    Client::new()
+        .post(url)
+        .json(payload)
+        .send()
+        .await
+        .map_err(|e| MonitorError::Webhook(format!("Failed to post webhook: {}", e)))?
+        .error_for_status()
+        .map_err(|e| MonitorError::Webhook(format!("Webhook rejected alert: {}", e)))?;
+    Ok(())
+}
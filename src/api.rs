@@ -0,0 +1,325 @@
+use ethers::prelude::{Address, U256};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{header, Body, Method, Request, Response, Server, StatusCode};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+
+use crate::chains::ethereum::ethereum_chain::{ChainState, HealthFactorSample};
+use crate::chains::solana::solana_chain::SOLANA;
+use crate::chains::{
+    enabled_chains, get_liquidation_threshold, get_solana_enabled, get_solana_user_address,
+};
+use crate::error::MonitorError;
+use crate::{
+    calculate_liquidation_prices, calculate_liquidation_threshold, calculate_position_usd,
+    compute_health_factor, reserve_value_in_usd, simulate_price_shock,
+    weighted_liquidation_threshold, LiquidationPrice,
+};
+
+/// One reserve of the position with its raw amount and USD value
+#[derive(Debug, Serialize)]
+pub struct ReserveBalance {
+    pub reserve: String,
+    // Raw token amount as a decimal string, U256 doesn't fit a JSON number
+    pub amount: String,
+    pub usd: f64,
+    // Stable-rate part of `amount`, only set on borrowed reserves
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stable_amount: Option<String>,
+    // Whether the reserve backs the debt, only set on supplied reserves
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub collateral: Option<bool>,
+}
+
+/// Body returned by `GET /position/<chain>`
+#[derive(Debug, Serialize)]
+pub struct PositionResponse {
+    pub chain: String,
+    pub user_address: String,
+    pub supplied: Vec<ReserveBalance>,
+    pub borrowed: Vec<ReserveBalance>,
+    pub supply_usd: f64,
+    pub borrow_usd: f64,
+    // Every supplied reserve, collateral or not, minus the debt
+    pub net_usd: f64,
+    // `null` when there is no debt (infinite health factor)
+    pub health_factor: Option<f64>,
+    // Collateral-weighted liquidation threshold used for the health factor
+    pub liquidation_threshold: f64,
+    // Price of each collateral at which the health factor reaches 1.0 (empty without debt)
+    pub liquidation_prices: Vec<LiquidationPrice>,
+    // Unix time of the last position change
+    pub last_updated: Option<u64>,
+}
+
+/// Body returned by `GET /simulate/<chain>`
+#[derive(Debug, Serialize)]
+pub struct SimulationResponse {
+    pub chain: String,
+    pub collateral_change_pct: f64,
+    pub debt_change_pct: f64,
+    pub supply_usd: f64,
+    pub borrow_usd: f64,
+    // `null` when there is no debt (infinite health factor)
+    pub health_factor: Option<f64>,
+    pub in_liquidation_range: bool,
+}
+
+/// Body returned by `GET /history/<chain>`
+#[derive(Debug, Serialize)]
+pub struct HistoryResponse {
+    pub chain: String,
+    pub user_address: String,
+    // Most recent health-factor checks, oldest first
+    pub samples: Vec<HealthFactorSample>,
+}
+
+// Value every reserve with the same prices the alert loop uses
+async fn reserve_balances(
+    chain: &ChainState,
+    balances: &HashMap<Address, U256>,
+) -> Result<Vec<ReserveBalance>, MonitorError> {
+    let mut reserves: Vec<_> = balances.iter().collect();
+    reserves.sort_by_key(|(reserve, _)| **reserve);
+    let mut result = Vec::with_capacity(reserves.len());
+    for (reserve, amount) in reserves {
+        result.push(ReserveBalance {
+            reserve: format!("{:?}", reserve),
+            amount: amount.to_string(),
+            usd: reserve_value_in_usd(chain, reserve, *amount).await?,
+            stable_amount: None,
+            collateral: None,
+        });
+    }
+    Ok(result)
+}
+
+async fn current_position(chain: &ChainState) -> Result<PositionResponse, MonitorError> {
+    let position = chain.position()?;
+    let mut supplied = reserve_balances(chain, &position.supplied).await?;
+    let mut borrowed = reserve_balances(chain, &position.borrowed).await?;
+    // `borrowed` is sorted by reserve, as are these keys
+    let mut debt_reserves: Vec<_> = position.borrowed.keys().collect();
+    debt_reserves.sort();
+    for (reserve, balance) in debt_reserves.into_iter().zip(&mut borrowed) {
+        balance.stable_amount = Some(position.stable_borrowed_amount(reserve).to_string());
+    }
+    // `supplied` is sorted by reserve, as are these keys
+    let mut supplied_reserves: Vec<_> = position.supplied.keys().collect();
+    supplied_reserves.sort();
+    let mut collateral = Vec::new();
+    for (reserve, balance) in supplied_reserves.into_iter().zip(&mut supplied) {
+        let is_collateral = position.is_collateral(reserve);
+        balance.collateral = Some(is_collateral);
+        if is_collateral {
+            collateral.push((balance.usd, chain.liquidation_threshold(reserve)));
+        }
+    }
+    // Only collateral backs the debt, so it alone makes up the supply side
+    let supply_usd: f64 = collateral.iter().map(|(usd, _)| usd).sum();
+    let borrow_usd: f64 = borrowed.iter().map(|balance| balance.usd).sum();
+    let net_usd = supplied.iter().map(|balance| balance.usd).sum::<f64>() - borrow_usd;
+    let liquidation_threshold =
+        weighted_liquidation_threshold(&collateral, get_liquidation_threshold());
+    let health_factor = compute_health_factor(supply_usd, borrow_usd, liquidation_threshold);
+    let liquidation_prices =
+        calculate_liquidation_prices(chain, supply_usd, borrow_usd, liquidation_threshold).await?;
+
+    Ok(PositionResponse {
+        chain: chain.name.to_string(),
+        user_address: chain.user_address(),
+        supplied,
+        borrowed,
+        supply_usd,
+        borrow_usd,
+        net_usd,
+        health_factor: health_factor.is_finite().then_some(health_factor),
+        liquidation_threshold,
+        liquidation_prices,
+        last_updated: chain.updated_at(),
+    })
+}
+
+async fn simulate(
+    chain: &ChainState,
+    collateral_change_pct: f64,
+    debt_change_pct: f64,
+) -> Result<SimulationResponse, MonitorError> {
+    let (supply_usd, borrow_usd) = calculate_position_usd(chain).await?;
+    let shock = simulate_price_shock(
+        supply_usd,
+        borrow_usd,
+        calculate_liquidation_threshold(chain).await?,
+        collateral_change_pct,
+        debt_change_pct,
+    );
+    Ok(SimulationResponse {
+        chain: chain.name.to_string(),
+        collateral_change_pct,
+        debt_change_pct,
+        supply_usd: shock.supply_in_usd,
+        borrow_usd: shock.borrowed_in_usd,
+        health_factor: shock
+            .health_factor
+            .is_finite()
+            .then_some(shock.health_factor),
+        in_liquidation_range: shock.in_liquidation_range,
+    })
+}
+
+fn history(chain: &ChainState) -> Result<HistoryResponse, MonitorError> {
+    Ok(HistoryResponse {
+        chain: chain.name.to_string(),
+        user_address: chain.user_address(),
+        samples: chain.health_factor_history()?,
+    })
+}
+
+fn solana_history() -> Result<HistoryResponse, MonitorError> {
+    Ok(HistoryResponse {
+        chain: "solana".to_string(),
+        user_address: get_solana_user_address().unwrap_or_default(),
+        samples: SOLANA.health_factor_history()?,
+    })
+}
+
+// Percentage from the query string, 0 when absent
+fn percentage_param(query: Option<&str>, name: &str) -> Result<f64, String> {
+    let value = query.and_then(|query| {
+        query.split('&').find_map(|pair| {
+            let (key, value) = pair.split_once('=')?;
+            (key == name).then_some(value)
+        })
+    });
+    match value {
+        None => Ok(0.0),
+        Some(value) => value
+            .parse::<f64>()
+            .ok()
+            .filter(|pct| pct.is_finite())
+            .ok_or_else(|| format!("Invalid {}: {}", name, value)),
+    }
+}
+
+fn json_response(status: StatusCode, body: String) -> Response<Body> {
+    let mut response = Response::new(Body::from(body));
+    *response.status_mut() = status;
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        header::HeaderValue::from_static("application/json"),
+    );
+    response
+}
+
+fn error_response(status: StatusCode, message: &str) -> Response<Body> {
+    json_response(status, serde_json::json!({ "error": message }).to_string())
+}
+
+// `<route>` serves the first enabled chain, `<route>/<chain>` a specific one and
+// `<route>/<chain>/<address>` one of its other tracked accounts
+fn chain_for_path(path: &str, route: &str) -> Option<&'static ChainState> {
+    let chains = enabled_chains();
+    match path.strip_prefix(route)? {
+        "" | "/" => chains.first().copied(),
+        rest => {
+            let rest = rest.strip_prefix('/')?;
+            let (name, account) = match rest.split_once('/') {
+                Some((name, account)) => (name, Some(account)),
+                None => (rest, None),
+            };
+            chains.into_iter().find(|chain| {
+                chain.name == name
+                    && match account {
+                        Some(account) => chain.user_address().eq_ignore_ascii_case(account),
+                        None => chain.is_primary_account(),
+                    }
+            })
+        }
+    }
+}
+
+fn serialized<T: Serialize>(result: Result<T, MonitorError>) -> Response<Body> {
+    match result {
+        Ok(value) => match serde_json::to_string(&value) {
+            Ok(body) => json_response(StatusCode::OK, body),
+            Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()),
+        },
+        Err(e) => error_response(StatusCode::BAD_GATEWAY, &e.to_string()),
+    }
+}
+
+// Until missed events are replayed the position may still be the startup seed
+fn unsynced_response(chain: &ChainState) -> Option<Response<Body>> {
+    (!chain.is_initial_sync_complete()).then(|| {
+        error_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Initial on-chain sync has not completed yet",
+        )
+    })
+}
+
+pub async fn handle_request(request: Request<Body>) -> Result<Response<Body>, Infallible> {
+    if request.method() != Method::GET {
+        return Ok(error_response(StatusCode::NOT_FOUND, "Not Found"));
+    }
+    let path = request.uri().path();
+
+    if let Some(chain) = chain_for_path(path, "/position") {
+        if let Some(response) = unsynced_response(chain) {
+            return Ok(response);
+        }
+        return Ok(serialized(current_position(chain).await));
+    }
+
+    // Recorded checks only, so no need to wait for the initial sync
+    if let Some(chain) = chain_for_path(path, "/history") {
+        return Ok(serialized(history(chain)));
+    }
+    if path == "/history/solana" && get_solana_enabled() {
+        return Ok(serialized(solana_history()));
+    }
+
+    // What-if: `/simulate?collateral_change_pct=-20&debt_change_pct=5`
+    if let Some(chain) = chain_for_path(path, "/simulate") {
+        let query = request.uri().query();
+        let shocks = percentage_param(query, "collateral_change_pct")
+            .and_then(|collateral| Ok((collateral, percentage_param(query, "debt_change_pct")?)));
+        let (collateral_change_pct, debt_change_pct) = match shocks {
+            Ok(shocks) => shocks,
+            Err(message) => return Ok(error_response(StatusCode::BAD_REQUEST, &message)),
+        };
+        if let Some(response) = unsynced_response(chain) {
+            return Ok(response);
+        }
+        return Ok(serialized(
+            simulate(chain, collateral_change_pct, debt_change_pct).await,
+        ));
+    }
+
+    Ok(error_response(StatusCode::NOT_FOUND, "Not Found"))
+}
+
+/// Serve the read-only position API on `addr` until `shutdown` resolves
+pub async fn serve_api(
+    addr: SocketAddr,
+    shutdown: impl Future<Output = ()>,
+) -> Result<(), MonitorError> {
+    let make_service =
+        make_service_fn(|_conn| async { Ok::<_, Infallible>(service_fn(handle_request)) });
+
+    let server = Server::try_bind(&addr)
+        .map_err(|e| MonitorError::Http(format!("Failed to bind API address {}: {}", addr, e)))?
+        .serve(make_service);
+    tracing::info!(
+        "Serving position API on http://{}/position, /simulate and /history",
+        addr
+    );
+
+    server
+        .with_graceful_shutdown(shutdown)
+        .await
+        .map_err(|e| MonitorError::Http(format!("API server failed: {}", e)))
+}
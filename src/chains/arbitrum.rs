@@ -0,0 +1,42 @@
+pub mod arbitrum_chain {
+    use crate::chains::endpoints::EndpointPool;
+    use crate::chains::ethereum::ethereum_chain::ChainState;
+    use crate::chains::{
+        get_arbitrum_last_processed_block_file, get_arbitrum_pool_v3_address,
+        get_arbitrum_position_file, get_arbitrum_rpc_urls, get_arbitrum_ws_urls, Chain,
+    };
+
+    // Aave V3 on Arbitrum emits the same Pool events as on Ethereum, so only the
+    // connection details and the tracked state differ
+    lazy_static::lazy_static! {
+        pub static ref ARBITRUM: ChainState = ChainState::new(
+            "arbitrum",
+            get_arbitrum_last_processed_block_file,
+            get_arbitrum_position_file,
+        );
+        pub static ref ARBITRUM_RPC_ENDPOINTS: EndpointPool =
+            EndpointPool::new("Arbitrum RPC", get_arbitrum_rpc_urls);
+        pub static ref ARBITRUM_WS_ENDPOINTS: EndpointPool =
+            EndpointPool::new("Arbitrum WS", get_arbitrum_ws_urls);
+    }
+
+    pub struct Arbitrum;
+
+    impl Chain for Arbitrum {
+        fn state(&self) -> &'static ChainState {
+            &ARBITRUM
+        }
+
+        fn ws_endpoints(&self) -> &'static EndpointPool {
+            &ARBITRUM_WS_ENDPOINTS
+        }
+
+        fn rpc_endpoints(&self) -> &'static EndpointPool {
+            &ARBITRUM_RPC_ENDPOINTS
+        }
+
+        fn pool_address(&self) -> String {
+            get_arbitrum_pool_v3_address()
+        }
+    }
+}
@@ -0,0 +1,42 @@
+pub mod base_chain {
+    use crate::chains::endpoints::EndpointPool;
+    use crate::chains::ethereum::ethereum_chain::ChainState;
+    use crate::chains::{
+        get_base_last_processed_block_file, get_base_pool_v3_address, get_base_position_file,
+        get_base_rpc_urls, get_base_ws_urls, Chain,
+    };
+
+    // Aave V3 on Base emits the same Pool events as on Ethereum, so only the
+    // connection details and the tracked state differ
+    lazy_static::lazy_static! {
+        pub static ref BASE: ChainState = ChainState::new(
+            "base",
+            get_base_last_processed_block_file,
+            get_base_position_file,
+        );
+        pub static ref BASE_RPC_ENDPOINTS: EndpointPool =
+            EndpointPool::new("Base RPC", get_base_rpc_urls);
+        pub static ref BASE_WS_ENDPOINTS: EndpointPool =
+            EndpointPool::new("Base WS", get_base_ws_urls);
+    }
+
+    pub struct Base;
+
+    impl Chain for Base {
+        fn state(&self) -> &'static ChainState {
+            &BASE
+        }
+
+        fn ws_endpoints(&self) -> &'static EndpointPool {
+            &BASE_WS_ENDPOINTS
+        }
+
+        fn rpc_endpoints(&self) -> &'static EndpointPool {
+            &BASE_RPC_ENDPOINTS
+        }
+
+        fn pool_address(&self) -> String {
+            get_base_pool_v3_address()
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use crate::chains::get_endpoint_failover_threshold;
+use crate::error::MonitorError;
+
+/// Split a comma-separated list of endpoint URLs, dropping empty entries
+pub fn parse_endpoint_list(raw: &str) -> Vec<String> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|url| !url.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+// Failures seen on one endpoint
+#[derive(Debug, Default, Clone, Copy)]
+struct EndpointHealth {
+    consecutive_failures: u32,
+    total_failures: u64,
+}
+
+/// The RPC or WS endpoints of one chain, in order of preference. The pool stays
+/// on an endpoint while it works and moves to the next one after
+/// `ENDPOINT_FAILOVER_THRESHOLD` consecutive failures.
+pub struct EndpointPool {
+    label: &'static str,
+    // Looked up on use so the list follows the current configuration
+    urls: fn() -> Vec<String>,
+    current: AtomicUsize,
+    health: Mutex<HashMap<String, EndpointHealth>>,
+}
+
+impl EndpointPool {
+    pub fn new(label: &'static str, urls: fn() -> Vec<String>) -> Self {
+        Self {
+            label,
+            urls,
+            current: AtomicUsize::new(0),
+            health: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Endpoint to use for the next request
+    pub fn current(&self) -> String {
+        let urls = (self.urls)();
+        if urls.is_empty() {
+            return String::new();
+        }
+        urls[self.current.load(Ordering::SeqCst) % urls.len()].clone()
+    }
+
+    pub fn report_success(&self, url: &str) {
+        if let Ok(mut health) = self.health.lock() {
+            if let Some(endpoint) = health.get_mut(url) {
+                endpoint.consecutive_failures = 0;
+            }
+        }
+    }
+
+    /// Count a failed request against `url`, failing over to the next endpoint
+    /// once it has failed too many times in a row
+    pub fn report_failure(&self, url: &str) {
+        let (consecutive_failures, total_failures) = match self.health.lock() {
+            Ok(mut health) => {
+                let endpoint = health.entry(url.to_string()).or_default();
+                endpoint.consecutive_failures += 1;
+                endpoint.total_failures += 1;
+                (endpoint.consecutive_failures, endpoint.total_failures)
+            }
+            Err(_) => return,
+        };
+        let urls = (self.urls)();
+        if urls.len() < 2
+            || url != self.current()
+            || consecutive_failures < get_endpoint_failover_threshold()
+        {
+            return;
+        }
+
+        let next = (self.current.load(Ordering::SeqCst) + 1) % urls.len();
+        self.current.store(next, Ordering::SeqCst);
+        if let Ok(mut health) = self.health.lock() {
+            if let Some(endpoint) = health.get_mut(url) {
+                endpoint.consecutive_failures = 0;
+            }
+        }
+        tracing::warn!(
+            "{} endpoint {} failed {} times in a row ({} in total), failing over to {}",
+            self.label,
+            url,
+            consecutive_failures,
+            total_failures,
+            urls[next]
+        );
+    }
+
+    /// Record the outcome of a request made against `url`. Only RPC errors say
+    /// anything about the endpoint's health.
+    pub fn record<T>(&self, url: &str, result: &Result<T, MonitorError>) {
+        match result {
+            Ok(_) => self.report_success(url),
+            Err(MonitorError::Rpc(_)) => self.report_failure(url),
+            Err(_) => {}
+        }
+    }
+}
@@ -1,72 +1,882 @@
 pub mod ethereum_chain {
-    use crate::chains::{get_ethereum_ws_url, get_pool_v3_address, get_user_address_to_track};
-    use alloy_primitives::hex;
+    use crate::chains::endpoints::EndpointPool;
+    use crate::chains::{
+        ensure_token_decimals, get_account_field, get_block_number_retry_ms,
+        get_confirmation_depth, get_ethereum_rpc_urls, get_ethereum_ws_urls,
+        get_health_factor_history_size, get_large_event_alert_usd, get_last_processed_block_file,
+        get_liquidation_threshold, get_liquidation_threshold_override, get_pool_v3_address,
+        get_pool_version, get_position_file, get_reserve_metadata, get_start_block,
+        get_user_address_to_track, get_user_addresses_to_track, get_ws_block_timeout_secs,
+        lock_recovering, set_reserve_liquidation_threshold, AccountField, Chain, PoolVersion,
+        PositionData,
+    };
+    use crate::error::MonitorError;
+    use crate::metrics::{record_event, record_underflow};
+    use crate::publish::publish_position;
+    use crate::stats::STATS;
     use alloy_primitives::{Log, B256};
     use alloy_sol_types::sol;
     use alloy_sol_types::SolEvent;
     use ethers::prelude::*;
-    use log::error;
+    use serde::{Deserialize, Serialize};
+    use std::collections::{HashMap, HashSet, VecDeque};
     use std::str::FromStr;
-    use std::sync::atomic::{AtomicU64, Ordering};
-    use std::sync::{Arc, Mutex};
+    use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+    use std::sync::{Arc, Mutex, MutexGuard};
+    use std::time::Duration;
+    use tracing::{error, info, warn};
 
-    static ETHEREUM_BLOCK_NUMBER: AtomicU64 = AtomicU64::new(0);
+    /// Everything tracked for one account on one monitored chain: the position
+    /// itself plus the bookkeeping needed to replay, roll back and persist it
+    pub struct ChainState {
+        pub name: &'static str,
+        // Tracked account, `None` for the first configured one (which may be an ENS
+        // name resolved at startup)
+        account: Option<Address>,
+        position: Mutex<PositionData>,
+        // Bumped on every event-driven position change so alerting can tell
+        // event-triggered crossings apart from price-driven ones
+        event_count: AtomicU64,
+        // Unix time of the last position change, 0 until the position is seeded
+        updated_at: AtomicU64,
+        // Latest chain head seen, kept per chain so chains don't overwrite each other's
+        latest_block: AtomicU64,
+        // Set once missed events have been replayed after the first connection
+        initial_sync_complete: AtomicBool,
+        // Deltas of the block currently being applied, committed once the block is done
+        pending_deltas: Mutex<Vec<PositionDelta>>,
+        block_history: Mutex<BlockHistory>,
+        // Logs already applied, so one fetched again by an overlapping range is skipped
+        applied_logs: Mutex<AppliedLogs>,
+        liquidation_notices: Mutex<Vec<LiquidationNotice>>,
+        // Supplies, withdrawals, repays and borrows waiting to be checked against
+        // LARGE_EVENT_ALERT_USD, only queued while that alert is enabled
+        large_event_notices: Mutex<Vec<LargeEventNotice>>,
+        // Most recent health-factor checks, capped at HEALTH_FACTOR_HISTORY_SIZE
+        health_factor_history: Mutex<VecDeque<HealthFactorSample>>,
+        // Position as of the last fully processed block, waiting to be written to disk
+        pending_snapshot: Mutex<Option<PositionSnapshot>>,
+        // Last index reading per reserve, the base for the next interest accrual
+        reserve_indexes: Mutex<HashMap<Address, ReserveIndexes>>,
+        // Woken by every tracked event so balance polling refreshes right away
+        balance_refresh: tokio::sync::Notify,
+        // Liquidation threshold per collateral reserve as configured in the Pool
+        liquidation_thresholds: Mutex<HashMap<Address, f64>>,
+        // eMode category and isolated collateral, read along with the thresholds
+        account_mode: Mutex<AccountMode>,
+        // Woken when an event shows the tracked position has drifted from the chain
+        resync: tokio::sync::Notify,
+        // Files are looked up on use so they follow the current configuration
+        last_processed_block_file: fn() -> String,
+        position_file: fn() -> String,
+    }
 
-    // Struct to represent borrowed and supplied amounts
-    #[derive(Debug, Clone)]
-    pub struct PositionData {
-        pub supplied_amount: U256,
-        pub borrowed_amount: U256,
+    /// One health-factor check as kept in the history
+    #[derive(Debug, Clone, PartialEq, Serialize)]
+    pub struct HealthFactorSample {
+        // Unix time of the check
+        pub timestamp: u64,
+        // `null` when there is no debt (infinite health factor)
+        pub health_factor: Option<f64>,
+        pub collateral_usd: f64,
+        pub debt_usd: f64,
+    }
+
+    lazy_static::lazy_static! {
+        pub static ref ETHEREUM: ChainState = ChainState::new(
+            "ethereum",
+            get_last_processed_block_file,
+            get_position_file,
+        );
+        pub static ref ETHEREUM_RPC_ENDPOINTS: EndpointPool =
+            EndpointPool::new("Ethereum RPC", get_ethereum_rpc_urls);
+        pub static ref ETHEREUM_WS_ENDPOINTS: EndpointPool =
+            EndpointPool::new("Ethereum WS", get_ethereum_ws_urls);
+        // States of the accounts tracked after the first, keyed by chain and address
+        static ref ACCOUNT_STATES: Mutex<HashMap<(&'static str, Address), &'static ChainState>> =
+            Mutex::new(HashMap::new());
+    }
+
+    /// State of every tracked account on `primary`'s chain, starting with `primary`
+    /// itself (the first configured account). States are created on first use and
+    /// live for the rest of the run; entries that aren't addresses are skipped.
+    pub fn account_states(primary: &'static ChainState) -> Vec<&'static ChainState> {
+        let mut states = vec![primary];
+        let Ok(mut registry) = ACCOUNT_STATES.lock() else {
+            return states;
+        };
+        for account in get_user_addresses_to_track().iter().skip(1) {
+            let Ok(address) = account.trim().parse::<Address>() else {
+                continue;
+            };
+            if states.iter().any(|state| {
+                state
+                    .user_address()
+                    .eq_ignore_ascii_case(&format!("{:?}", address))
+            }) {
+                continue;
+            }
+            let state = *registry
+                .entry((primary.name, address))
+                .or_insert_with(|| Box::leak(Box::new(primary.for_account(address))));
+            states.push(state);
+        }
+        states
     }
 
-    impl PositionData {
-        pub fn new() -> Self {
+    impl ChainState {
+        pub fn new(
+            name: &'static str,
+            last_processed_block_file: fn() -> String,
+            position_file: fn() -> String,
+        ) -> Self {
+            Self {
+                name,
+                account: None,
+                position: Mutex::new(PositionData::new()),
+                event_count: AtomicU64::new(0),
+                updated_at: AtomicU64::new(0),
+                latest_block: AtomicU64::new(0),
+                initial_sync_complete: AtomicBool::new(false),
+                pending_deltas: Mutex::new(Vec::new()),
+                block_history: Mutex::new(BlockHistory::default()),
+                applied_logs: Mutex::new(AppliedLogs::default()),
+                liquidation_notices: Mutex::new(Vec::new()),
+                large_event_notices: Mutex::new(Vec::new()),
+                health_factor_history: Mutex::new(VecDeque::new()),
+                pending_snapshot: Mutex::new(None),
+                reserve_indexes: Mutex::new(HashMap::new()),
+                balance_refresh: tokio::sync::Notify::new(),
+                resync: tokio::sync::Notify::new(),
+                liquidation_thresholds: Mutex::new(HashMap::new()),
+                account_mode: Mutex::new(AccountMode::default()),
+                last_processed_block_file,
+                position_file,
+            }
+        }
+
+        // Function to create the state of another account tracked on the same chain,
+        // persisted next to the first account's files
+        pub fn for_account(&self, account: Address) -> Self {
             Self {
-                supplied_amount: U256::from(0),
-                borrowed_amount: U256::from(0),
+                account: Some(account),
+                ..Self::new(
+                    self.name,
+                    self.last_processed_block_file,
+                    self.position_file,
+                )
+            }
+        }
+
+        // Function to get the tracked account's address
+        pub fn user_address(&self) -> String {
+            match self.account {
+                Some(account) => format!("{:?}", account),
+                None => get_user_address_to_track(),
+            }
+        }
+
+        // Function to tell the first configured account apart from the others
+        pub fn is_primary_account(&self) -> bool {
+            self.account.is_none()
+        }
+
+        // Files of accounts other than the first carry the address as a suffix
+        fn account_file(&self, path: String) -> String {
+            match self.account {
+                Some(account) => format!("{}.{:?}", path, account),
+                None => path,
+            }
+        }
+
+        // Function to get current position data
+        pub fn position(&self) -> Result<PositionData, MonitorError> {
+            Ok(self.lock_position().clone())
+        }
+
+        // A panic mid-update can leave the position half-written, so a poisoned lock
+        // is recovered and the position resynced from the chain
+        fn lock_position(&self) -> MutexGuard<'_, PositionData> {
+            self.position.lock().unwrap_or_else(|poisoned| {
+                warn!(
+                    chain = self.name,
+                    account = %self.user_address(),
+                    "Position lock was poisoned, resyncing from the chain"
+                );
+                self.position.clear_poison();
+                self.request_resync();
+                poisoned.into_inner()
+            })
+        }
+
+        // Function to replace the whole position, e.g. when seeding it at startup
+        pub fn set_position(&self, position: PositionData) -> Result<(), MonitorError> {
+            *self.lock_position() = position;
+            self.mark_updated();
+            Ok(())
+        }
+
+        fn mark_updated(&self) {
+            self.updated_at
+                .store(chrono::Utc::now().timestamp() as u64, Ordering::SeqCst);
+        }
+
+        // Function to get the unix time of the last position change
+        pub fn updated_at(&self) -> Option<u64> {
+            match self.updated_at.load(Ordering::SeqCst) {
+                0 => None,
+                timestamp => Some(timestamp),
+            }
+        }
+
+        // Function to tell whether missed events have been replayed since startup
+        pub fn is_initial_sync_complete(&self) -> bool {
+            self.initial_sync_complete.load(Ordering::SeqCst)
+        }
+
+        // Latest block number seen on this chain, 0 before the first one
+        pub fn latest_block(&self) -> u64 {
+            self.latest_block.load(Ordering::SeqCst)
+        }
+
+        pub fn set_latest_block(&self, number: u64) {
+            self.latest_block.store(number, Ordering::SeqCst);
+        }
+
+        // Function to switch a supplied reserve's use as collateral on or off
+        pub fn set_collateral(&self, reserve: Address, enabled: bool) -> Result<(), MonitorError> {
+            let before = {
+                let mut position = self.lock_position();
+                let before = position.is_collateral(&reserve);
+                position.set_collateral(reserve, enabled);
+                before
+            };
+            self.journal_delta(PositionDelta {
+                side: PositionSide::Collateral,
+                reserve,
+                before: U256::from(before as u8),
+                after: U256::from(enabled as u8),
+            })
+        }
+
+        // Function to get the number of event-driven position changes so far
+        pub fn event_count(&self) -> u64 {
+            self.event_count.load(Ordering::SeqCst)
+        }
+
+        // Function to update supplied amount
+        pub fn update_supplied_amount(
+            &self,
+            reserve: Address,
+            new_amount: U256,
+        ) -> Result<(), MonitorError> {
+            let before = {
+                let mut position = self.lock_position();
+                let before = position.supplied_amount(&reserve);
+                position.update_supplied_amount(reserve, new_amount);
+                before
+            };
+            self.journal_delta(PositionDelta {
+                side: PositionSide::Supplied,
+                reserve,
+                before,
+                after: new_amount,
+            })
+        }
+
+        // Function to update borrowed amount
+        pub fn update_borrowed_amount(
+            &self,
+            reserve: Address,
+            new_amount: U256,
+        ) -> Result<(), MonitorError> {
+            let before = {
+                let mut position = self.lock_position();
+                let before = position.borrowed_amount(&reserve);
+                position.update_borrowed_amount(reserve, new_amount);
+                before
+            };
+            self.journal_delta(PositionDelta {
+                side: PositionSide::Borrowed,
+                reserve,
+                before,
+                after: new_amount,
+            })
+        }
+
+        // Function to update the stable-rate part of a reserve's debt. It always goes
+        // with a change to the total, which already counted the event.
+        pub fn update_stable_borrowed_amount(
+            &self,
+            reserve: Address,
+            new_amount: U256,
+        ) -> Result<(), MonitorError> {
+            let before = {
+                let mut position = self.lock_position();
+                let before = position.stable_borrowed_amount(&reserve);
+                position.update_stable_borrowed_amount(reserve, new_amount);
+                before
+            };
+            lock_recovering(&self.pending_deltas).push(PositionDelta {
+                side: PositionSide::StableBorrowed,
+                reserve,
+                before,
+                after: new_amount,
+            });
+            Ok(())
+        }
+
+        fn journal_delta(&self, delta: PositionDelta) -> Result<(), MonitorError> {
+            lock_recovering(&self.pending_deltas).push(delta);
+            self.event_count.fetch_add(1, Ordering::SeqCst);
+            self.mark_updated();
+            Ok(())
+        }
+
+        // Function to close the current block, moving its deltas into the reorg history
+        pub fn commit_block(&self, number: u64, hash: H256) -> Result<(), MonitorError> {
+            let deltas = std::mem::take(&mut *lock_recovering(&self.pending_deltas));
+            lock_recovering(&self.block_history).commit(AppliedBlock {
+                number,
+                hash,
+                deltas,
+            });
+            Ok(())
+        }
+
+        /// Drop the reorg history and applied-log keys of blocks more than `window`
+        /// blocks behind the last applied one, which trails the head by
+        /// `confirmation_depth`. Returns how many entries were dropped.
+        pub fn prune_history(
+            &self,
+            window: u64,
+            confirmation_depth: u64,
+        ) -> Result<usize, MonitorError> {
+            let head = self.latest_block();
+            if head == 0 {
+                return Ok(0);
+            }
+            let min_block = head.saturating_sub(confirmation_depth.saturating_add(window));
+            let blocks = lock_recovering(&self.block_history).prune_below(min_block);
+            let logs = lock_recovering(&self.applied_logs).prune_below(min_block);
+            Ok(blocks + logs)
+        }
+
+        /// Entries held in the reorg history and the applied-log set
+        pub fn history_sizes(&self) -> Result<(usize, usize), MonitorError> {
+            Ok((
+                lock_recovering(&self.block_history).len(),
+                lock_recovering(&self.applied_logs).len(),
+            ))
+        }
+
+        fn conflicts_with_history(&self, number: u64, hash: H256) -> Result<bool, MonitorError> {
+            Ok(lock_recovering(&self.block_history).conflicts(number, hash))
+        }
+
+        fn history_before(&self, below: u64) -> Result<Vec<(u64, H256)>, MonitorError> {
+            Ok(lock_recovering(&self.block_history).before(below))
+        }
+
+        // Whether `log` was already applied, e.g. fetched again after a reconnection
+        fn is_log_applied(&self, log: &ethers::types::Log) -> Result<bool, MonitorError> {
+            let Some((_, key)) = applied_log_key(log) else {
+                return Ok(false);
+            };
+            Ok(lock_recovering(&self.applied_logs).contains(&key))
+        }
+
+        fn mark_log_applied(&self, log: &ethers::types::Log) -> Result<(), MonitorError> {
+            if let Some((block, key)) = applied_log_key(log) {
+                lock_recovering(&self.applied_logs).insert(block, key);
+            }
+            Ok(())
+        }
+
+        // Function to undo every block processed after `fork_block`
+        fn rollback_to(&self, fork_block: u64) -> Result<usize, MonitorError> {
+            // The replacement blocks' logs must apply even when they keep their keys
+            lock_recovering(&self.applied_logs).rollback_to(fork_block);
+            let reverted = lock_recovering(&self.block_history).rollback_to(fork_block);
+            revert_blocks(&mut *self.lock_position(), &reverted);
+            if !reverted.is_empty() {
+                self.event_count.fetch_add(1, Ordering::SeqCst);
+                self.mark_updated();
+            }
+            Ok(reverted.len())
+        }
+
+        // Function to grow every balance by the interest accrued since the previous index
+        // reading. The first reading of a reserve only records its indexes.
+        pub fn accrue_interest(
+            &self,
+            indexes: &HashMap<Address, ReserveIndexes>,
+        ) -> Result<(), MonitorError> {
+            let mut previous_indexes = lock_recovering(&self.reserve_indexes);
+            let mut position = self.lock_position();
+            let mut changed = false;
+            for (reserve, current) in indexes {
+                let Some(previous) = previous_indexes.insert(*reserve, *current) else {
+                    continue;
+                };
+                if let Some(amount) = position.supplied.get_mut(reserve) {
+                    let accrued = accrue(*amount, previous.liquidity, current.liquidity);
+                    changed |= accrued != *amount;
+                    *amount = accrued;
+                }
+                // Stable debt grows at the rate each loan was taken at rather than
+                // with the reserve's index, so only the variable part accrues here
+                let stable = position.stable_borrowed_amount(reserve);
+                if let Some(amount) = position.borrowed.get_mut(reserve) {
+                    let variable = amount.saturating_sub(stable);
+                    let accrued =
+                        stable + accrue(variable, previous.variable_debt, current.variable_debt);
+                    changed |= accrued != *amount;
+                    *amount = accrued;
+                }
+            }
+            drop(position);
+            if changed {
+                self.mark_updated();
+            }
+            Ok(())
+        }
+
+        // Function to record the transaction that triggered the latest position change
+        // and the time of its block, which then counts as the last update
+        pub fn record_event_tx(
+            &self,
+            tx_hash: Option<H256>,
+            block_timestamp: Option<u64>,
+        ) -> Result<(), MonitorError> {
+            {
+                let mut position = self.lock_position();
+                position.last_event_tx = tx_hash;
+                position.last_event_timestamp = block_timestamp;
+            }
+            if let Some(timestamp) = block_timestamp {
+                self.updated_at.store(timestamp, Ordering::SeqCst);
+            }
+            self.balance_refresh.notify_one();
+            Ok(())
+        }
+
+        // Function to get a reserve's liquidation threshold: the LIQUIDATION_THRESHOLD
+        // override when set, else the eMode category's for a reserve in the account's
+        // category, else the Pool's value, else the one seeded through
+        // AAVE_KNOWN_ASSETS, else the configured fallback
+        pub fn liquidation_threshold(&self, reserve: &Address) -> f64 {
+            get_liquidation_threshold_override()
+                .or_else(|| {
+                    lock_recovering(&self.account_mode).emode_liquidation_threshold(reserve)
+                })
+                .or_else(|| {
+                    lock_recovering(&self.liquidation_thresholds)
+                        .get(reserve)
+                        .copied()
+                })
+                .or_else(|| get_reserve_metadata(reserve)?.liquidation_threshold)
+                .unwrap_or_else(get_liquidation_threshold)
+        }
+
+        pub fn set_liquidation_threshold(
+            &self,
+            reserve: Address,
+            threshold: f64,
+        ) -> Result<(), MonitorError> {
+            lock_recovering(&self.liquidation_thresholds).insert(reserve, threshold);
+            Ok(())
+        }
+
+        pub fn account_mode(&self) -> Result<AccountMode, MonitorError> {
+            Ok(lock_recovering(&self.account_mode).clone())
+        }
+
+        pub fn set_account_mode(&self, mode: AccountMode) -> Result<(), MonitorError> {
+            *lock_recovering(&self.account_mode) = mode;
+            Ok(())
+        }
+
+        // Function to ask for an immediate on-chain resync of the position
+        pub fn request_resync(&self) {
+            self.resync.notify_one();
+        }
+
+        // Function to wait until a resync is requested
+        pub async fn resync_requested(&self) {
+            self.resync.notified().await
+        }
+
+        // Function to wait until a tracked event asks for a balance refresh
+        pub async fn balance_refresh_requested(&self) {
+            self.balance_refresh.notified().await
+        }
+
+        // Function to drain liquidations detected since the last call
+        pub fn take_liquidation_notices(&self) -> Result<Vec<LiquidationNotice>, MonitorError> {
+            Ok(std::mem::take(&mut *lock_recovering(
+                &self.liquidation_notices,
+            )))
+        }
+
+        // Function to drain position changes queued for the large-event alert
+        pub fn take_large_event_notices(&self) -> Result<Vec<LargeEventNotice>, MonitorError> {
+            Ok(std::mem::take(&mut *lock_recovering(
+                &self.large_event_notices,
+            )))
+        }
+
+        // Function to remember a health-factor check, dropping the oldest ones beyond
+        // `capacity` so the history stays bounded however long the bot runs
+        pub fn record_health_factor_sample(
+            &self,
+            sample: HealthFactorSample,
+            capacity: usize,
+        ) -> Result<(), MonitorError> {
+            let mut history = lock_recovering(&self.health_factor_history);
+            history.push_back(sample);
+            while history.len() > capacity {
+                history.pop_front();
+            }
+            Ok(())
+        }
+
+        // Function to get the recorded health-factor checks, oldest first
+        pub fn health_factor_history(&self) -> Result<Vec<HealthFactorSample>, MonitorError> {
+            Ok(lock_recovering(&self.health_factor_history)
+                .iter()
+                .cloned()
+                .collect())
+        }
+
+        pub fn last_processed_block(&self) -> Option<u64> {
+            let path = self.account_file((self.last_processed_block_file)());
+            let raw = std::fs::read_to_string(&path).ok()?;
+            let state = serde_json::from_str::<ProcessedBlockState>(&raw).ok()?;
+            if let Err(e) = check_state_version(&path, state.version) {
+                warn!(chain = self.name, "{}", e);
+                return None;
             }
+            Some(state.last_processed_block)
+        }
+
+        pub fn set_last_processed_block(&self, block_number: u64) -> Result<(), MonitorError> {
+            // Snapshot here so the saved position always matches the block it claims to reflect
+            let snapshot = PositionSnapshot {
+                version: STATE_VERSION,
+                user_address: self.user_address(),
+                last_processed_block: block_number,
+                position: self.position()?,
+            };
+            *lock_recovering(&self.pending_snapshot) = Some(snapshot);
+            let raw = serde_json::to_string(&ProcessedBlockState {
+                version: STATE_VERSION,
+                last_processed_block: block_number,
+            })
+            .map_err(|e| {
+                MonitorError::Storage(format!("Failed to serialize last processed block: {}", e))
+            })?;
+            write_atomically(&self.account_file((self.last_processed_block_file)()), &raw).map_err(
+                |e| MonitorError::Storage(format!("Failed to persist last processed block: {}", e)),
+            )
+        }
+
+        // Function to take the snapshot recorded since the last save, if any
+        pub fn take_pending_snapshot(&self) -> Result<Option<PositionSnapshot>, MonitorError> {
+            Ok(lock_recovering(&self.pending_snapshot).take())
         }
 
-        pub fn update_supplied_amount(&mut self, new_amount: U256) {
-            self.supplied_amount = new_amount;
+        pub fn load_snapshot(&self) -> Result<Option<PositionSnapshot>, MonitorError> {
+            let path = self.account_file((self.position_file)());
+            let raw = match std::fs::read_to_string(&path) {
+                Ok(raw) => raw,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+                Err(e) => {
+                    return Err(MonitorError::Storage(format!(
+                        "Failed to read position file {}: {}",
+                        path, e
+                    )))
+                }
+            };
+            let snapshot: PositionSnapshot = serde_json::from_str(&raw).map_err(|e| {
+                MonitorError::Decode(format!("Corrupt position file {}: {}", path, e))
+            })?;
+            check_state_version(&path, snapshot.version)?;
+            Ok(Some(snapshot))
         }
 
-        pub fn update_borrowed_amount(&mut self, new_amount: U256) {
-            self.borrowed_amount = new_amount;
+        pub fn save_snapshot(&self, snapshot: &PositionSnapshot) -> Result<(), MonitorError> {
+            let path = self.account_file((self.position_file)());
+            let raw = serde_json::to_string_pretty(snapshot).map_err(|e| {
+                MonitorError::Storage(format!("Failed to serialize position: {}", e))
+            })?;
+            write_atomically(&path, &raw)
+                .map_err(|e| MonitorError::Storage(format!("Failed to persist position: {}", e)))
         }
     }
 
-    // Global position data that can be shared between threads
-    lazy_static::lazy_static! {
-        static ref POSITION_DATA: Arc<Mutex<PositionData>> = Arc::new(Mutex::new(PositionData::new()));
+    // Aave's cumulative indexes of a reserve, in ray (1e27) units. Balances grow by
+    // the ratio between two readings of the matching index.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct ReserveIndexes {
+        pub liquidity: U256,
+        pub variable_debt: U256,
+    }
+
+    // Scale an amount by how much its index grew; an unknown base leaves it unchanged
+    pub fn accrue(amount: U256, previous_index: U256, current_index: U256) -> U256 {
+        if previous_index.is_zero() {
+            return amount;
+        }
+        amount
+            .checked_mul(current_index)
+            .map(|scaled| scaled / previous_index)
+            .unwrap_or(amount)
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum PositionSide {
+        Supplied,
+        Borrowed,
+        StableBorrowed,
+        // Collateral flag of a supplied reserve, 1 when enabled
+        Collateral,
+    }
+
+    // One change applied to the position, with the amount before and after it
+    #[derive(Debug, Clone)]
+    pub struct PositionDelta {
+        pub side: PositionSide,
+        pub reserve: Address,
+        pub before: U256,
+        pub after: U256,
+    }
+
+    // Deltas applied by one block, kept so they can be reversed if the block is reorged out
+    #[derive(Debug, Clone)]
+    pub struct AppliedBlock {
+        pub number: u64,
+        pub hash: H256,
+        pub deltas: Vec<PositionDelta>,
+    }
+
+    // Most processed blocks remembered for reorg handling, whatever their age; older
+    // ones are pruned sooner by `ChainState::prune_history`
+    const MAX_BLOCK_HISTORY: usize = 1024;
+
+    // Liquidation and large-event notices kept for the next health check; the oldest
+    // are dropped beyond this if checks stop taking them
+    const MAX_QUEUED_NOTICES: usize = 1_000;
+
+    // Recently processed blocks with the deltas they applied, oldest first
+    #[derive(Debug, Default)]
+    pub struct BlockHistory {
+        blocks: VecDeque<AppliedBlock>,
+    }
+
+    impl BlockHistory {
+        pub fn commit(&mut self, block: AppliedBlock) {
+            self.blocks.push_back(block);
+            while self.blocks.len() > MAX_BLOCK_HISTORY {
+                self.blocks.pop_front();
+            }
+        }
+
+        // A block conflicts when we processed a different block at the same height
+        pub fn conflicts(&self, number: u64, hash: H256) -> bool {
+            self.blocks
+                .iter()
+                .any(|block| block.number == number && block.hash != hash)
+        }
+
+        // Processed blocks below `below`, newest first
+        pub fn before(&self, below: u64) -> Vec<(u64, H256)> {
+            self.blocks
+                .iter()
+                .rev()
+                .filter(|block| block.number < below)
+                .map(|block| (block.number, block.hash))
+                .collect()
+        }
+
+        // Drop the blocks below `min_block`, returning how many were dropped
+        pub fn prune_below(&mut self, min_block: u64) -> usize {
+            let before = self.blocks.len();
+            self.blocks.retain(|block| block.number >= min_block);
+            before - self.blocks.len()
+        }
+
+        pub fn len(&self) -> usize {
+            self.blocks.len()
+        }
+
+        // Remove every block after `fork_block`, newest first
+        pub fn rollback_to(&mut self, fork_block: u64) -> Vec<AppliedBlock> {
+            let mut reverted = Vec::new();
+            while self
+                .blocks
+                .back()
+                .is_some_and(|block| block.number > fork_block)
+            {
+                if let Some(block) = self.blocks.pop_back() {
+                    reverted.push(block);
+                }
+            }
+            reverted
+        }
+    }
+
+    // How many applied logs are remembered to catch duplicates; overlapping fetches
+    // only ever cover recent blocks
+    const MAX_APPLIED_LOGS: usize = 10_000;
+
+    // Transaction hash and log index, unique for a log within the canonical chain
+    pub type AppliedLogKey = (H256, U256);
+
+    // Block number and key of a log, `None` for pending logs lacking either
+    fn applied_log_key(log: &ethers::types::Log) -> Option<(u64, AppliedLogKey)> {
+        Some((
+            log.block_number?.as_u64(),
+            (log.transaction_hash?, log.log_index?),
+        ))
+    }
+
+    // Recently applied logs, oldest first, capped at MAX_APPLIED_LOGS
+    #[derive(Debug, Default)]
+    pub struct AppliedLogs {
+        order: VecDeque<(u64, AppliedLogKey)>,
+        keys: HashSet<AppliedLogKey>,
+    }
+
+    impl AppliedLogs {
+        pub fn contains(&self, key: &AppliedLogKey) -> bool {
+            self.keys.contains(key)
+        }
+
+        pub fn insert(&mut self, block: u64, key: AppliedLogKey) {
+            if !self.keys.insert(key) {
+                return;
+            }
+            self.order.push_back((block, key));
+            while self.order.len() > MAX_APPLIED_LOGS {
+                if let Some((_, oldest)) = self.order.pop_front() {
+                    self.keys.remove(&oldest);
+                }
+            }
+        }
+
+        // Forget the logs of blocks below `min_block`, returning how many were forgotten
+        pub fn prune_below(&mut self, min_block: u64) -> usize {
+            let before = self.order.len();
+            while self
+                .order
+                .front()
+                .is_some_and(|(block, _)| *block < min_block)
+            {
+                if let Some((_, key)) = self.order.pop_front() {
+                    self.keys.remove(&key);
+                }
+            }
+            before - self.order.len()
+        }
+
+        pub fn len(&self) -> usize {
+            self.order.len()
+        }
+
+        // Forget the logs of every block after `fork_block`
+        pub fn rollback_to(&mut self, fork_block: u64) {
+            let keys = &mut self.keys;
+            self.order.retain(|(block, key)| {
+                let keep = *block <= fork_block;
+                if !keep {
+                    keys.remove(key);
+                }
+                keep
+            });
+        }
     }
 
-    // Function to get current position data
-    pub fn get_position_data() -> Result<PositionData, String> {
-        POSITION_DATA
-            .lock()
-            .map(|data| data.clone())
-            .map_err(|e| format!("Failed to acquire lock: {}", e))
+    // Undo the deltas of reverted blocks, which must be ordered newest first
+    pub fn revert_blocks(position: &mut PositionData, reverted: &[AppliedBlock]) {
+        for block in reverted {
+            for delta in block.deltas.iter().rev() {
+                match delta.side {
+                    PositionSide::Supplied => {
+                        position.update_supplied_amount(delta.reserve, delta.before)
+                    }
+                    PositionSide::Borrowed => {
+                        position.update_borrowed_amount(delta.reserve, delta.before)
+                    }
+                    PositionSide::StableBorrowed => {
+                        position.update_stable_borrowed_amount(delta.reserve, delta.before)
+                    }
+                    PositionSide::Collateral => {
+                        position.set_collateral(delta.reserve, !delta.before.is_zero())
+                    }
+                }
+            }
+        }
     }
 
-    // Function to update supplied amount
-    pub fn update_supplied_amount(new_amount: U256) -> Result<(), String> {
-        POSITION_DATA
-            .lock()
-            .map_err(|e| format!("Failed to acquire lock: {}", e))?
-            .update_supplied_amount(new_amount);
+    /// Format of the state files. Bump it when their layout changes and migrate
+    /// older versions on load; files written before versioning read as 0.
+    pub const STATE_VERSION: u32 = 1;
+
+    // A file from a newer build may mean something else, so it isn't guessed at
+    fn check_state_version(path: &str, version: u32) -> Result<(), MonitorError> {
+        if version > STATE_VERSION {
+            return Err(MonitorError::Storage(format!(
+                "{} has format version {}, this build reads up to {}",
+                path, version, STATE_VERSION
+            )));
+        }
         Ok(())
     }
 
-    // Function to update borrowed amount
-    pub fn update_borrowed_amount(new_amount: U256) -> Result<(), String> {
-        POSITION_DATA
-            .lock()
-            .map_err(|e| format!("Failed to acquire lock: {}", e))?
-            .update_borrowed_amount(new_amount);
+    /// Replace `path` with `contents` so that a crash leaves either the old or the new
+    /// file, never a partial one: the data goes to a sibling temp file, is flushed to
+    /// disk, and only then renamed over the original.
+    pub fn write_atomically(path: &str, contents: &str) -> std::io::Result<()> {
+        use std::io::Write;
+
+        let tmp_path = format!("{}.tmp", path);
+        let mut file = std::fs::File::create(&tmp_path)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+        std::fs::rename(&tmp_path, path)?;
+        // Persist the rename itself. Directories can't be opened on every platform,
+        // and the data is already safe, so this is best effort.
+        let parent = match std::path::Path::new(path).parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => std::path::Path::new("."),
+        };
+        if let Ok(dir) = std::fs::File::open(parent) {
+            let _ = dir.sync_all();
+        }
         Ok(())
     }
 
+    // Persisted marker of the last block whose logs were fully applied
+    #[derive(Serialize, Deserialize)]
+    struct ProcessedBlockState {
+        #[serde(default)]
+        version: u32,
+        last_processed_block: u64,
+    }
+
+    /// Tracked position together with the block it reflects, so a restart only
+    /// replays the events that came after it
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct PositionSnapshot {
+        // See STATE_VERSION
+        #[serde(default)]
+        pub version: u32,
+        pub user_address: String,
+        pub last_processed_block: u64,
+        pub position: PositionData,
+    }
+
     sol! {
         #[derive(Debug)]
         event BulkWithdraw(address indexed asset, uint256 shareAmount);
@@ -80,6 +890,15 @@ pub mod ethereum_chain {
         "a534c8dbe71f871f9f3530e97a74601fea17b426cae02e1c5aee42c96c784051";
     pub const BORROW_EVENT_TOPIC: &str =
         "b3d084820fb1a9decffb176436bd02558d15fac9b0ddfed8c465bc7359d7dce0";
+    pub const LIQUIDATION_CALL_EVENT_TOPIC: &str =
+        "e413a321e8681d831f4dbccbca790d2952b56f977908e45be37335533e005286";
+    pub const RESERVE_USED_AS_COLLATERAL_ENABLED_EVENT_TOPIC: &str =
+        "00058a56ea94653cdf4f152d227ace22d4c00ad99e2a43f58cb7d9e3feb295f2";
+    pub const RESERVE_USED_AS_COLLATERAL_DISABLED_EVENT_TOPIC: &str =
+        "44c58d81365b66dd4b1a7f36c25aa97b8c71c361ee4937adc1a00000227db5dd";
+
+    // `interestRateMode` of a stable-rate Borrow; 2 is variable
+    pub const STABLE_RATE_MODE: u8 = 1;
 
     //all this events are from Aave Pool V3 and help us to track the supply, withdraw, repay and borrow events to calculate health factor in real time based on user activity
     sol! {
@@ -91,35 +910,175 @@ pub mod ethereum_chain {
         #[derive(Debug)]
         //topic 0x3115d1449a7b732c986cba18244e897a450f61e1bb8d589cd2e69e6c8924f9f7
         event Withdraw (address indexed reserve, address indexed user, address indexed to, uint256 amount);
-        //https://etherscan.io/tx/0x02e072cad5cb5d913a9638c88f67959a4313c09273b9b743458f31340b104c26#eventlog Aave: Pool V2 Repay event example. It is just example, we will use the same event for both Pool V2 and V3
         #[derive(Debug)]
         //topic 0xa534c8dbe71f871f9f3530e97a74601fea17b426cae02e1c5aee42c96c784051
-        event Repay (address indexed reserve, address user, address indexed repayer, uint256 amount, bool useATokens);
+        event Repay (address indexed reserve, address indexed user, address indexed repayer, uint256 amount, bool useATokens);
         //topic 0xb3d084820fb1a9decffb176436bd02558d15fac9b0ddfed8c465bc7359d7dce0
         #[derive(Debug)]
         event Borrow (address indexed reserve, address user, address indexed onBehalfOf, uint256 amount, uint8 interestRateMode, uint256 borrowRate, uint16 indexed referralCode);
+        //topic 0xe413a321e8681d831f4dbccbca790d2952b56f977908e45be37335533e005286
+        #[derive(Debug)]
+        event LiquidationCall (address indexed collateralAsset, address indexed debtAsset, address indexed user, uint256 debtToCover, uint256 liquidatedCollateralAmount, address liquidator, bool receiveAToken);
+        // Emitted when a supplied reserve starts or stops backing the user's debt,
+        // identical in V2 and V3
+        //topic 0x00058a56ea94653cdf4f152d227ace22d4c00ad99e2a43f58cb7d9e3feb295f2
+        #[derive(Debug)]
+        event ReserveUsedAsCollateralEnabled (address indexed reserve, address indexed user);
+        //topic 0x44c58d81365b66dd4b1a7f36c25aa97b8c71c361ee4937adc1a00000227db5dd
+        #[derive(Debug)]
+        event ReserveUsedAsCollateralDisabled (address indexed reserve, address indexed user);
     }
 
-    pub async fn get_current_block_number_ethereum(rpc_url: &str) -> Result<(), String> {
-        // Create the provider, handling any errors that may occur
-        let provider = Provider::<Http>::try_from(rpc_url).map_err(|e| {
-            let err_msg = format!("Failed to create provider: {}", e);
-            eprintln!("{}", err_msg);
-            err_msg
-        })?;
+    pub const DEPOSIT_V2_EVENT_TOPIC: &str =
+        "de6857219544bb5b7746f48ed30be6386fefc61b2f864cacf559893bf50fd951";
+    pub const REPAY_V2_EVENT_TOPIC: &str =
+        "4cdde6e09bb755c9a5589ebaec640bbfedff1362d4b255ebf8339782b9942faa";
+    pub const BORROW_V2_EVENT_TOPIC: &str =
+        "c6a898309e823ee50bac64e45ca8adba6690e99e7841c45d754e2a38e9019d9b";
+
+    // Aave V2 LendingPool events whose signature differs from V3. Withdraw and
+    // LiquidationCall are unchanged, so the V3 definitions decode them.
+    pub mod pool_v2 {
+        use alloy_sol_types::sol;
+
+        sol! {
+            #[derive(Debug)]
+            //topic 0xde6857219544bb5b7746f48ed30be6386fefc61b2f864cacf559893bf50fd951
+            event Deposit(address indexed reserve, address user, address indexed onBehalfOf, uint256 amount, uint16 indexed referral);
+            #[derive(Debug)]
+            //topic 0x4cdde6e09bb755c9a5589ebaec640bbfedff1362d4b255ebf8339782b9942faa
+            event Repay(address indexed reserve, address indexed user, address indexed repayer, uint256 amount);
+            #[derive(Debug)]
+            //topic 0xc6a898309e823ee50bac64e45ca8adba6690e99e7841c45d754e2a38e9019d9b
+            event Borrow(address indexed reserve, address user, address indexed onBehalfOf, uint256 amount, uint256 borrowRateMode, uint256 borrowRate, uint16 indexed referral);
+        }
+    }
+
+    // V2 events carry the same position change as their V3 counterparts
+    impl From<pool_v2::Deposit> for Supply {
+        fn from(event: pool_v2::Deposit) -> Self {
+            Supply {
+                reserve: event.reserve,
+                user: event.user,
+                onBehalfOf: event.onBehalfOf,
+                amount: event.amount,
+                referralCode: event.referral,
+            }
+        }
+    }
+
+    impl From<pool_v2::Repay> for Repay {
+        fn from(event: pool_v2::Repay) -> Self {
+            Repay {
+                reserve: event.reserve,
+                user: event.user,
+                repayer: event.repayer,
+                amount: event.amount,
+                useATokens: false, // Repaying with aTokens only exists on V3
+            }
+        }
+    }
+
+    impl From<pool_v2::Borrow> for Borrow {
+        fn from(event: pool_v2::Borrow) -> Self {
+            Borrow {
+                reserve: event.reserve,
+                user: event.user,
+                onBehalfOf: event.onBehalfOf,
+                amount: event.amount,
+                interestRateMode: event.borrowRateMode.saturating_to::<u8>(),
+                borrowRate: event.borrowRate,
+                referralCode: event.referral,
+            }
+        }
+    }
+
+    /// Pool event a log's topic0 identifies
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum PoolEvent {
+        Supply,
+        Withdraw,
+        Repay,
+        Borrow,
+        LiquidationCall,
+        CollateralEnabled,
+        CollateralDisabled,
+    }
+
+    /// Topic0 of every event the listener handles on `version` of the Pool. A new
+    /// event is one entry here plus its arm in `apply_log`.
+    pub fn pool_events(version: PoolVersion) -> Vec<(&'static str, PoolEvent)> {
+        let (supply, repay, borrow) = match version {
+            PoolVersion::V2 => (
+                DEPOSIT_V2_EVENT_TOPIC,
+                REPAY_V2_EVENT_TOPIC,
+                BORROW_V2_EVENT_TOPIC,
+            ),
+            PoolVersion::V3 => (SUPPLY_EVENT_TOPIC, REPAY_EVENT_TOPIC, BORROW_EVENT_TOPIC),
+        };
+        vec![
+            (supply, PoolEvent::Supply),
+            (WITHDRAW_EVENT_TOPIC, PoolEvent::Withdraw),
+            (repay, PoolEvent::Repay),
+            (borrow, PoolEvent::Borrow),
+            (LIQUIDATION_CALL_EVENT_TOPIC, PoolEvent::LiquidationCall),
+            (
+                RESERVE_USED_AS_COLLATERAL_ENABLED_EVENT_TOPIC,
+                PoolEvent::CollateralEnabled,
+            ),
+            (
+                RESERVE_USED_AS_COLLATERAL_DISABLED_EVENT_TOPIC,
+                PoolEvent::CollateralDisabled,
+            ),
+        ]
+    }
+
+    pub fn pool_event_topics(version: PoolVersion) -> Vec<&'static str> {
+        pool_events(version)
+            .into_iter()
+            .map(|(topic, _)| topic)
+            .collect()
+    }
+
+    /// `pool_events` keyed by parsed topic, for dispatching logs
+    pub fn pool_event_map(version: PoolVersion) -> HashMap<H256, PoolEvent> {
+        pool_events(version)
+            .into_iter()
+            .filter_map(|(topic, event)| Some((H256::from_str(topic).ok()?, event)))
+            .collect()
+    }
 
+    pub async fn get_current_block_number(
+        chain: &ChainState,
+        endpoints: &EndpointPool,
+    ) -> Result<(), MonitorError> {
         loop {
+            // Asked again on every attempt, so retries move on to the next endpoint
+            let rpc_url = endpoints.current();
+            let provider = Provider::<Http>::try_from(rpc_url.as_str()).map_err(|e| {
+                let err_msg = MonitorError::Config(format!("Failed to create provider: {}", e));
+                error!("{}", err_msg);
+                err_msg
+            })?;
             match provider.get_block_number().await {
                 Ok(res) => {
-                    // Store the block number safely
-                    ETHEREUM_BLOCK_NUMBER.store(res.as_u64(), Ordering::SeqCst);
-                    println!("Current Ethereum block number: {}", res);
+                    chain.set_latest_block(res.as_u64());
+                    info!(
+                        chain = chain.name,
+                        block = res.as_u64(),
+                        "Current block number"
+                    );
+                    endpoints.report_success(&rpc_url);
                     break;
                 }
                 Err(e) => {
                     // Log the error and retry after a delay
-                    eprintln!("Failed to get block number: {}", e);
-                    tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+                    warn!(endpoint = %rpc_url, "Failed to get block number: {}", e);
+                    endpoints.report_failure(&rpc_url);
+                    tokio::time::sleep(std::time::Duration::from_millis(
+                        get_block_number_retry_ms(),
+                    ))
+                    .await;
                 }
             }
         }
@@ -127,244 +1086,1813 @@ pub mod ethereum_chain {
         Ok(())
     }
 
-    use futures::stream::StreamExt;
+    abigen!(
+        Erc20,
+        r#"[
+            function decimals() external view returns (uint8)
+            function symbol() external view returns (string)
+            function balanceOf(address account) external view returns (uint256)
+        ]"#
+    );
 
-    fn refresh_position_after_supply(event: Supply) -> Result<(), String> {
-        let current_position = get_position_data()?;
-        let event_amount = U256::from_dec_str(&event.amount.to_string())
-            .expect("Failed to parse U256 from string");
-        let new_supplied_amount = current_position.supplied_amount + event_amount;
-        update_supplied_amount(new_supplied_amount)?;
-        println!(
-            "Updated supplied amount after supply event: {} -> {}",
-            current_position.supplied_amount, new_supplied_amount
-        );
-        Ok(())
+    // Decimals read from token contracts; they never change, so each token is queried once
+    lazy_static::lazy_static! {
+        static ref ON_CHAIN_DECIMALS: Mutex<HashMap<Address, u64>> = Mutex::new(HashMap::new());
     }
 
-    fn refresh_position_after_withdraw(event: Withdraw) -> Result<(), String> {
-        let current_position = get_position_data()?;
-        let event_amount = U256::from_dec_str(&event.amount.to_string())
-            .expect("Failed to parse U256 from string");
-        let new_supplied_amount = if current_position.supplied_amount >= event_amount {
-            current_position.supplied_amount - event_amount
-        } else {
-            U256::from(0)
-        };
-        update_supplied_amount(new_supplied_amount)?;
-        println!(
-            "Updated supplied amount after withdraw event: {} -> {}",
-            current_position.supplied_amount, new_supplied_amount
-        );
-        Ok(())
+    pub fn get_cached_token_decimals(token: &Address) -> Option<u64> {
+        ON_CHAIN_DECIMALS.lock().ok()?.get(token).copied()
     }
 
-    fn refresh_position_after_repay(event: Repay) -> Result<(), String> {
-        let current_position = get_position_data()?;
-        let event_amount = U256::from_dec_str(&event.amount.to_string())
-            .expect("Failed to parse U256 from string");
-        let new_borrowed_amount = if current_position.borrowed_amount >= event_amount {
-            current_position.borrowed_amount - event_amount
-        } else {
-            U256::from(0)
-        };
-        update_borrowed_amount(new_borrowed_amount)?;
-        println!(
-            "Updated borrowed amount after repay event: {} -> {}",
-            current_position.borrowed_amount, new_borrowed_amount
-        );
-        Ok(())
+    /// Address an ENS name points to. An unregistered name, or one without an
+    /// address record, is a configuration error rather than a transient one.
+    pub async fn resolve_ens_name(rpc_url: &str, name: &str) -> Result<Address, MonitorError> {
+        let provider = Provider::<Http>::try_from(rpc_url)
+            .map_err(|e| MonitorError::Config(format!("Failed to create provider: {}", e)))?;
+        provider.resolve_name(name).await.map_err(|e| match e {
+            ProviderError::EnsError(_) | ProviderError::EnsNotOwned(_) => MonitorError::Config(
+                format!("ENS name {} does not resolve to an address: {}", name, e),
+            ),
+            e => MonitorError::Rpc(format!("Failed to resolve ENS name {}: {}", name, e)),
+        })
     }
 
-    fn refresh_position_after_borrow(event: Borrow) -> Result<(), String> {
-        let current_position = get_position_data()?;
-        let event_amount = U256::from_dec_str(&event.amount.to_string())
-            .expect("Failed to parse U256 from string");
-        let new_borrowed_amount = current_position.borrowed_amount + event_amount;
-        update_borrowed_amount(new_borrowed_amount)?;
-        println!(
-            "Updated borrowed amount after borrow event: {} -> {}",
-            current_position.borrowed_amount, new_borrowed_amount
-        );
-        Ok(())
+    pub async fn get_token_decimals_ethereum(
+        rpc_url: &str,
+        token_address: &str,
+    ) -> Result<u64, MonitorError> {
+        let provider = Provider::<Http>::try_from(rpc_url)
+            .map_err(|e| MonitorError::Config(format!("Failed to create provider: {}", e)))?;
+        let token = token_address
+            .parse::<Address>()
+            .map_err(|e| MonitorError::Config(format!("Failed to parse token address: {}", e)))?;
+
+        fetch_token_decimals(Arc::new(provider), token).await
     }
 
-    pub async fn ethereum_listening() -> Result<(), String> {
-        let ws_url = get_ethereum_ws_url();
+    // Function to read `decimals()` from a token contract, served from the cache when known
+    pub async fn fetch_token_decimals<M: Middleware + 'static>(
+        client: Arc<M>,
+        token: Address,
+    ) -> Result<u64, MonitorError> {
+        if let Some(decimals) = get_cached_token_decimals(&token) {
+            return Ok(decimals);
+        }
 
-        let provider_ws = Ws::connect(&ws_url)
+        let decimals = Erc20::new(token, client)
+            .decimals()
+            .call()
             .await
-            .map_err(|e| format!("Failed to connect to WebSocket: {}", e))
-            .map(Provider::new)?;
+            .map_err(|e| {
+                MonitorError::Rpc(format!("Failed to fetch decimals for {:?}: {}", token, e))
+            })? as u64;
+
+        ON_CHAIN_DECIMALS.lock()?.insert(token, decimals);
+        Ok(decimals)
+    }
 
-        let mut stream = provider_ws
-            .subscribe_blocks()
+    // Function to read `symbol()` from a token contract
+    pub async fn get_token_symbol_ethereum(
+        rpc_url: &str,
+        token: Address,
+    ) -> Result<String, MonitorError> {
+        let provider = Provider::<Http>::try_from(rpc_url)
+            .map_err(|e| MonitorError::Config(format!("Failed to create provider: {}", e)))?;
+        Erc20::new(token, Arc::new(provider))
+            .symbol()
+            .call()
             .await
-            .map_err(|e| format!("Failed to subscribe to blocks: {}", e))?;
+            .map_err(|e| MonitorError::Rpc(format!("Failed to fetch symbol of {:?}: {}", token, e)))
+    }
 
-        let mut filter = Filter::new().select(BlockNumber::Latest);
+    abigen!(
+        AavePoolV3,
+        r#"[
+            function getUserAccountData(address user) external view returns (uint256 totalCollateralBase, uint256 totalDebtBase, uint256 availableBorrowsBase, uint256 currentLiquidationThreshold, uint256 ltv, uint256 healthFactor)
+            function getReserveNormalizedIncome(address asset) external view returns (uint256)
+            function getReserveNormalizedVariableDebt(address asset) external view returns (uint256)
+            struct ReserveConfigurationMap { uint256 data; }
+            struct ReserveData { ReserveConfigurationMap configuration; uint128 liquidityIndex; uint128 currentLiquidityRate; uint128 variableBorrowIndex; uint128 currentVariableBorrowRate; uint128 currentStableBorrowRate; uint40 lastUpdateTimestamp; uint16 id; address aTokenAddress; address stableDebtTokenAddress; address variableDebtTokenAddress; address interestRateStrategyAddress; uint128 accruedToTreasury; uint128 unbacked; uint128 isolationModeTotalDebt; }
+            function getReserveData(address asset) external view returns (ReserveData)
+            function getConfiguration(address asset) external view returns (ReserveConfigurationMap)
+            function getUserEMode(address user) external view returns (uint256)
+            struct EModeCategoryConfig { uint16 ltv; uint16 liquidationThreshold; uint16 liquidationBonus; address priceSource; string label; }
+            function getEModeCategoryData(uint8 id) external view returns (EModeCategoryConfig)
+        ]"#
+    );
 
-        let aave_pool_v3_address = get_pool_v3_address().parse::<Address>().map_err(|e| {
-            let err_msg = format!("Failed to parse contract address: {}", e);
-            eprintln!("{}", err_msg);
-            err_msg
-        })?;
+    // V2 orders the indexes differently and has no treasury or isolation fields,
+    // the rest of the LendingPool interface used here is the same as V3
+    abigen!(
+        AaveLendingPoolV2,
+        r#"[
+            struct ReserveDataV2 { uint256 configuration; uint128 liquidityIndex; uint128 variableBorrowIndex; uint128 currentLiquidityRate; uint128 currentVariableBorrowRate; uint128 currentStableBorrowRate; uint40 lastUpdateTimestamp; address aTokenAddress; address stableDebtTokenAddress; address variableDebtTokenAddress; address interestRateStrategyAddress; uint8 id; }
+            function getReserveData(address asset) external view returns (ReserveDataV2)
+        ]"#
+    );
 
-        let aave_user_address_to_track =
-            get_user_address_to_track()
-                .parse::<Address>()
-                .map_err(|e| {
-                    let err_msg = format!("Failed to parse contract address: {}", e);
-                    eprintln!("{}", err_msg);
-                    err_msg
-                })?;
+    // Account totals as reported by Aave, denominated in the pool's base currency
+    #[derive(Debug, Clone)]
+    pub struct UserAccountData {
+        pub total_collateral_base: U256,
+        pub total_debt_base: U256,
+        pub current_liquidation_threshold: U256,
+        pub ltv: U256,
+        pub health_factor: U256,
+    }
 
-        filter.topics = [
-            Some(ValueOrArray::Array(vec![
-                Some(
-                    hex!("2b627736bca15cd5381dcf80b0bf11fd197d01a037c52b927a881a10fb73ba61").into(),
-                ), //supply event
-                Some(
-                    hex!("3115d1449a7b732c986cba18244e897a450f61e1bb8d589cd2e69e6c8924f9f7").into(),
-                ), //withdraw event
-                Some(
-                    hex!("a534c8dbe71f871f9f3530e97a74601fea17b426cae02e1c5aee42c96c784051").into(),
-                ), //repay event
-                Some(
-                    hex!("b3d084820fb1a9decffb176436bd02558d15fac9b0ddfed8c465bc7359d7dce0").into(),
-                ), //borrow event
-            ])),
-            None,
-            None,
-            None,
-        ];
+    // Aave V3's base currency is USD with 8 decimals
+    const BASE_CURRENCY_DECIMALS: i32 = 8;
+    // A V2 LendingPool reports its totals in ETH, as wei
+    const V2_BASE_CURRENCY_DECIMALS: i32 = 18;
+    // Thresholds and LTV are in basis points
+    const PERCENTAGE_FACTOR: f64 = 10_000.0;
+    // The health factor is a wad (1e18)
+    const WAD_DECIMALS: i32 = 18;
 
-        fn fetch_event<T: SolEvent>(
-            topic: &H256,
-            data: String,
-            topic_str: &str,
-            from_str: &str,
-        ) -> Result<Option<T>, String> {
-            if topic
-                != &H256::from_str(topic_str).map_err(|e| format!("Failed to parse H256: {}", e))?
-            {
-                return Ok(None);
+    /// `getUserAccountData` converted to plain numbers: USD totals, thresholds as
+    /// fractions (0.825 for 82.5%) and an infinite health factor when there is no debt
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct OnChainAccountData {
+        pub total_collateral_usd: f64,
+        pub total_debt_usd: f64,
+        pub liquidation_threshold: f64,
+        pub ltv: f64,
+        pub health_factor: f64,
+    }
+
+    impl OnChainAccountData {
+        // Same borrowed/supplied ratio we compute from prices, but from the protocol
+        pub fn debt_to_collateral_ratio(&self) -> Option<f64> {
+            if self.total_collateral_usd <= 0.0 {
+                return None;
             }
-            let log = Log::new(
-                vec![B256::from_str(from_str).unwrap()],
-                hex::decode(data).unwrap().into(),
-            )
-            .unwrap();
-            let event = T::decode_log_object(&log, true)
-                .map_err(|e| format!("Failed to decode log object: {}", e))?;
-            Ok(Some(event))
-        }
-
-        while let Some(block) = stream.next().await {
-            if let Some(_number) = block.number {
-                println!("New block: {:?}", block.number);
-                use chrono::Local;
-                let now = Local::now();
-                println!("Current local time: {}", now.format("%H:%M:%S"));
-
-                // continue;
-                match provider_ws.get_logs(&filter).await {
-                    Ok(logs) => {
-                        for log in logs {
-                            if log.address != aave_pool_v3_address {
-                                continue; // Skip logs not Aave Pool V3 but from other contracts with same events topics
-                            }
-                            let data_string = format!("{}", log.data);
-                            let data = data_string[2..].to_string();
-                            let topics = log.topics.clone();
-
-                            let Some(topic) = topics.get(0) else {
-                                error!("No topic found for log: {:?}", log);
-                                continue;
-                            };
-
-                            let supply_event = fetch_event::<Supply>(
-                                &topic,
-                                data.clone(),
-                                SUPPLY_EVENT_TOPIC,
-                                &format!("0x{}", SUPPLY_EVENT_TOPIC),
-                            )?;
-                            // Handle Supply event
-                            if let Some(event) = supply_event {
-                                //convert event.user Address to H160
-                                let event_user_address = H160::from_str(&event.user.to_string())
-                                    .expect("Failed to parse H160 from string");
-                                if event_user_address != aave_user_address_to_track {
-                                    continue;
-                                }
-                                println!("Supply event detected: {:?}", event);
-                                refresh_position_after_supply(event)?;
-                                continue;
-                            }
-
-                            let withdraw_event = fetch_event::<Withdraw>(
-                                &topic,
-                                data.clone(),
-                                WITHDRAW_EVENT_TOPIC,
-                                &format!("0x{}", WITHDRAW_EVENT_TOPIC),
-                            )?;
-                            // Handle Withdraw event
-                            if let Some(event) = withdraw_event {
-                                let event_user_address = H160::from_str(&event.user.to_string())
-                                    .expect("Failed to parse H160 from string");
-                                if event_user_address != aave_user_address_to_track {
-                                    continue;
-                                }
-                                println!("Withdraw event detected: {:?}", event);
-                                refresh_position_after_withdraw(event)?;
-                                continue;
-                            }
-
-                            let repay_event = fetch_event::<Repay>(
-                                &topic,
-                                data.clone(),
-                                REPAY_EVENT_TOPIC,
-                                &format!("0x{}", REPAY_EVENT_TOPIC),
-                            )?;
-                            // Handle Repay event
-                            if let Some(event) = repay_event {
-                                let event_user_address = H160::from_str(&event.user.to_string())
-                                    .expect("Failed to parse H160 from string");
-                                if event_user_address != aave_user_address_to_track {
-                                    continue;
-                                }
-                                println!("Repay event detected: {:?}", event);
-                                refresh_position_after_repay(event)?;
-                                continue;
-                            }
-
-                            let borrow_event = fetch_event::<Borrow>(
-                                &topic,
-                                data.clone(),
-                                BORROW_EVENT_TOPIC,
-                                &format!("0x{}", BORROW_EVENT_TOPIC),
-                            )?;
-                            // Handle Borrow event
-                            if let Some(event) = borrow_event {
-                                let event_user_address = H160::from_str(&event.user.to_string())
-                                    .expect("Failed to parse H160 from string");
-                                if event_user_address != aave_user_address_to_track {
-                                    continue;
-                                }
-                                println!("Borrow event detected: {:?}", event);
-                                refresh_position_after_borrow(event)?;
-                                continue;
-                            }
-                        }
-                    }
-                    Err(err) => {
-                        eprintln!("Error fetching logs: {:?}", err);
-                        return Err(format!("Error fetching logs: {}", err));
-                    }
+            Some(self.total_debt_usd / self.total_collateral_usd)
+        }
+    }
+
+    // Going through the decimal string keeps every digit f64 can hold, where
+    // `U256::as_u128` would overflow on the health factor of a debt-free account
+    fn scale_down(value: U256, decimals: i32) -> Result<f64, MonitorError> {
+        let value = value.to_string().parse::<f64>().map_err(|e| {
+            MonitorError::Decode(format!("Failed to convert {} to f64: {}", value, e))
+        })?;
+        Ok(value / 10_f64.powi(decimals))
+    }
+
+    impl UserAccountData {
+        /// Scale to plain numbers. `base_currency_usd` is the USD value of one unit of
+        /// the pool's base currency: 1 on V3, whose totals are USD, and the ETH price
+        /// on V2, whose totals are ETH.
+        pub fn scaled(
+            &self,
+            version: PoolVersion,
+            base_currency_usd: f64,
+        ) -> Result<OnChainAccountData, MonitorError> {
+            let decimals = match version {
+                PoolVersion::V2 => V2_BASE_CURRENCY_DECIMALS,
+                PoolVersion::V3 => BASE_CURRENCY_DECIMALS,
+            };
+            Ok(OnChainAccountData {
+                total_collateral_usd: scale_down(self.total_collateral_base, decimals)?
+                    * base_currency_usd,
+                total_debt_usd: scale_down(self.total_debt_base, decimals)? * base_currency_usd,
+                liquidation_threshold: scale_down(self.current_liquidation_threshold, 0)?
+                    / PERCENTAGE_FACTOR,
+                ltv: scale_down(self.ltv, 0)? / PERCENTAGE_FACTOR,
+                // Aave reports `type(uint256).max` for an account without debt
+                health_factor: if self.health_factor == U256::MAX {
+                    f64::INFINITY
+                } else {
+                    scale_down(self.health_factor, WAD_DECIMALS)?
+                },
+            })
+        }
+    }
+
+    pub async fn get_user_account_data_ethereum(
+        rpc_url: &str,
+        user: Address,
+    ) -> Result<UserAccountData, MonitorError> {
+        let provider = Provider::<Http>::try_from(rpc_url)
+            .map_err(|e| MonitorError::Config(format!("Failed to create provider: {}", e)))?;
+        let pool = get_pool_v3_address().parse::<Address>().map_err(|e| {
+            MonitorError::Config(format!("Failed to parse contract address: {}", e))
+        })?;
+
+        let (
+            total_collateral_base,
+            total_debt_base,
+            _available_borrows_base,
+            current_liquidation_threshold,
+            ltv,
+            health_factor,
+        ) = AavePoolV3::new(pool, Arc::new(provider))
+            .get_user_account_data(user)
+            .call()
+            .await
+            .map_err(|e| MonitorError::Rpc(format!("Failed to fetch user account data: {}", e)))?;
+
+        Ok(UserAccountData {
+            total_collateral_base,
+            total_debt_base,
+            current_liquidation_threshold,
+            ltv,
+            health_factor,
+        })
+    }
+
+    /// Read the current liquidity and variable debt indexes of every reserve in
+    /// `chain`'s position and grow its balances by the interest accrued since the
+    /// previous reading
+    pub async fn accrue_pool_interest(
+        chain: &ChainState,
+        rpc_url: &str,
+        pool_address: &str,
+    ) -> Result<(), MonitorError> {
+        let provider = Provider::<Http>::try_from(rpc_url)
+            .map_err(|e| MonitorError::Config(format!("Failed to create provider: {}", e)))?;
+        let pool_address = pool_address.parse::<Address>().map_err(|e| {
+            MonitorError::Config(format!("Failed to parse contract address: {}", e))
+        })?;
+        let pool = AavePoolV3::new(pool_address, Arc::new(provider));
+
+        let position = chain.position()?;
+        let reserves: HashSet<Address> = position
+            .supplied
+            .keys()
+            .chain(position.borrowed.keys())
+            .copied()
+            .collect();
+
+        let mut indexes = HashMap::new();
+        for reserve in reserves {
+            let liquidity = pool
+                .get_reserve_normalized_income(reserve)
+                .call()
+                .await
+                .map_err(|e| {
+                    MonitorError::Rpc(format!(
+                        "Failed to fetch liquidity index of {:?}: {}",
+                        reserve, e
+                    ))
+                })?;
+            let variable_debt = pool
+                .get_reserve_normalized_variable_debt(reserve)
+                .call()
+                .await
+                .map_err(|e| {
+                    MonitorError::Rpc(format!(
+                        "Failed to fetch variable debt index of {:?}: {}",
+                        reserve, e
+                    ))
+                })?;
+            indexes.insert(
+                reserve,
+                ReserveIndexes {
+                    liquidity,
+                    variable_debt,
+                },
+            );
+        }
+
+        chain.accrue_interest(&indexes)
+    }
+
+    // Bits 16-31 of a reserve's configuration bitmap hold its liquidation threshold in bps
+    pub fn liquidation_threshold_from_configuration(configuration: U256) -> f64 {
+        ((configuration >> 16) & U256::from(0xFFFF)).as_u64() as f64 / 10_000.0
+    }
+
+    // Bits 168-175 hold the reserve's eMode category, 0 for none (V3 only)
+    pub fn emode_category_from_configuration(configuration: U256) -> u8 {
+        ((configuration >> 168) & U256::from(0xFF)).as_u64() as u8
+    }
+
+    // Bits 212-251 hold the debt ceiling; a non-zero one makes the reserve isolated
+    // collateral (V3 only)
+    pub fn is_isolated_from_configuration(configuration: U256) -> bool {
+        !((configuration >> 212) & U256::from(0xFF_FFFF_FFFF_u64)).is_zero()
+    }
+
+    /// An Aave V3 efficiency-mode category, from `getEModeCategoryData`
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct EModeCategory {
+        pub id: u8,
+        pub label: String,
+        pub ltv: f64,
+        pub liquidation_threshold: f64,
+    }
+
+    /// The modes that change how an account's collateral is valued
+    #[derive(Debug, Clone, Default, PartialEq)]
+    pub struct AccountMode {
+        // `None` outside eMode
+        pub emode: Option<EModeCategory>,
+        // Supplied reserves belonging to the eMode category
+        pub emode_reserves: HashSet<Address>,
+        // Supplied reserves with a debt ceiling. Using one as collateral puts the
+        // account in isolation mode, where it is the only collateral Aave counts.
+        pub isolated_reserves: HashSet<Address>,
+    }
+
+    impl AccountMode {
+        /// The category's threshold for a reserve in it; other reserves keep their own
+        pub fn emode_liquidation_threshold(&self, reserve: &Address) -> Option<f64> {
+            let emode = self.emode.as_ref()?;
+            self.emode_reserves
+                .contains(reserve)
+                .then_some(emode.liquidation_threshold)
+        }
+    }
+
+    /// Read the liquidation threshold of every collateral reserve of `chain`'s
+    /// position from the Pool's reserve configuration, and on V3 the account's eMode
+    /// category and which of its reserves are isolated
+    pub async fn refresh_liquidation_thresholds(
+        chain: &ChainState,
+        rpc_url: &str,
+        pool_address: &str,
+        version: PoolVersion,
+    ) -> Result<(), MonitorError> {
+        let provider = Provider::<Http>::try_from(rpc_url)
+            .map_err(|e| MonitorError::Config(format!("Failed to create provider: {}", e)))?;
+        let pool_address = pool_address.parse::<Address>().map_err(|e| {
+            MonitorError::Config(format!("Failed to parse contract address: {}", e))
+        })?;
+        let pool = AavePoolV3::new(pool_address, Arc::new(provider));
+
+        let reserves: Vec<Address> = chain.position()?.supplied.keys().copied().collect();
+        let mut reserve_categories = Vec::new();
+        let mut mode = AccountMode::default();
+        for reserve in reserves {
+            let configuration = pool.get_configuration(reserve).call().await.map_err(|e| {
+                MonitorError::Rpc(format!(
+                    "Failed to fetch configuration of {:?}: {}",
+                    reserve, e
+                ))
+            })?;
+            let threshold = liquidation_threshold_from_configuration(configuration.data);
+            chain.set_liquidation_threshold(reserve, threshold)?;
+            set_reserve_liquidation_threshold(reserve, threshold);
+            info!(
+                chain = chain.name,
+                reserve = ?reserve,
+                threshold,
+                "Liquidation threshold refreshed"
+            );
+            if version == PoolVersion::V3 {
+                reserve_categories.push((
+                    reserve,
+                    emode_category_from_configuration(configuration.data),
+                ));
+                if is_isolated_from_configuration(configuration.data) {
+                    mode.isolated_reserves.insert(reserve);
+                }
+            }
+        }
+        // eMode and isolation mode only exist on V3
+        if version == PoolVersion::V3 {
+            let user = chain.user_address().parse::<Address>().map_err(|e| {
+                MonitorError::Config(format!("Failed to parse user address: {}", e))
+            })?;
+            let category = pool.get_user_e_mode(user).call().await.map_err(|e| {
+                MonitorError::Rpc(format!("Failed to fetch eMode of {:?}: {}", user, e))
+            })?;
+            let category = u8::try_from(category.low_u64()).unwrap_or_default();
+            if category != 0 {
+                let data = pool
+                    .get_e_mode_category_data(category)
+                    .call()
+                    .await
+                    .map_err(|e| {
+                        MonitorError::Rpc(format!(
+                            "Failed to fetch eMode category {}: {}",
+                            category, e
+                        ))
+                    })?;
+                mode.emode = Some(EModeCategory {
+                    id: category,
+                    label: data.label,
+                    ltv: f64::from(data.ltv) / 10_000.0,
+                    liquidation_threshold: f64::from(data.liquidation_threshold) / 10_000.0,
+                });
+                mode.emode_reserves = reserve_categories
+                    .into_iter()
+                    .filter(|(_, reserve_category)| *reserve_category == category)
+                    .map(|(reserve, _)| reserve)
+                    .collect();
+            }
+        }
+        if chain.account_mode()? != mode {
+            info!(
+                chain = chain.name,
+                account = %chain.user_address(),
+                emode = ?mode.emode,
+                emode_reserves = ?mode.emode_reserves,
+                isolated_reserves = ?mode.isolated_reserves,
+                "Account eMode and isolation refreshed"
+            );
+        }
+        chain.set_account_mode(mode)
+    }
+
+    abigen!(
+        ChainlinkAggregator,
+        r#"[
+            function decimals() external view returns (uint8)
+            function description() external view returns (string)
+            function latestRoundData() external view returns (uint80 roundId, int256 answer, uint256 startedAt, uint256 updatedAt, uint80 answeredInRound)
+        ]"#
+    );
+
+    /// Latest answer of a Chainlink aggregator
+    #[derive(Debug, Clone)]
+    pub struct ChainlinkAnswer {
+        // e.g. "ETH / USD"
+        pub description: String,
+        pub price: f64,
+        // Unix time the answer was last updated on-chain
+        pub updated_at: u64,
+    }
+
+    /// Read the latest round of a Chainlink aggregator, scaled by its decimals
+    pub async fn fetch_chainlink_answer(
+        rpc_url: &str,
+        aggregator: Address,
+    ) -> Result<ChainlinkAnswer, MonitorError> {
+        let provider = Provider::<Http>::try_from(rpc_url)
+            .map_err(|e| MonitorError::Config(format!("Failed to create provider: {}", e)))?;
+        let feed = ChainlinkAggregator::new(aggregator, Arc::new(provider));
+        let rpc_error = |e: ContractError<Provider<Http>>| {
+            MonitorError::Rpc(format!(
+                "Failed to read Chainlink feed {:?}: {}",
+                aggregator, e
+            ))
+        };
+
+        let decimals = feed.decimals().call().await.map_err(rpc_error)?;
+        let description = feed.description().call().await.map_err(rpc_error)?;
+        let (_, answer, _, updated_at, _) =
+            feed.latest_round_data().call().await.map_err(rpc_error)?;
+        if answer <= I256::zero() {
+            return Err(MonitorError::Price(format!(
+                "Chainlink feed {:?} answered {}",
+                aggregator, answer
+            )));
+        }
+        let answer = answer
+            .to_string()
+            .parse::<f64>()
+            .map_err(|e| MonitorError::Decode(format!("Invalid Chainlink answer: {}", e)))?;
+        Ok(ChainlinkAnswer {
+            description,
+            price: answer / 10_f64.powi(decimals as i32),
+            updated_at: updated_at.low_u64(),
+        })
+    }
+
+    abigen!(
+        UniswapV3Factory,
+        r#"[
+            function getPool(address tokenA, address tokenB, uint24 fee) external view returns (address pool)
+        ]"#
+    );
+
+    abigen!(
+        UniswapV3Pool,
+        r#"[
+            function liquidity() external view returns (uint128)
+            function observe(uint32[] secondsAgos) external view returns (int56[] tickCumulatives, uint160[] secondsPerLiquidityCumulativeX128s)
+        ]"#
+    );
+
+    /// Fee tiers, in hundredths of a basis point, a Uniswap V3 pair can have pools at
+    pub const UNISWAP_V3_FEE_TIERS: [u32; 4] = [100, 500, 3_000, 10_000];
+
+    // Pool chosen per (factory, token, quote), kept for the life of the process so
+    // every price check doesn't re-read four pools
+    lazy_static::lazy_static! {
+        static ref UNISWAP_POOLS: Mutex<HashMap<(Address, Address, Address), Address>> =
+            Mutex::new(HashMap::new());
+    }
+
+    /// Pool of `token` and `quote` holding the most in-range liquidity across the fee
+    /// tiers, `None` when the factory has no pool for the pair at all
+    pub async fn find_deepest_uniswap_pool(
+        rpc_url: &str,
+        factory: Address,
+        token: Address,
+        quote: Address,
+    ) -> Result<Option<Address>, MonitorError> {
+        let key = (factory, token, quote);
+        if let Some(pool) = UNISWAP_POOLS.lock()?.get(&key) {
+            return Ok(Some(*pool));
+        }
+        let provider = Arc::new(
+            Provider::<Http>::try_from(rpc_url)
+                .map_err(|e| MonitorError::Config(format!("Failed to create provider: {}", e)))?,
+        );
+        let factory_contract = UniswapV3Factory::new(factory, provider.clone());
+        let mut deepest: Option<(Address, u128)> = None;
+        for fee in UNISWAP_V3_FEE_TIERS {
+            let pool = factory_contract
+                .get_pool(token, quote, fee)
+                .call()
+                .await
+                .map_err(|e| {
+                    MonitorError::Rpc(format!(
+                        "Failed to look up Uniswap V3 pool {:?}/{:?} at fee {}: {}",
+                        token, quote, fee, e
+                    ))
+                })?;
+            if pool.is_zero() {
+                continue;
+            }
+            let liquidity = UniswapV3Pool::new(pool, provider.clone())
+                .liquidity()
+                .call()
+                .await
+                .map_err(|e| {
+                    MonitorError::Rpc(format!(
+                        "Failed to read liquidity of Uniswap V3 pool {:?}: {}",
+                        pool, e
+                    ))
+                })?;
+            if deepest.is_none_or(|(_, deepest)| liquidity > deepest) {
+                deepest = Some((pool, liquidity));
+            }
+        }
+        let Some((pool, liquidity)) = deepest else {
+            return Ok(None);
+        };
+        tracing::info!(
+            token = ?token,
+            quote = ?quote,
+            pool = ?pool,
+            liquidity,
+            "Selected Uniswap V3 pool for TWAP prices"
+        );
+        UNISWAP_POOLS.lock()?.insert(key, pool);
+        Ok(Some(pool))
+    }
+
+    /// Average tick of `pool` over the last `window_secs`, from its `observe` oracle.
+    /// Fails when the pool doesn't keep observations that far back.
+    pub async fn fetch_uniswap_twap_tick(
+        rpc_url: &str,
+        pool: Address,
+        window_secs: u32,
+    ) -> Result<i32, MonitorError> {
+        let provider = Provider::<Http>::try_from(rpc_url)
+            .map_err(|e| MonitorError::Config(format!("Failed to create provider: {}", e)))?;
+        let (tick_cumulatives, _) = UniswapV3Pool::new(pool, Arc::new(provider))
+            .observe(vec![window_secs, 0])
+            .call()
+            .await
+            .map_err(|e| {
+                // A revert with "OLD" means the oldest observation is newer than the window
+                MonitorError::Rpc(format!(
+                    "Failed to observe Uniswap V3 pool {:?} over {}s: {}",
+                    pool, window_secs, e
+                ))
+            })?;
+        match tick_cumulatives.as_slice() {
+            [start, end] => Ok(twap_tick(*start, *end, window_secs)),
+            _ => Err(MonitorError::Decode(format!(
+                "Uniswap V3 pool {:?} returned {} tick cumulatives, expected 2",
+                pool,
+                tick_cumulatives.len()
+            ))),
+        }
+    }
+
+    /// Arithmetic mean tick between two `tickCumulative`s `window_secs` apart,
+    /// rounded towards negative infinity like Uniswap's `OracleLibrary.consult`
+    pub fn twap_tick(
+        tick_cumulative_start: i64,
+        tick_cumulative_end: i64,
+        window_secs: u32,
+    ) -> i32 {
+        let window = i64::from(window_secs.max(1));
+        let delta = tick_cumulative_end - tick_cumulative_start;
+        let mut tick = delta / window;
+        if delta < 0 && delta % window != 0 {
+            tick -= 1;
+        }
+        tick as i32
+    }
+
+    /// Price of one whole `token` in whole `quote` tokens at `tick`. A pool's tick
+    /// prices token0 (the lower address) in raw units of token1: 1.0001^tick.
+    pub fn uniswap_tick_to_price(
+        tick: i32,
+        token: Address,
+        quote: Address,
+        token_decimals: u64,
+        quote_decimals: u64,
+    ) -> f64 {
+        let raw_price = if token < quote {
+            1.0001_f64.powf(f64::from(tick))
+        } else {
+            1.0001_f64.powf(-f64::from(tick))
+        };
+        raw_price * 10_f64.powi(token_decimals as i32 - quote_decimals as i32)
+    }
+
+    // Token contracts holding a reserve's balances: the aToken for collateral and
+    // one debt token per interest rate mode
+    #[derive(Debug, Clone, Copy)]
+    pub struct ReserveTokens {
+        pub a_token: Address,
+        pub stable_debt_token: Address,
+        pub variable_debt_token: Address,
+    }
+
+    // A reserve's tokens never change, so each (pool, reserve) pair is resolved once
+    lazy_static::lazy_static! {
+        static ref RESERVE_TOKENS: Mutex<HashMap<(Address, Address), ReserveTokens>> =
+            Mutex::new(HashMap::new());
+    }
+
+    async fn fetch_reserve_tokens<M: Middleware + 'static>(
+        pool: &AavePoolV3<M>,
+        version: PoolVersion,
+        reserve: Address,
+    ) -> Result<ReserveTokens, MonitorError> {
+        let key = (pool.address(), reserve);
+        if let Some(tokens) = RESERVE_TOKENS.lock()?.get(&key) {
+            return Ok(*tokens);
+        }
+
+        let reserve_data_error = |e: ContractError<M>| {
+            MonitorError::Rpc(format!(
+                "Failed to fetch reserve data of {:?}: {}",
+                reserve, e
+            ))
+        };
+        let tokens = match version {
+            PoolVersion::V2 => {
+                let data = AaveLendingPoolV2::new(pool.address(), pool.client())
+                    .get_reserve_data(reserve)
+                    .call()
+                    .await
+                    .map_err(reserve_data_error)?;
+                ReserveTokens {
+                    a_token: data.a_token_address,
+                    stable_debt_token: data.stable_debt_token_address,
+                    variable_debt_token: data.variable_debt_token_address,
                 }
             }
+            PoolVersion::V3 => {
+                let data = pool
+                    .get_reserve_data(reserve)
+                    .call()
+                    .await
+                    .map_err(reserve_data_error)?;
+                ReserveTokens {
+                    a_token: data.a_token_address,
+                    stable_debt_token: data.stable_debt_token_address,
+                    variable_debt_token: data.variable_debt_token_address,
+                }
+            }
+        };
+        RESERVE_TOKENS.lock()?.insert(key, tokens);
+        Ok(tokens)
+    }
+
+    async fn fetch_balance<M: Middleware + 'static>(
+        client: Arc<M>,
+        token: Address,
+        account: Address,
+    ) -> Result<U256, MonitorError> {
+        if token.is_zero() {
+            return Ok(U256::zero()); // Reserve without this kind of token
+        }
+        Erc20::new(token, client)
+            .balance_of(account)
+            .call()
+            .await
+            .map_err(|e| {
+                MonitorError::Rpc(format!("Failed to fetch balance in {:?}: {}", token, e))
+            })
+    }
+
+    /// `chain`'s position as its aToken and debt token balances report it, for every
+    /// reserve it already tracks. Collateral flags and the last event are kept; the
+    /// tracked position itself is left untouched.
+    pub async fn read_position_from_balances(
+        chain: &ChainState,
+        rpc_url: &str,
+        pool_address: &str,
+        version: PoolVersion,
+    ) -> Result<PositionData, MonitorError> {
+        let client = Arc::new(
+            Provider::<Http>::try_from(rpc_url)
+                .map_err(|e| MonitorError::Config(format!("Failed to create provider: {}", e)))?,
+        );
+        let pool_address = pool_address.parse::<Address>().map_err(|e| {
+            MonitorError::Config(format!("Failed to parse contract address: {}", e))
+        })?;
+        let pool = AavePoolV3::new(pool_address, Arc::clone(&client));
+        let user = chain
+            .user_address()
+            .parse::<Address>()
+            .map_err(|e| MonitorError::Config(format!("Failed to parse user address: {}", e)))?;
+
+        let mut position = chain.position()?;
+        let reserves: HashSet<Address> = position
+            .supplied
+            .keys()
+            .chain(position.borrowed.keys())
+            .copied()
+            .collect();
+
+        for reserve in reserves {
+            let tokens = fetch_reserve_tokens(&pool, version, reserve).await?;
+            let supplied = fetch_balance(Arc::clone(&client), tokens.a_token, user).await?;
+            let stable_borrowed =
+                fetch_balance(Arc::clone(&client), tokens.stable_debt_token, user).await?;
+            let borrowed = fetch_balance(Arc::clone(&client), tokens.variable_debt_token, user)
+                .await?
+                + stable_borrowed;
+            // Only touch a side the reserve is already on, or that now holds a balance
+            if !supplied.is_zero() || position.supplied.contains_key(&reserve) {
+                position.update_supplied_amount(reserve, supplied);
+            }
+            if !borrowed.is_zero() || position.borrowed.contains_key(&reserve) {
+                position.update_borrowed_amount(reserve, borrowed);
+                position.update_stable_borrowed_amount(reserve, stable_borrowed);
+            }
         }
 
+        Ok(position)
+    }
+
+    use futures::stream::StreamExt;
+
+    pub(crate) fn refresh_position_after_supply(
+        chain: &ChainState,
+        event: Supply,
+    ) -> Result<(), MonitorError> {
+        let reserve = to_h160(event.reserve);
+        let current_amount = chain.position()?.supplied_amount(&reserve);
+        let event_amount = to_u256(event.amount);
+        let Some(new_supplied_amount) = current_amount.checked_add(event_amount) else {
+            report_overflow(chain, "supply", reserve, current_amount, event_amount);
+            return Ok(());
+        };
+        chain.update_supplied_amount(reserve, new_supplied_amount)?;
+        info!(
+            chain = chain.name,
+            event_type = "supply",
+            reserve = ?reserve,
+            from = %current_amount,
+            to = %new_supplied_amount,
+            "Updated supplied amount"
+        );
         Ok(())
     }
+
+    // An event taking out more than we track means an earlier event was missed and
+    // the health factor can't be trusted, so flag it loudly and resync right away
+    fn report_underflow(
+        chain: &ChainState,
+        event_type: &'static str,
+        reserve: Address,
+        tracked: U256,
+        event_amount: U256,
+    ) {
+        warn!(
+            chain = chain.name,
+            event_type,
+            reserve = ?reserve,
+            amount = %event_amount,
+            tracked = %tracked,
+            "Event exceeds the tracked amount; the position is out of sync, resyncing"
+        );
+        record_underflow(chain.name, event_type);
+        chain.request_resync();
+    }
+
+    // An amount that would overflow the tracked balance can only come from a malformed
+    // or hostile event. The balance is left as it was and read back from the chain.
+    fn report_overflow(
+        chain: &ChainState,
+        event_type: &'static str,
+        reserve: Address,
+        tracked: U256,
+        event_amount: U256,
+    ) {
+        error!(
+            chain = chain.name,
+            event_type,
+            reserve = ?reserve,
+            amount = %event_amount,
+            tracked = %tracked,
+            "Event amount overflows the tracked amount; ignoring it and resyncing"
+        );
+        chain.request_resync();
+    }
+
+    pub(crate) fn refresh_position_after_withdraw(
+        chain: &ChainState,
+        event: Withdraw,
+    ) -> Result<(), MonitorError> {
+        let reserve = to_h160(event.reserve);
+        let current_amount = chain.position()?.supplied_amount(&reserve);
+        let event_amount = to_u256(event.amount);
+        let new_supplied_amount = match current_amount.checked_sub(event_amount) {
+            Some(remaining) => remaining,
+            None => {
+                report_underflow(chain, "withdraw", reserve, current_amount, event_amount);
+                U256::zero()
+            }
+        };
+        chain.update_supplied_amount(reserve, new_supplied_amount)?;
+        info!(
+            chain = chain.name,
+            event_type = "withdraw",
+            reserve = ?reserve,
+            from = %current_amount,
+            to = %new_supplied_amount,
+            "Updated supplied amount"
+        );
+        Ok(())
+    }
+
+    pub(crate) fn refresh_position_after_repay(
+        chain: &ChainState,
+        event: Repay,
+    ) -> Result<(), MonitorError> {
+        let reserve = to_h160(event.reserve);
+        let current_amount = chain.position()?.borrowed_amount(&reserve);
+        let event_amount = to_u256(event.amount);
+        let new_borrowed_amount = match current_amount.checked_sub(event_amount) {
+            Some(remaining) => remaining,
+            None => {
+                report_underflow(chain, "repay", reserve, current_amount, event_amount);
+                U256::zero()
+            }
+        };
+        chain.update_borrowed_amount(reserve, new_borrowed_amount)?;
+        keep_stable_within_total(chain, reserve, new_borrowed_amount)?;
+        info!(
+            chain = chain.name,
+            event_type = "repay",
+            reserve = ?reserve,
+            from = %current_amount,
+            to = %new_borrowed_amount,
+            "Updated borrowed amount"
+        );
+        Ok(())
+    }
+
+    // Repay and LiquidationCall don't say which rate mode they paid off. Aave
+    // liquidations burn variable debt first, and repays are taken to do the same,
+    // so the stable part only shrinks once the variable part is gone.
+    fn keep_stable_within_total(
+        chain: &ChainState,
+        reserve: Address,
+        total: U256,
+    ) -> Result<(), MonitorError> {
+        if chain.position()?.stable_borrowed_amount(&reserve) > total {
+            chain.update_stable_borrowed_amount(reserve, total)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn refresh_position_after_borrow(
+        chain: &ChainState,
+        event: Borrow,
+    ) -> Result<(), MonitorError> {
+        let reserve = to_h160(event.reserve);
+        let current_amount = chain.position()?.borrowed_amount(&reserve);
+        let event_amount = to_u256(event.amount);
+        let Some(new_borrowed_amount) = current_amount.checked_add(event_amount) else {
+            report_overflow(chain, "borrow", reserve, current_amount, event_amount);
+            return Ok(());
+        };
+        chain.update_borrowed_amount(reserve, new_borrowed_amount)?;
+        if event.interestRateMode == STABLE_RATE_MODE {
+            // The stable part is within the total, which just took the amount
+            let stable_amount = chain.position()?.stable_borrowed_amount(&reserve);
+            chain.update_stable_borrowed_amount(
+                reserve,
+                stable_amount.saturating_add(event_amount),
+            )?;
+        }
+        info!(
+            chain = chain.name,
+            event_type = "borrow",
+            reserve = ?reserve,
+            rate_mode = if event.interestRateMode == STABLE_RATE_MODE {
+                "stable"
+            } else {
+                "variable"
+            },
+            from = %current_amount,
+            to = %new_borrowed_amount,
+            "Updated borrowed amount"
+        );
+        Ok(())
+    }
+
+    // Details of a liquidation of the tracked position, queued for a dedicated alert
+    #[derive(Debug, Clone)]
+    pub struct LiquidationNotice {
+        pub collateral_asset: Address,
+        pub debt_asset: Address,
+        pub debt_to_cover: U256,
+        pub liquidated_collateral_amount: U256,
+        pub liquidator: Address,
+        pub tx_hash: Option<H256>,
+    }
+
+    // A single supply, withdrawal, repay or borrow of the tracked account, valued
+    // later by the health-factor loop since pricing it here would stall the listener
+    #[derive(Debug, Clone)]
+    pub struct LargeEventNotice {
+        pub event_type: &'static str,
+        pub reserve: Address,
+        pub amount: U256,
+        pub tx_hash: Option<H256>,
+    }
+
+    // Queue a live event for the large-event alert. Replays don't alert, as the
+    // activity they go through is not new.
+    fn queue_large_event(
+        listener: &PoolListener<'_>,
+        chain: &ChainState,
+        log: &ethers::types::Log,
+        event_type: &'static str,
+        reserve: alloy_primitives::Address,
+        amount: alloy_primitives::U256,
+    ) -> Result<(), MonitorError> {
+        if !listener.publish || get_large_event_alert_usd() <= 0.0 {
+            return Ok(());
+        }
+        push_notice(
+            &mut lock_recovering(&chain.large_event_notices),
+            LargeEventNotice {
+                event_type,
+                reserve: to_h160(reserve),
+                amount: to_u256(amount),
+                tx_hash: log.transaction_hash,
+            },
+        );
+        Ok(())
+    }
+
+    // Queue a notice for the next health check, dropping the oldest beyond MAX_QUEUED_NOTICES
+    pub fn push_notice<T>(queue: &mut Vec<T>, notice: T) {
+        queue.push(notice);
+        if queue.len() > MAX_QUEUED_NOTICES {
+            let dropped = queue.len() - MAX_QUEUED_NOTICES;
+            queue.drain(..dropped);
+            warn!(dropped, "Notice queue full, dropped the oldest notices");
+        }
+    }
+
+    pub(crate) fn refresh_position_after_liquidation(
+        chain: &ChainState,
+        event: LiquidationCall,
+        tx_hash: Option<H256>,
+    ) -> Result<(), MonitorError> {
+        let collateral_asset = to_h160(event.collateralAsset);
+        let debt_asset = to_h160(event.debtAsset);
+        let current_position = chain.position()?;
+        let liquidated_collateral_amount = to_u256(event.liquidatedCollateralAmount);
+        let debt_to_cover = to_u256(event.debtToCover);
+
+        let current_supplied = current_position.supplied_amount(&collateral_asset);
+        let new_supplied_amount = current_supplied.saturating_sub(liquidated_collateral_amount);
+        chain.update_supplied_amount(collateral_asset, new_supplied_amount)?;
+
+        let current_borrowed = current_position.borrowed_amount(&debt_asset);
+        let new_borrowed_amount = current_borrowed.saturating_sub(debt_to_cover);
+        chain.update_borrowed_amount(debt_asset, new_borrowed_amount)?;
+        keep_stable_within_total(chain, debt_asset, new_borrowed_amount)?;
+
+        info!(
+            chain = chain.name,
+            event_type = "liquidation_call",
+            collateral_asset = ?collateral_asset,
+            supplied_from = %current_supplied,
+            supplied_to = %new_supplied_amount,
+            debt_asset = ?debt_asset,
+            borrowed_from = %current_borrowed,
+            borrowed_to = %new_borrowed_amount,
+            "Updated position after liquidation"
+        );
+
+        push_notice(
+            &mut lock_recovering(&chain.liquidation_notices),
+            LiquidationNotice {
+                collateral_asset,
+                debt_asset,
+                debt_to_cover,
+                liquidated_collateral_amount,
+                liquidator: to_h160(event.liquidator),
+                tx_hash,
+            },
+        );
+        Ok(())
+    }
+
+    /// Decode a log as event `T` from its raw topics and data; its topic0 has already
+    /// picked `T`. A log with too many topics is skipped, data that doesn't fit `T`
+    /// is a `Decode` error, which the caller skips rather than failing the batch.
+    pub(crate) fn decode_event<T: SolEvent>(
+        topics: &[H256],
+        data: &[u8],
+    ) -> Result<Option<T>, MonitorError> {
+        // Indexed fields (reserve, onBehalfOf, ...) live in the topics, not the data
+        let Some(log) = Log::new(
+            topics
+                .iter()
+                .map(|topic| B256::from_slice(topic.as_bytes()))
+                .collect(),
+            alloy_primitives::Bytes::copy_from_slice(data),
+        ) else {
+            warn!("Skipping log with {} topics", topics.len());
+            return Ok(None);
+        };
+        let event = T::decode_log_object(&log, true)
+            .map_err(|e| MonitorError::Decode(format!("Failed to decode log object: {}", e)))?;
+        Ok(Some(event))
+    }
+
+    pub(crate) fn to_h160(address: alloy_primitives::Address) -> H160 {
+        H160::from_slice(address.as_slice())
+    }
+
+    // Both are 256 bits wide, so every decoded amount converts
+    pub(crate) fn to_u256(value: alloy_primitives::U256) -> U256 {
+        U256::from_big_endian(&value.to_be_bytes::<32>())
+    }
+
+    // Smart accounts (ERC-4337) and routers act through contracts, so the tracked
+    // address may appear as the caller or as the account credited with the position
+    pub(crate) fn is_tracked_account(tracked: H160, parties: &[H160]) -> bool {
+        parties.iter().any(|party| *party == tracked)
+    }
+
+    // Addresses of an event that may identify the tracked account: the caller
+    // (`user`) and the account acting for it (`onBehalfOf`, or `to` for Withdraw)
+    pub(crate) fn event_parties(field: AccountField, user: H160, on_behalf_of: H160) -> Vec<H160> {
+        match field {
+            AccountField::Any => vec![user, on_behalf_of],
+            AccountField::User => vec![user],
+            AccountField::OnBehalfOf => vec![on_behalf_of],
+        }
+    }
+
+    // Remember which transaction last changed the position, and when it happened on chain
+    async fn record_applied_event(
+        listener: &PoolListener<'_>,
+        chain: &ChainState,
+        log: &ethers::types::Log,
+        event_type: &'static str,
+    ) -> Result<(), MonitorError> {
+        let timestamp = listener.block_timestamp(log).await;
+        chain.record_event_tx(log.transaction_hash, timestamp)?;
+        record_event(chain.name, event_type, log.transaction_hash);
+        STATS.record_event(event_type);
+        if listener.publish {
+            publish_position(event_type, chain);
+        }
+        Ok(())
+    }
+
+    // Apply a log, logging and skipping it when it can't be decoded: proxies and
+    // non-standard emitters occasionally produce odd logs, and one of them must not
+    // tear down the subscription
+    pub(crate) async fn apply_log_or_skip(
+        listener: &PoolListener<'_>,
+        log: &ethers::types::Log,
+    ) -> Result<(), MonitorError> {
+        match apply_log(listener, log).await {
+            Err(MonitorError::Decode(e)) => {
+                warn!(
+                    chain = listener.chain.name,
+                    block = ?log.block_number,
+                    tx_hash = ?log.transaction_hash,
+                    "Skipping undecodable log: {}", e
+                );
+                Ok(())
+            }
+            result => result,
+        }
+    }
+
+    // Decode a single Aave Pool log and apply it to the tracked position.
+    // Shared by the live subscription and the startup backfill.
+    async fn apply_log(
+        listener: &PoolListener<'_>,
+        log: &ethers::types::Log,
+    ) -> Result<(), MonitorError> {
+        STATS.record_log();
+        if log.address != listener.pool_address {
+            return Ok(()); // Skip logs not from the Aave Pool but from other contracts with same events topics
+        }
+        // Fast path for logs whose topic0 isn't an event we handle
+        let Some(event) = log
+            .topics
+            .first()
+            .and_then(|topic| listener.events.get(topic))
+        else {
+            return Ok(());
+        };
+        // Backfill, reconnections and per-block fetches can overlap, and applying a
+        // log twice would double its amount
+        if listener.chain.is_log_applied(log)? {
+            info!(
+                chain = listener.chain.name,
+                block = ?log.block_number,
+                tx_hash = ?log.transaction_hash,
+                log_index = ?log.log_index,
+                "Skipping already applied log"
+            );
+            return Ok(());
+        }
+        let data: &[u8] = &log.data;
+        let topics = &log.topics;
+
+        match event {
+            PoolEvent::Supply => {
+                // V2 names Supply "Deposit"
+                let event = match listener.version {
+                    PoolVersion::V2 => {
+                        decode_event::<pool_v2::Deposit>(topics, data)?.map(Supply::from)
+                    }
+                    PoolVersion::V3 => decode_event::<Supply>(topics, data)?,
+                };
+                let Some(event) = event else { return Ok(()) };
+                let Some(chain) = listener.account_state(&event_parties(
+                    listener.account_field,
+                    to_h160(event.user),
+                    to_h160(event.onBehalfOf),
+                )) else {
+                    return Ok(());
+                };
+                log_event_detected(chain, log, "supply", event.reserve, event.amount);
+                queue_large_event(listener, chain, log, "supply", event.reserve, event.amount)?;
+                ensure_token_decimals(&listener.rpc_url, to_h160(event.reserve)).await;
+                refresh_position_after_supply(chain, event)?;
+                record_applied_event(listener, chain, log, "supply").await?;
+            }
+            PoolEvent::Withdraw => {
+                let Some(event) = decode_event::<Withdraw>(topics, data)? else {
+                    return Ok(());
+                };
+                let Some(chain) = listener.account_state(&event_parties(
+                    listener.account_field,
+                    to_h160(event.user),
+                    to_h160(event.to),
+                )) else {
+                    return Ok(());
+                };
+                log_event_detected(chain, log, "withdraw", event.reserve, event.amount);
+                queue_large_event(
+                    listener,
+                    chain,
+                    log,
+                    "withdraw",
+                    event.reserve,
+                    event.amount,
+                )?;
+                refresh_position_after_withdraw(chain, event)?;
+                record_applied_event(listener, chain, log, "withdraw").await?;
+            }
+            PoolEvent::Repay => {
+                // V2's Repay carries different fields
+                let event = match listener.version {
+                    PoolVersion::V2 => {
+                        decode_event::<pool_v2::Repay>(topics, data)?.map(Repay::from)
+                    }
+                    PoolVersion::V3 => decode_event::<Repay>(topics, data)?,
+                };
+                let Some(event) = event else { return Ok(()) };
+                let Some(chain) = listener.account_state(&[to_h160(event.user)]) else {
+                    return Ok(());
+                };
+                log_event_detected(chain, log, "repay", event.reserve, event.amount);
+                queue_large_event(listener, chain, log, "repay", event.reserve, event.amount)?;
+                refresh_position_after_repay(chain, event)?;
+                record_applied_event(listener, chain, log, "repay").await?;
+            }
+            PoolEvent::Borrow => {
+                let event = match listener.version {
+                    PoolVersion::V2 => {
+                        decode_event::<pool_v2::Borrow>(topics, data)?.map(Borrow::from)
+                    }
+                    PoolVersion::V3 => decode_event::<Borrow>(topics, data)?,
+                };
+                let Some(event) = event else { return Ok(()) };
+                let Some(chain) = listener.account_state(&event_parties(
+                    listener.account_field,
+                    to_h160(event.user),
+                    to_h160(event.onBehalfOf),
+                )) else {
+                    return Ok(());
+                };
+                log_event_detected(chain, log, "borrow", event.reserve, event.amount);
+                queue_large_event(listener, chain, log, "borrow", event.reserve, event.amount)?;
+                ensure_token_decimals(&listener.rpc_url, to_h160(event.reserve)).await;
+                refresh_position_after_borrow(chain, event)?;
+                record_applied_event(listener, chain, log, "borrow").await?;
+            }
+            PoolEvent::LiquidationCall => {
+                let Some(event) = decode_event::<LiquidationCall>(topics, data)? else {
+                    return Ok(());
+                };
+                let Some(chain) = listener.account_state(&[to_h160(event.user)]) else {
+                    return Ok(());
+                };
+                log_event_detected(
+                    chain,
+                    log,
+                    "liquidation_call",
+                    event.debtAsset,
+                    event.debtToCover,
+                );
+                refresh_position_after_liquidation(chain, event, log.transaction_hash)?;
+                record_applied_event(listener, chain, log, "liquidation_call").await?;
+            }
+            // Collateral switches change the health factor without moving funds
+            PoolEvent::CollateralEnabled | PoolEvent::CollateralDisabled => {
+                let enabled = *event == PoolEvent::CollateralEnabled;
+                let toggle = if enabled {
+                    decode_event::<ReserveUsedAsCollateralEnabled>(topics, data)?
+                        .map(|event| (event.reserve, event.user))
+                } else {
+                    decode_event::<ReserveUsedAsCollateralDisabled>(topics, data)?
+                        .map(|event| (event.reserve, event.user))
+                };
+                let Some((reserve, user)) = toggle else {
+                    return Ok(());
+                };
+                let Some(chain) = listener.account_state(&[to_h160(user)]) else {
+                    return Ok(());
+                };
+                let event_type = if enabled {
+                    "reserve_used_as_collateral_enabled"
+                } else {
+                    "reserve_used_as_collateral_disabled"
+                };
+                info!(
+                    chain = chain.name,
+                    account = %chain.user_address(),
+                    block = ?log.block_number,
+                    tx_hash = ?log.transaction_hash,
+                    event_type,
+                    reserve = ?to_h160(reserve),
+                    "Aave event detected"
+                );
+                chain.set_collateral(to_h160(reserve), enabled)?;
+                record_applied_event(listener, chain, log, event_type).await?;
+            }
+        }
+        listener.chain.mark_log_applied(log)
+    }
+
+    // One structured line per tracked event, before it is applied
+    fn log_event_detected(
+        chain: &ChainState,
+        log: &ethers::types::Log,
+        event_type: &'static str,
+        reserve: alloy_primitives::Address,
+        amount: alloy_primitives::U256,
+    ) {
+        info!(
+            chain = chain.name,
+            account = %chain.user_address(),
+            block = ?log.block_number,
+            tx_hash = ?log.transaction_hash,
+            event_type,
+            reserve = ?to_h160(reserve),
+            amount = %amount,
+            "Aave event detected"
+        );
+    }
+
+    // Largest block range requested per get_logs call while backfilling
+    pub(crate) const BACKFILL_CHUNK_BLOCKS: u64 = 2_000;
+
+    /// Filter matching every Pool event the listener handles. It doesn't pin the
+    /// Pool address: logs from other contracts are dropped when applied.
+    pub fn pool_filter(version: PoolVersion) -> Filter {
+        let mut filter = Filter::new().select(BlockNumber::Latest);
+        filter.topics = [
+            Some(ValueOrArray::Array(
+                pool_event_topics(version)
+                    .into_iter()
+                    .map(|topic| H256::from_str(topic).ok())
+                    .collect(),
+            )),
+            None,
+            None,
+            None,
+        ];
+        filter
+    }
+
+    // Logs matching `filter` between two blocks (inclusive), in chain order
+    pub(crate) async fn fetch_pool_logs<M: Middleware>(
+        provider: &M,
+        filter: &Filter,
+        from_block: u64,
+        to_block: u64,
+    ) -> Result<Vec<ethers::types::Log>, MonitorError> {
+        let mut logs = provider
+            .get_logs(&filter.clone().from_block(from_block).to_block(to_block))
+            .await
+            .map_err(|e| MonitorError::Rpc(format!("Error fetching logs: {}", e)))?;
+        logs.sort_by_key(|log| (log.block_number, log.log_index));
+        Ok(logs)
+    }
+
+    // Replay events between the persisted last processed block and `to_block`.
+    // Progress is persisted per block, so a crash mid-block re-processes that block cleanly.
+    pub(crate) async fn backfill_missed_events<M: Middleware>(
+        listener: &PoolListener<'_>,
+        provider: &M,
+        filter: &Filter,
+        to_block: u64,
+    ) -> Result<(), MonitorError> {
+        let chain = listener.chain;
+        let last_processed = match chain.last_processed_block() {
+            Some(last_processed) => last_processed,
+            None => match get_start_block(chain.name)? {
+                Some(start_block) => {
+                    let head = provider
+                        .get_block_number()
+                        .await
+                        .map_err(|e| {
+                            MonitorError::Rpc(format!("Failed to get block number: {}", e))
+                        })?
+                        .as_u64();
+                    let first_block = start_block.first_block(to_block, head)?;
+                    info!(
+                        chain = chain.name,
+                        start_block = ?start_block,
+                        first_block,
+                        "No last processed block persisted, starting from the configured start block"
+                    );
+                    // Blocks past `to_block` are left to the live subscription
+                    let last_processed = first_block.saturating_sub(1);
+                    listener.set_last_processed_block(last_processed)?;
+                    last_processed
+                }
+                // Nothing persisted yet, start tracking from here
+                None => return listener.set_last_processed_block(to_block),
+            },
+        };
+        if last_processed >= to_block {
+            return Ok(());
+        }
+        info!(
+            chain = chain.name,
+            from_block = last_processed + 1,
+            to_block,
+            "Backfilling Aave events"
+        );
+
+        let mut from_block = last_processed + 1;
+        while from_block <= to_block {
+            let chunk_end = (from_block + BACKFILL_CHUNK_BLOCKS - 1).min(to_block);
+            let logs = fetch_pool_logs(provider, filter, from_block, chunk_end).await?;
+
+            let mut current_block: Option<(u64, H256)> = None;
+            for log in &logs {
+                let block = log
+                    .block_number
+                    .zip(log.block_hash)
+                    .map(|(number, hash)| (number.as_u64(), hash));
+                if let Some((number, hash)) = current_block {
+                    if block.map(|(next, _)| next) != Some(number) {
+                        listener.commit_block(number, hash)?;
+                        listener.set_last_processed_block(number)?;
+                    }
+                }
+                current_block = block;
+                apply_log_or_skip(listener, log).await?;
+            }
+            if let Some((number, hash)) = current_block {
+                listener.commit_block(number, hash)?;
+            }
+            listener.set_last_processed_block(chunk_end)?;
+            from_block = chunk_end + 1;
+        }
+
+        Ok(())
+    }
+
+    // Roll back blocks that are no longer canonical and replay the canonical chain
+    // up to (but excluding) `block_number`
+    async fn handle_reorg<M: Middleware>(
+        listener: &PoolListener<'_>,
+        provider: &M,
+        filter: &Filter,
+        block_number: u64,
+    ) -> Result<(), MonitorError> {
+        let chain = listener.chain;
+        // Walk back through history until a block is still canonical
+        let history = chain.history_before(block_number)?;
+        let mut fork_block = history
+            .last()
+            .map(|(number, _)| number.saturating_sub(1))
+            .unwrap_or_else(|| block_number.saturating_sub(1));
+        for (number, hash) in history {
+            let canonical = provider
+                .get_block(number)
+                .await
+                .map_err(|e| MonitorError::Rpc(format!("Failed to get block {}: {}", number, e)))?
+                .and_then(|block| block.hash);
+            if canonical == Some(hash) {
+                fork_block = number;
+                break;
+            }
+        }
+
+        let reverted = listener.rollback_to(fork_block)?;
+        warn!(
+            chain = chain.name,
+            block = block_number,
+            reverted,
+            fork_block,
+            "Reorg detected, reverted blocks back to the fork"
+        );
+        listener.set_last_processed_block(fork_block)?;
+
+        backfill_missed_events(listener, provider, filter, block_number.saturating_sub(1)).await
+    }
+
+    pub struct Ethereum;
+
+    impl Chain for Ethereum {
+        fn state(&self) -> &'static ChainState {
+            &ETHEREUM
+        }
+
+        fn ws_endpoints(&self) -> &'static EndpointPool {
+            &ETHEREUM_WS_ENDPOINTS
+        }
+
+        fn rpc_endpoints(&self) -> &'static EndpointPool {
+            &ETHEREUM_RPC_ENDPOINTS
+        }
+
+        fn pool_address(&self) -> String {
+            get_pool_v3_address()
+        }
+
+        fn pool_version(&self) -> PoolVersion {
+            get_pool_version()
+        }
+    }
+
+    // Connection details of one chain's Aave Pool, shared by the listener helpers
+    pub(crate) struct PoolListener<'a> {
+        chain: &'a ChainState,
+        rpc_url: String,
+        pool_address: Address,
+        // Every tracked account with the state its events go to, the first
+        // account's (`chain`) first
+        accounts: Vec<(Address, &'a ChainState)>,
+        account_field: AccountField,
+        version: PoolVersion,
+        // Handled events by topic0, see `pool_events`
+        events: HashMap<H256, PoolEvent>,
+        // Whether applied events are streamed to PUBLISH_URL; off while replaying
+        publish: bool,
+        // Number and timestamp of the block whose logs are being applied
+        block_time: Mutex<Option<(u64, u64)>>,
+    }
+
+    impl<'a> PoolListener<'a> {
+        pub(crate) fn new(
+            chain: &'a ChainState,
+            rpc_url: &str,
+            pool_address: Address,
+            user_address: Address,
+            account_field: AccountField,
+            version: PoolVersion,
+        ) -> Self {
+            Self {
+                chain,
+                rpc_url: rpc_url.to_string(),
+                pool_address,
+                accounts: vec![(user_address, chain)],
+                account_field,
+                version,
+                events: pool_event_map(version),
+                publish: true,
+                block_time: Mutex::new(None),
+            }
+        }
+
+        // Also apply `account`'s events to `state`, sharing this listener's subscription
+        pub(crate) fn track_account(mut self, account: Address, state: &'a ChainState) -> Self {
+            self.accounts.push((account, state));
+            self
+        }
+
+        // State of the first tracked account among an event's parties
+        fn account_state(&self, parties: &[H160]) -> Option<&'a ChainState> {
+            self.accounts
+                .iter()
+                .find(|(account, _)| is_tracked_account(*account, parties))
+                .map(|(_, state)| *state)
+        }
+
+        // Block bookkeeping is kept per account, so every state moves in lockstep
+        fn commit_block(&self, number: u64, hash: H256) -> Result<(), MonitorError> {
+            for (_, state) in &self.accounts {
+                state.commit_block(number, hash)?;
+            }
+            STATS.record_block();
+            Ok(())
+        }
+
+        fn set_last_processed_block(&self, number: u64) -> Result<(), MonitorError> {
+            for (_, state) in &self.accounts {
+                state.set_last_processed_block(number)?;
+            }
+            Ok(())
+        }
+
+        fn rollback_to(&self, fork_block: u64) -> Result<usize, MonitorError> {
+            let mut reverted = 0;
+            for (_, state) in &self.accounts {
+                reverted = reverted.max(state.rollback_to(fork_block)?);
+            }
+            Ok(reverted)
+        }
+
+        // Apply events without streaming them, so a replay of past blocks isn't
+        // mistaken for live position changes downstream
+        pub(crate) fn without_publishing(mut self) -> Self {
+            self.publish = false;
+            self
+        }
+
+        fn remember_block_time(&self, number: u64, timestamp: u64) {
+            if let Ok(mut block_time) = self.block_time.lock() {
+                *block_time = Some((number, timestamp));
+            }
+        }
+
+        // Timestamp of the block a log was emitted in. Live blocks are known from their
+        // header; backfilled ones are looked up once per block with a tracked event.
+        async fn block_timestamp(&self, log: &ethers::types::Log) -> Option<u64> {
+            let number = log.block_number?.as_u64();
+            let known = self
+                .block_time
+                .lock()
+                .ok()
+                .and_then(|block_time| *block_time);
+            if let Some((known_number, timestamp)) = known {
+                if known_number == number {
+                    return Some(timestamp);
+                }
+            }
+            let provider = Provider::<Http>::try_from(self.rpc_url.as_str()).ok()?;
+            match provider.get_block(number).await {
+                Ok(Some(block)) => {
+                    let timestamp = block.timestamp.as_u64();
+                    self.remember_block_time(number, timestamp);
+                    Some(timestamp)
+                }
+                Ok(None) => None,
+                Err(e) => {
+                    warn!(block = number, "Failed to fetch block timestamp: {}", e);
+                    None
+                }
+            }
+        }
+    }
+
+    // Block time as shown in logs, e.g. `2024-05-01 12:00:00 UTC`
+    pub fn format_block_time(timestamp: u64) -> String {
+        chrono::DateTime::from_timestamp(timestamp as i64, 0)
+            .map(|time| time.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+            .unwrap_or_else(|| timestamp.to_string())
+    }
+
+    /// Follow an Aave Pool deployment over WebSocket and apply each tracked account's
+    /// events to its state in `accounts`, the first configured account's first. The
+    /// event topics depend only on the pool version.
+    pub async fn listen_pool(
+        accounts: &[&ChainState],
+        ws_endpoints: &EndpointPool,
+        rpc_url: &str,
+        pool_address: &str,
+        version: PoolVersion,
+    ) -> Result<(), MonitorError> {
+        let ws_url = ws_endpoints.current();
+        let subscribed = async {
+            let provider_ws = Ws::connect(ws_url.as_str())
+                .await
+                .map_err(|e| MonitorError::Rpc(format!("Failed to connect to WebSocket: {}", e)))
+                .map(Provider::new)?;
+            let stream = provider_ws
+                .subscribe_blocks()
+                .await
+                .map_err(|e| MonitorError::Rpc(format!("Failed to subscribe to blocks: {}", e)))?;
+            Ok::<_, MonitorError>((provider_ws, stream))
+        }
+        .await;
+        ws_endpoints.record(&ws_url, &subscribed);
+        let (provider_ws, mut stream) = subscribed?;
+
+        let filter = pool_filter(version);
+
+        let aave_pool_v3_address = pool_address.parse::<Address>().map_err(|e| {
+            let err_msg = MonitorError::Config(format!("Failed to parse contract address: {}", e));
+            error!("{}", err_msg);
+            err_msg
+        })?;
+
+        let parse_account = |state: &ChainState| {
+            state.user_address().parse::<Address>().map_err(|e| {
+                let err_msg = MonitorError::Config(format!("Failed to parse user address: {}", e));
+                error!("{}", err_msg);
+                err_msg
+            })
+        };
+        let Some((&chain, others)) = accounts.split_first() else {
+            return Err(MonitorError::Config("No account to track".to_string()));
+        };
+        let mut listener = PoolListener::new(
+            chain,
+            rpc_url,
+            aave_pool_v3_address,
+            parse_account(chain)?,
+            get_account_field(),
+            version,
+        );
+        for &state in others {
+            listener = listener.track_account(parse_account(state)?, state);
+        }
+
+        let confirmation_depth = get_confirmation_depth();
+        let head = provider_ws
+            .get_block_number()
+            .await
+            .map_err(|e| MonitorError::Rpc(format!("Failed to get block number: {}", e)))?
+            .as_u64();
+        backfill_missed_events(
+            &listener,
+            &provider_ws,
+            &filter,
+            head.saturating_sub(confirmation_depth),
+        )
+        .await?;
+        for state in accounts {
+            state.initial_sync_complete.store(true, Ordering::SeqCst);
+        }
+
+        let block_timeout = Duration::from_secs(get_ws_block_timeout_secs());
+        loop {
+            let block = next_block(&mut stream, block_timeout).await;
+            // A dead subscription counts against the endpoint, like a failed connect
+            ws_endpoints.record(&ws_url, &block);
+            let block = block.inspect_err(|e| {
+                warn!(
+                    chain = chain.name,
+                    "Block subscription lost, reconnecting: {}", e
+                )
+            })?;
+            if let Some(number) = block.number {
+                for state in accounts {
+                    state.set_latest_block(number.as_u64());
+                }
+                info!(
+                    chain = chain.name,
+                    block = number.as_u64(),
+                    time = %format_block_time(block.timestamp.as_u64()),
+                    "New block"
+                );
+
+                apply_head(&listener, &provider_ws, &filter, block, confirmation_depth).await?;
+            }
+        }
+    }
+
+    /// Apply the block `confirmation_depth` blocks below a new head. Until then its
+    /// events are only pending: the block at that height is looked up when it gets
+    /// there and its logs are read by hash, so a block orphaned before reaching the
+    /// depth never has its events applied.
+    pub(crate) async fn apply_head<M: Middleware>(
+        listener: &PoolListener<'_>,
+        provider: &M,
+        filter: &Filter,
+        head: Block<H256>,
+        confirmation_depth: u64,
+    ) -> Result<(), MonitorError> {
+        let Some(target_number) = head
+            .number
+            .and_then(|number| number.as_u64().checked_sub(confirmation_depth))
+        else {
+            return Ok(());
+        };
+        let target = if confirmation_depth == 0 {
+            head
+        } else {
+            match provider.get_block(target_number).await {
+                Ok(Some(target)) => target,
+                // Picked up by the next head's gap backfill
+                Ok(None) => return Ok(()),
+                Err(e) => {
+                    return Err(MonitorError::Rpc(format!(
+                        "Failed to get block {}: {}",
+                        target_number, e
+                    )))
+                }
+            }
+        };
+        let Some(target_hash) = target.hash else {
+            return Ok(());
+        };
+        listener.remember_block_time(target_number, target.timestamp.as_u64());
+
+        apply_confirmed_block(
+            listener,
+            provider,
+            filter,
+            target_number,
+            target_hash,
+            target.parent_hash,
+        )
+        .await
+    }
+
+    /// Apply the events of a confirmed block, after undoing a reorg it reveals.
+    /// Blocks between the last processed one and it (heads the subscription
+    /// skipped, or a confirmed block that couldn't be fetched) are backfilled
+    /// first; logs already applied are skipped, so overlaps are harmless.
+    pub(crate) async fn apply_confirmed_block<M: Middleware>(
+        listener: &PoolListener<'_>,
+        provider: &M,
+        filter: &Filter,
+        number: u64,
+        hash: H256,
+        parent_hash: H256,
+    ) -> Result<(), MonitorError> {
+        let chain = listener.chain;
+        if chain.conflicts_with_history(number, hash)?
+            || chain.conflicts_with_history(number.saturating_sub(1), parent_hash)?
+        {
+            handle_reorg(listener, provider, filter, number).await?;
+        } else if chain
+            .last_processed_block()
+            .is_some_and(|last| last >= number)
+        {
+            return Ok(()); // Already applied, e.g. during backfill
+        } else if chain
+            .last_processed_block()
+            .is_some_and(|last| last + 1 < number)
+        {
+            backfill_missed_events(listener, provider, filter, number - 1).await?;
+        }
+
+        match provider.get_logs(&filter.clone().at_block_hash(hash)).await {
+            Ok(logs) => {
+                for log in logs {
+                    apply_log_or_skip(listener, &log).await?;
+                }
+                // Only mark the block once all of its logs are applied
+                listener.commit_block(number, hash)?;
+                listener.set_last_processed_block(number)
+            }
+            Err(err) => {
+                error!(chain = chain.name, "Error fetching logs: {:?}", err);
+                Err(MonitorError::Rpc(format!("Error fetching logs: {}", err)))
+            }
+        }
+    }
+
+    /// Next block of a subscription. The stream ending, or no block arriving for
+    /// `timeout` (zero waits forever), means the connection is dead: it is returned
+    /// as an error so the listener reconnects with backoff instead of going blind.
+    pub async fn next_block<S>(
+        stream: &mut S,
+        timeout: Duration,
+    ) -> Result<Block<H256>, MonitorError>
+    where
+        S: futures::Stream<Item = Block<H256>> + Unpin,
+    {
+        let next = if timeout.is_zero() {
+            stream.next().await
+        } else {
+            tokio::time::timeout(timeout, stream.next())
+                .await
+                .map_err(|_| {
+                    MonitorError::Rpc(format!("No new block for {}s", timeout.as_secs()))
+                })?
+        };
+        next.ok_or_else(|| MonitorError::Rpc("Block subscription ended".to_string()))
+    }
 }
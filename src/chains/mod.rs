@@ -1,51 +1,594 @@
+use async_trait::async_trait;
 use ethers::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::env;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Mutex, MutexGuard};
+use tracing::{info, warn};
 
-use crate::chains::ethereum::ethereum_chain::get_current_block_number_ethereum;
+use crate::error::MonitorError;
+
+use crate::chains::arbitrum::arbitrum_chain::{Arbitrum, ARBITRUM, ARBITRUM_RPC_ENDPOINTS};
+use crate::chains::base::base_chain::{Base, BASE, BASE_RPC_ENDPOINTS};
+use crate::chains::endpoints::{parse_endpoint_list, EndpointPool};
+use crate::chains::ethereum::ethereum_chain::{
+    account_states, accrue_pool_interest, get_cached_token_decimals, get_current_block_number,
+    get_token_decimals_ethereum, get_token_symbol_ethereum, listen_pool,
+    read_position_from_balances, refresh_liquidation_thresholds, resolve_ens_name, ChainState,
+    Ethereum, ETHEREUM, ETHEREUM_RPC_ENDPOINTS,
+};
+use crate::chains::optimism::optimism_chain::{Optimism, OPTIMISM, OPTIMISM_RPC_ENDPOINTS};
+use crate::chains::polygon::polygon_chain::{Polygon, POLYGON, POLYGON_RPC_ENDPOINTS};
+use crate::chains::solana::solana_chain::{
+    is_solana_address, parse_solana_positions, SolanaReserve,
+};
+
+pub mod endpoints;
 
 pub mod ethereum;
 
+pub mod polygon;
+
+pub mod arbitrum;
+
+pub mod optimism;
+
+pub mod base;
+
+pub mod solana;
+
 pub mod pk;
 
+// Struct to represent borrowed and supplied amounts per reserve
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct PositionData {
-    pub supplied_amount: U256,
-    pub borrowed_amount: U256,
+    // Raw token amounts keyed by reserve (token) address
+    #[serde(with = "decimal_amounts")]
+    pub supplied: HashMap<Address, U256>,
+    #[serde(with = "decimal_amounts")]
+    pub borrowed: HashMap<Address, U256>,
+    // Stable-rate part of `borrowed`; the rest of a reserve's debt is variable-rate
+    #[serde(with = "decimal_amounts", default)]
+    pub stable_borrowed: HashMap<Address, U256>,
+    // Transaction of the last event that changed the position, kept as an
+    // exemplar so metrics can link a health-factor move to its cause
+    pub last_event_tx: Option<H256>,
+    // Unix time of that event's block, as opposed to when we happened to see it
+    #[serde(default)]
+    pub last_event_timestamp: Option<u64>,
+    // Supplied reserves the user switched off as collateral. They still earn
+    // interest but don't back the debt, so the health factor leaves them out.
+    #[serde(default)]
+    pub collateral_disabled: HashSet<Address>,
+    // Unix time of the last change made through the update methods
+    #[serde(default)]
+    pub last_updated: Option<u64>,
 }
 
 impl PositionData {
     pub fn new() -> Self {
-        Self {
-            supplied_amount: U256::from(0),
-            borrowed_amount: U256::from(0),
+        Self::default()
+    }
+
+    pub fn supplied_amount(&self, reserve: &Address) -> U256 {
+        self.supplied.get(reserve).copied().unwrap_or_default()
+    }
+
+    /// Total debt in a reserve, stable and variable
+    pub fn borrowed_amount(&self, reserve: &Address) -> U256 {
+        self.borrowed.get(reserve).copied().unwrap_or_default()
+    }
+
+    pub fn stable_borrowed_amount(&self, reserve: &Address) -> U256 {
+        self.stable_borrowed
+            .get(reserve)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn variable_borrowed_amount(&self, reserve: &Address) -> U256 {
+        self.borrowed_amount(reserve)
+            .saturating_sub(self.stable_borrowed_amount(reserve))
+    }
+
+    /// Variable-rate debt of every borrowed reserve
+    pub fn variable_borrowed(&self) -> HashMap<Address, U256> {
+        self.borrowed
+            .keys()
+            .map(|reserve| (*reserve, self.variable_borrowed_amount(reserve)))
+            .collect()
+    }
+
+    pub fn update_supplied_amount(&mut self, reserve: Address, new_amount: U256) {
+        self.supplied.insert(reserve, new_amount);
+        self.touch();
+    }
+
+    pub fn update_borrowed_amount(&mut self, reserve: Address, new_amount: U256) {
+        self.borrowed.insert(reserve, new_amount);
+        self.touch();
+    }
+
+    pub fn update_stable_borrowed_amount(&mut self, reserve: Address, new_amount: U256) {
+        self.stable_borrowed.insert(reserve, new_amount);
+        self.touch();
+    }
+
+    fn touch(&mut self) {
+        self.last_updated = Some(chrono::Utc::now().timestamp() as u64);
+    }
+
+    /// Whether a supplied reserve counts toward the collateral. Reserves are
+    /// collateral until a `ReserveUsedAsCollateralDisabled` event says otherwise.
+    pub fn is_collateral(&self, reserve: &Address) -> bool {
+        !self.collateral_disabled.contains(reserve)
+    }
+
+    pub fn set_collateral(&mut self, reserve: Address, enabled: bool) {
+        if enabled {
+            self.collateral_disabled.remove(&reserve);
+        } else {
+            self.collateral_disabled.insert(reserve);
         }
+        self.touch();
+    }
+
+    /// Supplied amounts of the reserves used as collateral
+    pub fn collateral(&self) -> HashMap<Address, U256> {
+        self.supplied
+            .iter()
+            .filter(|(reserve, _)| self.is_collateral(reserve))
+            .map(|(reserve, amount)| (*reserve, *amount))
+            .collect()
     }
 }
 
-pub fn get_position_data() -> Result<PositionData, String> {
-    let mut position_data = PositionData::new();
-    // Read initial values from environment variables
-    if let Ok(supplied_amount_str) = env::var("INITIAL_SUPPLIED_AMOUNT") {
-        if let Ok(amount) = supplied_amount_str.parse::<u64>() {
-            position_data.supplied_amount = U256::from(amount);
-        }
+/// Lock `mutex` even when a thread panicked while holding it. Position data and its
+/// bookkeeping are plain values that stay usable after a panic, so one panic mustn't
+/// fail every later read and write for the rest of the run.
+pub fn lock_recovering<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|poisoned| {
+        warn!("Recovering a lock poisoned by a panicked thread");
+        mutex.clear_poison();
+        poisoned.into_inner()
+    })
+}
+
+// Amounts are stored as decimal strings: readable, and U256 doesn't fit a JSON number
+mod decimal_amounts {
+    use ethers::prelude::{Address, U256};
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::collections::{BTreeMap, HashMap};
+
+    pub fn serialize<S: Serializer>(
+        amounts: &HashMap<Address, U256>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        amounts
+            .iter()
+            .map(|(reserve, amount)| (format!("{:?}", reserve), amount.to_string()))
+            .collect::<BTreeMap<_, _>>()
+            .serialize(serializer)
     }
-    if let Ok(borrowed_amount_str) = env::var("INITIAL_BORROWED_AMOUNT") {
-        if let Ok(amount) = borrowed_amount_str.parse::<u64>() {
-            position_data.borrowed_amount = U256::from(amount);
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<HashMap<Address, U256>, D::Error> {
+        BTreeMap::<String, String>::deserialize(deserializer)?
+            .into_iter()
+            .map(|(reserve, amount)| {
+                let reserve = reserve
+                    .parse::<Address>()
+                    .map_err(|e| Error::custom(format!("invalid reserve {}: {}", reserve, e)))?;
+                let amount = U256::from_dec_str(&amount)
+                    .map_err(|e| Error::custom(format!("invalid amount {}: {}", amount, e)))?;
+                Ok((reserve, amount))
+            })
+            .collect()
+    }
+}
+
+/// One reserve of a multi-asset position, configured as `address:decimals:initial_amount`
+#[derive(Debug, Clone)]
+pub struct AssetConfig {
+    pub address: Address,
+    pub decimals: u64,
+    pub initial_amount: U256,
+    // Decimals set explicitly by the user take precedence over the token contract
+    pub decimals_overridden: bool,
+}
+
+pub fn parse_asset_positions(raw: &str) -> Result<Vec<AssetConfig>, MonitorError> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let parts: Vec<&str> = entry.split(':').map(str::trim).collect();
+            if parts.len() != 3 {
+                return Err(MonitorError::Config(format!(
+                    "Invalid asset entry '{}', expected address:decimals:initial_amount",
+                    entry
+                )));
+            }
+            let address = parts[0].parse::<Address>().map_err(|e| {
+                MonitorError::Config(format!("Invalid asset address '{}': {}", parts[0], e))
+            })?;
+            let decimals = parts[1].parse::<u64>().map_err(|e| {
+                MonitorError::Config(format!("Invalid decimals '{}': {}", parts[1], e))
+            })?;
+            let initial_amount = U256::from_dec_str(parts[2]).map_err(|e| {
+                MonitorError::Config(format!("Invalid initial amount '{}': {}", parts[2], e))
+            })?;
+            Ok(AssetConfig {
+                address,
+                decimals,
+                initial_amount,
+                decimals_overridden: true,
+            })
+        })
+        .collect()
+}
+
+/// Raw initial amount of a token. 18-decimal amounts routinely exceed `u64::MAX`,
+/// so this parses straight into a `U256`. An invalid value is reported and
+/// treated as zero.
+pub fn parse_initial_amount(env_name: &str, raw: &str) -> U256 {
+    match U256::from_dec_str(raw.trim()) {
+        Ok(amount) => amount,
+        Err(e) => {
+            warn!(
+                "Invalid {} '{}', expected a raw token amount: {}. Starting from 0",
+                env_name, raw, e
+            );
+            U256::zero()
         }
     }
-    Ok(position_data)
+}
+
+// Single-pair configuration kept for backward compatibility
+fn legacy_asset(
+    address: String,
+    decimals: u64,
+    decimals_env: &str,
+    amount_env: &str,
+) -> Result<Vec<AssetConfig>, MonitorError> {
+    let address = address
+        .parse::<Address>()
+        .map_err(|e| MonitorError::Config(format!("Invalid token address '{}': {}", address, e)))?;
+    let initial_amount = env::var(amount_env)
+        .map(|raw| parse_initial_amount(amount_env, &raw))
+        .unwrap_or_default();
+    Ok(vec![AssetConfig {
+        address,
+        decimals,
+        initial_amount,
+        decimals_overridden: env::var(decimals_env).is_ok(),
+    }])
+}
+
+pub fn get_supply_assets() -> Result<Vec<AssetConfig>, MonitorError> {
+    match env::var("AAVE_SUPPLY_POSITIONS") {
+        Ok(raw) => parse_asset_positions(&raw),
+        Err(_) => legacy_asset(
+            get_supply_token_address(),
+            get_supply_token_decimals(),
+            "AAVE_SUPPLY_TOKEN_DECIMALS",
+            "INITIAL_SUPPLIED_AMOUNT",
+        ),
+    }
+}
+
+pub fn get_borrowed_assets() -> Result<Vec<AssetConfig>, MonitorError> {
+    match env::var("AAVE_BORROWED_POSITIONS") {
+        Ok(raw) => parse_asset_positions(&raw),
+        Err(_) => legacy_asset(
+            get_borrowed_token_address(),
+            get_borrowed_token_decimals(),
+            "AAVE_BORROWED_TOKEN_DECIMALS",
+            "INITIAL_BORROWED_AMOUNT",
+        ),
+    }
+}
+
+// Build the starting position from configuration
+pub fn get_initial_position_data() -> Result<PositionData, MonitorError> {
+    Ok(position_from_assets(
+        &get_supply_assets()?,
+        &get_borrowed_assets()?,
+    ))
+}
+
+pub fn get_polygon_initial_position_data() -> Result<PositionData, MonitorError> {
+    Ok(position_from_assets(
+        &get_polygon_supply_assets()?,
+        &get_polygon_borrowed_assets()?,
+    ))
+}
+
+pub fn get_arbitrum_initial_position_data() -> Result<PositionData, MonitorError> {
+    Ok(position_from_assets(
+        &get_arbitrum_supply_assets()?,
+        &get_arbitrum_borrowed_assets()?,
+    ))
+}
+
+pub fn get_optimism_initial_position_data() -> Result<PositionData, MonitorError> {
+    Ok(position_from_assets(
+        &get_optimism_supply_assets()?,
+        &get_optimism_borrowed_assets()?,
+    ))
+}
+
+pub fn get_base_initial_position_data() -> Result<PositionData, MonitorError> {
+    Ok(position_from_assets(
+        &get_base_supply_assets()?,
+        &get_base_borrowed_assets()?,
+    ))
+}
+
+fn position_from_assets(supplied: &[AssetConfig], borrowed: &[AssetConfig]) -> PositionData {
+    let mut position_data = PositionData::new();
+    for asset in supplied {
+        position_data.update_supplied_amount(asset.address, asset.initial_amount);
+    }
+    for asset in borrowed {
+        position_data.update_borrowed_amount(asset.address, asset.initial_amount);
+    }
+    position_data
+}
+
+/// What is known about a reserve, gathered from AAVE_KNOWN_ASSETS, the position
+/// configuration and on-chain reads. A field stays `None` until one of them fills it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReserveMetadata {
+    pub decimals: Option<u64>,
+    pub symbol: Option<String>,
+    // Whether Aave accepts the reserve as collateral at all, i.e. a nonzero threshold
+    pub is_collateral: Option<bool>,
+    pub liquidation_threshold: Option<f64>,
+}
+
+// Metadata of every reserve we value, keyed by token address
+lazy_static::lazy_static! {
+    static ref RESERVE_METADATA: Mutex<HashMap<Address, ReserveMetadata>> =
+        Mutex::new(HashMap::new());
+}
+
+pub fn get_reserve_metadata(reserve: &Address) -> Option<ReserveMetadata> {
+    RESERVE_METADATA.lock().ok()?.get(reserve).cloned()
+}
+
+/// Change a reserve's metadata in place, creating an empty entry first if needed
+pub fn update_reserve_metadata(reserve: Address, update: impl FnOnce(&mut ReserveMetadata)) {
+    if let Ok(mut registry) = RESERVE_METADATA.lock() {
+        update(registry.entry(reserve).or_default());
+    }
+}
+
+pub fn get_token_decimals(reserve: &Address) -> Option<u64> {
+    if let Some(decimals) = get_reserve_metadata(reserve).and_then(|known| known.decimals) {
+        return Some(decimals);
+    }
+    get_supply_assets()
+        .ok()?
+        .into_iter()
+        .chain(get_borrowed_assets().ok()?)
+        .find(|asset| &asset.address == reserve)
+        .map(|asset| asset.decimals)
+}
+
+pub fn set_token_decimals(reserve: Address, decimals: u64) {
+    update_reserve_metadata(reserve, |known| known.decimals = Some(decimals));
+}
+
+pub fn get_token_symbol(reserve: &Address) -> Option<String> {
+    get_reserve_metadata(reserve)?.symbol
+}
+
+/// Record a reserve's liquidation threshold as read from a Pool. A zero threshold
+/// means the reserve can't be used as collateral.
+pub fn set_reserve_liquidation_threshold(reserve: Address, threshold: f64) {
+    update_reserve_metadata(reserve, |known| {
+        known.liquidation_threshold = Some(threshold);
+        known.is_collateral = Some(threshold > 0.0);
+    });
+}
+
+/// Parse AAVE_KNOWN_ASSETS: comma-separated `address:symbol:decimals` entries with an
+/// optional `:liquidation_threshold`, e.g. `0xdac1...1ec7:USDT:6:0.78`
+pub fn parse_known_assets(raw: &str) -> Result<Vec<(Address, ReserveMetadata)>, MonitorError> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let parts: Vec<&str> = entry.split(':').map(str::trim).collect();
+            if parts.len() != 3 && parts.len() != 4 {
+                return Err(MonitorError::Config(format!(
+                    "Invalid known asset '{}', expected address:symbol:decimals[:liquidation_threshold]",
+                    entry
+                )));
+            }
+            let address = parts[0].parse::<Address>().map_err(|e| {
+                MonitorError::Config(format!("Invalid asset address '{}': {}", parts[0], e))
+            })?;
+            let decimals = parts[2].parse::<u64>().map_err(|e| {
+                MonitorError::Config(format!("Invalid decimals '{}': {}", parts[2], e))
+            })?;
+            let liquidation_threshold = match parts.get(3) {
+                Some(raw) => match raw.parse::<f64>() {
+                    Ok(threshold) if (0.0..=1.0).contains(&threshold) => Some(threshold),
+                    _ => {
+                        return Err(MonitorError::Config(format!(
+                            "Invalid liquidation threshold '{}', expected a number in [0, 1]",
+                            raw
+                        )))
+                    }
+                },
+                None => None,
+            };
+            Ok((
+                address,
+                ReserveMetadata {
+                    decimals: Some(decimals),
+                    symbol: (!parts[1].is_empty()).then(|| parts[1].to_string()),
+                    is_collateral: liquidation_threshold.map(|threshold| threshold > 0.0),
+                    liquidation_threshold,
+                },
+            ))
+        })
+        .collect()
+}
+
+pub fn get_known_assets() -> Result<Vec<(Address, ReserveMetadata)>, MonitorError> {
+    parse_known_assets(&env::var("AAVE_KNOWN_ASSETS").unwrap_or_default())
+}
+
+/// Fill the registry from AAVE_KNOWN_ASSETS, so valuation works without reading the
+/// token contracts (offline tests, or RPCs that can't serve `decimals()`)
+pub fn seed_known_assets() -> Result<(), MonitorError> {
+    for (reserve, metadata) in get_known_assets()? {
+        update_reserve_metadata(reserve, |known| *known = metadata);
+    }
+    Ok(())
+}
+
+/// Register decimals for a reserve first seen in an event by reading the token
+/// contract on the chain it was seen on
+pub async fn ensure_token_decimals(rpc_url: &str, reserve: Address) {
+    if get_token_decimals(&reserve).is_some() {
+        return;
+    }
+    match get_token_decimals_ethereum(rpc_url, &format!("{:?}", reserve)).await {
+        Ok(decimals) => set_token_decimals(reserve, decimals),
+        Err(e) => warn!(
+            reserve = ?reserve,
+            "Failed to fetch decimals for new reserve: {}", e
+        ),
+    }
+    register_token_symbol(rpc_url, reserve).await;
+}
+
+// Read a reserve's symbol for display unless it is already known. Best effort:
+// some tokens (e.g. MKR) return bytes32 instead of a string.
+async fn register_token_symbol(rpc_url: &str, reserve: Address) {
+    if get_token_symbol(&reserve).is_some() {
+        return;
+    }
+    match get_token_symbol_ethereum(rpc_url, reserve).await {
+        Ok(symbol) => update_reserve_metadata(reserve, |known| known.symbol = Some(symbol)),
+        Err(e) => tracing::debug!(reserve = ?reserve, "Failed to fetch token symbol: {}", e),
+    }
 }
 
 // Configuration functions to read from environment variables
-pub fn get_user_address_to_track() -> String {
-    env::var("AAVE_USER_ADDRESS_TO_TRACK")
+/// Tracked accounts as configured, comma-separated: hex addresses or ENS names
+pub fn get_configured_user_accounts() -> Vec<String> {
+    let accounts: Vec<String> = env::var("AAVE_USER_ADDRESS_TO_TRACK")
         .unwrap_or_else(|_| "0xBDD3B59416Fc0263354953aeeFC51Ba3A94E134e".to_string())
+        .split(',')
+        .map(str::trim)
+        .filter(|account| !account.is_empty())
+        .map(str::to_string)
+        .collect();
+    if accounts.is_empty() {
+        vec!["0xBDD3B59416Fc0263354953aeeFC51Ba3A94E134e".to_string()]
+    } else {
+        accounts
+    }
+}
+
+/// First tracked account as configured. It owns the configured initial position.
+pub fn get_configured_user_account() -> String {
+    get_configured_user_accounts().remove(0)
+}
+
+/// Every tracked address, with ENS names replaced by the addresses they resolved to
+pub fn get_user_addresses_to_track() -> Vec<String> {
+    get_configured_user_accounts()
+        .iter()
+        .map(|account| resolved_account(account))
+        .collect()
+}
+
+/// First tracked address, with an ENS name replaced by the address it resolved to
+pub fn get_user_address_to_track() -> String {
+    resolved_account(&get_configured_user_account())
+}
+
+// ENS names resolved by `resolve_user_address`, keyed by lowercased name
+lazy_static::lazy_static! {
+    static ref ENS_ADDRESSES: Mutex<HashMap<String, Address>> = Mutex::new(HashMap::new());
+}
+
+/// Whether an account is an ENS name such as `vitalik.eth` rather than a hex address
+pub fn is_ens_name(account: &str) -> bool {
+    let account = account.trim();
+    !account.starts_with("0x") && account.contains('.')
+}
+
+pub fn set_ens_address(name: &str, address: Address) {
+    if let Ok(mut resolved) = ENS_ADDRESSES.lock() {
+        resolved.insert(name.trim().to_lowercase(), address);
+    }
+}
+
+/// `account` as a hex address when it is an ENS name that has been resolved,
+/// otherwise unchanged
+pub fn resolved_account(account: &str) -> String {
+    if !is_ens_name(account) {
+        return account.to_string();
+    }
+    ENS_ADDRESSES
+        .lock()
+        .ok()
+        .and_then(|resolved| resolved.get(&account.trim().to_lowercase()).copied())
+        .map(|address| format!("{:?}", address))
+        .unwrap_or_else(|| account.to_string())
+}
+
+/// Resolve every tracked account through ENS on Ethereum when it is a name, so every
+/// later lookup sees the address. Fails when a name has no address.
+pub async fn resolve_user_address(rpc_url: &str) -> Result<(), MonitorError> {
+    for account in get_configured_user_accounts() {
+        if !is_ens_name(&account) {
+            continue;
+        }
+        let address = resolve_ens_name(rpc_url, account.trim()).await;
+        ETHEREUM_RPC_ENDPOINTS.record(rpc_url, &address);
+        let address = address?;
+        info!(name = account.trim(), address = ?address, "Resolved ENS name");
+        set_ens_address(&account, address);
+    }
+    Ok(())
 }
 
 pub fn get_pool_v3_address() -> String {
-    env::var("AAVE_POOL_V3_ADDRESS")
-        .unwrap_or_else(|_| "0x87870Bca3F3fD6335C3F4ce8392D69350B4fA4E2".to_string())
+    env::var("AAVE_POOL_V3_ADDRESS").unwrap_or_else(|_| match get_pool_version() {
+        PoolVersion::V2 => "0x7d2768dE32b0b80b7a3454c06BdAc94A69DDc7A9".to_string(), // LendingPool V2
+        PoolVersion::V3 => "0x87870Bca3F3fD6335C3F4ce8392D69350B4fA4E2".to_string(),
+    })
+}
+
+/// Aave protocol version of the Ethereum pool. V2 emits Deposit instead of
+/// Supply and differently shaped Borrow and Repay events.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PoolVersion {
+    V2,
+    V3,
+}
+
+pub fn get_pool_version() -> PoolVersion {
+    match env::var("AAVE_POOL_VERSION")
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase()
+        .as_str()
+    {
+        "2" | "v2" => PoolVersion::V2,
+        _ => PoolVersion::V3,
+    }
 }
 
 pub fn get_supply_token_address() -> String {
@@ -60,72 +603,1953 @@ pub fn get_borrowed_token_address() -> String {
     // Default: wBTC
 }
 
+// Decimals come from the token contract once fetched; the env var only overrides them
 pub fn get_supply_token_decimals() -> u64 {
     env::var("AAVE_SUPPLY_TOKEN_DECIMALS")
-        .unwrap_or_else(|_| "6".to_string()) // Default: USDT has 6 decimals
-        .parse::<u64>()
-        .unwrap_or(6)
+        .ok()
+        .and_then(|decimals| decimals.parse::<u64>().ok())
+        .or_else(|| cached_decimals_of(&get_supply_token_address()))
+        .unwrap_or(6) // Default: USDT has 6 decimals
 }
 
 pub fn get_borrowed_token_decimals() -> u64 {
     env::var("AAVE_BORROWED_TOKEN_DECIMALS")
-        .unwrap_or_else(|_| "8".to_string()) // Default: wBTC has 8 decimals
-        .parse::<u64>()
-        .unwrap_or(8)
+        .ok()
+        .and_then(|decimals| decimals.parse::<u64>().ok())
+        .or_else(|| cached_decimals_of(&get_borrowed_token_address()))
+        .unwrap_or(8) // Default: wBTC has 8 decimals
+}
+
+fn cached_decimals_of(token_address: &str) -> Option<u64> {
+    get_cached_token_decimals(&token_address.parse::<Address>().ok()?)
+}
+
+// ETHEREUM_RPC_URL may list several endpoints, comma-separated, tried in order
+pub fn get_ethereum_rpc_urls() -> Vec<String> {
+    parse_endpoint_list(
+        &env::var("ETHEREUM_RPC_URL")
+            .unwrap_or_else(|_| "https://mainnet.infura.io/v3/123".to_string()),
+    )
+}
+
+pub fn get_ethereum_ws_urls() -> Vec<String> {
+    parse_endpoint_list(
+        &env::var("ETHEREUM_WS_URL")
+            .unwrap_or_else(|_| "wss://mainnet.infura.io/ws/v3/123".to_string()),
+    )
 }
 
+/// The Ethereum RPC endpoint currently in use
 pub fn get_ethereum_rpc_url() -> String {
-    env::var("ETHEREUM_RPC_URL").unwrap_or_else(|_| "https://mainnet.infura.io/v3/123".to_string())
+    ETHEREUM_RPC_ENDPOINTS.current()
 }
 
-pub fn get_ethereum_ws_url() -> String {
-    env::var("ETHEREUM_WS_URL")
-        .unwrap_or_else(|_| "wss://mainnet.infura.io/ws/v3/123".to_string())
+// Consecutive failures after which a chain moves on to its next endpoint
+pub fn get_endpoint_failover_threshold() -> u32 {
+    env::var("ENDPOINT_FAILOVER_THRESHOLD")
+        .unwrap_or_else(|_| "3".to_string())
+        .parse::<u32>()
+        .unwrap_or(3)
 }
 
-pub fn get_liquidation_threshold() -> f64 {
-    env::var("LIQUIDATION_THRESHOLD")
-        .unwrap_or_else(|_| "0.89".to_string())
-        .parse::<f64>()
-        .unwrap_or(0.89)
+pub fn get_ethereum_enabled() -> bool {
+    env::var("ETHEREUM_ENABLED")
+        .map(|enabled| enabled.trim().eq_ignore_ascii_case("true"))
+        .unwrap_or(true)
 }
 
-/// Print initial configuration when application starts
-pub fn print_initial_configuration() {
-    println!("=== Aave Liquidator Configuration ===");
-    println!("User Address to Track: {}", get_user_address_to_track());
-    println!("Pool V3 Address: {}", get_pool_v3_address());
-    println!(
-        "Supply Token Address: {} (Decimals: {}) - Default: USDT",
-        get_supply_token_address(),
-        get_supply_token_decimals()
-    );
-    println!(
-        "Borrow Token Address: {} (Decimals: {}) - Default: wBTC",
-        get_borrowed_token_address(),
-        get_borrowed_token_decimals()
-    );
-        println!("Ethereum RPC URL: {}", get_ethereum_rpc_url());
-    println!("Ethereum WS URL: {}", get_ethereum_ws_url());
-    println!("Liquidation Threshold: {} ({}%)", get_liquidation_threshold(), (get_liquidation_threshold() * 100.0) as i32);
-    
-    // Print initial position values
-    match get_position_data() {
-        Ok(position) => {
-            println!("Initial Supplied Amount: {}", position.supplied_amount);
-            println!("Initial Borrowed Amount: {}", position.borrowed_amount);
+pub fn get_polygon_enabled() -> bool {
+    env::var("POLYGON_ENABLED")
+        .map(|enabled| enabled.trim().eq_ignore_ascii_case("true"))
+        .unwrap_or(false)
+}
+
+pub fn get_arbitrum_enabled() -> bool {
+    env::var("ARBITRUM_ENABLED")
+        .map(|enabled| enabled.trim().eq_ignore_ascii_case("true"))
+        .unwrap_or(false)
+}
+
+pub fn get_optimism_enabled() -> bool {
+    env::var("OPTIMISM_ENABLED")
+        .map(|enabled| enabled.trim().eq_ignore_ascii_case("true"))
+        .unwrap_or(false)
+}
+
+pub fn get_base_enabled() -> bool {
+    env::var("BASE_ENABLED")
+        .map(|enabled| enabled.trim().eq_ignore_ascii_case("true"))
+        .unwrap_or(false)
+}
+
+// Solana isn't an Aave deployment, so it is tracked apart from `configured_chains`
+pub fn get_solana_enabled() -> bool {
+    env::var("SOLANA_ENABLED")
+        .map(|enabled| enabled.trim().eq_ignore_ascii_case("true"))
+        .unwrap_or(false)
+}
+
+/// An Aave Pool deployment to monitor. A chain only provides its connection
+/// details and the state its position is tracked in; listening is shared.
+#[async_trait]
+pub trait Chain: Send + Sync {
+    fn state(&self) -> &'static ChainState;
+
+    fn ws_endpoints(&self) -> &'static EndpointPool;
+
+    fn rpc_endpoints(&self) -> &'static EndpointPool;
+
+    fn rpc_url(&self) -> String {
+        self.rpc_endpoints().current()
+    }
+
+    fn pool_address(&self) -> String;
+
+    /// Every chain but Ethereum only has V3 deployments
+    fn pool_version(&self) -> PoolVersion {
+        PoolVersion::V3
+    }
+
+    fn name(&self) -> &'static str {
+        self.state().name
+    }
+
+    /// State of every tracked account on this chain, the first account's (`state`) first
+    fn accounts(&self) -> Vec<&'static ChainState> {
+        account_states(self.state())
+    }
+
+    /// Follow the Pool's events until the connection drops
+    async fn listen(&self) -> Result<(), MonitorError> {
+        listen_pool(
+            &self.accounts(),
+            self.ws_endpoints(),
+            &self.rpc_url(),
+            &self.pool_address(),
+            self.pool_version(),
+        )
+        .await
+    }
+
+    /// Grow every account's tracked balances by the interest Aave accrued since the last call
+    async fn accrue_interest(&self) -> Result<(), MonitorError> {
+        for state in self.accounts() {
+            let rpc_url = self.rpc_url();
+            let result = accrue_pool_interest(state, &rpc_url, &self.pool_address()).await;
+            self.rpc_endpoints().record(&rpc_url, &result);
+            result?;
+        }
+        Ok(())
+    }
+
+    /// Read the liquidation threshold of every account's collateral reserves from the Pool
+    async fn refresh_liquidation_thresholds(&self) -> Result<(), MonitorError> {
+        for state in self.accounts() {
+            let rpc_url = self.rpc_url();
+            let result = refresh_liquidation_thresholds(
+                state,
+                &rpc_url,
+                &self.pool_address(),
+                self.pool_version(),
+            )
+            .await;
+            self.rpc_endpoints().record(&rpc_url, &result);
+            result?;
+        }
+        Ok(())
+    }
+
+    /// Overwrite every account's tracked position with its aToken and debt token balances
+    async fn refresh_balances(&self) -> Result<(), MonitorError> {
+        for state in self.accounts() {
+            self.refresh_account_balances(state).await?;
         }
-        Err(e) => println!("Error getting initial position data: {}", e),
+        Ok(())
+    }
+
+    /// Overwrite one account's tracked position with its token balances
+    async fn refresh_account_balances(&self, state: &ChainState) -> Result<(), MonitorError> {
+        state.set_position(self.read_account_balances(state).await?)
+    }
+
+    /// One account's position as its token balances report it, without applying it
+    async fn read_account_balances(
+        &self,
+        state: &ChainState,
+    ) -> Result<PositionData, MonitorError> {
+        let rpc_url = self.rpc_url();
+        let result =
+            read_position_from_balances(state, &rpc_url, &self.pool_address(), self.pool_version())
+                .await;
+        self.rpc_endpoints().record(&rpc_url, &result);
+        result
     }
-    println!("=====================================");
 }
 
-pub async fn init_system() {
-    // Set default RPC URL if not provided
-    if env::var("ETHEREUM_RPC_URL").is_err() {
-        env::set_var("ETHEREUM_RPC_URL", "https://mainnet.infura.io/v3/123");
+/// Every chain that is enabled in the configuration
+pub fn configured_chains() -> Vec<Box<dyn Chain>> {
+    let mut chains: Vec<Box<dyn Chain>> = Vec::new();
+    if get_ethereum_enabled() {
+        chains.push(Box::new(Ethereum));
+    }
+    if get_polygon_enabled() {
+        chains.push(Box::new(Polygon));
+    }
+    if get_arbitrum_enabled() {
+        chains.push(Box::new(Arbitrum));
+    }
+    if get_optimism_enabled() {
+        chains.push(Box::new(Optimism));
+    }
+    if get_base_enabled() {
+        chains.push(Box::new(Base));
+    }
+    chains
+}
+
+/// State of every tracked account on every chain that is enabled in the configuration
+pub fn enabled_chains() -> Vec<&'static ChainState> {
+    configured_chains()
+        .iter()
+        .flat_map(|chain| chain.accounts())
+        .collect()
+}
+
+pub fn get_polygon_rpc_urls() -> Vec<String> {
+    parse_endpoint_list(
+        &env::var("POLYGON_RPC_URL")
+            .unwrap_or_else(|_| "https://polygon-mainnet.infura.io/v3/123".to_string()),
+    )
+}
+
+pub fn get_polygon_ws_urls() -> Vec<String> {
+    parse_endpoint_list(
+        &env::var("POLYGON_WS_URL")
+            .unwrap_or_else(|_| "wss://polygon-mainnet.infura.io/ws/v3/123".to_string()),
+    )
+}
+
+/// The Polygon RPC endpoint currently in use
+pub fn get_polygon_rpc_url() -> String {
+    POLYGON_RPC_ENDPOINTS.current()
+}
+
+pub fn get_polygon_pool_v3_address() -> String {
+    env::var("POLYGON_AAVE_POOL_V3_ADDRESS")
+        .unwrap_or_else(|_| "0x794a61358D6845594F94dc1DB02A252b5b4814aD".to_string())
+}
+
+// Polygon has no legacy single-pair settings, its position is configured as a list only
+pub fn get_polygon_supply_assets() -> Result<Vec<AssetConfig>, MonitorError> {
+    parse_asset_positions(&env::var("POLYGON_SUPPLY_POSITIONS").unwrap_or_default())
+}
+
+pub fn get_polygon_borrowed_assets() -> Result<Vec<AssetConfig>, MonitorError> {
+    parse_asset_positions(&env::var("POLYGON_BORROWED_POSITIONS").unwrap_or_default())
+}
+
+pub fn get_polygon_last_processed_block_file() -> String {
+    env::var("POLYGON_LAST_PROCESSED_BLOCK_FILE")
+        .unwrap_or_else(|_| "polygon_last_processed_block.json".to_string())
+}
+
+pub fn get_polygon_position_file() -> String {
+    env::var("POLYGON_POSITION_FILE").unwrap_or_else(|_| "polygon_position.json".to_string())
+}
+
+pub fn get_arbitrum_rpc_urls() -> Vec<String> {
+    parse_endpoint_list(
+        &env::var("ARBITRUM_RPC_URL")
+            .unwrap_or_else(|_| "https://arbitrum-mainnet.infura.io/v3/123".to_string()),
+    )
+}
+
+pub fn get_arbitrum_ws_urls() -> Vec<String> {
+    parse_endpoint_list(
+        &env::var("ARBITRUM_WS_URL")
+            .unwrap_or_else(|_| "wss://arbitrum-mainnet.infura.io/ws/v3/123".to_string()),
+    )
+}
+
+/// The Arbitrum RPC endpoint currently in use
+pub fn get_arbitrum_rpc_url() -> String {
+    ARBITRUM_RPC_ENDPOINTS.current()
+}
+
+pub fn get_arbitrum_pool_v3_address() -> String {
+    env::var("ARBITRUM_AAVE_POOL_V3_ADDRESS")
+        .unwrap_or_else(|_| "0x794a61358D6845594F94dc1DB02A252b5b4814aD".to_string())
+}
+
+// Like Polygon, Arbitrum's position is configured as a list only
+pub fn get_arbitrum_supply_assets() -> Result<Vec<AssetConfig>, MonitorError> {
+    parse_asset_positions(&env::var("ARBITRUM_SUPPLY_POSITIONS").unwrap_or_default())
+}
+
+pub fn get_arbitrum_borrowed_assets() -> Result<Vec<AssetConfig>, MonitorError> {
+    parse_asset_positions(&env::var("ARBITRUM_BORROWED_POSITIONS").unwrap_or_default())
+}
+
+pub fn get_arbitrum_last_processed_block_file() -> String {
+    env::var("ARBITRUM_LAST_PROCESSED_BLOCK_FILE")
+        .unwrap_or_else(|_| "arbitrum_last_processed_block.json".to_string())
+}
+
+pub fn get_arbitrum_position_file() -> String {
+    env::var("ARBITRUM_POSITION_FILE").unwrap_or_else(|_| "arbitrum_position.json".to_string())
+}
+
+pub fn get_optimism_rpc_urls() -> Vec<String> {
+    parse_endpoint_list(
+        &env::var("OPTIMISM_RPC_URL")
+            .unwrap_or_else(|_| "https://optimism-mainnet.infura.io/v3/123".to_string()),
+    )
+}
+
+pub fn get_optimism_ws_urls() -> Vec<String> {
+    parse_endpoint_list(
+        &env::var("OPTIMISM_WS_URL")
+            .unwrap_or_else(|_| "wss://optimism-mainnet.infura.io/ws/v3/123".to_string()),
+    )
+}
+
+/// The Optimism RPC endpoint currently in use
+pub fn get_optimism_rpc_url() -> String {
+    OPTIMISM_RPC_ENDPOINTS.current()
+}
+
+pub fn get_optimism_pool_v3_address() -> String {
+    env::var("OPTIMISM_AAVE_POOL_V3_ADDRESS")
+        .unwrap_or_else(|_| "0x794a61358D6845594F94dc1DB02A252b5b4814aD".to_string())
+}
+
+// Like Polygon, Optimism's position is configured as a list only
+pub fn get_optimism_supply_assets() -> Result<Vec<AssetConfig>, MonitorError> {
+    parse_asset_positions(&env::var("OPTIMISM_SUPPLY_POSITIONS").unwrap_or_default())
+}
+
+pub fn get_optimism_borrowed_assets() -> Result<Vec<AssetConfig>, MonitorError> {
+    parse_asset_positions(&env::var("OPTIMISM_BORROWED_POSITIONS").unwrap_or_default())
+}
+
+pub fn get_optimism_last_processed_block_file() -> String {
+    env::var("OPTIMISM_LAST_PROCESSED_BLOCK_FILE")
+        .unwrap_or_else(|_| "optimism_last_processed_block.json".to_string())
+}
+
+pub fn get_optimism_position_file() -> String {
+    env::var("OPTIMISM_POSITION_FILE").unwrap_or_else(|_| "optimism_position.json".to_string())
+}
+
+pub fn get_base_rpc_urls() -> Vec<String> {
+    parse_endpoint_list(
+        &env::var("BASE_RPC_URL")
+            .unwrap_or_else(|_| "https://base-mainnet.infura.io/v3/123".to_string()),
+    )
+}
+
+pub fn get_base_ws_urls() -> Vec<String> {
+    parse_endpoint_list(
+        &env::var("BASE_WS_URL")
+            .unwrap_or_else(|_| "wss://base-mainnet.infura.io/ws/v3/123".to_string()),
+    )
+}
+
+/// The Base RPC endpoint currently in use
+pub fn get_base_rpc_url() -> String {
+    BASE_RPC_ENDPOINTS.current()
+}
+
+pub fn get_base_pool_v3_address() -> String {
+    env::var("BASE_AAVE_POOL_V3_ADDRESS")
+        .unwrap_or_else(|_| "0xA238Dd80C259a72e81d7e4664a9801593F98d1c5".to_string())
+}
+
+// Base positions are configured as lists too
+pub fn get_base_supply_assets() -> Result<Vec<AssetConfig>, MonitorError> {
+    parse_asset_positions(&env::var("BASE_SUPPLY_POSITIONS").unwrap_or_default())
+}
+
+pub fn get_base_borrowed_assets() -> Result<Vec<AssetConfig>, MonitorError> {
+    parse_asset_positions(&env::var("BASE_BORROWED_POSITIONS").unwrap_or_default())
+}
+
+pub fn get_base_last_processed_block_file() -> String {
+    env::var("BASE_LAST_PROCESSED_BLOCK_FILE")
+        .unwrap_or_else(|_| "base_last_processed_block.json".to_string())
+}
+
+pub fn get_base_position_file() -> String {
+    env::var("BASE_POSITION_FILE").unwrap_or_else(|_| "base_position.json".to_string())
+}
+
+pub fn get_solana_rpc_url() -> String {
+    env::var("SOLANA_RPC_URL").unwrap_or_else(|_| "https://api.mainnet-beta.solana.com".to_string())
+}
+
+pub fn get_solana_ws_url() -> String {
+    env::var("SOLANA_WS_URL").unwrap_or_else(|_| "wss://api.mainnet-beta.solana.com".to_string())
+}
+
+/// Wallet or lending account whose transactions trigger a balance refresh
+pub fn get_solana_user_address() -> Option<String> {
+    env::var("SOLANA_USER_ADDRESS")
+        .ok()
+        .map(|address| address.trim().to_string())
+        .filter(|address| !address.is_empty())
+}
+
+/// Lending program a transaction must invoke to trigger a refresh, any when unset
+pub fn get_solana_program_id() -> Option<String> {
+    env::var("SOLANA_PROGRAM_ID")
+        .ok()
+        .map(|program_id| program_id.trim().to_string())
+        .filter(|program_id| !program_id.is_empty())
+}
+
+pub fn get_solana_supply_positions() -> Result<Vec<SolanaReserve>, MonitorError> {
+    parse_solana_positions(&env::var("SOLANA_SUPPLY_POSITIONS").unwrap_or_default())
+}
+
+pub fn get_solana_borrowed_positions() -> Result<Vec<SolanaReserve>, MonitorError> {
+    parse_solana_positions(&env::var("SOLANA_BORROWED_POSITIONS").unwrap_or_default())
+}
+
+// The lending program's threshold; LIQUIDATION_THRESHOLD or its default otherwise
+pub fn get_solana_liquidation_threshold() -> f64 {
+    env::var("SOLANA_LIQUIDATION_THRESHOLD")
+        .ok()
+        .and_then(|threshold| threshold.trim().parse::<f64>().ok())
+        .unwrap_or_else(get_liquidation_threshold)
+}
+
+pub fn get_telegram_bot_token() -> Option<String> {
+    env::var("TELEGRAM_BOT_TOKEN")
+        .ok()
+        .filter(|token| !token.trim().is_empty())
+}
+
+pub fn get_telegram_chat_id() -> Option<String> {
+    env::var("TELEGRAM_CHAT_ID")
+        .ok()
+        .filter(|chat_id| !chat_id.trim().is_empty())
+}
+
+pub fn get_alert_pause_default_mins() -> u64 {
+    env::var("ALERT_PAUSE_DEFAULT_MINS")
+        .unwrap_or_else(|_| "60".to_string())
+        .parse::<u64>()
+        .unwrap_or(60)
+}
+
+pub fn get_discord_webhook_url() -> Option<String> {
+    env::var("DISCORD_WEBHOOK_URL")
+        .ok()
+        .filter(|url| !url.trim().is_empty())
+}
+
+/// Slack incoming webhook alerts are posted to
+pub fn get_slack_webhook_url() -> Option<String> {
+    env::var("SLACK_WEBHOOK_URL")
+        .ok()
+        .filter(|url| !url.trim().is_empty())
+}
+
+/// Broker position events are published to, `redis://` or `nats://`
+pub fn get_publish_url() -> Option<String> {
+    env::var("PUBLISH_URL")
+        .ok()
+        .filter(|url| !url.trim().is_empty())
+}
+
+/// Redis channel or NATS subject position events are published on
+pub fn get_publish_channel() -> String {
+    env::var("PUBLISH_CHANNEL").unwrap_or_else(|_| "aave-monitor".to_string())
+}
+
+/// InfluxDB 2 server health-factor checks are pushed to, e.g. `http://localhost:8086`
+pub fn get_influx_url() -> Option<String> {
+    env::var("INFLUX_URL")
+        .ok()
+        .filter(|url| !url.trim().is_empty())
+}
+
+pub fn get_influx_org() -> String {
+    env::var("INFLUX_ORG").unwrap_or_default()
+}
+
+pub fn get_influx_bucket() -> String {
+    env::var("INFLUX_BUCKET").unwrap_or_else(|_| "aave-monitor".to_string())
+}
+
+/// API token sent as `Authorization: Token <token>`
+pub fn get_influx_token() -> Option<String> {
+    env::var("INFLUX_TOKEN")
+        .ok()
+        .filter(|token| !token.trim().is_empty())
+}
+
+// Seconds between InfluxDB writes; a full batch is written sooner
+pub fn get_influx_flush_interval_secs() -> u64 {
+    env::var("INFLUX_FLUSH_INTERVAL_SECS")
+        .unwrap_or_else(|_| "10".to_string())
+        .parse::<u64>()
+        .unwrap_or(10)
+}
+
+// Points that trigger a write before the flush interval is up
+pub fn get_influx_batch_size() -> usize {
+    env::var("INFLUX_BATCH_SIZE")
+        .unwrap_or_else(|_| "500".to_string())
+        .parse::<usize>()
+        .unwrap_or(500)
+}
+
+pub fn get_alert_webhook_url() -> Option<String> {
+    env::var("ALERT_WEBHOOK_URL")
+        .ok()
+        .filter(|url| !url.trim().is_empty())
+}
+
+/// Sent as `Authorization: Bearer <token>` with every generic webhook alert
+pub fn get_alert_webhook_bearer_token() -> Option<String> {
+    env::var("ALERT_WEBHOOK_BEARER_TOKEN")
+        .ok()
+        .filter(|token| !token.trim().is_empty())
+}
+
+/// Key the generic webhook body is signed with, in an `X-Signature-256` header
+pub fn get_alert_webhook_secret() -> Option<String> {
+    env::var("ALERT_WEBHOOK_SECRET")
+        .ok()
+        .filter(|secret| !secret.is_empty())
+}
+
+pub fn get_simplehash_api_key() -> Result<String, MonitorError> {
+    match env::var("SIMPLEHASH_API_KEY") {
+        Ok(key) if !key.trim().is_empty() => Ok(key),
+        Ok(_) => Err(MonitorError::Config(
+            "SIMPLEHASH_API_KEY environment variable is empty".to_string(),
+        )),
+        Err(_) => Err(MonitorError::Config(
+            "SIMPLEHASH_API_KEY environment variable not set".to_string(),
+        )),
+    }
+}
+
+/// SimpleHash API root, for proxies, caching gateways or mock servers
+pub fn get_simplehash_base_url() -> String {
+    env::var("SIMPLEHASH_BASE_URL").unwrap_or_else(|_| "https://api.simplehash.com".to_string())
+}
+
+pub fn get_price_request_timeout_secs() -> u64 {
+    env::var("PRICE_REQUEST_TIMEOUT_SECS")
+        .unwrap_or_else(|_| "10".to_string())
+        .parse::<u64>()
+        .unwrap_or(10)
+}
+
+/// A source of token prices, see PRICE_SOURCE
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PriceSourceKind {
+    SimpleHash,
+    /// Chainlink aggregators read on-chain, for the tokens listed in CHAINLINK_FEEDS
+    Chainlink,
+    /// Coingecko's token price API, for chains listed in COINGECKO_PLATFORMS
+    Coingecko,
+    /// Uniswap V3 time-weighted averages, for the pairs listed in UNISWAP_TWAP_PAIRS
+    UniswapTwap,
+}
+
+/// Sources named in PRICE_SOURCE (e.g. `coingecko,simplehash`), in the order they are
+/// asked. Unknown names are skipped; sources not named are asked after these.
+pub fn get_price_source_order() -> Vec<PriceSourceKind> {
+    let mut order = Vec::new();
+    for name in env::var("PRICE_SOURCE").unwrap_or_default().split(',') {
+        let kind = match name.trim().to_ascii_lowercase().as_str() {
+            "simplehash" => PriceSourceKind::SimpleHash,
+            "chainlink" => PriceSourceKind::Chainlink,
+            "coingecko" => PriceSourceKind::Coingecko,
+            "uniswap" => PriceSourceKind::UniswapTwap,
+            _ => continue,
+        };
+        if !order.contains(&kind) {
+            order.push(kind);
+        }
+    }
+    order
+}
+
+pub fn get_coingecko_api_key() -> Option<String> {
+    env::var("COINGECKO_API_KEY")
+        .ok()
+        .filter(|key| !key.trim().is_empty())
+}
+
+/// Coingecko API root; keys for the paid plan need `https://pro-api.coingecko.com/api/v3`
+pub fn get_coingecko_base_url() -> String {
+    env::var("COINGECKO_BASE_URL")
+        .unwrap_or_else(|_| "https://api.coingecko.com/api/v3".to_string())
+}
+
+/// Coingecko asset platform per chain, the defaults overridden by `chain:platform`
+/// pairs in COINGECKO_PLATFORMS
+pub fn parse_coingecko_platforms(raw: &str) -> Result<HashMap<String, String>, MonitorError> {
+    let mut platforms: HashMap<String, String> = [
+        ("ethereum", "ethereum"),
+        ("polygon", "polygon-pos"),
+        ("arbitrum", "arbitrum-one"),
+        ("optimism", "optimistic-ethereum"),
+        ("base", "base"),
+    ]
+    .into_iter()
+    .map(|(chain, platform)| (chain.to_string(), platform.to_string()))
+    .collect();
+    for entry in raw
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+    {
+        let (chain, platform) = entry
+            .split_once(':')
+            .map(|(chain, platform)| (chain.trim(), platform.trim()))
+            .filter(|(chain, platform)| !chain.is_empty() && !platform.is_empty())
+            .ok_or_else(|| {
+                MonitorError::Config(format!(
+                    "Invalid Coingecko platform '{}', expected chain:platform",
+                    entry
+                ))
+            })?;
+        platforms.insert(chain.to_ascii_lowercase(), platform.to_string());
+    }
+    Ok(platforms)
+}
+
+pub fn get_coingecko_platforms() -> Result<HashMap<String, String>, MonitorError> {
+    parse_coingecko_platforms(&env::var("COINGECKO_PLATFORMS").unwrap_or_default())
+}
+
+/// Parse `token:aggregator` pairs into aggregators keyed by fungible id. Tokens may
+/// be chain-qualified (`polygon.0x...`); a bare address is an Ethereum token.
+pub fn parse_chainlink_feeds(raw: &str) -> Result<HashMap<String, Address>, MonitorError> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (token, aggregator) = entry.split_once(':').ok_or_else(|| {
+                MonitorError::Config(format!(
+                    "Invalid Chainlink feed '{}', expected token:aggregator",
+                    entry
+                ))
+            })?;
+            let (chain, token) = token.trim().split_once('.').unwrap_or(("ethereum", token));
+            let token = token.trim().parse::<Address>().map_err(|e| {
+                MonitorError::Config(format!("Invalid token address '{}': {}", token, e))
+            })?;
+            let aggregator = aggregator.trim().parse::<Address>().map_err(|e| {
+                MonitorError::Config(format!(
+                    "Invalid aggregator address '{}': {}",
+                    aggregator, e
+                ))
+            })?;
+            Ok((format!("{}.{:?}", chain.trim(), token), aggregator))
+        })
+        .collect()
+}
+
+pub fn get_chainlink_feeds() -> Result<HashMap<String, Address>, MonitorError> {
+    parse_chainlink_feeds(&env::var("CHAINLINK_FEEDS").unwrap_or_default())
+}
+
+/// Parse `token:quote_token` pairs into quote tokens keyed by fungible id. Tokens may
+/// be chain-qualified (`polygon.0x...`); the quote token is on the same chain.
+pub fn parse_uniswap_twap_pairs(raw: &str) -> Result<HashMap<String, Address>, MonitorError> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (token, quote) = entry.split_once(':').ok_or_else(|| {
+                MonitorError::Config(format!(
+                    "Invalid Uniswap TWAP pair '{}', expected token:quote_token",
+                    entry
+                ))
+            })?;
+            let (chain, token) = token.trim().split_once('.').unwrap_or(("ethereum", token));
+            let token = token.trim().parse::<Address>().map_err(|e| {
+                MonitorError::Config(format!("Invalid token address '{}': {}", token, e))
+            })?;
+            let quote = quote.trim().parse::<Address>().map_err(|e| {
+                MonitorError::Config(format!("Invalid quote token address '{}': {}", quote, e))
+            })?;
+            if quote == token {
+                return Err(MonitorError::Config(format!(
+                    "Uniswap TWAP pair '{}' quotes a token in itself",
+                    entry
+                )));
+            }
+            Ok((format!("{}.{:?}", chain.trim(), token), quote))
+        })
+        .collect()
+}
+
+pub fn get_uniswap_twap_pairs() -> Result<HashMap<String, Address>, MonitorError> {
+    parse_uniswap_twap_pairs(&env::var("UNISWAP_TWAP_PAIRS").unwrap_or_default())
+}
+
+// Seconds the Uniswap TWAP is averaged over; longer windows are costlier to manipulate
+// but need pools that keep enough observations
+pub fn get_uniswap_twap_window_secs() -> u32 {
+    env::var("UNISWAP_TWAP_WINDOW_SECS")
+        .unwrap_or_else(|_| "1800".to_string())
+        .parse::<u32>()
+        .unwrap_or(1800)
+}
+
+/// Uniswap V3 factory pools are looked up in: UNISWAP_V3_FACTORY, or the canonical
+/// deployment on `chain_name`
+pub fn get_uniswap_v3_factory(chain_name: &str) -> Result<Address, MonitorError> {
+    let factory = match env::var("UNISWAP_V3_FACTORY") {
+        Ok(factory) if !factory.trim().is_empty() => factory,
+        _ => match chain_name {
+            "base" => "0x33128a8fC17869897dcE68Ed026d694621f6FDfD".to_string(),
+            _ => "0x1F98431c8aD98523631AE4a59f267346ea31F984".to_string(),
+        },
+    };
+    factory
+        .trim()
+        .parse::<Address>()
+        .map_err(|e| MonitorError::Config(format!("Invalid UNISWAP_V3_FACTORY: {}", e)))
+}
+
+/// Currency collateral, debt and liquidation prices are shown in, e.g. `EUR`
+pub fn get_quote_currency() -> String {
+    env::var("QUOTE_CURRENCY")
+        .unwrap_or_else(|_| "USD".to_string())
+        .trim()
+        .to_ascii_uppercase()
+}
+
+/// Chainlink `<QUOTE_CURRENCY> / USD` aggregator on Ethereum that USD values are
+/// converted with. Only needed when QUOTE_CURRENCY isn't USD.
+pub fn get_quote_currency_feed() -> Result<Option<Address>, MonitorError> {
+    match env::var("QUOTE_CURRENCY_FEED") {
+        Ok(feed) if !feed.trim().is_empty() => feed
+            .trim()
+            .parse::<Address>()
+            .map(Some)
+            .map_err(|e| MonitorError::Config(format!("Invalid QUOTE_CURRENCY_FEED: {}", e))),
+        _ => Ok(None),
+    }
+}
+
+// Prices observed longer ago than this are never used for the health factor (0 disables)
+pub fn get_price_max_age_secs() -> u64 {
+    env::var("PRICE_MAX_AGE_SECS")
+        .unwrap_or_else(|_| "120".to_string())
+        .parse::<u64>()
+        .unwrap_or(120)
+}
+
+/// Average SimpleHash requests per second across all chains and tasks, 0 for no limit
+pub fn get_simplehash_rps() -> f64 {
+    env::var("SIMPLEHASH_RPS")
+        .unwrap_or_else(|_| "5".to_string())
+        .parse::<f64>()
+        .ok()
+        .filter(|rps| rps.is_finite())
+        .unwrap_or(5.0)
+}
+
+pub fn get_price_max_retries() -> u32 {
+    env::var("PRICE_MAX_RETRIES")
+        .unwrap_or_else(|_| "3".to_string())
+        .parse::<u32>()
+        .unwrap_or(3)
+}
+
+pub fn get_price_cache_ttl_secs() -> u64 {
+    env::var("PRICE_CACHE_TTL_SECS")
+        .unwrap_or_else(|_| "30".to_string())
+        .parse::<u64>()
+        .unwrap_or(30)
+}
+
+pub fn get_last_processed_block_file() -> String {
+    env::var("LAST_PROCESSED_BLOCK_FILE")
+        .unwrap_or_else(|_| "last_processed_block.json".to_string())
+}
+
+pub fn get_position_file() -> String {
+    env::var("POSITION_FILE").unwrap_or_else(|_| "position.json".to_string())
+}
+
+pub fn get_position_persist_interval_secs() -> u64 {
+    env::var("POSITION_PERSIST_INTERVAL_SECS")
+        .unwrap_or_else(|_| "10".to_string())
+        .parse::<u64>()
+        .unwrap_or(10)
+}
+
+/// Seconds tasks get to stop on ctrl_c before the process exits regardless
+pub fn get_shutdown_timeout_secs() -> u64 {
+    env::var("SHUTDOWN_TIMEOUT_SECS")
+        .unwrap_or_else(|_| "10".to_string())
+        .parse::<u64>()
+        .unwrap_or(10)
+}
+
+pub fn get_confirmation_depth() -> u64 {
+    env::var("CONFIRMATION_DEPTH")
+        .unwrap_or_else(|_| "5".to_string())
+        .parse::<u64>()
+        .unwrap_or(5)
+}
+
+// Deepest reorg below the confirmation depth that can still be rolled back
+pub fn get_reorg_history_blocks() -> u64 {
+    env::var("REORG_HISTORY_BLOCKS")
+        .unwrap_or_else(|_| "128".to_string())
+        .parse::<u64>()
+        .unwrap_or(128)
+}
+
+/// Where a chain's listener starts when no last processed block has been persisted yet
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StartBlock {
+    /// The current head: only new events are processed
+    Latest,
+    /// This many blocks before the head
+    BeforeLatest(u64),
+    /// A fixed block number, processed itself
+    Number(u64),
+}
+
+impl StartBlock {
+    /// First block to process given the newest block the listener processes, e.g.
+    /// the head less the confirmation depth. A fixed block beyond `head` (the actual
+    /// chain head) is a configuration error.
+    pub fn first_block(&self, newest: u64, head: u64) -> Result<u64, MonitorError> {
+        match *self {
+            StartBlock::Latest => Ok(newest + 1),
+            StartBlock::BeforeLatest(blocks) => Ok(newest.saturating_sub(blocks) + 1),
+            StartBlock::Number(number) if number > head => Err(MonitorError::Config(format!(
+                "Start block {} is beyond the chain head {}",
+                number, head
+            ))),
+            StartBlock::Number(number) => Ok(number),
+        }
+    }
+}
+
+/// Parse `latest`, `latest-N` or a block number; empty means unset
+pub fn parse_start_block(raw: &str) -> Result<Option<StartBlock>, MonitorError> {
+    let raw = raw.trim().to_ascii_lowercase();
+    let invalid = || {
+        MonitorError::Config(format!(
+            "Invalid start block '{}', expected a block number, latest or latest-N",
+            raw
+        ))
+    };
+    if raw.is_empty() {
+        return Ok(None);
+    }
+    if raw == "latest" {
+        return Ok(Some(StartBlock::Latest));
+    }
+    if let Some(blocks) = raw.strip_prefix("latest-") {
+        return blocks
+            .trim()
+            .parse::<u64>()
+            .map(|blocks| Some(StartBlock::BeforeLatest(blocks)))
+            .map_err(|_| invalid());
+    }
+    raw.parse::<u64>()
+        .map(|number| Some(StartBlock::Number(number)))
+        .map_err(|_| invalid())
+}
+
+// Variable holding a chain's start block: START_BLOCK for Ethereum, block numbers
+// being per chain, and <CHAIN>_START_BLOCK for the others
+fn start_block_var(chain_name: &str) -> String {
+    if chain_name == "ethereum" {
+        "START_BLOCK".to_string()
+    } else {
+        format!("{}_START_BLOCK", chain_name.to_ascii_uppercase())
+    }
+}
+
+/// Where `chain_name`'s listener starts without a persisted last processed block;
+/// unset means the current head
+pub fn get_start_block(chain_name: &str) -> Result<Option<StartBlock>, MonitorError> {
+    let name = start_block_var(chain_name);
+    parse_start_block(&env::var(&name).unwrap_or_default())
+        .map_err(|e| MonitorError::Config(format!("{}: {}", name, e)))
+}
+
+/// Seconds without a new block after which a WebSocket subscription is taken for
+/// dead and reconnected (0 waits forever)
+pub fn get_ws_block_timeout_secs() -> u64 {
+    env::var("WS_BLOCK_TIMEOUT_SECS")
+        .unwrap_or_else(|_| "60".to_string())
+        .parse::<u64>()
+        .unwrap_or(60)
+}
+
+/// Health-factor samples kept per account for `GET /history`, oldest dropped first
+pub fn get_health_factor_history_size() -> usize {
+    env::var("HEALTH_FACTOR_HISTORY_SIZE")
+        .unwrap_or_else(|_| "1440".to_string())
+        .parse::<usize>()
+        .unwrap_or(1440)
+}
+
+pub fn get_health_check_interval_secs() -> u64 {
+    env::var("HEALTH_CHECK_INTERVAL_SECS")
+        .unwrap_or_else(|_| "15".to_string())
+        .parse::<u64>()
+        .unwrap_or(15)
+}
+
+pub fn get_block_number_retry_ms() -> u64 {
+    env::var("BLOCK_NUMBER_RETRY_MS")
+        .unwrap_or_else(|_| "500".to_string())
+        .parse::<u64>()
+        .unwrap_or(500)
+}
+
+/// Which address of a Supply, Borrow or Withdraw event must match the tracked one
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AccountField {
+    /// Either the caller or the account it acts for
+    Any,
+    /// Only the caller (`user`)
+    User,
+    /// Only the account credited or debited (`onBehalfOf`, or `to` for Withdraw)
+    OnBehalfOf,
+}
+
+pub fn get_account_field() -> AccountField {
+    match env::var("EVENT_ACCOUNT_FIELD")
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase()
+        .as_str()
+    {
+        "user" => AccountField::User,
+        "on_behalf_of" => AccountField::OnBehalfOf,
+        _ => AccountField::Any,
+    }
+}
+
+/// Where the tracked position amounts come from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PositionSource {
+    /// Sum the Pool's event deltas and accrue interest from the reserve indexes
+    Events,
+    /// Poll aToken and debt token balances, refreshed right away on every tracked event
+    Balances,
+}
+
+pub fn get_position_source() -> PositionSource {
+    match env::var("POSITION_SOURCE")
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase()
+        .as_str()
+    {
+        "balances" => PositionSource::Balances,
+        _ => PositionSource::Events,
+    }
+}
+
+/// How log lines are written
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    /// Human-readable lines
+    Text,
+    /// One JSON object per line, with the structured fields as keys
+    Json,
+}
+
+pub fn get_log_format() -> LogFormat {
+    match env::var("LOG_FORMAT")
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase()
+        .as_str()
+    {
+        "json" => LogFormat::Json,
+        _ => LogFormat::Text,
+    }
+}
+
+pub fn get_balance_poll_interval_secs() -> u64 {
+    env::var("BALANCE_POLL_INTERVAL_SECS")
+        .unwrap_or_else(|_| "30".to_string())
+        .parse::<u64>()
+        .unwrap_or(30)
+}
+
+pub fn get_interest_accrual_interval_secs() -> u64 {
+    env::var("INTEREST_ACCRUAL_INTERVAL_SECS")
+        .unwrap_or_else(|_| "60".to_string())
+        .parse::<u64>()
+        .unwrap_or(60)
+}
+
+pub fn get_resync_interval_secs() -> u64 {
+    env::var("RESYNC_INTERVAL_SECS")
+        .unwrap_or_else(|_| "300".to_string())
+        .parse::<u64>()
+        .unwrap_or(300)
+}
+
+pub fn get_position_drift_tolerance() -> f64 {
+    env::var("POSITION_DRIFT_TOLERANCE")
+        .unwrap_or_else(|_| "0.01".to_string())
+        .parse::<f64>()
+        .unwrap_or(0.01)
+}
+
+/// LIQUIDATION_THRESHOLD when set explicitly; it then overrides the per-reserve
+/// thresholds read from the Pool
+pub fn get_liquidation_threshold_override() -> Option<f64> {
+    env::var("LIQUIDATION_THRESHOLD")
+        .ok()
+        .and_then(|threshold| threshold.parse::<f64>().ok())
+}
+
+pub fn get_liquidation_threshold_refresh_secs() -> u64 {
+    env::var("LIQUIDATION_THRESHOLD_REFRESH_SECS")
+        .unwrap_or_else(|_| "3600".to_string())
+        .parse::<u64>()
+        .unwrap_or(3600)
+}
+
+// Used for reserves whose on-chain threshold hasn't been read yet
+pub fn get_liquidation_threshold() -> f64 {
+    env::var("LIQUIDATION_THRESHOLD")
+        .unwrap_or_else(|_| "0.89".to_string())
+        .parse::<f64>()
+        .unwrap_or(0.89)
+}
+
+pub fn get_alert_tier_warn_ltv() -> f64 {
+    env::var("ALERT_TIER_WARN_LTV")
+        .unwrap_or_else(|_| "0.80".to_string())
+        .parse::<f64>()
+        .unwrap_or(0.80)
+}
+
+pub fn get_alert_tier_danger_ltv() -> f64 {
+    env::var("ALERT_TIER_DANGER_LTV")
+        .unwrap_or_else(|_| "0.85".to_string())
+        .parse::<f64>()
+        .unwrap_or(0.85)
+}
+
+// Defaults to the liquidation threshold, where the health factor reaches 1.0
+pub fn get_alert_tier_critical_ltv() -> f64 {
+    env::var("ALERT_TIER_CRITICAL_LTV")
+        .ok()
+        .and_then(|ltv| ltv.parse::<f64>().ok())
+        .unwrap_or_else(get_liquidation_threshold)
+}
+
+/// USD value below which both sides of a position must stay for its risk alerts to be
+/// skipped, 0 to alert on any position
+pub fn get_min_position_usd() -> f64 {
+    env::var("MIN_POSITION_USD")
+        .unwrap_or_else(|_| "0".to_string())
+        .parse::<f64>()
+        .unwrap_or(0.0)
+}
+
+pub fn get_alert_warmup_secs() -> u64 {
+    env::var("ALERT_WARMUP_SECS")
+        .unwrap_or_else(|_| "30".to_string())
+        .parse::<u64>()
+        .unwrap_or(30)
+}
+
+pub fn get_alert_cooldown_secs() -> u64 {
+    env::var("ALERT_COOLDOWN_SECS")
+        .unwrap_or_else(|_| "300".to_string())
+        .parse::<u64>()
+        .unwrap_or(300)
+}
+
+/// Intervals between critical reminders from ALERT_ESCALATION_SCHEDULE_SECS, e.g.
+/// `300,120`. Empty disables escalation, leaving critical alerts on the cooldown.
+pub fn get_alert_escalation_schedule_secs() -> Vec<u64> {
+    env::var("ALERT_ESCALATION_SCHEDULE_SECS")
+        .unwrap_or_else(|_| "300,120".to_string())
+        .split(',')
+        .filter_map(|secs| secs.trim().parse::<u64>().ok())
+        .filter(|secs| *secs > 0)
+        .collect()
+}
+
+pub fn get_alert_escalation_max_repeats() -> u32 {
+    env::var("ALERT_ESCALATION_MAX_REPEATS")
+        .unwrap_or_else(|_| "10".to_string())
+        .parse::<u32>()
+        .unwrap_or(10)
+}
+
+pub fn get_liquidation_price_alerts_enabled() -> bool {
+    env::var("LIQUIDATION_PRICE_ALERTS_ENABLED")
+        .unwrap_or_else(|_| "true".to_string())
+        .parse::<bool>()
+        .unwrap_or(true)
+}
+
+pub fn get_recovery_alerts_enabled() -> bool {
+    env::var("RECOVERY_ALERTS_ENABLED")
+        .unwrap_or_else(|_| "true".to_string())
+        .parse::<bool>()
+        .unwrap_or(true)
+}
+
+pub fn get_alert_digest_window_secs() -> u64 {
+    env::var("ALERT_DIGEST_WINDOW_SECS")
+        .unwrap_or_else(|_| "0".to_string())
+        .parse::<u64>()
+        .unwrap_or(0)
+}
+
+pub fn get_price_alert_confirmation_ticks() -> u32 {
+    env::var("PRICE_ALERT_CONFIRMATION_TICKS")
+        .unwrap_or_else(|_| "1".to_string())
+        .parse::<u32>()
+        .unwrap_or(1)
+}
+
+/// Consecutive health checks that must fail to price the position before the price
+/// feed is reported as unavailable (0 disables the alert)
+pub fn get_price_outage_alert_failures() -> u32 {
+    env::var("PRICE_OUTAGE_ALERT_FAILURES")
+        .unwrap_or_else(|_| "3".to_string())
+        .parse::<u32>()
+        .unwrap_or(3)
+}
+
+/// How long those failures must have lasted, so a short blip doesn't alert
+pub fn get_price_outage_alert_secs() -> u64 {
+    env::var("PRICE_OUTAGE_ALERT_SECS")
+        .unwrap_or_else(|_| "300".to_string())
+        .parse::<u64>()
+        .unwrap_or(300)
+}
+
+/// USD value above which a single supply, withdrawal, repay or borrow of a tracked
+/// account triggers an informational alert, 0 to disable
+pub fn get_large_event_alert_usd() -> f64 {
+    env::var("LARGE_EVENT_ALERT_USD")
+        .unwrap_or_else(|_| "0".to_string())
+        .parse::<f64>()
+        .unwrap_or(0.0)
+}
+
+pub fn get_self_test_interval_mins() -> u64 {
+    env::var("SELF_TEST_INTERVAL_MINS")
+        .unwrap_or_else(|_| "10".to_string())
+        .parse::<u64>()
+        .unwrap_or(10)
+}
+
+pub fn get_hf_drift_tolerance() -> f64 {
+    env::var("HF_DRIFT_TOLERANCE")
+        .unwrap_or_else(|_| "0.05".to_string())
+        .parse::<f64>()
+        .unwrap_or(0.05)
+}
+
+pub fn get_metrics_port() -> u16 {
+    env::var("METRICS_PORT")
+        .unwrap_or_else(|_| "9100".to_string())
+        .parse::<u16>()
+        .unwrap_or(9100)
+}
+
+/// Seconds between stats lines in the log (0 disables them)
+pub fn get_stats_interval_secs() -> u64 {
+    env::var("STATS_INTERVAL_SECS")
+        .unwrap_or_else(|_| "60".to_string())
+        .parse::<u64>()
+        .unwrap_or(60)
+}
+
+/// Address serving the read-only position API, `None` when `API_PORT` is 0
+pub fn get_api_address() -> Option<SocketAddr> {
+    let host = env::var("API_BIND_ADDRESS")
+        .ok()
+        .and_then(|host| host.trim().parse::<IpAddr>().ok())
+        .unwrap_or(IpAddr::from([127, 0, 0, 1]));
+    let port = env::var("API_PORT")
+        .unwrap_or_else(|_| "8080".to_string())
+        .parse::<u16>()
+        .unwrap_or(8080);
+    (port != 0).then(|| SocketAddr::new(host, port))
+}
+
+/// Pick the decimals to use for a token given an optional user override and the
+/// on-chain `decimals()` value. The override wins; a warning is returned when they disagree.
+pub fn reconcile_token_decimals(
+    token_address: &str,
+    overridden: Option<u64>,
+    on_chain: u64,
+) -> (u64, Option<String>) {
+    let Some(configured) = overridden else {
+        return (on_chain, None);
+    };
+    if configured == on_chain {
+        return (on_chain, None);
+    }
+    let warning = format!(
+        "WARNING: configured decimals for token {} are {} but the contract reports {}; using the configured override {}",
+        token_address, configured, on_chain, configured
+    );
+    (configured, Some(warning))
+}
+
+/// Human-readable summary of the resolved configuration, one line per setting.
+/// Logged at startup and printed by `--print-config`.
+pub fn configuration_summary() -> Vec<String> {
+    let mut lines = Vec::new();
+    lines.push("=== Aave Liquidator Configuration ===".to_string());
+    for account in get_configured_user_accounts() {
+        if is_ens_name(&account) {
+            lines.push(format!(
+                "User Address to Track: {} ({})",
+                resolved_account(&account),
+                account.trim()
+            ));
+        } else {
+            lines.push(format!("User Address to Track: {}", account));
+        }
+    }
+    lines.push(format!(
+        "Pool Address: {} (Aave {:?})",
+        get_pool_v3_address(),
+        get_pool_version()
+    ));
+    lines.push(format!(
+        "Supply Token Address: {} (Decimals: {}) - Default: USDT",
+        get_supply_token_address(),
+        get_supply_token_decimals()
+    ));
+    lines.push(format!(
+        "Borrow Token Address: {} (Decimals: {}) - Default: wBTC",
+        get_borrowed_token_address(),
+        get_borrowed_token_decimals()
+    ));
+    if let Ok(known) = get_known_assets() {
+        if !known.is_empty() {
+            lines.push(format!("Known Assets: {} pre-seeded", known.len()));
+        }
+    }
+    lines.push(format!(
+        "Ethereum RPC URLs: {}",
+        get_ethereum_rpc_urls().join(", ")
+    ));
+    lines.push(format!(
+        "Ethereum WS URLs: {}",
+        get_ethereum_ws_urls().join(", ")
+    ));
+    let status = |enabled: bool| if enabled { "enabled" } else { "disabled" };
+    lines.push(format!(
+        "Chains: Ethereum {}, Polygon {}, Arbitrum {}, Optimism {}, Base {}, Solana {}",
+        status(get_ethereum_enabled()),
+        status(get_polygon_enabled()),
+        status(get_arbitrum_enabled()),
+        status(get_optimism_enabled()),
+        status(get_base_enabled()),
+        status(get_solana_enabled()),
+    ));
+    if get_polygon_enabled() {
+        lines.push(format!(
+            "Polygon Pool V3 Address: {}",
+            get_polygon_pool_v3_address()
+        ));
+        lines.push(format!(
+            "Polygon RPC URLs: {}",
+            get_polygon_rpc_urls().join(", ")
+        ));
+        lines.push(format!(
+            "Polygon WS URLs: {}",
+            get_polygon_ws_urls().join(", ")
+        ));
+    }
+    if get_arbitrum_enabled() {
+        lines.push(format!(
+            "Arbitrum Pool V3 Address: {}",
+            get_arbitrum_pool_v3_address()
+        ));
+        lines.push(format!(
+            "Arbitrum RPC URLs: {}",
+            get_arbitrum_rpc_urls().join(", ")
+        ));
+        lines.push(format!(
+            "Arbitrum WS URLs: {}",
+            get_arbitrum_ws_urls().join(", ")
+        ));
+    }
+    if get_optimism_enabled() {
+        lines.push(format!(
+            "Optimism Pool V3 Address: {}",
+            get_optimism_pool_v3_address()
+        ));
+        lines.push(format!(
+            "Optimism RPC URLs: {}",
+            get_optimism_rpc_urls().join(", ")
+        ));
+        lines.push(format!(
+            "Optimism WS URLs: {}",
+            get_optimism_ws_urls().join(", ")
+        ));
+    }
+    if get_base_enabled() {
+        lines.push(format!(
+            "Base Pool V3 Address: {}",
+            get_base_pool_v3_address()
+        ));
+        lines.push(format!("Base RPC URLs: {}", get_base_rpc_urls().join(", ")));
+        lines.push(format!("Base WS URLs: {}", get_base_ws_urls().join(", ")));
+    }
+    if get_solana_enabled() {
+        lines.push(format!(
+            "Solana Account: {} (program {})",
+            get_solana_user_address().unwrap_or_else(|| "not set".to_string()),
+            get_solana_program_id().unwrap_or_else(|| "any".to_string())
+        ));
+        lines.push(format!(
+            "Solana RPC URL: {}, WS URL: {}",
+            get_solana_rpc_url(),
+            get_solana_ws_url()
+        ));
+        lines.push(format!(
+            "Solana Liquidation Threshold: {}",
+            get_solana_liquidation_threshold()
+        ));
+    }
+    lines.push(format!(
+        "Alert Channels: Telegram {}, Discord {}, Slack {}, Webhook {}",
+        if get_telegram_bot_token().is_some() && get_telegram_chat_id().is_some() {
+            "enabled"
+        } else {
+            "disabled"
+        },
+        if get_discord_webhook_url().is_some() {
+            "enabled"
+        } else {
+            "disabled"
+        },
+        if get_slack_webhook_url().is_some() {
+            "enabled"
+        } else {
+            "disabled"
+        },
+        if get_alert_webhook_url().is_some() {
+            "enabled"
+        } else {
+            "disabled"
+        }
+    ));
+    if get_alert_webhook_url().is_some() {
+        lines.push(format!(
+            "Webhook Auth: bearer token {}, HMAC signature {}",
+            if get_alert_webhook_bearer_token().is_some() {
+                "set"
+            } else {
+                "not set"
+            },
+            if get_alert_webhook_secret().is_some() {
+                "enabled"
+            } else {
+                "disabled"
+            }
+        ));
+    }
+    if get_telegram_bot_token().is_some() && get_telegram_chat_id().is_some() {
+        lines.push(format!(
+            "Telegram Commands: /pause (default {} min), /resume, /status, /healthfactor, /position, /testalert",
+            get_alert_pause_default_mins()
+        ));
+    }
+    match get_publish_url() {
+        Some(url) => lines.push(format!(
+            "Event Publishing: {} on {}",
+            url.split('@').last().unwrap_or_default(),
+            get_publish_channel()
+        )),
+        None => lines.push("Event Publishing: disabled".to_string()),
+    }
+    match get_influx_url() {
+        Some(url) => lines.push(format!(
+            "InfluxDB Export: {} bucket {}, every {}s or {} points",
+            url.trim(),
+            get_influx_bucket(),
+            get_influx_flush_interval_secs(),
+            get_influx_batch_size()
+        )),
+        None => lines.push("InfluxDB Export: disabled".to_string()),
+    }
+    match get_liquidation_threshold_override() {
+        Some(threshold) => lines.push(format!(
+            "Liquidation Threshold: {} ({}%, overriding on-chain values)",
+            threshold,
+            (threshold * 100.0) as i32
+        )),
+        None => lines.push(format!(
+            "Liquidation Threshold: per reserve from the Pool, refreshed every {}s (fallback {})",
+            get_liquidation_threshold_refresh_secs(),
+            get_liquidation_threshold()
+        )),
+    }
+    lines.push(format!(
+        "Alert Tiers (LTV): warn {}, danger {}, critical {}",
+        get_alert_tier_warn_ltv(),
+        get_alert_tier_danger_ltv(),
+        get_alert_tier_critical_ltv()
+    ));
+    lines.push(format!(
+        "Confirmation Depth: {} block(s), reorg history {} block(s) behind that",
+        get_confirmation_depth(),
+        get_reorg_history_blocks()
+    ));
+    for chain in configured_chains() {
+        if let Ok(Some(start_block)) = get_start_block(chain.name()) {
+            lines.push(format!(
+                "Start Block ({}): {:?}, unless a last processed block is persisted",
+                chain.name(),
+                start_block
+            ));
+        }
+    }
+    lines.push(match get_ws_block_timeout_secs() {
+        0 => "Block Subscription Timeout: disabled".to_string(),
+        secs => format!(
+            "Block Subscription Timeout: reconnect after {}s without a block",
+            secs
+        ),
+    });
+    lines.push(format!(
+        "Position File: {} (saved every {}s and on shutdown, {}s shutdown timeout)",
+        get_position_file(),
+        get_position_persist_interval_secs(),
+        get_shutdown_timeout_secs()
+    ));
+    lines.push(format!(
+        "Price Cache TTL: {}s, max price age {}s",
+        get_price_cache_ttl_secs(),
+        get_price_max_age_secs()
+    ));
+    lines.push(format!(
+        "Price Sources: {:?} first, {} Chainlink feed(s) configured, {} Uniswap TWAP pair(s) over {}s",
+        get_price_source_order(),
+        get_chainlink_feeds().map(|feeds| feeds.len()).unwrap_or(0),
+        get_uniswap_twap_pairs().map(|pairs| pairs.len()).unwrap_or(0),
+        get_uniswap_twap_window_secs()
+    ));
+    lines.push(match get_quote_currency_feed() {
+        Ok(Some(feed)) if get_quote_currency() != "USD" => format!(
+            "Quote Currency: {} (converted with feed {:?})",
+            get_quote_currency(),
+            feed
+        ),
+        _ => format!("Quote Currency: {}", get_quote_currency()),
+    });
+    lines.push(format!(
+        "Price Requests: {}, {}s timeout, up to {} retries",
+        get_simplehash_base_url(),
+        get_price_request_timeout_secs(),
+        get_price_max_retries()
+    ));
+    lines.push(match get_simplehash_rps() {
+        rps if rps > 0.0 => format!("SimpleHash Rate Limit: {} request(s)/s", rps),
+        _ => "SimpleHash Rate Limit: disabled".to_string(),
+    });
+    lines.push(format!(
+        "Position Resync: every {}s, drift tolerance {}",
+        get_resync_interval_secs(),
+        get_position_drift_tolerance()
+    ));
+    lines.push(format!("Event Account Field: {:?}", get_account_field()));
+    lines.push(format!(
+        "Health Check Interval: {}s",
+        get_health_check_interval_secs()
+    ));
+    lines.push(format!(
+        "Health Factor History: last {} check(s)",
+        get_health_factor_history_size()
+    ));
+    match get_position_source() {
+        PositionSource::Events => lines.push(format!(
+            "Position Source: events, interest accrued every {}s",
+            get_interest_accrual_interval_secs()
+        )),
+        PositionSource::Balances => lines.push(format!(
+            "Position Source: token balances, polled every {}s",
+            get_balance_poll_interval_secs()
+        )),
+    }
+    lines.push(format!("Alert Warm-up: {}s", get_alert_warmup_secs()));
+    lines.push(match get_min_position_usd() {
+        floor if floor > 0.0 => format!("Minimum Position: ${} before alerting", floor),
+        _ => "Minimum Position: none".to_string(),
+    });
+    lines.push(format!("Alert Cooldown: {}s", get_alert_cooldown_secs()));
+    let escalation = get_alert_escalation_schedule_secs();
+    if escalation.is_empty() {
+        lines.push("Critical Escalation: disabled".to_string());
+    } else {
+        lines.push(format!(
+            "Critical Escalation: every {}s, at most {} reminders",
+            escalation
+                .iter()
+                .map(u64::to_string)
+                .collect::<Vec<_>>()
+                .join("s then "),
+            get_alert_escalation_max_repeats()
+        ));
+    }
+    lines.push(format!(
+        "Recovery Alerts: {}",
+        get_recovery_alerts_enabled()
+    ));
+    lines.push(format!(
+        "Liquidation Prices In Alerts: {}",
+        get_liquidation_price_alerts_enabled()
+    ));
+    lines.push(format!(
+        "Alert Digest Window: {}s",
+        get_alert_digest_window_secs()
+    ));
+    lines.push(format!(
+        "Price Alert Confirmation: {} tick(s)",
+        get_price_alert_confirmation_ticks()
+    ));
+    lines.push(match get_price_outage_alert_failures() {
+        0 => "Price Outage Alert: disabled".to_string(),
+        failures => format!(
+            "Price Outage Alert: after {} failed check(s) over at least {}s",
+            failures,
+            get_price_outage_alert_secs()
+        ),
+    });
+    lines.push(match get_large_event_alert_usd() {
+        threshold if threshold > 0.0 => format!("Large Event Alert: above ${}", threshold),
+        _ => "Large Event Alert: disabled".to_string(),
+    });
+    lines.push(format!(
+        "Health Factor Self-test: every {} min(s), tolerance {}",
+        get_self_test_interval_mins(),
+        get_hf_drift_tolerance()
+    ));
+    match get_metrics_port() {
+        0 => lines.push("Prometheus Metrics: disabled".to_string()),
+        port => lines.push(format!(
+            "Prometheus Metrics: port {} (/metrics, /healthz, /readyz)",
+            port
+        )),
+    }
+    match get_stats_interval_secs() {
+        0 => lines.push("Stats Log: disabled".to_string()),
+        secs => lines.push(format!("Stats Log: every {}s", secs)),
+    }
+    match get_api_address() {
+        Some(addr) => lines.push(format!("Position API: {}", addr)),
+        None => lines.push("Position API: disabled".to_string()),
+    }
+
+    // Initial position values
+    match get_initial_position_data() {
+        Ok(position) => {
+            for (reserve, amount) in &position.supplied {
+                lines.push(format!(
+                    "Initial Supplied Amount of {:?}: {}",
+                    reserve, amount
+                ));
+            }
+            for (reserve, amount) in &position.borrowed {
+                lines.push(format!(
+                    "Initial Borrowed Amount of {:?}: {}",
+                    reserve, amount
+                ));
+            }
+        }
+        Err(e) => lines.push(format!("Error getting initial position data: {}", e)),
+    }
+    lines.push("=====================================".to_string());
+    lines
+}
+
+/// Prepare configuration and the tracked positions. Returns whether the Ethereum
+/// position was restored from `POSITION_FILE` rather than seeded from configuration.
+/// Check every address, URL and threshold in the configuration up front. Every
+/// problem found is listed in the one error, so a misconfiguration is fixed in one go
+/// instead of surfacing one restart at a time.
+pub fn validate_config() -> Result<(), MonitorError> {
+    let mut problems = Vec::new();
+    let mut check_address = |name: &str, value: &str| {
+        if let Err(e) = value.trim().parse::<Address>() {
+            problems.push(format!("{} '{}' is not an address: {}", name, value, e));
+        }
+    };
+
+    // ENS names are checked when they are resolved
+    for account in get_configured_user_accounts() {
+        if !is_ens_name(&account) {
+            check_address("AAVE_USER_ADDRESS_TO_TRACK", &account);
+        }
+    }
+    if get_ethereum_enabled() {
+        check_address("AAVE_POOL_V3_ADDRESS", &get_pool_v3_address());
+    }
+    if get_polygon_enabled() {
+        check_address(
+            "POLYGON_AAVE_POOL_V3_ADDRESS",
+            &get_polygon_pool_v3_address(),
+        );
+    }
+    if get_arbitrum_enabled() {
+        check_address(
+            "ARBITRUM_AAVE_POOL_V3_ADDRESS",
+            &get_arbitrum_pool_v3_address(),
+        );
+    }
+    if get_optimism_enabled() {
+        check_address(
+            "OPTIMISM_AAVE_POOL_V3_ADDRESS",
+            &get_optimism_pool_v3_address(),
+        );
+    }
+    if get_base_enabled() {
+        check_address("BASE_AAVE_POOL_V3_ADDRESS", &get_base_pool_v3_address());
+    }
+    if get_solana_enabled() {
+        match get_solana_user_address() {
+            Some(address) if is_solana_address(&address) => {}
+            Some(address) => problems.push(format!(
+                "SOLANA_USER_ADDRESS '{}' is not a Solana address",
+                address
+            )),
+            None => problems.push("SOLANA_ENABLED needs SOLANA_USER_ADDRESS".to_string()),
+        }
+        if let Some(program_id) = get_solana_program_id().filter(|id| !is_solana_address(id)) {
+            problems.push(format!(
+                "SOLANA_PROGRAM_ID '{}' is not a Solana address",
+                program_id
+            ));
+        }
+        for result in [
+            get_solana_supply_positions(),
+            get_solana_borrowed_positions(),
+        ] {
+            if let Err(e) = result {
+                problems.push(e.to_string());
+            }
+        }
+        match get_solana_liquidation_threshold() {
+            threshold if threshold > 0.0 && threshold <= 1.0 => {}
+            threshold => problems.push(format!(
+                "SOLANA_LIQUIDATION_THRESHOLD {} must be a number in (0, 1]",
+                threshold
+            )),
+        }
+    }
+
+    let mut assets: Vec<Result<Vec<AssetConfig>, MonitorError>> = Vec::new();
+    if get_ethereum_enabled() {
+        assets.extend([get_supply_assets(), get_borrowed_assets()]);
+    }
+    if get_polygon_enabled() {
+        assets.extend([get_polygon_supply_assets(), get_polygon_borrowed_assets()]);
+    }
+    if get_arbitrum_enabled() {
+        assets.extend([get_arbitrum_supply_assets(), get_arbitrum_borrowed_assets()]);
+    }
+    if get_optimism_enabled() {
+        assets.extend([get_optimism_supply_assets(), get_optimism_borrowed_assets()]);
+    }
+    if get_base_enabled() {
+        assets.extend([get_base_supply_assets(), get_base_borrowed_assets()]);
+    }
+    for result in assets {
+        if let Err(e) = result {
+            problems.push(e.to_string());
+        }
+    }
+    if let Err(e) = get_known_assets() {
+        problems.push(e.to_string());
+    }
+    if let Err(e) = get_chainlink_feeds() {
+        problems.push(e.to_string());
+    }
+    if let Err(e) = get_coingecko_platforms() {
+        problems.push(e.to_string());
+    }
+    for chain_name in ["ethereum", "polygon", "arbitrum", "optimism", "base"] {
+        if let Err(e) = get_start_block(chain_name) {
+            problems.push(e.to_string());
+        }
+    }
+    match get_uniswap_twap_pairs() {
+        Ok(pairs) if !pairs.is_empty() => {
+            if let Err(e) = get_uniswap_v3_factory("ethereum") {
+                problems.push(e.to_string());
+            }
+            if get_uniswap_twap_window_secs() == 0 {
+                problems.push("UNISWAP_TWAP_WINDOW_SECS must be at least 1".to_string());
+            }
+        }
+        Ok(_) => {}
+        Err(e) => problems.push(e.to_string()),
+    }
+    if let Err(e) = get_quote_currency_feed() {
+        problems.push(e.to_string());
+    }
+
+    if get_reorg_history_blocks() == 0 {
+        problems.push("REORG_HISTORY_BLOCKS must be at least 1".to_string());
+    }
+
+    if let Ok(raw) = env::var("LIQUIDATION_THRESHOLD") {
+        match raw.trim().parse::<f64>() {
+            Ok(threshold) if threshold > 0.0 && threshold <= 1.0 => {}
+            _ => problems.push(format!(
+                "LIQUIDATION_THRESHOLD '{}' must be a number in (0, 1]",
+                raw
+            )),
+        }
+    }
+
+    let mut endpoints = Vec::new();
+    if get_ethereum_enabled() {
+        endpoints.push((
+            "ETHEREUM_RPC_URL",
+            get_ethereum_rpc_urls(),
+            ["http", "https"],
+        ));
+        endpoints.push(("ETHEREUM_WS_URL", get_ethereum_ws_urls(), ["ws", "wss"]));
+    }
+    if get_polygon_enabled() {
+        endpoints.push(("POLYGON_RPC_URL", get_polygon_rpc_urls(), ["http", "https"]));
+        endpoints.push(("POLYGON_WS_URL", get_polygon_ws_urls(), ["ws", "wss"]));
+    }
+    if get_arbitrum_enabled() {
+        endpoints.push((
+            "ARBITRUM_RPC_URL",
+            get_arbitrum_rpc_urls(),
+            ["http", "https"],
+        ));
+        endpoints.push(("ARBITRUM_WS_URL", get_arbitrum_ws_urls(), ["ws", "wss"]));
+    }
+    if get_optimism_enabled() {
+        endpoints.push((
+            "OPTIMISM_RPC_URL",
+            get_optimism_rpc_urls(),
+            ["http", "https"],
+        ));
+        endpoints.push(("OPTIMISM_WS_URL", get_optimism_ws_urls(), ["ws", "wss"]));
+    }
+    if get_base_enabled() {
+        endpoints.push(("BASE_RPC_URL", get_base_rpc_urls(), ["http", "https"]));
+        endpoints.push(("BASE_WS_URL", get_base_ws_urls(), ["ws", "wss"]));
+    }
+    if get_solana_enabled() {
+        endpoints.push((
+            "SOLANA_RPC_URL",
+            vec![get_solana_rpc_url()],
+            ["http", "https"],
+        ));
+        endpoints.push(("SOLANA_WS_URL", vec![get_solana_ws_url()], ["ws", "wss"]));
+    }
+    for (name, urls, schemes) in endpoints {
+        for url in urls {
+            match reqwest::Url::parse(&url) {
+                Ok(parsed) if schemes.contains(&parsed.scheme()) => {}
+                Ok(parsed) => problems.push(format!(
+                    "{} '{}' has scheme {}, expected {}",
+                    name,
+                    url,
+                    parsed.scheme(),
+                    schemes.join(" or ")
+                )),
+                Err(e) => problems.push(format!("{} '{}' is not a URL: {}", name, url, e)),
+            }
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(MonitorError::Config(format!(
+            "{} configuration problem(s):\n  - {}",
+            problems.len(),
+            problems.join("\n  - ")
+        )))
+    }
+}
+
+pub async fn init_system() -> Result<bool, MonitorError> {
+    // Fail fast on a missing price API key instead of getting no prices later
+    get_simplehash_api_key()?;
+
+    // Set default RPC URL if not provided
+    if env::var("ETHEREUM_RPC_URL").is_err() {
+        env::set_var("ETHEREUM_RPC_URL", "https://mainnet.infura.io/v3/123");
+    }
+    validate_config()?;
+    seed_known_assets()?;
+
+    // Every chain tracks the same accounts, and ENS lives on Ethereum
+    resolve_user_address(&get_ethereum_rpc_url()).await?;
+
+    let mut restored = false;
+    if get_ethereum_enabled() {
+        let _ = get_current_block_number(&ETHEREUM, &ETHEREUM_RPC_ENDPOINTS).await;
+        let ethereum_rpc = get_ethereum_rpc_url();
+        let assets: Vec<AssetConfig> = get_supply_assets()?
+            .into_iter()
+            .chain(get_borrowed_assets()?)
+            .collect();
+        register_token_decimals(&ethereum_rpc, &assets).await;
+        restored = restore_or_seed_position(&ETHEREUM, get_initial_position_data()?)?;
+    }
+
+    if get_polygon_enabled() {
+        let assets: Vec<AssetConfig> = get_polygon_supply_assets()?
+            .into_iter()
+            .chain(get_polygon_borrowed_assets()?)
+            .collect();
+        register_token_decimals(&get_polygon_rpc_url(), &assets).await;
+        restore_or_seed_position(&POLYGON, get_polygon_initial_position_data()?)?;
+    }
+
+    if get_arbitrum_enabled() {
+        let assets: Vec<AssetConfig> = get_arbitrum_supply_assets()?
+            .into_iter()
+            .chain(get_arbitrum_borrowed_assets()?)
+            .collect();
+        register_token_decimals(&get_arbitrum_rpc_url(), &assets).await;
+        restore_or_seed_position(&ARBITRUM, get_arbitrum_initial_position_data()?)?;
+    }
+
+    if get_optimism_enabled() {
+        let assets: Vec<AssetConfig> = get_optimism_supply_assets()?
+            .into_iter()
+            .chain(get_optimism_borrowed_assets()?)
+            .collect();
+        register_token_decimals(&get_optimism_rpc_url(), &assets).await;
+        restore_or_seed_position(&OPTIMISM, get_optimism_initial_position_data()?)?;
+    }
+
+    if get_base_enabled() {
+        let assets: Vec<AssetConfig> = get_base_supply_assets()?
+            .into_iter()
+            .chain(get_base_borrowed_assets()?)
+            .collect();
+        register_token_decimals(&get_base_rpc_url(), &assets).await;
+        restore_or_seed_position(&BASE, get_base_initial_position_data()?)?;
+    }
+
+    for chain in configured_chains() {
+        seed_other_accounts(chain.as_ref()).await?;
+    }
+
+    Ok(restored)
+}
+
+// The configured amounts belong to the first account. Every other account starts
+// from its saved position, or from its token balances in the same reserves.
+async fn seed_other_accounts(chain: &dyn Chain) -> Result<(), MonitorError> {
+    let primary = chain.state().position()?;
+    for state in chain.accounts().into_iter().skip(1) {
+        let mut seed = PositionData::new();
+        for reserve in primary.supplied.keys() {
+            seed.update_supplied_amount(*reserve, U256::zero());
+        }
+        for reserve in primary.borrowed.keys() {
+            seed.update_borrowed_amount(*reserve, U256::zero());
+        }
+        if restore_or_seed_position(state, seed)? {
+            continue;
+        }
+        match chain.refresh_account_balances(state).await {
+            Ok(_) => info!(
+                chain = state.name,
+                account = %state.user_address(),
+                "Initial position read from token balances"
+            ),
+            Err(e) => warn!(
+                chain = state.name,
+                account = %state.user_address(),
+                "Failed to read initial position, starting empty: {}", e
+            ),
+        }
+    }
+    Ok(())
+}
+
+// Read decimals from the token contracts unless the user overrides them
+async fn register_token_decimals(rpc_url: &str, assets: &[AssetConfig]) {
+    for asset in assets {
+        let token_address = format!("{:?}", asset.address);
+        match get_token_decimals_ethereum(rpc_url, &token_address).await {
+            Ok(on_chain) => apply_token_decimals(asset, on_chain),
+            Err(e) => {
+                warn!(
+                    "Failed to verify decimals for {}, keeping configured value {}: {}",
+                    token_address, asset.decimals, e
+                );
+                set_token_decimals(asset.address, asset.decimals);
+            }
+        }
+        register_token_symbol(rpc_url, asset.address).await;
+    }
+}
+
+/// Use the decimals `reconcile_token_decimals` picks for `asset` given its on-chain
+/// value, logging a warning when a user override disagrees with the contract
+pub fn apply_token_decimals(asset: &AssetConfig, on_chain: u64) {
+    let token_address = format!("{:?}", asset.address);
+    let overridden = asset.decimals_overridden.then_some(asset.decimals);
+    let (decimals, warning) = reconcile_token_decimals(&token_address, overridden, on_chain);
+    if let Some(warning) = warning {
+        warn!("{}", warning);
+    }
+    set_token_decimals(asset.address, decimals);
+}
+
+// Restore the position saved by the last run, falling back to the configured seed
+fn restore_or_seed_position(chain: &ChainState, seed: PositionData) -> Result<bool, MonitorError> {
+    match chain.load_snapshot() {
+        Ok(Some(snapshot))
+            if snapshot
+                .user_address
+                .eq_ignore_ascii_case(&chain.user_address()) =>
+        {
+            chain.set_position(snapshot.position)?;
+            // Replay only the events that came after the snapshot
+            chain.set_last_processed_block(snapshot.last_processed_block)?;
+            return Ok(true);
+        }
+        Ok(Some(snapshot)) => warn!(
+            "Ignoring {} position file saved for {}, seeding from configuration",
+            chain.name, snapshot.user_address
+        ),
+        Ok(None) => {}
+        Err(e) => {
+            warn!(
+                "Failed to restore {} position, seeding from configuration: {}",
+                chain.name, e
+            );
+        }
     }
 
-    let ethereum_rpc = get_ethereum_rpc_url();
-    let _ = get_current_block_number_ethereum(&ethereum_rpc).await;
+    // Seed the tracked position from configuration
+    chain.set_position(seed)?;
+    Ok(false)
 }
@@ -0,0 +1,42 @@
+pub mod optimism_chain {
+    use crate::chains::endpoints::EndpointPool;
+    use crate::chains::ethereum::ethereum_chain::ChainState;
+    use crate::chains::{
+        get_optimism_last_processed_block_file, get_optimism_pool_v3_address,
+        get_optimism_position_file, get_optimism_rpc_urls, get_optimism_ws_urls, Chain,
+    };
+
+    // Aave V3 on Optimism emits the same Pool events as on Ethereum, so only the
+    // connection details and the tracked state differ
+    lazy_static::lazy_static! {
+        pub static ref OPTIMISM: ChainState = ChainState::new(
+            "optimism",
+            get_optimism_last_processed_block_file,
+            get_optimism_position_file,
+        );
+        pub static ref OPTIMISM_RPC_ENDPOINTS: EndpointPool =
+            EndpointPool::new("Optimism RPC", get_optimism_rpc_urls);
+        pub static ref OPTIMISM_WS_ENDPOINTS: EndpointPool =
+            EndpointPool::new("Optimism WS", get_optimism_ws_urls);
+    }
+
+    pub struct Optimism;
+
+    impl Chain for Optimism {
+        fn state(&self) -> &'static ChainState {
+            &OPTIMISM
+        }
+
+        fn ws_endpoints(&self) -> &'static EndpointPool {
+            &OPTIMISM_WS_ENDPOINTS
+        }
+
+        fn rpc_endpoints(&self) -> &'static EndpointPool {
+            &OPTIMISM_RPC_ENDPOINTS
+        }
+
+        fn pool_address(&self) -> String {
+            get_optimism_pool_v3_address()
+        }
+    }
+}
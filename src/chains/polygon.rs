@@ -0,0 +1,42 @@
+pub mod polygon_chain {
+    use crate::chains::endpoints::EndpointPool;
+    use crate::chains::ethereum::ethereum_chain::ChainState;
+    use crate::chains::{
+        get_polygon_last_processed_block_file, get_polygon_pool_v3_address,
+        get_polygon_position_file, get_polygon_rpc_urls, get_polygon_ws_urls, Chain,
+    };
+
+    // Aave V3 on Polygon emits the same Pool events as on Ethereum, so only the
+    // connection details and the tracked state differ
+    lazy_static::lazy_static! {
+        pub static ref POLYGON: ChainState = ChainState::new(
+            "polygon",
+            get_polygon_last_processed_block_file,
+            get_polygon_position_file,
+        );
+        pub static ref POLYGON_RPC_ENDPOINTS: EndpointPool =
+            EndpointPool::new("Polygon RPC", get_polygon_rpc_urls);
+        pub static ref POLYGON_WS_ENDPOINTS: EndpointPool =
+            EndpointPool::new("Polygon WS", get_polygon_ws_urls);
+    }
+
+    pub struct Polygon;
+
+    impl Chain for Polygon {
+        fn state(&self) -> &'static ChainState {
+            &POLYGON
+        }
+
+        fn ws_endpoints(&self) -> &'static EndpointPool {
+            &POLYGON_WS_ENDPOINTS
+        }
+
+        fn rpc_endpoints(&self) -> &'static EndpointPool {
+            &POLYGON_RPC_ENDPOINTS
+        }
+
+        fn pool_address(&self) -> String {
+            get_polygon_pool_v3_address()
+        }
+    }
+}
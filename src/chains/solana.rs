@@ -0,0 +1,418 @@
+pub mod solana_chain {
+    use futures::{SinkExt, StreamExt};
+    use reqwest::Client;
+    use serde_json::{json, Value};
+    use std::collections::VecDeque;
+    use std::future::Future;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Mutex;
+    use std::time::{Duration, Instant};
+    use tokio_tungstenite::{connect_async, tungstenite::Message};
+    use tracing::{error, info, warn};
+
+    use crate::chains::ethereum::ethereum_chain::HealthFactorSample;
+    use crate::chains::{
+        get_balance_poll_interval_secs, get_solana_borrowed_positions, get_solana_program_id,
+        get_solana_rpc_url, get_solana_supply_positions, get_solana_user_address,
+        get_solana_ws_url, lock_recovering,
+    };
+    use crate::error::MonitorError;
+    use crate::ReconnectBackoff;
+
+    // Wrapped SOL; an entry for it reads the account's lamports rather than a token balance
+    pub const NATIVE_MINT: &str = "So11111111111111111111111111111111111111112";
+
+    const BASE58_ALPHABET: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+    // A node slower than this fails the refresh, which is retried on the next poll
+    const RPC_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// One mint on either side of the Solana position
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct SolanaReserve {
+        pub mint: String,
+        pub decimals: u32,
+        // Raw amount in the mint's smallest unit
+        pub amount: u64,
+        // Account whose balance replaces `amount` on every refresh, if any
+        pub token_account: Option<String>,
+    }
+
+    #[derive(Debug, Clone, Default, PartialEq)]
+    pub struct SolanaPosition {
+        pub supplied: Vec<SolanaReserve>,
+        pub borrowed: Vec<SolanaReserve>,
+    }
+
+    /// Parse `mint:decimals:amount[:token_account]` entries, comma-separated
+    pub fn parse_solana_positions(raw: &str) -> Result<Vec<SolanaReserve>, MonitorError> {
+        raw.split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                let parts: Vec<&str> = entry.split(':').map(str::trim).collect();
+                if parts.len() != 3 && parts.len() != 4 {
+                    return Err(MonitorError::Config(format!(
+                        "Invalid Solana entry '{}', expected mint:decimals:amount[:token_account]",
+                        entry
+                    )));
+                }
+                for account in [Some(parts[0]), parts.get(3).copied()]
+                    .into_iter()
+                    .flatten()
+                {
+                    if !is_solana_address(account) {
+                        return Err(MonitorError::Config(format!(
+                            "Invalid Solana address '{}'",
+                            account
+                        )));
+                    }
+                }
+                let decimals = parts[1].parse::<u32>().map_err(|e| {
+                    MonitorError::Config(format!("Invalid decimals '{}': {}", parts[1], e))
+                })?;
+                let amount = parts[2].parse::<u64>().map_err(|e| {
+                    MonitorError::Config(format!("Invalid amount '{}': {}", parts[2], e))
+                })?;
+                Ok(SolanaReserve {
+                    mint: parts[0].to_string(),
+                    decimals,
+                    amount,
+                    token_account: parts.get(3).map(|account| account.to_string()),
+                })
+            })
+            .collect()
+    }
+
+    /// Whether `value` is a base58-encoded 32-byte public key
+    pub fn is_solana_address(value: &str) -> bool {
+        // Big number in base 256, least significant byte first
+        let mut bytes: Vec<u8> = Vec::new();
+        for c in value.bytes() {
+            let Some(digit) = BASE58_ALPHABET.iter().position(|&symbol| symbol == c) else {
+                return false;
+            };
+            let mut carry = digit as u32;
+            for byte in bytes.iter_mut() {
+                carry += *byte as u32 * 58;
+                *byte = carry as u8;
+                carry >>= 8;
+            }
+            while carry > 0 {
+                bytes.push(carry as u8);
+                carry >>= 8;
+            }
+        }
+        // Each leading '1' stands for a zero byte
+        let leading_zeros = value.bytes().take_while(|&c| c == b'1').count();
+        !value.is_empty() && bytes.len() + leading_zeros == 32
+    }
+
+    /// SimpleHash fungible id of a mint, priced through the same sources as EVM tokens
+    pub fn solana_fungible_id(mint: &str) -> String {
+        format!("solana.{}", mint)
+    }
+
+    /// Tracked Solana position, kept current by `poll_solana_balances`
+    pub struct SolanaState {
+        position: Mutex<SolanaPosition>,
+        // Bumped whenever a refresh changes an amount, so alerting can tell
+        // transaction-triggered crossings apart from price-driven ones
+        change_count: AtomicU64,
+        // Slot of the last transaction seen for the account
+        latest_slot: AtomicU64,
+        // Woken by the log subscription so balances are read right away
+        refresh_requested: tokio::sync::Notify,
+        health_factor_history: Mutex<VecDeque<HealthFactorSample>>,
+    }
+
+    impl SolanaState {
+        fn from_config() -> Self {
+            // Invalid entries are reported by `validate_config` before this is used
+            let position = SolanaPosition {
+                supplied: get_solana_supply_positions().unwrap_or_default(),
+                borrowed: get_solana_borrowed_positions().unwrap_or_default(),
+            };
+            Self {
+                position: Mutex::new(position),
+                change_count: AtomicU64::new(0),
+                latest_slot: AtomicU64::new(0),
+                refresh_requested: tokio::sync::Notify::new(),
+                health_factor_history: Mutex::new(VecDeque::new()),
+            }
+        }
+
+        pub fn position(&self) -> Result<SolanaPosition, MonitorError> {
+            Ok(lock_recovering(&self.position).clone())
+        }
+
+        pub fn change_count(&self) -> u64 {
+            self.change_count.load(Ordering::Relaxed)
+        }
+
+        pub fn latest_slot(&self) -> u64 {
+            self.latest_slot.load(Ordering::Relaxed)
+        }
+
+        /// Remember a health-factor check, keeping the latest `capacity` ones
+        pub fn record_health_factor_sample(
+            &self,
+            sample: HealthFactorSample,
+            capacity: usize,
+        ) -> Result<(), MonitorError> {
+            let mut history = lock_recovering(&self.health_factor_history);
+            history.push_back(sample);
+            while history.len() > capacity {
+                history.pop_front();
+            }
+            Ok(())
+        }
+
+        /// Recorded health-factor checks, oldest first
+        pub fn health_factor_history(&self) -> Result<Vec<HealthFactorSample>, MonitorError> {
+            Ok(lock_recovering(&self.health_factor_history)
+                .iter()
+                .cloned()
+                .collect())
+        }
+
+        /// Set the amount of every reserve read from `token_account`; returns whether
+        /// any amount changed
+        pub fn set_account_amount(
+            &self,
+            token_account: &str,
+            amount: u64,
+        ) -> Result<bool, MonitorError> {
+            let mut guard = lock_recovering(&self.position);
+            let position = &mut *guard;
+            let mut changed = false;
+            for reserve in position
+                .supplied
+                .iter_mut()
+                .chain(position.borrowed.iter_mut())
+                .filter(|reserve| reserve.token_account.as_deref() == Some(token_account))
+            {
+                changed |= reserve.amount != amount;
+                reserve.amount = amount;
+            }
+            if changed {
+                self.change_count.fetch_add(1, Ordering::Relaxed);
+            }
+            Ok(changed)
+        }
+
+        /// Read every token account behind the position from `rpc_url`
+        pub async fn refresh(&self, rpc_url: &str) -> Result<(), MonitorError> {
+            let position = self.position()?;
+            for reserve in position.supplied.iter().chain(&position.borrowed) {
+                let Some(token_account) = &reserve.token_account else {
+                    continue;
+                };
+                let amount = read_account_amount(rpc_url, token_account, &reserve.mint).await?;
+                if self.set_account_amount(token_account, amount)? {
+                    info!(
+                        chain = "solana",
+                        mint = %reserve.mint,
+                        account = %token_account,
+                        amount,
+                        "Solana balance changed"
+                    );
+                }
+            }
+            Ok(())
+        }
+
+        fn request_refresh(&self) {
+            self.refresh_requested.notify_one();
+        }
+    }
+
+    lazy_static::lazy_static! {
+        pub static ref SOLANA: SolanaState = SolanaState::from_config();
+    }
+
+    async fn rpc_call(rpc_url: &str, method: &str, params: Value) -> Result<Value, MonitorError> {
+        let response: Value = Client::new()
+            .post(rpc_url)
+            .timeout(RPC_TIMEOUT)
+            .json(&json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": params}))
+            .send()
+            .await
+            .map_err(|e| MonitorError::Rpc(format!("Solana {} failed: {}", method, e)))?
+            .json()
+            .await
+            .map_err(|e| MonitorError::Decode(format!("Invalid Solana {} reply: {}", method, e)))?;
+        rpc_result(method, response)
+    }
+
+    /// `result` of a JSON-RPC reply, or its `error` as an RPC failure
+    pub fn rpc_result(method: &str, mut response: Value) -> Result<Value, MonitorError> {
+        if let Some(error) = response.get("error") {
+            return Err(MonitorError::Rpc(format!(
+                "Solana {} failed: {}",
+                method, error
+            )));
+        }
+        match response.get_mut("result") {
+            Some(result) => Ok(result.take()),
+            None => Err(MonitorError::Decode(format!(
+                "Solana {} reply has no result",
+                method
+            ))),
+        }
+    }
+
+    /// Raw amount of a `getTokenAccountBalance` or `getBalance` result
+    pub fn balance_amount(result: &Value) -> Result<u64, MonitorError> {
+        let value = &result["value"];
+        // Token balances are decimal strings, lamports a plain number
+        let amount = match value.get("amount") {
+            Some(amount) => amount.as_str().and_then(|amount| amount.parse().ok()),
+            None => value.as_u64(),
+        };
+        amount.ok_or_else(|| MonitorError::Decode(format!("Unexpected Solana balance {}", value)))
+    }
+
+    async fn read_account_amount(
+        rpc_url: &str,
+        account: &str,
+        mint: &str,
+    ) -> Result<u64, MonitorError> {
+        let method = if mint == NATIVE_MINT {
+            "getBalance"
+        } else {
+            "getTokenAccountBalance"
+        };
+        let result = rpc_call(
+            rpc_url,
+            method,
+            json!([account, {"commitment": "confirmed"}]),
+        )
+        .await?;
+        balance_amount(&result)
+    }
+
+    /// `logsSubscribe` request for every transaction that mentions `account`
+    pub fn logs_subscribe_request(account: &str) -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "logsSubscribe",
+            "params": [{"mentions": [account]}, {"commitment": "confirmed"}],
+        })
+    }
+
+    /// Slot of a `logsNotification` for a successful transaction that invoked
+    /// `program_id` (any program when `None`), `None` for any other message
+    pub fn relevant_log_slot(message: &Value, program_id: Option<&str>) -> Option<u64> {
+        if message["method"] != "logsNotification" {
+            return None;
+        }
+        let result = &message["params"]["result"];
+        // Failed transactions change nothing
+        if !result["value"]["err"].is_null() {
+            return None;
+        }
+        let invoked = program_id.is_none_or(|program_id| {
+            let invoke = format!("Program {} invoke", program_id);
+            result["value"]["logs"].as_array().is_some_and(|logs| {
+                logs.iter()
+                    .filter_map(Value::as_str)
+                    .any(|line| line.starts_with(&invoke))
+            })
+        });
+        invoked.then(|| result["context"]["slot"].as_u64().unwrap_or_default())
+    }
+
+    // Follow the account's transactions until the connection drops
+    async fn follow_account_logs(account: &str) -> Result<(), MonitorError> {
+        let ws_url = get_solana_ws_url();
+        let (mut socket, _) = connect_async(ws_url.as_str())
+            .await
+            .map_err(|e| MonitorError::Rpc(format!("Failed to connect to {}: {}", ws_url, e)))?;
+        socket
+            .send(Message::Text(logs_subscribe_request(account).to_string()))
+            .await
+            .map_err(|e| MonitorError::Rpc(format!("Failed to subscribe to Solana logs: {}", e)))?;
+        info!(
+            chain = "solana",
+            account, "Subscribed to Solana transaction logs"
+        );
+        // Transactions missed while disconnected are covered by reading the balances now
+        SOLANA.request_refresh();
+
+        let program_id = get_solana_program_id();
+        while let Some(message) = socket.next().await {
+            let message = message
+                .map_err(|e| MonitorError::Rpc(format!("Solana WebSocket failed: {}", e)))?;
+            let Message::Text(text) = message else {
+                continue;
+            };
+            let Ok(message) = serde_json::from_str::<Value>(&text) else {
+                warn!(chain = "solana", "Ignoring unreadable Solana message");
+                continue;
+            };
+            if let Some(error) = message.get("error") {
+                return Err(MonitorError::Rpc(format!(
+                    "Solana logsSubscribe failed: {}",
+                    error
+                )));
+            }
+            if let Some(slot) = relevant_log_slot(&message, program_id.as_deref()) {
+                SOLANA.latest_slot.fetch_max(slot, Ordering::Relaxed);
+                SOLANA.request_refresh();
+            }
+        }
+        Ok(())
+    }
+
+    /// Subscribe to SOLANA_USER_ADDRESS's transaction logs on SOLANA_WS_URL,
+    /// reconnecting with backoff, until `shutdown` resolves
+    pub async fn listen_solana(shutdown: impl Future<Output = ()>) {
+        let Some(account) = get_solana_user_address() else {
+            return;
+        };
+        let mut backoff = ReconnectBackoff::new(
+            Duration::from_secs(1),
+            Duration::from_secs(60),
+            Duration::from_secs(60),
+        );
+        tokio::pin!(shutdown);
+        loop {
+            let connected_at = Instant::now();
+            tokio::select! {
+                result = follow_account_logs(&account) => match result {
+                    Ok(_) => info!(chain = "solana", "Solana log subscription closed"),
+                    Err(e) => warn!(chain = "solana", "Solana log subscription failed: {}", e),
+                },
+                _ = &mut shutdown => return,
+            }
+            let delay = backoff.next_delay(connected_at.elapsed());
+            info!(
+                chain = "solana",
+                "Resubscribing to Solana logs in {:?}", delay
+            );
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {}
+                _ = &mut shutdown => return,
+            }
+        }
+    }
+
+    /// Read the position's token accounts every BALANCE_POLL_INTERVAL_SECS and as
+    /// soon as the log subscription sees a transaction, until `shutdown` resolves
+    pub async fn poll_solana_balances(shutdown: impl Future<Output = ()>) {
+        let interval = Duration::from_secs(get_balance_poll_interval_secs().max(1));
+        tokio::pin!(shutdown);
+        loop {
+            if let Err(e) = SOLANA.refresh(&get_solana_rpc_url()).await {
+                error!(chain = "solana", "Failed to read Solana balances: {}", e);
+            }
+            tokio::select! {
+                _ = tokio::time::sleep(interval) => {}
+                _ = SOLANA.refresh_requested.notified() => {}
+                _ = &mut shutdown => return,
+            }
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use clap::Parser;
+use std::env;
+use std::path::PathBuf;
+
+/// Command-line overrides for the key settings. A flag takes precedence over its
+/// environment variable, which takes precedence over the `--config` file and then
+/// the default. Flags are applied
+/// by setting the variable, so every getter in `chains` picks them up unchanged.
+#[derive(Debug, Parser)]
+#[command(
+    version,
+    about = "Watch an Aave V3 position and alert before it can be liquidated"
+)]
+pub struct Cli {
+    /// Address or ENS name whose position is tracked [env: AAVE_USER_ADDRESS_TO_TRACK]
+    #[arg(long)]
+    pub user_address: Option<String>,
+
+    /// Aave Pool V3 address on Ethereum [env: AAVE_POOL_V3_ADDRESS]
+    #[arg(long)]
+    pub pool_address: Option<String>,
+
+    /// Supplied token address [env: AAVE_SUPPLY_TOKEN_ADDRESS]
+    #[arg(long)]
+    pub supply_token: Option<String>,
+
+    /// Decimals of the supplied token [env: AAVE_SUPPLY_TOKEN_DECIMALS]
+    #[arg(long)]
+    pub supply_token_decimals: Option<u64>,
+
+    /// Borrowed token address [env: AAVE_BORROWED_TOKEN_ADDRESS]
+    #[arg(long)]
+    pub borrowed_token: Option<String>,
+
+    /// Decimals of the borrowed token [env: AAVE_BORROWED_TOKEN_DECIMALS]
+    #[arg(long)]
+    pub borrowed_token_decimals: Option<u64>,
+
+    /// Ethereum HTTP RPC endpoint(s), comma-separated [env: ETHEREUM_RPC_URL]
+    #[arg(long)]
+    pub rpc_url: Option<String>,
+
+    /// Ethereum WebSocket endpoint(s), comma-separated [env: ETHEREUM_WS_URL]
+    #[arg(long)]
+    pub ws_url: Option<String>,
+
+    /// Liquidation threshold overriding the on-chain values [env: LIQUIDATION_THRESHOLD]
+    #[arg(long)]
+    pub liquidation_threshold: Option<f64>,
+
+    /// Seconds before the same alert is sent again [env: ALERT_COOLDOWN_SECS]
+    #[arg(long)]
+    pub alert_cooldown_secs: Option<u64>,
+
+    /// TOML file with settings to use where neither a flag nor the environment
+    /// sets them (see config.example.toml)
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+
+    /// Print the resolved configuration and exit
+    #[arg(long)]
+    pub print_config: bool,
+
+    /// Replay the Pool's events from this block instead of monitoring, starting
+    /// from the configured initial position
+    #[arg(long)]
+    pub replay_from: Option<u64>,
+
+    /// Last block to replay, the chain head by default
+    #[arg(long, requires = "replay_from")]
+    pub replay_to: Option<u64>,
+
+    /// Send the alerts the replay decides on instead of only logging them
+    #[arg(long, requires = "replay_from")]
+    pub replay_send_alerts: bool,
+
+    /// Send a test message through every alert channel on startup and exit with an
+    /// error if any of them fails
+    #[arg(long)]
+    pub test_alerts: bool,
+}
+
+impl Cli {
+    // Environment variable behind each flag, with the flag's value when given
+    fn overrides(&self) -> [(&'static str, Option<String>); 10] {
+        [
+            ("AAVE_USER_ADDRESS_TO_TRACK", self.user_address.clone()),
+            ("AAVE_POOL_V3_ADDRESS", self.pool_address.clone()),
+            ("AAVE_SUPPLY_TOKEN_ADDRESS", self.supply_token.clone()),
+            (
+                "AAVE_SUPPLY_TOKEN_DECIMALS",
+                self.supply_token_decimals.map(|d| d.to_string()),
+            ),
+            ("AAVE_BORROWED_TOKEN_ADDRESS", self.borrowed_token.clone()),
+            (
+                "AAVE_BORROWED_TOKEN_DECIMALS",
+                self.borrowed_token_decimals.map(|d| d.to_string()),
+            ),
+            ("ETHEREUM_RPC_URL", self.rpc_url.clone()),
+            ("ETHEREUM_WS_URL", self.ws_url.clone()),
+            (
+                "LIQUIDATION_THRESHOLD",
+                self.liquidation_threshold.map(|t| t.to_string()),
+            ),
+            (
+                "ALERT_COOLDOWN_SECS",
+                self.alert_cooldown_secs.map(|s| s.to_string()),
+            ),
+        ]
+    }
+
+    /// Set the environment variable of every flag that was given. Must run before
+    /// any task reads the configuration.
+    pub fn apply(&self) {
+        for (var, value) in self.overrides() {
+            if let Some(value) = value {
+                env::set_var(var, value);
+            }
+        }
+    }
+}
@@ -0,0 +1,280 @@
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use teloxide::prelude::*;
+
+use crate::alerts::{configured_alerters, send_test_alert, test_alert_report};
+use crate::chains::ethereum::ethereum_chain::ChainState;
+use crate::chains::{
+    enabled_chains, get_alert_pause_default_mins, get_telegram_bot_token, get_telegram_chat_id,
+    get_user_addresses_to_track,
+};
+use crate::{calculate_health_factor, calculate_net_usd, format_quote, reserve_value_in_usd};
+
+/// Whether risk alerts are currently delivered. `/pause` silences them until a
+/// deadline; the health-factor loop keeps checking and tracking state meanwhile.
+pub struct AlertPause {
+    paused_until: Mutex<Option<Instant>>,
+}
+
+impl AlertPause {
+    pub fn new() -> Self {
+        Self {
+            paused_until: Mutex::new(None),
+        }
+    }
+
+    pub fn pause(&self, now: Instant, duration: Duration) {
+        if let Ok(mut paused_until) = self.paused_until.lock() {
+            *paused_until = Some(now + duration);
+        }
+    }
+
+    pub fn resume(&self) {
+        if let Ok(mut paused_until) = self.paused_until.lock() {
+            *paused_until = None;
+        }
+    }
+
+    /// Time left before alerts resume on their own, `None` when not paused
+    pub fn remaining(&self, now: Instant) -> Option<Duration> {
+        let mut paused_until = self.paused_until.lock().ok()?;
+        match *paused_until {
+            Some(until) if until > now => Some(until - now),
+            Some(_) => {
+                // The pause ran out: resume automatically
+                *paused_until = None;
+                tracing::info!("Alert pause expired, alerts resumed");
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub fn alerts_enabled(&self, now: Instant) -> bool {
+        self.remaining(now).is_none()
+    }
+}
+
+impl Default for AlertPause {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+lazy_static::lazy_static! {
+    /// Shared between the Telegram command handler and every health-factor loop
+    pub static ref ALERT_PAUSE: AlertPause = AlertPause::new();
+}
+
+/// Command accepted from the configured Telegram chat
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// Silence alerts for the given time, or `ALERT_PAUSE_DEFAULT_MINS`
+    Pause(Option<Duration>),
+    Resume,
+    /// Reply with the tracked position and health factor
+    Status,
+    /// Reply with the health factor and alert level only
+    HealthFactor,
+    /// Reply with the tracked amounts and their USD values
+    Position,
+    /// Send a test message through every alert channel and report which delivered
+    TestAlert,
+}
+
+/// Parse `/pause [minutes]`, `/resume`, `/status`, `/healthfactor`, `/position` or
+/// `/testalert`. A `@botname` suffix is
+/// accepted, as Telegram adds it in group chats.
+pub fn parse_command(text: &str) -> Result<Option<Command>, String> {
+    let mut words = text.split_whitespace();
+    let Some(first) = words.next() else {
+        return Ok(None);
+    };
+    let Some(name) = first.strip_prefix('/') else {
+        return Ok(None);
+    };
+    let name = name.split('@').next().unwrap_or_default();
+    let argument = words.next();
+    match name.to_ascii_lowercase().as_str() {
+        "pause" => match argument {
+            None => Ok(Some(Command::Pause(None))),
+            Some(minutes) => match minutes.parse::<u64>() {
+                Ok(minutes) if minutes > 0 => Ok(Some(Command::Pause(Some(Duration::from_secs(
+                    minutes * 60,
+                ))))),
+                _ => Err(format!(
+                    "Invalid pause length '{}', expected a number of minutes",
+                    minutes
+                )),
+            },
+        },
+        "resume" => Ok(Some(Command::Resume)),
+        "status" => Ok(Some(Command::Status)),
+        "healthfactor" => Ok(Some(Command::HealthFactor)),
+        "position" => Ok(Some(Command::Position)),
+        "testalert" => Ok(Some(Command::TestAlert)),
+        _ => Ok(None),
+    }
+}
+
+/// Answer commands from TELEGRAM_CHAT_ID, alongside the listeners and health-factor
+/// loops, until `shutdown` resolves. Does nothing when Telegram is not configured.
+pub async fn serve_commands(shutdown: impl Future<Output = ()>) {
+    let (Some(bot_token), Some(chat_id)) = (get_telegram_bot_token(), get_telegram_chat_id())
+    else {
+        return;
+    };
+    let Ok(chat_id) = chat_id.trim().parse::<i64>() else {
+        tracing::warn!("Invalid TELEGRAM_CHAT_ID, Telegram commands disabled");
+        return;
+    };
+
+    let handler = Update::filter_message()
+        .filter(move |msg: Message| msg.chat.id == ChatId(chat_id))
+        .endpoint(handle_message);
+    let mut dispatcher = Dispatcher::builder(Bot::new(bot_token), handler).build();
+    tracing::info!("Listening for Telegram commands");
+    tokio::select! {
+        _ = dispatcher.dispatch() => {}
+        _ = shutdown => {}
+    }
+}
+
+async fn handle_message(bot: Bot, msg: Message) -> ResponseResult<()> {
+    let reply = match parse_command(msg.text().unwrap_or_default()) {
+        Ok(Some(command)) => run_command(command).await,
+        Ok(None) => return Ok(()),
+        Err(e) => e,
+    };
+    bot.send_message(msg.chat.id, reply).await?;
+    Ok(())
+}
+
+async fn run_command(command: Command) -> String {
+    tracing::info!(command = ?command, "Telegram command received");
+    match command {
+        Command::Pause(duration) => {
+            let duration =
+                duration.unwrap_or(Duration::from_secs(get_alert_pause_default_mins() * 60));
+            ALERT_PAUSE.pause(Instant::now(), duration);
+            format!(
+                "Alerts paused for {} minute(s). Send /resume to turn them back on.",
+                duration.as_secs() / 60
+            )
+        }
+        Command::Resume => {
+            ALERT_PAUSE.resume();
+            "Alerts resumed.".to_string()
+        }
+        Command::Status => status_reply().await,
+        Command::HealthFactor => {
+            let mut lines = Vec::new();
+            for chain in enabled_chains() {
+                lines.push(format!(
+                    "{}: {}",
+                    account_label(chain),
+                    health_factor_line(chain).await
+                ));
+            }
+            lines.join("\n")
+        }
+        Command::Position => {
+            let mut lines = Vec::new();
+            for chain in enabled_chains() {
+                lines.push(format!("{}:", account_label(chain)));
+                lines.extend(position_lines(chain).await);
+            }
+            lines.join("\n")
+        }
+        Command::TestAlert => {
+            let results = send_test_alert(&configured_alerters()).await;
+            test_alert_report(&results).join("\n")
+        }
+    }
+}
+
+// Chain name, followed by the account's address when several accounts are tracked
+fn account_label(chain: &ChainState) -> String {
+    if get_user_addresses_to_track().len() > 1 {
+        format!("{} {}", chain.name, chain.user_address())
+    } else {
+        chain.name.to_string()
+    }
+}
+
+// Same position and prices the alert loop values
+async fn position_lines(chain: &ChainState) -> Vec<String> {
+    let position = match chain.position() {
+        Ok(position) => position,
+        Err(e) => return vec![format!("  Position unavailable: {}", e)],
+    };
+    let mode = chain.account_mode().unwrap_or_default();
+    let mut lines = Vec::new();
+    if let Some(emode) = &mode.emode {
+        lines.push(format!(
+            "  eMode: {} (category {}, liquidation threshold {:.2}%)",
+            emode.label,
+            emode.id,
+            emode.liquidation_threshold * 100.0
+        ));
+    }
+    for (label, balances) in [
+        ("Supplied", &position.supplied),
+        ("Borrowed", &position.borrowed),
+    ] {
+        for (reserve, amount) in balances {
+            let usd = match reserve_value_in_usd(chain, reserve, *amount).await {
+                Ok(usd) => format_quote(usd),
+                Err(e) => format!("price unavailable: {}", e),
+            };
+            let note = if label != "Supplied" {
+                ""
+            } else if !position.is_collateral(reserve) {
+                ", not collateral"
+            } else if mode.isolated_reserves.contains(reserve) {
+                ", isolated collateral"
+            } else {
+                ""
+            };
+            lines.push(format!(
+                "  {} {:?}: {} ({}{})",
+                label, reserve, amount, usd, note
+            ));
+        }
+    }
+    match calculate_net_usd(chain).await {
+        Ok(net_usd) => lines.push(format!("  Net: {}", format_quote(net_usd))),
+        Err(e) => lines.push(format!("  Net: price unavailable: {}", e)),
+    }
+    lines
+}
+
+async fn health_factor_line(chain: &ChainState) -> String {
+    match calculate_health_factor(chain).await {
+        Ok(reading) => format!(
+            "Health factor {:.4} ({:?}), collateral {}, debt {}",
+            reading.health_factor,
+            reading.level,
+            format_quote(reading.supply_in_usd),
+            format_quote(reading.borrowed_in_usd)
+        ),
+        Err(e) => format!("Health factor unavailable: {}", e),
+    }
+}
+
+async fn status_reply() -> String {
+    let mut lines = Vec::new();
+    if let Some(remaining) = ALERT_PAUSE.remaining(Instant::now()) {
+        lines.push(format!(
+            "Alerts paused, resuming in {} minute(s)",
+            remaining.as_secs().div_ceil(60)
+        ));
+    }
+    for chain in enabled_chains() {
+        lines.push(format!("{}:", account_label(chain)));
+        lines.extend(position_lines(chain).await);
+        lines.push(format!("  {}", health_factor_line(chain).await));
+    }
+    lines.join("\n")
+}
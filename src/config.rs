@@ -0,0 +1,285 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::path::Path;
+
+use crate::error::MonitorError;
+
+/// Settings read from the file given with `--config`. Every setting stands for the
+/// environment variable in its comment and is applied by setting that variable when
+/// it isn't already set, so the environment (and `.env`) overrides the file, flags
+/// override both, and every getter in `chains` picks the result up unchanged.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    // AAVE_USER_ADDRESS_TO_TRACK, addresses or ENS names
+    pub user_addresses: Option<Vec<String>>,
+    // AAVE_POOL_VERSION
+    pub pool_version: Option<u8>,
+    // LIQUIDATION_THRESHOLD
+    pub liquidation_threshold: Option<f64>,
+    // HEALTH_CHECK_INTERVAL_SECS
+    pub health_check_interval_secs: Option<u64>,
+    #[serde(default)]
+    pub alerts: AlertsConfig,
+    #[serde(default)]
+    pub chains: ChainsConfig,
+    // Any other setting, by environment variable name
+    #[serde(default)]
+    pub env: BTreeMap<String, toml::Value>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AlertsConfig {
+    // TELEGRAM_BOT_TOKEN
+    pub telegram_bot_token: Option<String>,
+    // TELEGRAM_CHAT_ID
+    pub telegram_chat_id: Option<String>,
+    // DISCORD_WEBHOOK_URL
+    pub discord_webhook_url: Option<String>,
+    // SLACK_WEBHOOK_URL
+    pub slack_webhook_url: Option<String>,
+    // ALERT_WEBHOOK_URL
+    pub webhook_url: Option<String>,
+    // ALERT_WEBHOOK_BEARER_TOKEN
+    pub webhook_bearer_token: Option<String>,
+    // ALERT_WEBHOOK_SECRET
+    pub webhook_secret: Option<String>,
+    // ALERT_COOLDOWN_SECS
+    pub cooldown_secs: Option<u64>,
+    // ALERT_TIER_WARN_LTV, ALERT_TIER_DANGER_LTV and ALERT_TIER_CRITICAL_LTV
+    pub warn_ltv: Option<f64>,
+    pub danger_ltv: Option<f64>,
+    pub critical_ltv: Option<f64>,
+    // MIN_POSITION_USD
+    pub min_position_usd: Option<f64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChainsConfig {
+    pub ethereum: Option<ChainConfig>,
+    pub polygon: Option<ChainConfig>,
+    pub arbitrum: Option<ChainConfig>,
+    pub optimism: Option<ChainConfig>,
+    pub base: Option<ChainConfig>,
+}
+
+/// One chain's `<CHAIN>_*` settings. On Ethereum the pool, positions and files keep
+/// their historical names (`AAVE_POOL_V3_ADDRESS`, `AAVE_SUPPLY_POSITIONS`, ...)
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChainConfig {
+    pub enabled: Option<bool>,
+    // Tried in order, like a comma-separated `<CHAIN>_RPC_URL`
+    pub rpc_urls: Option<Vec<String>>,
+    pub ws_urls: Option<Vec<String>>,
+    pub pool_address: Option<String>,
+    pub supply: Option<Vec<AssetEntry>>,
+    pub borrowed: Option<Vec<AssetEntry>>,
+    pub position_file: Option<String>,
+    pub last_processed_block_file: Option<String>,
+}
+
+/// A tracked reserve, written to the `address:decimals:initial_amount` lists
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AssetEntry {
+    pub address: String,
+    pub decimals: u64,
+    // A string, as raw token amounts outgrow TOML integers
+    #[serde(default = "zero_amount")]
+    pub initial_amount: String,
+}
+
+fn zero_amount() -> String {
+    "0".to_string()
+}
+
+fn asset_list(assets: &[AssetEntry]) -> String {
+    assets
+        .iter()
+        .map(|asset| {
+            format!(
+                "{}:{}:{}",
+                asset.address, asset.decimals, asset.initial_amount
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+impl ChainConfig {
+    // `prefix` names the chain's RPC, WS and ENABLED variables, `positions_prefix`
+    // its asset lists and `file_prefix` its files
+    fn env_vars(
+        &self,
+        prefix: &str,
+        pool_var: &str,
+        positions_prefix: &str,
+        file_prefix: &str,
+        vars: &mut Vec<(String, String)>,
+    ) {
+        let mut push = |name: String, value: Option<String>| {
+            if let Some(value) = value {
+                vars.push((name, value));
+            }
+        };
+        push(
+            format!("{}_ENABLED", prefix),
+            self.enabled.map(|enabled| enabled.to_string()),
+        );
+        push(
+            format!("{}_RPC_URL", prefix),
+            self.rpc_urls.as_ref().map(|urls| urls.join(",")),
+        );
+        push(
+            format!("{}_WS_URL", prefix),
+            self.ws_urls.as_ref().map(|urls| urls.join(",")),
+        );
+        push(pool_var.to_string(), self.pool_address.clone());
+        push(
+            format!("{}_SUPPLY_POSITIONS", positions_prefix),
+            self.supply.as_deref().map(asset_list),
+        );
+        push(
+            format!("{}_BORROWED_POSITIONS", positions_prefix),
+            self.borrowed.as_deref().map(asset_list),
+        );
+        push(
+            format!("{}POSITION_FILE", file_prefix),
+            self.position_file.clone(),
+        );
+        push(
+            format!("{}LAST_PROCESSED_BLOCK_FILE", file_prefix),
+            self.last_processed_block_file.clone(),
+        );
+    }
+}
+
+impl Config {
+    pub fn parse(raw: &str) -> Result<Self, MonitorError> {
+        toml::from_str(raw).map_err(|e| MonitorError::Config(format!("Invalid config file: {}", e)))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, MonitorError> {
+        let raw = std::fs::read_to_string(path).map_err(|e| {
+            MonitorError::Config(format!(
+                "Failed to read config file {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+        Self::parse(&raw)
+    }
+
+    /// Environment variable and value of every setting present in the file
+    pub fn env_vars(&self) -> Result<Vec<(String, String)>, MonitorError> {
+        let mut vars = Vec::new();
+        let mut push = |name: &str, value: Option<String>| {
+            if let Some(value) = value {
+                vars.push((name.to_string(), value));
+            }
+        };
+        push(
+            "AAVE_USER_ADDRESS_TO_TRACK",
+            self.user_addresses
+                .as_ref()
+                .map(|accounts| accounts.join(",")),
+        );
+        push(
+            "AAVE_POOL_VERSION",
+            self.pool_version.map(|v| v.to_string()),
+        );
+        push(
+            "LIQUIDATION_THRESHOLD",
+            self.liquidation_threshold.map(|t| t.to_string()),
+        );
+        push(
+            "HEALTH_CHECK_INTERVAL_SECS",
+            self.health_check_interval_secs.map(|s| s.to_string()),
+        );
+        let alerts = &self.alerts;
+        push("TELEGRAM_BOT_TOKEN", alerts.telegram_bot_token.clone());
+        push("TELEGRAM_CHAT_ID", alerts.telegram_chat_id.clone());
+        push("DISCORD_WEBHOOK_URL", alerts.discord_webhook_url.clone());
+        push("SLACK_WEBHOOK_URL", alerts.slack_webhook_url.clone());
+        push("ALERT_WEBHOOK_URL", alerts.webhook_url.clone());
+        push(
+            "ALERT_WEBHOOK_BEARER_TOKEN",
+            alerts.webhook_bearer_token.clone(),
+        );
+        push("ALERT_WEBHOOK_SECRET", alerts.webhook_secret.clone());
+        push(
+            "ALERT_COOLDOWN_SECS",
+            alerts.cooldown_secs.map(|s| s.to_string()),
+        );
+        push(
+            "ALERT_TIER_WARN_LTV",
+            alerts.warn_ltv.map(|v| v.to_string()),
+        );
+        push(
+            "ALERT_TIER_DANGER_LTV",
+            alerts.danger_ltv.map(|v| v.to_string()),
+        );
+        push(
+            "ALERT_TIER_CRITICAL_LTV",
+            alerts.critical_ltv.map(|v| v.to_string()),
+        );
+        push(
+            "MIN_POSITION_USD",
+            alerts.min_position_usd.map(|v| v.to_string()),
+        );
+
+        let chains = &self.chains;
+        if let Some(chain) = &chains.ethereum {
+            chain.env_vars("ETHEREUM", "AAVE_POOL_V3_ADDRESS", "AAVE", "", &mut vars);
+        }
+        for (chain, prefix) in [
+            (&chains.polygon, "POLYGON"),
+            (&chains.arbitrum, "ARBITRUM"),
+            (&chains.optimism, "OPTIMISM"),
+            (&chains.base, "BASE"),
+        ] {
+            let Some(chain) = chain else {
+                continue;
+            };
+            chain.env_vars(
+                prefix,
+                &format!("{}_AAVE_POOL_V3_ADDRESS", prefix),
+                prefix,
+                &format!("{}_", prefix),
+                &mut vars,
+            );
+        }
+
+        for (name, value) in &self.env {
+            let value = match value {
+                toml::Value::String(value) => value.clone(),
+                toml::Value::Integer(_) | toml::Value::Float(_) | toml::Value::Boolean(_) => {
+                    value.to_string()
+                }
+                _ => {
+                    return Err(MonitorError::Config(format!(
+                        "Config setting env.{} must be a string, number or boolean",
+                        name
+                    )))
+                }
+            };
+            vars.push((name.clone(), value));
+        }
+        Ok(vars)
+    }
+
+    /// Set every variable from the file that isn't set yet. Must run before any task
+    /// reads the configuration.
+    pub fn apply(&self) -> Result<(), MonitorError> {
+        for (name, value) in self.env_vars()? {
+            if env::var_os(&name).is_none() {
+                env::set_var(name, value);
+            }
+        }
+        Ok(())
+    }
+}
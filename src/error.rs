@@ -0,0 +1,90 @@
+use ethers::prelude::{ContractError, Middleware, ProviderError, WsClientError};
+use std::sync::PoisonError;
+use thiserror::Error;
+
+/// Errors raised while monitoring a position, grouped by what went wrong so
+/// callers can tell transient failures (worth retrying) from fatal ones.
+#[derive(Debug, Error)]
+pub enum MonitorError {
+    #[error("RPC error: {0}")]
+    Rpc(String),
+    #[error("Decode error: {0}")]
+    Decode(String),
+    #[error("Configuration error: {0}")]
+    Config(String),
+    #[error("Price error: {0}")]
+    Price(String),
+    #[error("Failed to acquire lock: {0}")]
+    Lock(String),
+    #[error("Telegram error: {0}")]
+    Telegram(String),
+    #[error("Webhook error: {0}")]
+    Webhook(String),
+    #[error("Storage error: {0}")]
+    Storage(String),
+    #[error("HTTP server error: {0}")]
+    Http(String),
+    #[error("Publish error: {0}")]
+    Publish(String),
+}
+
+impl MonitorError {
+    /// Misconfiguration can't be fixed by retrying (e.g. reconnecting the listener)
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, MonitorError::Config(_))
+    }
+}
+
+impl From<ProviderError> for MonitorError {
+    fn from(e: ProviderError) -> Self {
+        MonitorError::Rpc(e.to_string())
+    }
+}
+
+impl From<WsClientError> for MonitorError {
+    fn from(e: WsClientError) -> Self {
+        MonitorError::Rpc(e.to_string())
+    }
+}
+
+impl<M: Middleware> From<ContractError<M>> for MonitorError {
+    fn from(e: ContractError<M>) -> Self {
+        MonitorError::Rpc(e.to_string())
+    }
+}
+
+impl From<alloy_sol_types::Error> for MonitorError {
+    fn from(e: alloy_sol_types::Error) -> Self {
+        MonitorError::Decode(e.to_string())
+    }
+}
+
+impl From<reqwest::Error> for MonitorError {
+    fn from(e: reqwest::Error) -> Self {
+        MonitorError::Price(e.to_string())
+    }
+}
+
+impl From<teloxide::RequestError> for MonitorError {
+    fn from(e: teloxide::RequestError) -> Self {
+        MonitorError::Telegram(e.to_string())
+    }
+}
+
+impl From<std::io::Error> for MonitorError {
+    fn from(e: std::io::Error) -> Self {
+        MonitorError::Storage(e.to_string())
+    }
+}
+
+impl From<serde_json::Error> for MonitorError {
+    fn from(e: serde_json::Error) -> Self {
+        MonitorError::Decode(e.to_string())
+    }
+}
+
+impl<T> From<PoisonError<T>> for MonitorError {
+    fn from(e: PoisonError<T>) -> Self {
+        MonitorError::Lock(e.to_string())
+    }
+}
@@ -0,0 +1,191 @@
+use reqwest::{Client, Url};
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::Notify;
+
+use crate::chains::{
+    get_influx_batch_size, get_influx_bucket, get_influx_flush_interval_secs, get_influx_org,
+    get_influx_token, get_influx_url,
+};
+use crate::error::MonitorError;
+
+// A database slower than this drops the batch rather than holding up the next one
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Points kept while the database is unreachable; the oldest are dropped beyond this
+const MAX_BUFFERED_POINTS: usize = 10_000;
+
+/// Where health-factor points are written: an InfluxDB 2 `/api/v2/write` endpoint
+pub struct InfluxExporter {
+    write_url: Url,
+    token: Option<String>,
+    batch_size: usize,
+    points: Mutex<Vec<String>>,
+    // Woken when a full batch is waiting
+    batch_ready: Notify,
+}
+
+impl InfluxExporter {
+    /// Exporter for INFLUX_URL, `None` when it is unset or invalid
+    pub fn configured() -> Option<Self> {
+        let raw_url = get_influx_url()?;
+        match influx_write_url(&raw_url, &get_influx_org(), &get_influx_bucket()) {
+            Ok(write_url) => Some(Self {
+                write_url,
+                token: get_influx_token(),
+                batch_size: get_influx_batch_size().max(1),
+                points: Mutex::new(Vec::new()),
+                batch_ready: Notify::new(),
+            }),
+            Err(e) => {
+                tracing::warn!("Ignoring INFLUX_URL: {}", e);
+                None
+            }
+        }
+    }
+
+    fn push(&self, lines: Vec<String>) {
+        let Ok(mut points) = self.points.lock() else {
+            return;
+        };
+        points.extend(lines);
+        if points.len() > MAX_BUFFERED_POINTS {
+            let dropped = points.len() - MAX_BUFFERED_POINTS;
+            points.drain(..dropped);
+        }
+        if points.len() >= self.batch_size {
+            self.batch_ready.notify_one();
+        }
+    }
+
+    fn take(&self) -> Vec<String> {
+        self.points
+            .lock()
+            .map(|mut points| std::mem::take(&mut *points))
+            .unwrap_or_default()
+    }
+
+    async fn write(&self, points: &[String]) -> Result<(), MonitorError> {
+        let mut request = Client::new()
+            .post(self.write_url.clone())
+            .timeout(WRITE_TIMEOUT)
+            .body(points.join("\n"));
+        if let Some(token) = &self.token {
+            request = request.header(reqwest::header::AUTHORIZATION, format!("Token {}", token));
+        }
+        request
+            .send()
+            .await
+            .map_err(|e| MonitorError::Publish(format!("Failed to write to InfluxDB: {}", e)))?
+            .error_for_status()
+            .map_err(|e| MonitorError::Publish(format!("InfluxDB rejected points: {}", e)))?;
+        Ok(())
+    }
+
+    async fn flush(&self) {
+        let points = self.take();
+        if points.is_empty() {
+            return;
+        }
+        if let Err(e) = self.write(&points).await {
+            tracing::warn!(points = points.len(), "Dropping InfluxDB batch: {}", e);
+        }
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref INFLUX: Option<InfluxExporter> = InfluxExporter::configured();
+}
+
+/// `/api/v2/write` URL of the InfluxDB at `base_url`, with second precision
+pub fn influx_write_url(base_url: &str, org: &str, bucket: &str) -> Result<Url, MonitorError> {
+    let mut url = Url::parse(base_url.trim())
+        .and_then(|url| url.join("api/v2/write"))
+        .map_err(|e| MonitorError::Config(format!("Invalid INFLUX_URL: {}", e)))?;
+    url.query_pairs_mut()
+        .append_pair("org", org)
+        .append_pair("bucket", bucket)
+        .append_pair("precision", "s");
+    Ok(url)
+}
+
+// Tag values escape commas, equals signs and spaces
+fn escape_tag(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(',', "\\,")
+        .replace('=', "\\=")
+        .replace(' ', "\\ ")
+}
+
+/// Line-protocol points for one health-factor check. Without debt the health factor
+/// is infinite, which line protocol can't carry, so only the USD values are written.
+pub fn health_factor_points(
+    chain: &str,
+    user: &str,
+    health_factor: f64,
+    collateral_usd: f64,
+    debt_usd: f64,
+    timestamp: u64,
+) -> Vec<String> {
+    let tags = format!("chain={},user={}", escape_tag(chain), escape_tag(user));
+    [
+        ("aave_health_factor", health_factor),
+        ("aave_collateral_usd", collateral_usd),
+        ("aave_debt_usd", debt_usd),
+    ]
+    .into_iter()
+    .filter(|(_, value)| value.is_finite())
+    .map(|(measurement, value)| format!("{},{} value={} {}", measurement, tags, value, timestamp))
+    .collect()
+}
+
+/// Queue a health-factor check for InfluxDB. Never waits: points are written in
+/// batches by `export_to_influx`, so a slow database can't hold up alerts.
+pub fn record_influx_points(
+    chain: &str,
+    user: &str,
+    health_factor: f64,
+    collateral_usd: f64,
+    debt_usd: f64,
+) {
+    let Some(exporter) = INFLUX.as_ref() else {
+        return;
+    };
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+    exporter.push(health_factor_points(
+        chain,
+        user,
+        health_factor,
+        collateral_usd,
+        debt_usd,
+        timestamp,
+    ));
+}
+
+/// Write queued points every INFLUX_FLUSH_INTERVAL_SECS, or as soon as a batch of
+/// INFLUX_BATCH_SIZE is waiting, until `shutdown` resolves; what is left is written
+/// then. Does nothing when INFLUX_URL is not set.
+pub async fn export_to_influx(shutdown: impl Future<Output = ()>) {
+    let Some(exporter) = INFLUX.as_ref() else {
+        return;
+    };
+    tracing::info!(url = %exporter.write_url, "Exporting health factors to InfluxDB");
+    let interval = Duration::from_secs(get_influx_flush_interval_secs().max(1));
+    tokio::pin!(shutdown);
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            _ = exporter.batch_ready.notified() => {}
+            _ = &mut shutdown => {
+                exporter.flush().await;
+                return;
+            }
+        }
+        exporter.flush().await;
+    }
+}
@@ -1,190 +1,2689 @@
+mod alerts;
+mod api;
 mod chains;
+mod cli;
+mod commands;
+mod config;
+mod error;
+mod influx;
+mod metrics;
+mod prices;
+mod probes;
+mod publish;
+mod ratelimit;
+mod replay;
+mod stats;
 
-use ethereum::ethereum_chain::{ethereum_listening, get_position_data};
-use reqwest::{Client, Url};
+use alerts::{
+    configured_alerters, send_test_alert, send_to_all, test_alert_report, AlertMessage,
+    AlertPosition, AlertSeverity, AlertToken, Alerter,
+};
+use ethereum::ethereum_chain::{
+    account_states, get_user_account_data_ethereum, ChainState, HealthFactorSample,
+    LargeEventNotice, LiquidationNotice, OnChainAccountData, ETHEREUM, ETHEREUM_RPC_ENDPOINTS,
+};
+use ethers::prelude::{Address, U256};
+use reqwest::{header, Client, StatusCode, Url};
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, env};
-use teloxide::{
-    prelude::*,
-    types::{ParseMode, Recipient},
+use solana::solana_chain::{
+    listen_solana, poll_solana_balances, solana_fungible_id, SolanaReserve, SolanaState, SOLANA,
+};
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
+use tokio::sync::watch;
+use tracing::{debug, error, info, warn};
+use tracing_subscriber::EnvFilter;
 
+use crate::api::serve_api;
 use crate::chains::*;
+use crate::cli::Cli;
+use crate::commands::{serve_commands, ALERT_PAUSE};
+use crate::config::Config;
+use crate::error::MonitorError;
+use crate::influx::{export_to_influx, record_influx_points};
+use crate::metrics::{record_health_factor, record_liquidation_prices, serve_metrics};
+use crate::prices::{
+    configured_price_sources, fetch_price_from_sources, quote_rate, refresh_quote_rate, PriceSource,
+};
+use crate::publish::publish_position;
+use crate::ratelimit::RateLimiter;
+use crate::stats::log_stats_periodically;
+use clap::Parser;
 #[cfg(test)]
 mod tests;
 
-async fn display_position_status() {
-    match get_position_data() {
-        Ok(position) => {
-            println!("Current Position Status:");
-            println!("  Supplied Amount: {}", position.supplied_amount);
-            println!("  Borrowed Amount: {}", position.borrowed_amount);
-        }
-        Err(e) => eprintln!("Failed to get position data: {}", e),
+async fn display_position_status(chain: &ChainState) {
+    match chain.position() {
+        Ok(position) => {
+            for (reserve, amount) in &position.supplied {
+                info!(
+                    chain = chain.name,
+                    account = %chain.user_address(),
+                    reserve = ?reserve,
+                    amount = %amount,
+                    "Supplied amount"
+                );
+            }
+            for (reserve, amount) in &position.borrowed {
+                info!(
+                    chain = chain.name,
+                    account = %chain.user_address(),
+                    reserve = ?reserve,
+                    amount = %amount,
+                    variable = %position.variable_borrowed_amount(reserve),
+                    stable = %position.stable_borrowed_amount(reserve),
+                    "Borrowed amount"
+                );
+            }
+        }
+        Err(e) => error!(chain = chain.name, "Failed to get position data: {}", e),
+    }
+}
+
+/// Install the global subscriber: RUST_LOG picks the level (default `info`) and
+/// LOG_FORMAT=json switches to one JSON object per line for log aggregators.
+/// Records from crates logging through `log` are forwarded too.
+fn init_logging() {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let subscriber = tracing_subscriber::fmt().with_env_filter(filter);
+    match get_log_format() {
+        LogFormat::Json => subscriber.json().init(),
+        LogFormat::Text => subscriber.init(),
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    //this liquidator tracker keeps one amount per reserve on the supply side and on the borrowed side
+
+    //for example user supply USDT and WETH and borrowed wBTC
+    //the health factor sums the USD value of all collateral against the USD value of all debt.
+    //When this bot starts it initializes the supplied and borrowed amount of user from environment variables.
+    //The bot listens to the events from the Aave protocol and updates the supplied and borrowed, repays or withdraws to update the position that effect the health factor.
+
+    dotenv::dotenv().ok();
+    // Command-line flags override the environment, including the .env file
+    let cli = Cli::parse();
+    cli.apply();
+    // The config file only fills in what neither a flag nor the environment set
+    if let Some(path) = &cli.config {
+        if let Err(e) = Config::load(path).and_then(|config| config.apply()) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+    if cli.print_config {
+        for line in configuration_summary() {
+            println!("{}", line);
+        }
+        return;
+    }
+    init_logging();
+
+    if cli.test_alerts {
+        // Surface a bad token, chat id or webhook now rather than during a liquidation
+        let results = send_test_alert(&configured_alerters()).await;
+        for line in test_alert_report(&results) {
+            info!("Test alert: {}", line);
+        }
+        if results.is_empty() || results.iter().any(|(_, result)| result.is_err()) {
+            error!("Alert self-test failed");
+            std::process::exit(1);
+        }
+    }
+
+    if let Some(from_block) = cli.replay_from {
+        // Dry run over past blocks: the live state and its files are left untouched
+        let alerters = configured_alerters();
+        match replay::replay_blocks(from_block, cli.replay_to, &alerters, cli.replay_send_alerts)
+            .await
+        {
+            Ok(_) => return,
+            Err(e) => {
+                error!("Replay failed: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let restored = match init_system().await {
+        Ok(restored) => restored,
+        Err(e) => {
+            error!("Failed to initialize: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if restored {
+        // The saved per-reserve amounts are more precise than the on-chain totals
+        info!(source = %get_position_file(), "Initial position restored");
+    } else if get_ethereum_enabled() {
+        // Prefer the authoritative on-chain position over the configured initial amounts
+        match initial_position_from_chain().await {
+            Ok(position) => match ETHEREUM.set_position(position) {
+                Ok(_) => info!(
+                    source = "getUserAccountData",
+                    "Initial position read on-chain"
+                ),
+                Err(e) => error!("Failed to store on-chain position: {}", e),
+            },
+            Err(e) => {
+                warn!(
+                    source = "environment",
+                    "Failed to read initial position on-chain, using configured amounts: {}", e
+                );
+            }
+        }
+    }
+
+    // Log initial configuration
+    for line in configuration_summary() {
+        info!("{}", line);
+    }
+
+    // Display initial position status
+    for chain in enabled_chains() {
+        display_position_status(chain).await;
+    }
+
+    // Flipped to true on ctrl_c; the listeners, health-factor loops and servers
+    // watch it to stop taking new work
+    let (shutdown, shutdown_signal) = watch::channel(false);
+    // Tasks awaited on shutdown before the final save
+    let mut stopping = Vec::new();
+
+    // Each chain has its own listener, reconnected independently
+    for chain in configured_chains() {
+        stopping.push(tokio::spawn(run_listener(
+            Arc::from(chain),
+            shutdown_signal.clone(),
+        )));
+    }
+
+    // Every configured notification channel receives every alert
+    let alerters: Arc<Vec<Box<dyn Alerter>>> = Arc::new(configured_alerters());
+
+    // Spawn a task per chain to periodically check if health factor is in liquidation range
+    for chain in enabled_chains() {
+        stopping.push(tokio::spawn(monitor_health_factor(
+            chain,
+            Arc::clone(&alerters),
+            shutdown_signal.clone(),
+        )));
+    }
+
+    // Solana is tracked apart from the Aave chains: its listener wakes the balance
+    // reads, which feed its own health-factor check
+    if get_solana_enabled() {
+        let mut listener_shutdown = shutdown_signal.clone();
+        stopping.push(tokio::spawn(async move {
+            listen_solana(shutdown_signalled(&mut listener_shutdown)).await
+        }));
+        let mut balances_shutdown = shutdown_signal.clone();
+        stopping.push(tokio::spawn(async move {
+            poll_solana_balances(shutdown_signalled(&mut balances_shutdown)).await
+        }));
+        stopping.push(tokio::spawn(monitor_solana_health_factor(
+            Arc::clone(&alerters),
+            shutdown_signal.clone(),
+        )));
+    }
+
+    // Spawn a task per chain that keeps the amounts current between events: either by
+    // accruing interest onto them, or by reading the token balances outright
+    for chain in configured_chains() {
+        match get_position_source() {
+            PositionSource::Events => tokio::spawn(accrue_interest_periodically(chain)),
+            PositionSource::Balances => tokio::spawn(poll_balances(chain)),
+        };
+    }
+
+    // Spawn a task per chain that reads each collateral's liquidation threshold from
+    // the Pool, unless LIQUIDATION_THRESHOLD overrides them
+    for chain in configured_chains() {
+        tokio::spawn(refresh_liquidation_thresholds_periodically(chain));
+    }
+
+    // Spawn a task that periodically overwrites every tracked position with on-chain values
+    tokio::spawn(async {
+        let interval_secs = get_resync_interval_secs();
+        if interval_secs == 0 {
+            return;
+        }
+        loop {
+            tokio::time::sleep(Duration::from_secs(interval_secs)).await;
+            resync_positions_from_chain().await;
+        }
+    });
+
+    // Spawn a task per chain and account that resyncs from token balances as soon as
+    // an event shows the tracked position has drifted
+    for chain in configured_chains() {
+        let chain: Arc<dyn Chain> = Arc::from(chain);
+        for state in chain.accounts() {
+            let chain = Arc::clone(&chain);
+            tokio::spawn(async move {
+                loop {
+                    state.resync_requested().await;
+                    match chain.refresh_account_balances(state).await {
+                        Ok(_) => info!(
+                            chain = chain.name(),
+                            account = %state.user_address(),
+                            "Position resynced from token balances"
+                        ),
+                        Err(e) => error!(
+                            chain = chain.name(),
+                            account = %state.user_address(),
+                            "Position resync failed: {}", e
+                        ),
+                    }
+                }
+            });
+        }
+    }
+
+    // Spawn a task that periodically cross-checks our health factor against Aave's own
+    let self_test_alerters = Arc::clone(&alerters);
+    tokio::spawn(async move {
+        let interval_mins = get_self_test_interval_mins();
+        if interval_mins == 0 || !get_ethereum_enabled() {
+            return;
+        }
+        loop {
+            tokio::time::sleep(Duration::from_secs(interval_mins * 60)).await;
+            if let Err(e) = run_health_factor_self_test(&self_test_alerters).await {
+                error!("Health factor self-test failed: {}", e);
+            }
+        }
+    });
+
+    // Periodically drop block history older than reorg handling needs
+    tokio::spawn(async {
+        loop {
+            tokio::time::sleep(HISTORY_PRUNE_INTERVAL).await;
+            prune_block_history();
+        }
+    });
+
+    // Periodically write the position as of the last processed block to disk
+    tokio::spawn(async {
+        let interval_secs = get_position_persist_interval_secs();
+        if interval_secs == 0 {
+            return;
+        }
+        loop {
+            tokio::time::sleep(Duration::from_secs(interval_secs)).await;
+            persist_positions();
+        }
+    });
+
+    // Log a heartbeat with the work done since the last one
+    tokio::spawn(async {
+        let interval_secs = get_stats_interval_secs();
+        if interval_secs == 0 {
+            return;
+        }
+        log_stats_periodically(Duration::from_secs(interval_secs)).await;
+    });
+
+    // Answer Telegram commands (/pause, /resume, /status) until shutdown
+    let mut commands_shutdown = shutdown_signal.clone();
+    stopping.push(tokio::spawn(async move {
+        serve_commands(shutdown_signalled(&mut commands_shutdown)).await
+    }));
+
+    // Serve Prometheus metrics until shutdown
+    let mut metrics_shutdown = shutdown_signal.clone();
+    stopping.push(tokio::spawn(async move {
+        let port = get_metrics_port();
+        if port == 0 {
+            return;
+        }
+        if let Err(e) = serve_metrics(port, shutdown_signalled(&mut metrics_shutdown)).await {
+            error!("Metrics server stopped: {}", e);
+        }
+    }));
+
+    // Push health-factor checks to InfluxDB in batches, flushing what is left on shutdown
+    let mut influx_shutdown = shutdown_signal.clone();
+    stopping.push(tokio::spawn(async move {
+        export_to_influx(shutdown_signalled(&mut influx_shutdown)).await
+    }));
+
+    // Serve the read-only position API until shutdown
+    let mut api_shutdown = shutdown_signal.clone();
+    stopping.push(tokio::spawn(async move {
+        let Some(addr) = get_api_address() else {
+            return;
+        };
+        if let Err(e) = serve_api(addr, shutdown_signalled(&mut api_shutdown)).await {
+            error!("Position API stopped: {}", e);
+        }
+    }));
+
+    // Every loop is spawned, so /healthz can report the bot as live
+    probes::mark_main_loops_running();
+
+    tokio::signal::ctrl_c()
+        .await
+        .expect("Failed to listen for ctrl_c signal");
+
+    // Stop taking work, let in-flight blocks, scrapes and requests finish, then save
+    info!("Shutting down...");
+    let _ = shutdown.send(true);
+    let timeout = Duration::from_secs(get_shutdown_timeout_secs());
+    let stopped = tokio::time::timeout(timeout, futures::future::join_all(stopping)).await;
+    persist_positions();
+    if stopped.is_err() {
+        error!(
+            "Tasks still running after {:?}, forcing exit with the last saved state",
+            timeout
+        );
+        std::process::exit(1);
+    }
+}
+
+/// Resolve once shutdown has been signalled
+async fn shutdown_signalled(shutdown: &mut watch::Receiver<bool>) {
+    // An error means main is gone, which is a shutdown too
+    let _ = shutdown.wait_for(|stop| *stop).await;
+}
+
+/// Keep one chain's listener running, reconnecting with exponential backoff
+/// until it fails with an error that retrying can't fix
+async fn run_listener(chain: Arc<dyn Chain>, mut shutdown: watch::Receiver<bool>) {
+    let name = chain.name();
+    let mut backoff = ReconnectBackoff::new(
+        Duration::from_secs(1),
+        Duration::from_secs(60),
+        Duration::from_secs(60),
+    );
+    loop {
+        let connected_at = Instant::now();
+        let listening = Arc::clone(&chain);
+        let mut handle0 = tokio::spawn(async move { listening.listen().await });
+        let result = tokio::select! {
+            result = &mut handle0 => result,
+            _ = shutdown_signalled(&mut shutdown) => {
+                // State is only persisted at block boundaries, so a block cut short
+                // here is replayed from the last processed block on restart
+                handle0.abort();
+                info!(chain = name, "Listener stopped");
+                return;
+            }
+        };
+        match result {
+            Ok(Ok(_)) => info!(chain = name, "Listening finished"),
+            Ok(Err(e)) if e.is_fatal() => {
+                error!(chain = name, "Listening stopped, not retrying: {}", e);
+                break;
+            }
+            Ok(Err(e)) => warn!(chain = name, "Listening failed with error: {}", e),
+            Err(join_err) => {
+                if join_err.is_panic() {
+                    error!(chain = name, "Listener task panicked! Restarting...");
+                } else {
+                    error!(
+                        chain = name,
+                        "Listener task failed unexpectedly: {:?}", join_err
+                    );
+                }
+            }
+        }
+        let uptime = connected_at.elapsed();
+        let delay = backoff.next_delay(uptime);
+        info!(
+            chain = name,
+            "Reconnecting listener in {:?} (last connection lasted {:?})", delay, uptime
+        );
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = shutdown_signalled(&mut shutdown) => return,
+        }
+    }
+}
+
+/// Grow a chain's position by the interest Aave accrues between events, so a
+/// loan left untouched still shows its growing debt
+async fn accrue_interest_periodically(chain: Box<dyn Chain>) {
+    let interval_secs = get_interest_accrual_interval_secs();
+    if interval_secs == 0 {
+        return;
+    }
+    loop {
+        if let Err(e) = chain.accrue_interest().await {
+            error!(chain = chain.name(), "Failed to accrue interest: {}", e);
+        }
+        tokio::time::sleep(Duration::from_secs(interval_secs)).await;
+    }
+}
+
+/// Keep a chain's per-reserve liquidation thresholds current, as governance can
+/// change them. Skipped when LIQUIDATION_THRESHOLD overrides the on-chain values.
+async fn refresh_liquidation_thresholds_periodically(chain: Box<dyn Chain>) {
+    let interval_secs = get_liquidation_threshold_refresh_secs();
+    if interval_secs == 0 || get_liquidation_threshold_override().is_some() {
+        return;
+    }
+    loop {
+        if let Err(e) = chain.refresh_liquidation_thresholds().await {
+            error!(
+                chain = chain.name(),
+                "Failed to refresh liquidation thresholds: {}", e
+            );
+        }
+        tokio::time::sleep(Duration::from_secs(interval_secs)).await;
+    }
+}
+
+/// Refresh a chain's positions from token balances on an interval and whenever
+/// a tracked event arrives for any of its accounts
+async fn poll_balances(chain: Box<dyn Chain>) {
+    let interval = Duration::from_secs(get_balance_poll_interval_secs().max(1));
+    let accounts = chain.accounts();
+    loop {
+        if let Err(e) = chain.refresh_balances().await {
+            error!(chain = chain.name(), "Failed to refresh balances: {}", e);
+        }
+        let refresh_requested = futures::future::select_all(
+            accounts
+                .iter()
+                .map(|state| Box::pin(state.balance_refresh_requested())),
+        );
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            _ = refresh_requested => {}
+        }
+    }
+}
+
+/// Check one chain's health factor every `HEALTH_CHECK_INTERVAL_SECS` and raise alerts.
+/// Warm-up, confirmation, cooldown and digest state are kept per chain.
+async fn monitor_health_factor(
+    chain: &'static ChainState,
+    alerters: Arc<Vec<Box<dyn Alerter>>>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let check_interval = Duration::from_secs(get_health_check_interval_secs().max(1));
+    let mut check = HealthCheck::from_config(Instant::now());
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(check_interval) => {}
+            _ = shutdown_signalled(&mut shutdown) => return,
+        }
+        check.run(chain, &alerters).await;
+    }
+}
+
+/// Check the Solana position's health factor every `HEALTH_CHECK_INTERVAL_SECS`,
+/// through the same `HealthCheck` as the Aave chains
+async fn monitor_solana_health_factor(
+    alerters: Arc<Vec<Box<dyn Alerter>>>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let check_interval = Duration::from_secs(get_health_check_interval_secs().max(1));
+    let mut check = HealthCheck::from_config(Instant::now());
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(check_interval) => {}
+            _ = shutdown_signalled(&mut shutdown) => return,
+        }
+        check.run(&*SOLANA, &alerters).await;
+    }
+}
+
+/// Position whose health factor a `HealthCheck` watches: an Aave account or the
+/// Solana position. What differs between them goes through here, so alerting,
+/// exports and history follow the same rules on every chain.
+#[async_trait::async_trait]
+pub trait MonitoredPosition: Sync {
+    /// Chain name used in logs, metrics and alerts
+    fn chain_name(&self) -> &'static str;
+
+    fn user_address(&self) -> String;
+
+    /// Changes applied to the position so far, to tell transaction-triggered
+    /// crossings apart from price-driven ones
+    fn change_count(&self) -> u64;
+
+    /// Whether the position has caught up with what happened while the bot was down
+    fn is_initial_sync_complete(&self) -> bool;
+
+    async fn calculate_health_factor(&self) -> Result<HealthFactorReading, MonitorError>;
+
+    /// Collateral prices at which the health factor reaches 1.0, if they are known
+    async fn calculate_liquidation_prices(
+        &self,
+        _reading: &HealthFactorReading,
+    ) -> Result<Vec<LiquidationPrice>, MonitorError> {
+        Ok(Vec::new())
+    }
+
+    /// Structured view of the position for the generic webhook
+    fn alert_position(&self, reading: Option<&HealthFactorReading>) -> AlertPosition;
+
+    fn record_health_factor_sample(
+        &self,
+        sample: HealthFactorSample,
+        capacity: usize,
+    ) -> Result<(), MonitorError>;
+
+    /// Send the alerts for events seen since the previous check that don't depend
+    /// on the health factor, such as liquidations
+    async fn report_events(&self, _alerters: &[Box<dyn Alerter>]) {}
+
+    /// Hand the checked position to the configured event publishers
+    fn publish_checked_position(&self) {}
+}
+
+#[async_trait::async_trait]
+impl MonitoredPosition for ChainState {
+    fn chain_name(&self) -> &'static str {
+        self.name
+    }
+
+    fn user_address(&self) -> String {
+        ChainState::user_address(self)
+    }
+
+    fn change_count(&self) -> u64 {
+        self.event_count()
+    }
+
+    fn is_initial_sync_complete(&self) -> bool {
+        ChainState::is_initial_sync_complete(self)
+    }
+
+    async fn calculate_health_factor(&self) -> Result<HealthFactorReading, MonitorError> {
+        calculate_health_factor(self).await
+    }
+
+    async fn calculate_liquidation_prices(
+        &self,
+        reading: &HealthFactorReading,
+    ) -> Result<Vec<LiquidationPrice>, MonitorError> {
+        calculate_liquidation_prices(
+            self,
+            reading.supply_in_usd,
+            reading.borrowed_in_usd,
+            reading.liquidation_threshold,
+        )
+        .await
+    }
+
+    fn alert_position(&self, reading: Option<&HealthFactorReading>) -> AlertPosition {
+        alert_position(self, reading)
+    }
+
+    fn record_health_factor_sample(
+        &self,
+        sample: HealthFactorSample,
+        capacity: usize,
+    ) -> Result<(), MonitorError> {
+        ChainState::record_health_factor_sample(self, sample, capacity)
+    }
+
+    async fn report_events(&self, alerters: &[Box<dyn Alerter>]) {
+        // Actual liquidations get their own alert, independent of warm-up and confirmation
+        match self.take_liquidation_notices() {
+            Ok(notices) => {
+                for notice in notices {
+                    report_alert_failures(
+                        send_to_all(alerters, &liquidated_alert_message(self, &notice)).await,
+                    );
+                }
+            }
+            Err(e) => error!(
+                chain = self.name,
+                "Failed to read liquidation notices: {}", e
+            ),
+        }
+        report_large_events(self, alerters).await;
+    }
+
+    fn publish_checked_position(&self) {
+        publish_position("health_factor", self);
+    }
+}
+
+#[async_trait::async_trait]
+impl MonitoredPosition for SolanaState {
+    fn chain_name(&self) -> &'static str {
+        "solana"
+    }
+
+    fn user_address(&self) -> String {
+        get_solana_user_address().unwrap_or_default()
+    }
+
+    fn change_count(&self) -> u64 {
+        SolanaState::change_count(self)
+    }
+
+    // Balances are read as they are, there is nothing to replay
+    fn is_initial_sync_complete(&self) -> bool {
+        true
+    }
+
+    async fn calculate_health_factor(&self) -> Result<HealthFactorReading, MonitorError> {
+        calculate_solana_health_factor().await
+    }
+
+    fn alert_position(&self, reading: Option<&HealthFactorReading>) -> AlertPosition {
+        solana_alert_position(&self.user_address(), reading)
+    }
+
+    fn record_health_factor_sample(
+        &self,
+        sample: HealthFactorSample,
+        capacity: usize,
+    ) -> Result<(), MonitorError> {
+        SolanaState::record_health_factor_sample(self, sample, capacity)
+    }
+}
+
+/// Alert state carried from one health-factor check of a chain to the next
+pub struct HealthCheck {
+    cooldown: AlertCooldown,
+    // Health factor of the last risk alert, to tell escalations whether it keeps falling
+    alerted_health_factor: Option<f64>,
+    warmup: AlertWarmup,
+    digest: AlertDigest,
+    confirmation: AlertConfirmation,
+    price_outage: PriceOutage,
+}
+
+impl HealthCheck {
+    pub fn from_config(started_at: Instant) -> Self {
+        Self {
+            cooldown: AlertCooldown::from_config(),
+            alerted_health_factor: None,
+            warmup: AlertWarmup::new(started_at, Duration::from_secs(get_alert_warmup_secs())),
+            digest: AlertDigest::new(Duration::from_secs(get_alert_digest_window_secs())),
+            confirmation: AlertConfirmation::new(get_price_alert_confirmation_ticks()),
+            price_outage: PriceOutage::new(
+                get_price_outage_alert_failures(),
+                Duration::from_secs(get_price_outage_alert_secs()),
+            ),
+        }
+    }
+
+    /// Check `position`'s health factor once and send whatever alert is due through
+    /// `alerters`, which may be empty. Returns the reading, `None` when the check failed.
+    pub async fn run(
+        &mut self,
+        position: &dyn MonitoredPosition,
+        alerters: &[Box<dyn Alerter>],
+    ) -> Option<HealthFactorReading> {
+        let chain_name = position.chain_name();
+        position.report_events(alerters).await;
+
+        let reading = match position.calculate_health_factor().await {
+            Ok(reading) => reading,
+            Err(e) => {
+                error!(chain = chain_name, "Failed to check health factor: {}", e);
+                // Without prices the monitor is blind, which the user must hear about.
+                // Like liquidations, this isn't a risk alert and ignores /pause.
+                if matches!(e, MonitorError::Price(_)) {
+                    if let Some(change) = self.price_outage.observe_failure(Instant::now()) {
+                        let message = price_outage_alert_message(position, &change, Some(&e));
+                        report_alert_failures(send_to_all(alerters, &message).await);
+                    }
+                }
+                return None;
+            }
+        };
+        if let Some(change) = self.price_outage.observe_success(Instant::now()) {
+            let message = price_outage_alert_message(position, &change, None);
+            report_alert_failures(send_to_all(alerters, &message).await);
+        }
+        // Metrics and points are labelled by user, so every account is exported
+        let user_address = position.user_address();
+        record_health_factor(
+            chain_name,
+            &user_address,
+            reading.supply_in_usd,
+            reading.borrowed_in_usd,
+            reading.health_factor,
+        );
+        record_influx_points(
+            chain_name,
+            &user_address,
+            reading.health_factor,
+            reading.supply_in_usd,
+            reading.borrowed_in_usd,
+        );
+        let sample = HealthFactorSample {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_secs())
+                .unwrap_or_default(),
+            health_factor: reading
+                .health_factor
+                .is_finite()
+                .then_some(reading.health_factor),
+            collateral_usd: reading.supply_in_usd,
+            debt_usd: reading.borrowed_in_usd,
+        };
+        if let Err(e) =
+            position.record_health_factor_sample(sample, get_health_factor_history_size())
+        {
+            error!(
+                chain = chain_name,
+                "Failed to record health factor history: {}", e
+            );
+        }
+        position.publish_checked_position();
+        let liquidation_prices = match position.calculate_liquidation_prices(&reading).await {
+            Ok(prices) => prices,
+            Err(e) => {
+                error!(
+                    chain = chain_name,
+                    "Failed to compute liquidation prices: {}", e
+                );
+                Vec::new()
+            }
+        };
+        record_liquidation_prices(
+            chain_name,
+            &user_address,
+            &liquidation_prices
+                .iter()
+                .map(|price| (price.reserve, price.liquidation_price))
+                .collect::<Vec<_>>(),
+        );
+        let is_at_risk = reading.level > AlertLevel::Info;
+
+        // A completed check priced every reserve; the position itself is only
+        // trusted once the backfill has replayed the events missed while down
+        self.warmup
+            .observe_priced_check(position.is_initial_sync_complete());
+        if !self.warmup.is_complete(Instant::now()) {
+            if is_at_risk {
+                info!(
+                    chain = chain_name,
+                    level = ?reading.level,
+                    "Alert suppressed during startup warm-up"
+                );
+            }
+            return Some(reading);
+        }
+
+        // Dust isn't worth paging anyone for; it is still tracked and exported above
+        if is_dust_position(&reading, get_min_position_usd()) {
+            if is_at_risk {
+                debug!(
+                    chain = chain_name,
+                    account = %user_address,
+                    level = ?reading.level,
+                    collateral_usd = reading.supply_in_usd,
+                    debt_usd = reading.borrowed_in_usd,
+                    "Alert suppressed: position below MIN_POSITION_USD"
+                );
+            }
+            return Some(reading);
+        }
+
+        // Queue an alert if the position reached a warning tier
+        let confirmed = self
+            .confirmation
+            .observe(is_at_risk, position.change_count());
+        let decision = self
+            .cooldown
+            .observe(Instant::now(), reading.level, confirmed);
+        if let Some(message) = decision_alert_message(
+            position,
+            &reading,
+            &liquidation_prices,
+            &decision,
+            &mut self.alerted_health_factor,
+        ) {
+            self.digest.push(Instant::now(), message);
+        }
+        if let Some(message) = self.digest.take_due(Instant::now()) {
+            // A pause only silences delivery; cooldown and digest state keep advancing
+            if ALERT_PAUSE.alerts_enabled(Instant::now()) {
+                report_alert_failures(send_to_all(alerters, &message).await);
+            } else {
+                info!(chain = chain_name, title = %message.title, "Alert suppressed while paused");
+            }
+        }
+        Some(reading)
+    }
+}
+
+// How often block history is pruned; a few minutes of blocks in between is harmless
+const HISTORY_PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Drop every chain's reorg history and applied-log keys once they fall more than
+/// REORG_HISTORY_BLOCKS behind the confirmation depth, so weeks of uptime don't
+/// grow them
+fn prune_block_history() {
+    let (window, confirmation_depth) = (get_reorg_history_blocks(), get_confirmation_depth());
+    for chain in enabled_chains() {
+        match chain.prune_history(window, confirmation_depth) {
+            Ok(0) => {}
+            Ok(pruned) => debug!(chain = chain.name, pruned, "Pruned block history"),
+            Err(e) => error!(chain = chain.name, "Failed to prune block history: {}", e),
+        }
+    }
+}
+
+/// Save the latest position snapshot of every chain that took one since the last save
+fn persist_positions() {
+    for chain in enabled_chains() {
+        let saved = match chain.take_pending_snapshot() {
+            Ok(Some(snapshot)) => chain.save_snapshot(&snapshot),
+            Ok(None) => Ok(()),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = saved {
+            error!(chain = chain.name, "Failed to persist position: {}", e);
+        }
+    }
+}
+
+/// Exponential reconnect delay (1s, 2s, 4s ... capped) that resets once a
+/// connection has stayed up long enough to be considered stable.
+pub struct ReconnectBackoff {
+    initial: Duration,
+    max: Duration,
+    stable_after: Duration,
+    current: Duration,
+}
+
+impl ReconnectBackoff {
+    pub fn new(initial: Duration, max: Duration, stable_after: Duration) -> Self {
+        Self {
+            initial,
+            max,
+            stable_after,
+            current: initial,
+        }
+    }
+
+    /// Delay before the next attempt, given how long the last connection stayed up
+    pub fn next_delay(&mut self, connection_uptime: Duration) -> Duration {
+        if connection_uptime >= self.stable_after {
+            self.current = self.initial;
+        }
+        let delay = self.current;
+        self.current = (self.current * 2).min(self.max);
+        delay
+    }
+}
+
+/// Keeps alerts quiet after startup until the monitor has a reconciled view
+/// of the position (warm-up period elapsed, missed events replayed and every
+/// reserve priced at least once).
+pub struct AlertWarmup {
+    started_at: Instant,
+    period: Duration,
+    reconciled: bool,
+}
+
+impl AlertWarmup {
+    pub fn new(started_at: Instant, period: Duration) -> Self {
+        Self {
+            started_at,
+            period,
+            reconciled: false,
+        }
+    }
+
+    pub fn mark_reconciled(&mut self) {
+        self.reconciled = true;
+    }
+
+    /// Record a health check that priced every reserve. It only reconciles the
+    /// position once the initial sync (the backfill) has completed.
+    pub fn observe_priced_check(&mut self, initial_sync_complete: bool) {
+        if initial_sync_complete {
+            self.mark_reconciled();
+        }
+    }
+
+    pub fn is_complete(&self, now: Instant) -> bool {
+        self.reconciled && now.duration_since(self.started_at) >= self.period
+    }
+}
+
+/// Start or end of a sustained failure to price the position
+#[derive(Debug, Clone, PartialEq)]
+pub enum PriceOutageChange {
+    Started { failures: u32, since: Duration },
+    Recovered { lasted: Duration },
+}
+
+/// Counts health checks that failed for lack of prices. Once `min_failures`
+/// consecutive failures span at least `min_duration` the outage is reported, once,
+/// and its end is reported by the next successful check.
+pub struct PriceOutage {
+    min_failures: u32,
+    min_duration: Duration,
+    failures: u32,
+    first_failure: Option<Instant>,
+    reported: bool,
+}
+
+impl PriceOutage {
+    /// `min_failures` of 0 never reports an outage
+    pub fn new(min_failures: u32, min_duration: Duration) -> Self {
+        Self {
+            min_failures,
+            min_duration,
+            failures: 0,
+            first_failure: None,
+            reported: false,
+        }
+    }
+
+    pub fn observe_failure(&mut self, now: Instant) -> Option<PriceOutageChange> {
+        self.failures = self.failures.saturating_add(1);
+        let since = now.saturating_duration_since(*self.first_failure.get_or_insert(now));
+        if self.reported
+            || self.min_failures == 0
+            || self.failures < self.min_failures
+            || since < self.min_duration
+        {
+            return None;
+        }
+        self.reported = true;
+        Some(PriceOutageChange::Started {
+            failures: self.failures,
+            since,
+        })
+    }
+
+    pub fn observe_success(&mut self, now: Instant) -> Option<PriceOutageChange> {
+        let first_failure = self.first_failure.take();
+        self.failures = 0;
+        if !std::mem::take(&mut self.reported) {
+            return None;
+        }
+        Some(PriceOutageChange::Recovered {
+            lasted: first_failure
+                .map(|first| now.saturating_duration_since(first))
+                .unwrap_or_default(),
+        })
+    }
+}
+
+/// Decides whether a liquidation-range reading should alert. Crossings caused
+/// by a position event alert immediately; crossings caused by price moves must
+/// persist for a number of consecutive ticks to filter out noise.
+pub struct AlertConfirmation {
+    required_price_ticks: u32,
+    consecutive_ticks: u32,
+    last_event_count: u64,
+}
+
+impl AlertConfirmation {
+    pub fn new(required_price_ticks: u32) -> Self {
+        Self {
+            required_price_ticks,
+            consecutive_ticks: 0,
+            last_event_count: 0,
+        }
+    }
+
+    pub fn observe(&mut self, is_liquidation_range: bool, event_count: u64) -> bool {
+        let event_triggered = event_count != self.last_event_count;
+        self.last_event_count = event_count;
+
+        if !is_liquidation_range {
+            self.consecutive_ticks = 0;
+            return false;
+        }
+        self.consecutive_ticks = self.consecutive_ticks.saturating_add(1);
+
+        event_triggered || self.consecutive_ticks >= self.required_price_ticks
+    }
+}
+
+/// Severity of the position, from safe up to liquidatable
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AlertLevel {
+    Info,
+    Warn,
+    Danger,
+    Critical,
+}
+
+impl AlertLevel {
+    pub fn emoji(&self) -> &'static str {
+        match self {
+            AlertLevel::Info => "ℹ️",
+            AlertLevel::Warn => "⚠️",
+            AlertLevel::Danger => "🔶",
+            AlertLevel::Critical => "🚨",
+        }
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            AlertLevel::Info => "POSITION UPDATE",
+            AlertLevel::Warn => "HEALTH FACTOR WARNING",
+            AlertLevel::Danger => "HEALTH FACTOR DANGER",
+            AlertLevel::Critical => "LIQUIDATION ALERT",
+        }
+    }
+
+    /// Colour of the tier on channels that colour-code alerts
+    pub fn severity(&self) -> AlertSeverity {
+        match self {
+            AlertLevel::Info => AlertSeverity::Resolved,
+            AlertLevel::Warn => AlertSeverity::Warning,
+            AlertLevel::Danger | AlertLevel::Critical => AlertSeverity::Critical,
+        }
+    }
+}
+
+/// Loan-to-value thresholds at which each warning tier starts
+#[derive(Debug, Clone, Copy)]
+pub struct AlertTiers {
+    pub warn: f64,
+    pub danger: f64,
+    pub critical: f64,
+}
+
+impl AlertTiers {
+    pub fn from_config() -> Self {
+        Self {
+            warn: get_alert_tier_warn_ltv(),
+            danger: get_alert_tier_danger_ltv(),
+            critical: get_alert_tier_critical_ltv(),
+        }
+    }
+
+    pub fn level(&self, ltv: f64) -> AlertLevel {
+        if ltv >= self.critical {
+            AlertLevel::Critical
+        } else if ltv >= self.danger {
+            AlertLevel::Danger
+        } else if ltv >= self.warn {
+            AlertLevel::Warn
+        } else {
+            AlertLevel::Info
+        }
+    }
+}
+
+/// Outcome of [`AlertCooldown::observe`]
+#[derive(Debug, PartialEq)]
+pub enum AlertDecision {
+    Suppress,
+    // First alert at this level, either on entering a tier or on escalation
+    Fire,
+    // Reminder after the cooldown while the position stayed at the same level
+    Repeat { in_range_for: Duration },
+    // Reminder on the escalation schedule while the position stays critical
+    Escalate { critical_for: Duration },
+    // The position dropped below every tier after an alert was sent
+    Recovered,
+}
+
+/// Stops a position sitting in a warning tier from re-alerting on every tick.
+/// After an alert, the next one fires only once the cooldown has elapsed, the
+/// position escalates to a higher tier, or it leaves the tiers and enters again.
+/// While critical, reminders can instead follow a shrinking escalation schedule.
+pub struct AlertCooldown {
+    cooldown: Duration,
+    last_alerted_at: Option<Instant>,
+    alerted_level: Option<AlertLevel>,
+    in_range_since: Option<Instant>,
+    // Intervals between critical reminders; the last one repeats until the cap
+    escalation: Vec<Duration>,
+    max_escalations: u32,
+    escalations: u32,
+    critical_since: Option<Instant>,
+}
+
+impl AlertCooldown {
+    pub fn new(cooldown: Duration) -> Self {
+        Self {
+            cooldown,
+            last_alerted_at: None,
+            alerted_level: None,
+            in_range_since: None,
+            escalation: Vec::new(),
+            max_escalations: 0,
+            escalations: 0,
+            critical_since: None,
+        }
+    }
+
+    /// Re-alert a critical position after each interval of `schedule` in turn,
+    /// repeating the last one, for at most `max_escalations` reminders. The
+    /// regular cooldown applies after that.
+    pub fn with_escalation(mut self, schedule: Vec<Duration>, max_escalations: u32) -> Self {
+        self.escalation = schedule;
+        self.max_escalations = max_escalations;
+        self
+    }
+
+    pub fn from_config() -> Self {
+        Self::new(Duration::from_secs(get_alert_cooldown_secs())).with_escalation(
+            get_alert_escalation_schedule_secs()
+                .into_iter()
+                .map(Duration::from_secs)
+                .collect(),
+            get_alert_escalation_max_repeats(),
+        )
+    }
+
+    // Wait before the next critical reminder, `None` once the schedule is used up
+    fn escalation_interval(&self) -> Option<Duration> {
+        if self.escalations >= self.max_escalations {
+            return None;
+        }
+        let step = (self.escalations as usize).min(self.escalation.len().checked_sub(1)?);
+        self.escalation.get(step).copied()
+    }
+
+    pub fn observe(&mut self, now: Instant, level: AlertLevel, confirmed: bool) -> AlertDecision {
+        if level != AlertLevel::Critical {
+            self.critical_since = None;
+            self.escalations = 0;
+        }
+        if level == AlertLevel::Info {
+            self.in_range_since = None;
+            self.last_alerted_at = None;
+            return match self.alerted_level.take() {
+                Some(_) => AlertDecision::Recovered,
+                None => AlertDecision::Suppress,
+            };
+        }
+        let in_range_since = *self.in_range_since.get_or_insert(now);
+        if level == AlertLevel::Critical {
+            self.critical_since.get_or_insert(now);
+        }
+        if !confirmed {
+            return AlertDecision::Suppress;
+        }
+
+        match (self.alerted_level, self.last_alerted_at) {
+            (Some(alerted), Some(last)) if level <= alerted => {
+                // Remember de-escalations so climbing back up alerts again
+                self.alerted_level = Some(level);
+                let escalation = self
+                    .critical_since
+                    .zip(self.escalation_interval())
+                    .filter(|_| level == AlertLevel::Critical);
+                if let Some((critical_since, interval)) = escalation {
+                    if now.duration_since(last) < interval {
+                        return AlertDecision::Suppress;
+                    }
+                    self.escalations += 1;
+                    self.last_alerted_at = Some(now);
+                    return AlertDecision::Escalate {
+                        critical_for: now.duration_since(critical_since),
+                    };
+                }
+                if now.duration_since(last) < self.cooldown {
+                    return AlertDecision::Suppress;
+                }
+                self.last_alerted_at = Some(now);
+                AlertDecision::Repeat {
+                    in_range_for: now.duration_since(in_range_since),
+                }
+            }
+            _ => {
+                self.alerted_level = Some(level);
+                self.last_alerted_at = Some(now);
+                AlertDecision::Fire
+            }
+        }
+    }
+}
+
+/// Coalesces alerts raised within a short window into a single digest message
+/// so a burst of threshold crossings doesn't turn into a burst of notifications.
+pub struct AlertDigest {
+    window: Duration,
+    opened_at: Option<Instant>,
+    pending: Vec<AlertMessage>,
+}
+
+impl AlertDigest {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            opened_at: None,
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, now: Instant, message: AlertMessage) {
+        if self.pending.is_empty() {
+            self.opened_at = Some(now);
+        }
+        self.pending.push(message);
+    }
+
+    /// Returns the combined message once the window since the first queued
+    /// alert has elapsed. The digest ends with the most recent (final) state.
+    pub fn take_due(&mut self, now: Instant) -> Option<AlertMessage> {
+        let opened_at = self.opened_at?;
+        if now.duration_since(opened_at) < self.window {
+            return None;
+        }
+        self.opened_at = None;
+        let mut pending = std::mem::take(&mut self.pending);
+        let latest = pending.pop()?;
+        if pending.is_empty() {
+            return Some(latest);
+        }
+        Some(AlertMessage {
+            emoji: "📋".to_string(),
+            title: "ALERT DIGEST".to_string(),
+            severity: latest.severity,
+            body: format!(
+                "{} alerts in the last {}s. Latest state: {}\n\n{}",
+                pending.len() + 1,
+                self.window.as_secs(),
+                latest.title,
+                latest.body
+            ),
+            fields: latest.fields,
+            position: latest.position,
+        })
+    }
+}
+
+/// Health factor together with the USD values it was computed from
+#[derive(Debug, Clone, Copy)]
+pub struct HealthFactorReading {
+    pub health_factor: f64,
+    pub supply_in_usd: f64,
+    pub borrowed_in_usd: f64,
+    pub ltv: f64,
+    // Collateral-weighted liquidation threshold the health factor was computed with
+    pub liquidation_threshold: f64,
+    pub level: AlertLevel,
+}
+
+/// Health factor of the position tracked on `chain` from SimpleHash prices
+pub async fn calculate_health_factor(
+    chain: &ChainState,
+) -> Result<HealthFactorReading, MonitorError> {
+    let (supply_in_usd, borrowed_in_usd) = calculate_position_usd(chain).await?;
+    // Only the values shown to users are converted; the health factor is a ratio
+    refresh_quote_rate().await;
+    let liquidation_threshold = calculate_liquidation_threshold(chain).await?;
+    let health_factor =
+        compute_health_factor(supply_in_usd, borrowed_in_usd, liquidation_threshold);
+    let ltv = compute_ltv(supply_in_usd, borrowed_in_usd);
+    let level = alert_level(health_factor, ltv, &AlertTiers::from_config());
+    info!(
+        chain = chain.name,
+        account = %chain.user_address(),
+        health_factor,
+        supply_usd = supply_in_usd,
+        borrow_usd = borrowed_in_usd,
+        level = ?level,
+        "Health factor checked"
+    );
+
+    Ok(HealthFactorReading {
+        health_factor,
+        supply_in_usd,
+        borrowed_in_usd,
+        ltv,
+        liquidation_threshold,
+        level,
+    })
+}
+
+/// Liquidation threshold of the collateral tracked on `chain`: each reserve's own
+/// threshold, weighted by its USD value
+pub async fn calculate_liquidation_threshold(chain: &ChainState) -> Result<f64, MonitorError> {
+    let position = chain.position()?.collateral();
+    let collateral =
+        futures::future::try_join_all(position.iter().map(|(reserve, amount)| async move {
+            let usd = reserve_value_in_usd(chain, reserve, *amount).await?;
+            Ok::<_, MonitorError>((usd, chain.liquidation_threshold(reserve)))
+        }))
+        .await?;
+    Ok(weighted_liquidation_threshold(
+        &collateral,
+        get_liquidation_threshold(),
+    ))
+}
+
+/// Average of `(usd_value, liquidation_threshold)` pairs weighted by value, as Aave
+/// does for a position holding several collaterals. `fallback` without collateral.
+pub fn weighted_liquidation_threshold(collateral: &[(f64, f64)], fallback: f64) -> f64 {
+    let total: f64 = collateral.iter().map(|(usd, _)| usd).sum();
+    if total <= 0.0 {
+        return fallback;
+    }
+    collateral
+        .iter()
+        .map(|(usd, threshold)| usd * threshold)
+        .sum::<f64>()
+        / total
+}
+
+/// Map a position to its alert tier. A liquidatable position is always critical,
+/// whatever the configured tiers say.
+pub fn alert_level(health_factor: f64, ltv: f64, tiers: &AlertTiers) -> AlertLevel {
+    if is_in_liquidation_range(health_factor) {
+        return AlertLevel::Critical;
     }
+    tiers.level(ltv)
 }
 
-#[tokio::main]
-async fn main() {
-    //so this liquidator tracker is designed to track the 1 possition in supply sude and 1 possition in borrowed side
+/// Whether a position is too small to alert on: both its collateral and its debt are
+/// worth less than `min_position_usd`. A floor of 0 never applies.
+pub fn is_dust_position(reading: &HealthFactorReading, min_position_usd: f64) -> bool {
+    min_position_usd > 0.0
+        && reading.supply_in_usd < min_position_usd
+        && reading.borrowed_in_usd < min_position_usd
+}
 
-    //for example user supply USDT and borrowed wBTC
-    //to extend it to any other token pair, we need to keep track of all the token pairs.
-    //but current implementation shows the general idea of how to track the position and calculate the health factor.
-    //When this bot starts it initializes the supplied and borrowed amount of user from environment variables.
-    //The bot listens to the events from the Aave protocol and updates the supplied and borrowed, repays or withdraws to update the position that effect the health factor.
+/// Aave-style health factor: `collateral * liquidation_threshold / debt`.
+/// A position without debt can never be liquidated, so its health factor is infinite.
+pub fn compute_health_factor(
+    supply_in_usd: f64,
+    borrowed_in_usd: f64,
+    liquidation_threshold: f64,
+) -> f64 {
+    if borrowed_in_usd <= 0.0 {
+        return f64::INFINITY;
+    }
+    supply_in_usd * liquidation_threshold / borrowed_in_usd
+}
 
-    dotenv::dotenv().ok();
-    init_system().await;
+/// Price of one collateral token at which the health factor reaches 1.0, with every
+/// other price unchanged: `(debt / liquidation_threshold - other_collateral) / amount`.
+/// `None` without debt or without this collateral, or when the rest of the collateral
+/// covers the debt on its own. A result at or above the current price means the
+/// position is already liquidatable.
+pub fn compute_liquidation_price(
+    collateral_amount: f64,
+    other_collateral_usd: f64,
+    borrowed_in_usd: f64,
+    liquidation_threshold: f64,
+) -> Option<f64> {
+    if borrowed_in_usd <= 0.0 || collateral_amount <= 0.0 || liquidation_threshold <= 0.0 {
+        return None;
+    }
+    let price =
+        (borrowed_in_usd / liquidation_threshold - other_collateral_usd) / collateral_amount;
+    (price > 0.0).then_some(price)
+}
 
-    // Print initial configuration
-    print_initial_configuration();
+/// Collateral price at which the position becomes liquidatable
+#[derive(Debug, Clone, Serialize)]
+pub struct LiquidationPrice {
+    pub reserve: Address,
+    pub symbol: String,
+    pub current_price: f64,
+    pub liquidation_price: f64,
+}
 
-    // Display initial position status
-    display_position_status().await;
+impl LiquidationPrice {
+    /// The collateral already trades at or below its liquidation price
+    pub fn is_breached(&self) -> bool {
+        self.current_price <= self.liquidation_price
+    }
+}
 
-    tokio::spawn(async {
-        loop {
-            let handle0 = tokio::spawn(async {
-                match ethereum_listening().await {
-                    Ok(_) => println!("Ethereum listening finished"),
-                    Err(e) => println!("Ethereum listening failed with error: {}", e),
-                };
+/// Liquidation price of every collateral reserve tracked on `chain`, given the
+/// position's current USD values
+pub async fn calculate_liquidation_prices(
+    chain: &ChainState,
+    supply_in_usd: f64,
+    borrowed_in_usd: f64,
+    liquidation_threshold: f64,
+) -> Result<Vec<LiquidationPrice>, MonitorError> {
+    if borrowed_in_usd <= 0.0 {
+        return Ok(Vec::new());
+    }
+    let collateral = chain.position()?.collateral();
+    let mut reserves: Vec<_> = collateral.iter().collect();
+    reserves.sort_by_key(|(reserve, _)| **reserve);
+
+    let mut prices = Vec::new();
+    for (reserve, amount) in reserves {
+        if amount.is_zero() {
+            continue;
+        }
+        let decimals = get_token_decimals(reserve).ok_or_else(|| {
+            MonitorError::Config(format!("Unknown decimals for reserve {:?}", reserve))
+        })?;
+        let price = get_checked_price(fungible_id(chain, reserve)).await?;
+        let collateral_amount = to_usd(*amount, decimals, 1.0)?;
+        let reserve_threshold = chain.liquidation_threshold(reserve);
+        if reserve_threshold <= 0.0 {
+            continue; // Not usable as collateral, so its price can't trigger liquidation
+        }
+        // The rest of the collateral, rescaled to this reserve's threshold
+        let other_collateral_usd = (supply_in_usd * liquidation_threshold
+            - collateral_amount * price.price * reserve_threshold)
+            / reserve_threshold;
+        if let Some(liquidation_price) = compute_liquidation_price(
+            collateral_amount,
+            other_collateral_usd,
+            borrowed_in_usd,
+            reserve_threshold,
+        ) {
+            prices.push(LiquidationPrice {
+                reserve: *reserve,
+                symbol: price.symbol,
+                current_price: price.price,
+                liquidation_price,
             });
-            match handle0.await {
-                Ok(_) => println!("Ethereum task completed successfully."),
-                Err(join_err) => {
-                    if join_err.is_panic() {
-                        println!("Ethereum task panicked! Restarting...");
-                    } else {
-                        println!("Ethereum task failed unexpectedly: {:?}", join_err);
-                    }
-                }
-            }
-            tokio::time::sleep(std::time::Duration::from_millis(200)).await;
         }
-    });
+    }
+    Ok(prices)
+}
 
-    // Spawn a task to periodically check if health factor is in liquidation range
-    tokio::spawn(async {
-        loop {
-            tokio::time::sleep(std::time::Duration::from_secs(2)).await;
-            let is_liquidation_range = is_health_factor_in_liquidation_range()
-                .await
-                .expect("Failed to check health factor");
-
-            // Send Telegram alert if in liquidation range
-            if let Err(e) = send_telegram_alert(is_liquidation_range).await {
-                eprintln!("Failed to send Telegram alert: {}", e);
-            }
-        }
-    });
+/// Loan-to-value ratio: borrowed value as a fraction of supplied value
+pub fn compute_ltv(supply_in_usd: f64, borrowed_in_usd: f64) -> f64 {
+    if supply_in_usd <= 0.0 {
+        return f64::INFINITY;
+    }
+    borrowed_in_usd / supply_in_usd
+}
 
-    tokio::signal::ctrl_c()
+/// Position values after a what-if move of every collateral and debt price
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PriceShock {
+    pub supply_in_usd: f64,
+    pub borrowed_in_usd: f64,
+    pub health_factor: f64,
+    pub in_liquidation_range: bool,
+}
+
+/// Recompute the health factor with collateral prices moved by `collateral_change_pct`
+/// and debt prices by `debt_change_pct` percent (e.g. `-20.0` for a 20% drop).
+/// Prices can't fall below zero, so changes below -100% are treated as -100%.
+pub fn simulate_price_shock(
+    supply_in_usd: f64,
+    borrowed_in_usd: f64,
+    liquidation_threshold: f64,
+    collateral_change_pct: f64,
+    debt_change_pct: f64,
+) -> PriceShock {
+    let supply_in_usd = supply_in_usd * (1.0 + collateral_change_pct / 100.0).max(0.0);
+    let borrowed_in_usd = borrowed_in_usd * (1.0 + debt_change_pct / 100.0).max(0.0);
+    let health_factor =
+        compute_health_factor(supply_in_usd, borrowed_in_usd, liquidation_threshold);
+    PriceShock {
+        supply_in_usd,
+        borrowed_in_usd,
+        health_factor,
+        in_liquidation_range: is_in_liquidation_range(health_factor),
+    }
+}
+
+/// Liquidation becomes possible once the health factor drops below 1.0
+pub fn is_in_liquidation_range(health_factor: f64) -> bool {
+    health_factor < 1.0
+}
+
+/// Value the collateral and borrow legs tracked on `chain` in USD using SimpleHash
+/// prices. Supplies disabled as collateral are left out.
+pub async fn calculate_position_usd(chain: &ChainState) -> Result<(f64, f64), MonitorError> {
+    let position = chain.position()?;
+
+    for (reserve, amount) in &position.supplied {
+        tracing::debug!(
+            chain = chain.name,
+            reserve = ?reserve,
+            amount = %amount,
+            collateral = position.is_collateral(reserve),
+            "Supplied amount"
+        );
+    }
+    for (reserve, amount) in &position.borrowed {
+        tracing::debug!(chain = chain.name, reserve = ?reserve, amount = %amount, "Borrowed amount");
+    }
+    if let Some(tx_hash) = position.last_event_tx {
+        tracing::debug!(chain = chain.name, tx_hash = ?tx_hash, "Last event");
+    }
+
+    // Both sides are priced at once so the check waits for a single round-trip
+    tokio::try_join!(
+        value_in_usd(chain, &position.collateral()),
+        value_in_usd(chain, &position.borrowed)
+    )
+}
+
+/// Net worth of the position tracked on `chain` at current prices: everything
+/// supplied, collateral or not, minus the debt
+pub async fn calculate_net_usd(chain: &ChainState) -> Result<f64, MonitorError> {
+    let position = chain.position()?;
+    let (supplied_usd, borrowed_usd) = tokio::try_join!(
+        value_in_usd(chain, &position.supplied),
+        value_in_usd(chain, &position.borrowed)
+    )?;
+    Ok(supplied_usd - borrowed_usd)
+}
+
+/// Sum the USD value of raw token amounts keyed by reserve address, fetching
+/// every reserve's price concurrently
+async fn value_in_usd(
+    chain: &ChainState,
+    balances: &HashMap<Address, U256>,
+) -> Result<f64, MonitorError> {
+    let values = futures::future::try_join_all(
+        balances
+            .iter()
+            .map(|(reserve, amount)| reserve_value_in_usd(chain, reserve, *amount)),
+    )
+    .await?;
+    Ok(values.into_iter().sum())
+}
+
+/// USD value of a raw amount of one reserve on `chain`
+pub async fn reserve_value_in_usd(
+    chain: &ChainState,
+    reserve: &Address,
+    amount: U256,
+) -> Result<f64, MonitorError> {
+    if amount.is_zero() {
+        return Ok(0.0);
+    }
+    // Prices are fetched concurrently, so name the reserve whose request failed
+    let price = get_checked_price(fungible_id(chain, reserve))
         .await
-        .expect("Failed to listen for ctrl_c signal");
+        .map_err(|e| {
+            MonitorError::Price(format!(
+                "Price request for reserve {:?} failed: {}",
+                reserve, e
+            ))
+        })?;
+    let (decimals, warning) = valuation_decimals(reserve, get_token_decimals(reserve), &price)?;
+    if let Some(warning) = warning {
+        warn_once_about_decimals(reserve, &warning);
+    }
+    to_usd(amount, decimals, price.price)
 }
 
-pub async fn is_health_factor_in_liquidation_range() -> Result<bool, String> {
-    //get supply position
-    //get borrowed position
-    //get price of supply and borrowed
-    //calculate health factor
+/// Decimals that scale a raw amount of `reserve` to whole tokens for valuation.
+///
+/// Prices are quoted per whole token, so the token's own decimals are authoritative:
+/// the configured override, else the contract's `decimals()` (see
+/// `reconcile_token_decimals`). The decimals a price source reports are only used
+/// when the token's are unknown. A disagreement is returned as a warning, since one
+/// side is wrong and the valuation would be off by orders of magnitude.
+pub fn valuation_decimals(
+    reserve: &Address,
+    token_decimals: Option<u64>,
+    price: &PriceResult,
+) -> Result<(u64, Option<String>), MonitorError> {
+    match token_decimals {
+        Some(decimals) if decimals == price.decimals => Ok((decimals, None)),
+        Some(decimals) => Ok((
+            decimals,
+            Some(format!(
+                "WARNING: {} price source reports {} decimals for {:?} but the token has {}; scaling by the token's {}",
+                price.symbol, price.decimals, reserve, decimals, decimals
+            )),
+        )),
+        None if price.decimals > 0 => Ok((
+            price.decimals,
+            Some(format!(
+                "WARNING: decimals of {:?} are unknown, scaling by the {} reported by its price source",
+                reserve, price.decimals
+            )),
+        )),
+        None => Err(MonitorError::Config(format!(
+            "Unknown decimals for reserve {:?}",
+            reserve
+        ))),
+    }
+}
 
-    let supply_position = get_position_data().expect("Failed to get supply position");
+lazy_static::lazy_static! {
+    // Reserves whose decimals mismatch was already reported
+    static ref DECIMALS_WARNED: Mutex<std::collections::HashSet<Address>> =
+        Mutex::new(std::collections::HashSet::new());
+}
 
-    println!("Current Position Status:");
-    println!("  Supplied Amount: {}", supply_position.supplied_amount);
-    println!("  Borrowed Amount: {}", supply_position.borrowed_amount);
+// Every health check values every reserve, so a mismatch is only reported once
+fn warn_once_about_decimals(reserve: &Address, warning: &str) {
+    let first = DECIMALS_WARNED
+        .lock()
+        .map(|mut warned| warned.insert(*reserve))
+        .unwrap_or(true);
+    if first {
+        warn!(reserve = ?reserve, "{}", warning);
+    }
+}
 
-    let borrowed_amount = supply_position.borrowed_amount;
-    let supply_amount = supply_position.supplied_amount;
+/// SimpleHash fungible id of a token, e.g. `polygon.0x...`
+pub fn fungible_id(chain: &ChainState, token: &Address) -> String {
+    format!("{}.{:?}", chain.name, token)
+}
 
-    //convert supply_amount and borrowed_amount to f64
-    let supply_amount_f64 = supply_amount
+/// Convert a raw token amount to USD
+pub fn to_usd(amount: U256, decimals: u64, price: f64) -> Result<f64, MonitorError> {
+    let amount_f64 = amount
         .to_string()
         .parse::<f64>()
-        .expect("Failed to convert supply amount to f64");
-    let borrowed_amount_f64 = borrowed_amount
+        .map_err(|e| MonitorError::Decode(format!("Failed to convert amount to f64: {}", e)))?;
+    Ok(price * amount_f64 / 10_f64.powf(decimals as f64))
+}
+
+/// Seed the position from `Pool.getUserAccountData`. Aave only reports totals in its
+/// base currency (USD on V3, ETH on V2; both scaled to USD), so they are converted
+/// back to token amounts with current prices. Totals can't be split across reserves,
+/// so this needs a single pair.
+async fn initial_position_from_chain() -> Result<PositionData, MonitorError> {
+    let supply_assets = get_supply_assets()?;
+    let borrowed_assets = get_borrowed_assets()?;
+    let ([supply_asset], [borrowed_asset]) = (supply_assets.as_slice(), borrowed_assets.as_slice())
+    else {
+        return Err(MonitorError::Config(
+            "getUserAccountData only reports totals, multi-asset positions are seeded from config"
+                .to_string(),
+        ));
+    };
+
+    let account = fetch_on_chain_account_data(tracked_address(&ETHEREUM)?).await?;
+    info!(
+        health_factor = account.health_factor,
+        collateral_usd = account.total_collateral_usd,
+        debt_usd = account.total_debt_usd,
+        "On-chain health factor read"
+    );
+
+    let mut position = PositionData::new();
+    for (asset, total_usd, is_supply) in [
+        (supply_asset, account.total_collateral_usd, true),
+        (borrowed_asset, account.total_debt_usd, false),
+    ] {
+        let decimals = get_token_decimals(&asset.address).unwrap_or(asset.decimals);
+        let price = get_checked_price(fungible_id(&ETHEREUM, &asset.address)).await?;
+        let amount = usd_to_token_amount(total_usd, price.price, decimals)?;
+        if is_supply {
+            position.update_supplied_amount(asset.address, amount);
+        } else {
+            position.update_borrowed_amount(asset.address, amount);
+        }
+    }
+    Ok(position)
+}
+
+/// Replace every tracked position with its aToken and debt token balances. An
+/// account that can't be read is logged and keeps its event-tracked position.
+async fn resync_positions_from_chain() {
+    for chain in configured_chains() {
+        for state in chain.accounts() {
+            if let Err(e) = resync_account_from_chain(chain.as_ref(), state).await {
+                error!(
+                    chain = chain.name(),
+                    account = %state.user_address(),
+                    "Position resync failed: {}", e
+                );
+            }
+        }
+    }
+}
+
+/// Overwrite one account's position with its token balances, warning about every
+/// reserve that drifted further than the configured tolerance. On Ethereum the
+/// totals are also checked against `getUserAccountData`.
+async fn resync_account_from_chain(
+    chain: &dyn Chain,
+    state: &ChainState,
+) -> Result<(), MonitorError> {
+    let local = state.position()?;
+    let on_chain = chain.read_account_balances(state).await?;
+    let tolerance = get_position_drift_tolerance();
+    for warning in position_drift(&local, &on_chain, tolerance) {
+        warn!(
+            chain = chain.name(),
+            account = %state.user_address(),
+            "{}", warning
+        );
+    }
+    if chain.name() == ETHEREUM.name {
+        // Valued before the overwrite, so it is the tracked position being checked
+        if let Err(e) = check_account_totals(state, tolerance).await {
+            warn!(
+                chain = chain.name(),
+                account = %state.user_address(),
+                "Skipped getUserAccountData drift check: {}", e
+            );
+        }
+    }
+    state.set_position(on_chain)?;
+    info!(
+        chain = chain.name(),
+        account = %state.user_address(),
+        "Position resynced from token balances"
+    );
+    Ok(())
+}
+
+/// Warn when the tracked collateral or debt differs from the totals Aave reports
+/// for the account by more than `tolerance` (relative)
+async fn check_account_totals(state: &ChainState, tolerance: f64) -> Result<(), MonitorError> {
+    let (collateral_usd, debt_usd) = calculate_position_usd(state).await?;
+    let account = fetch_on_chain_account_data(tracked_address(state)?).await?;
+    for (side, tracked, reported) in [
+        ("collateral", collateral_usd, account.total_collateral_usd),
+        ("debt", debt_usd, account.total_debt_usd),
+    ] {
+        if (tracked - reported).abs() > tolerance * reported.abs().max(f64::EPSILON) {
+            warn!(
+                chain = state.name,
+                account = %state.user_address(),
+                tracked_usd = tracked,
+                on_chain_usd = reported,
+                "Tracked {} drifted from getUserAccountData", side
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Describe every reserve whose tracked amount differs from the on-chain amount
+/// by more than `tolerance` (relative to the on-chain amount)
+pub fn position_drift(
+    local: &PositionData,
+    on_chain: &PositionData,
+    tolerance: f64,
+) -> Vec<String> {
+    let mut warnings = Vec::new();
+    for (side, local_amounts, chain_amounts) in [
+        ("supplied", &local.supplied, &on_chain.supplied),
+        ("borrowed", &local.borrowed, &on_chain.borrowed),
+    ] {
+        let reserves: std::collections::HashSet<&Address> =
+            local_amounts.keys().chain(chain_amounts.keys()).collect();
+        for reserve in reserves {
+            let tracked = local_amounts.get(reserve).copied().unwrap_or_default();
+            let actual = chain_amounts.get(reserve).copied().unwrap_or_default();
+            if tracked == actual {
+                continue;
+            }
+            let (Ok(tracked_f64), Ok(actual_f64)) = (u256_to_f64(tracked), u256_to_f64(actual))
+            else {
+                continue;
+            };
+            let drift = if actual_f64 == 0.0 {
+                f64::INFINITY
+            } else {
+                ((tracked_f64 - actual_f64) / actual_f64).abs()
+            };
+            if drift > tolerance {
+                warnings.push(format!(
+                    "{} amount of {:?} drifted: tracked {} vs on-chain {}",
+                    side, reserve, tracked, actual
+                ));
+            }
+        }
+    }
+    warnings
+}
+
+/// Convert a USD value to a raw token amount at `price`
+pub fn usd_to_token_amount(usd: f64, price: f64, decimals: u64) -> Result<U256, MonitorError> {
+    if price <= 0.0 {
+        return Err(MonitorError::Price(format!("Invalid price {}", price)));
+    }
+    let amount = usd / price * 10_f64.powf(decimals as f64);
+    U256::from_dec_str(&format!("{:.0}", amount)).map_err(|e| {
+        MonitorError::Decode(format!(
+            "Failed to convert amount {} to U256: {}",
+            amount, e
+        ))
+    })
+}
+
+fn u256_to_f64(value: U256) -> Result<f64, MonitorError> {
+    value
         .to_string()
         .parse::<f64>()
-        .expect("Failed to convert borrowed amount to f64");
+        .map_err(|e| MonitorError::Decode(format!("Failed to convert {} to f64: {}", value, e)))
+}
 
-    let supply_price = get_price(get_supply_token_address())
-        .await
-        .expect("Failed to get supply price")
-        .expect("Failed to get supply price");
-    let borrowed_price = get_price(get_borrowed_token_address())
-        .await
-        .expect("Failed to get borrowed price")
-        .expect("Failed to get borrowed price");
+/// Compare our health factor with the protocol's and describe the likely cause when
+/// they diverge by more than `tolerance` (relative). Two debt-free (infinite) health
+/// factors agree. `ltv_agrees` says whether the debt/collateral ratios matched: if
+/// they did, the values are right and the liquidation threshold is what differs.
+pub fn health_factor_drift_report(
+    ours: f64,
+    protocol: f64,
+    ltv_agrees: bool,
+    tolerance: f64,
+) -> Option<String> {
+    let drift = if ours.is_infinite() && protocol.is_infinite() {
+        0.0
+    } else if ours.is_infinite() || protocol.is_infinite() {
+        // One side sees debt the other doesn't
+        f64::INFINITY
+    } else if protocol == 0.0 {
+        ours.abs()
+    } else {
+        ((ours - protocol) / protocol).abs()
+    };
+    if drift <= tolerance {
+        return None;
+    }
+
+    // A ratio that is a clean power of ten points at wrong decimals
+    let magnitude = (ours / protocol).log10();
+    let likely_cause = if magnitude.is_finite()
+        && magnitude.abs() >= 0.9
+        && (magnitude - magnitude.round()).abs() < 0.1
+    {
+        "token decimals misconfigured"
+    } else if ltv_agrees && drift.is_finite() {
+        "liquidation threshold mismatch (eMode, per-reserve threshold or isolation)"
+    } else if drift <= 0.25 {
+        "price discrepancy between SimpleHash and the Aave oracle"
+    } else {
+        "stale or missed position events"
+    };
+
+    Some(format!(
+        "computed {:.6} vs protocol {:.6} ({:.2}% drift), likely cause: {}",
+        ours,
+        protocol,
+        drift * 100.0,
+        likely_cause
+    ))
+}
+
+// Calls to getUserAccountData before a transient RPC error is given up on
+const ACCOUNT_DATA_ATTEMPTS: u32 = 3;
 
-    let supply_in_usd =
-        supply_price.price * supply_amount_f64 / 10_f64.powf(get_supply_token_decimals() as f64);
-    let borrowed_in_usd = borrowed_price.price * borrowed_amount_f64
-        / 10_f64.powf(get_borrowed_token_decimals() as f64);
+// A V2 LendingPool's base currency is ETH, priced through WETH
+const WETH_ADDRESS: &str = "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2";
 
-    let health_factor = borrowed_in_usd / supply_in_usd;
-    let liquidation_threshold = get_liquidation_threshold();
-    if health_factor > liquidation_threshold {
-        // alert
-        return Ok(true);
+/// USD value of one unit of the Ethereum pool's base currency: V3 reports in USD,
+/// V2 in ETH
+async fn base_currency_usd(version: PoolVersion) -> Result<f64, MonitorError> {
+    match version {
+        PoolVersion::V3 => Ok(1.0),
+        PoolVersion::V2 => {
+            let weth = WETH_ADDRESS
+                .parse::<Address>()
+                .map_err(|e| MonitorError::Config(format!("Invalid WETH address: {}", e)))?;
+            Ok(get_checked_price(fungible_id(&ETHEREUM, &weth))
+                .await?
+                .price)
+        }
     }
+}
 
-    Ok(false)
+/// Address of the account tracked by `chain`, which `getUserAccountData` is asked about
+fn tracked_address(chain: &ChainState) -> Result<Address, MonitorError> {
+    chain.user_address().parse::<Address>().map_err(|e| {
+        MonitorError::Config(format!(
+            "Failed to parse user address {}: {}",
+            chain.user_address(),
+            e
+        ))
+    })
 }
 
-/// Send a Telegram alert when liquidation range is detected
-async fn send_telegram_alert(is_liquidation_range: bool) -> Result<(), Box<dyn std::error::Error>> {
-    // Get bot token and chat ID from environment variables
-    let bot_token =
-        env::var("TELEGRAM_BOT_TOKEN").expect("TELEGRAM_BOT_TOKEN environment variable not set");
-    let chat_id =
-        env::var("TELEGRAM_CHAT_ID").expect("TELEGRAM_CHAT_ID environment variable not set");
+/// Aave's own view of `user` on Ethereum, scaled to plain numbers. Each call is
+/// counted towards the endpoint's failover, so a retry after an RPC error may go
+/// to the next endpoint; other errors aren't retried.
+pub async fn fetch_on_chain_account_data(
+    user: Address,
+) -> Result<OnChainAccountData, MonitorError> {
+    let mut attempt = 0;
+    loop {
+        let rpc_url = get_ethereum_rpc_url();
+        let account = get_user_account_data_ethereum(&rpc_url, user).await;
+        ETHEREUM_RPC_ENDPOINTS.record(&rpc_url, &account);
+        match account {
+            Ok(account) => {
+                let version = get_pool_version();
+                return account.scaled(version, base_currency_usd(version).await?);
+            }
+            Err(MonitorError::Rpc(e)) if attempt + 1 < ACCOUNT_DATA_ATTEMPTS => {
+                let delay = Duration::from_millis(500 << attempt);
+                warn!(
+                    endpoint = %rpc_url,
+                    "getUserAccountData failed, retrying in {:?}: {}", delay, e
+                );
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
 
-    let bot = Bot::new(bot_token);
-    let chat_id = chat_id.parse::<u64>()?;
+/// Cross-check the health factor of every account tracked on Ethereum against
+/// `Pool.getUserAccountData` and alert on drift. An account that can't be checked
+/// is logged and skipped so the others still are.
+async fn run_health_factor_self_test(alerters: &[Box<dyn Alerter>]) -> Result<(), MonitorError> {
+    for chain in account_states(&ETHEREUM) {
+        if let Err(e) = self_test_account(chain, alerters).await {
+            error!(
+                chain = chain.name,
+                account = %chain.user_address(),
+                "Health factor self-test failed: {}", e
+            );
+        }
+    }
+    Ok(())
+}
 
-    let user_id = UserId(chat_id);
-    let recipient = Recipient::from(user_id);
+async fn self_test_account(
+    chain: &ChainState,
+    alerters: &[Box<dyn Alerter>],
+) -> Result<(), MonitorError> {
+    let reading = calculate_health_factor(chain).await?;
+    let account = fetch_on_chain_account_data(tracked_address(chain)?).await?;
+    let tolerance = get_hf_drift_tolerance();
+    // Matching debt/collateral ratios put any health-factor drift on the threshold
+    let ltv_agrees = account
+        .debt_to_collateral_ratio()
+        .is_some_and(|protocol_ltv| (reading.ltv - protocol_ltv).abs() <= tolerance * protocol_ltv);
 
-    if is_liquidation_range {
-        let message = format!(
-            "🚨 *LIQUIDATION ALERT* 🚨\n\n\
-            *Address:* `{}`\n\
-            *Supply Token:* `{}` \\(Decimals: {}\\)\n\
-            *Borrow Token:* `{}` \\(Decimals: {}\\)\n\n\
-            Your Aave position is now in liquidation range\\!\n\n\
-            Please check your position immediately and consider:\n\
-            • Repaying some debt\n\
-            • Adding more collateral\n\
-            • Closing the position\n\n\
-            Health factor is below {}\\.\n\
-            \\(Borrowed value is {}% of supply value\\)",
-            get_user_address_to_track(),
-            get_supply_token_address(),
-            get_supply_token_decimals(),
-            get_borrowed_token_address(),
-            get_borrowed_token_decimals(),
-            get_liquidation_threshold(),
-            (get_liquidation_threshold() * 100.0) as i32
+    info!(
+        chain = chain.name,
+        account = %chain.user_address(),
+        computed_health_factor = reading.health_factor,
+        protocol_health_factor = account.health_factor,
+        "Health factor self-test"
+    );
+
+    if let Some(report) = health_factor_drift_report(
+        reading.health_factor,
+        account.health_factor,
+        ltv_agrees,
+        tolerance,
+    ) {
+        warn!(
+            chain = chain.name,
+            account = %chain.user_address(),
+            "Health factor self-test drift: {}", report
         );
+        let message = AlertMessage::new("⚠️", "HEALTH FACTOR SELF-TEST DRIFT")
+            .field("Address", chain.user_address())
+            .body(report)
+            .position(alert_position(chain, Some(&reading)));
+        report_alert_failures(send_to_all(alerters, &message).await);
+    }
+
+    Ok(())
+}
+
+/// Health factor of the Solana position, with every mint priced as `solana.<mint>`
+/// and SOLANA_LIQUIDATION_THRESHOLD applied to all collateral
+pub async fn calculate_solana_health_factor() -> Result<HealthFactorReading, MonitorError> {
+    let position = SOLANA.position()?;
+    let supply_in_usd = solana_reserves_usd(&position.supplied).await?;
+    let borrowed_in_usd = solana_reserves_usd(&position.borrowed).await?;
+    refresh_quote_rate().await;
+    let liquidation_threshold = get_solana_liquidation_threshold();
+    let health_factor =
+        compute_health_factor(supply_in_usd, borrowed_in_usd, liquidation_threshold);
+    let ltv = compute_ltv(supply_in_usd, borrowed_in_usd);
+    let level = alert_level(health_factor, ltv, &AlertTiers::from_config());
+    info!(
+        chain = "solana",
+        health_factor,
+        supply_in_usd,
+        borrowed_in_usd,
+        slot = SOLANA.latest_slot(),
+        "Health factor checked"
+    );
+    Ok(HealthFactorReading {
+        health_factor,
+        supply_in_usd,
+        borrowed_in_usd,
+        ltv,
+        liquidation_threshold,
+        level,
+    })
+}
+
+async fn solana_reserves_usd(reserves: &[SolanaReserve]) -> Result<f64, MonitorError> {
+    let mut total = 0.0;
+    for reserve in reserves.iter().filter(|reserve| reserve.amount > 0) {
+        let price = get_checked_price(solana_fungible_id(&reserve.mint)).await?;
+        total += to_usd(
+            U256::from(reserve.amount),
+            reserve.decimals as u64,
+            price.price,
+        )?;
+    }
+    Ok(total)
+}
+
+/// Position attached to Solana alerts, with mints as the token addresses
+pub fn solana_alert_position(
+    account: &str,
+    reading: Option<&HealthFactorReading>,
+) -> AlertPosition {
+    let mut tokens = Vec::new();
+    match SOLANA.position() {
+        Ok(position) => {
+            for (side, reserves) in [
+                ("supplied", position.supplied),
+                ("borrowed", position.borrowed),
+            ] {
+                tokens.extend(reserves.into_iter().map(|reserve| AlertToken {
+                    side,
+                    address: reserve.mint,
+                    symbol: None,
+                    amount: reserve.amount.to_string(),
+                }));
+            }
+        }
+        Err(e) => warn!(chain = "solana", "Failed to read position for alert: {}", e),
+    }
+    AlertPosition {
+        chain: "solana".to_string(),
+        user_address: account.to_string(),
+        health_factor: reading.map(|reading| reading.health_factor),
+        collateral_usd: reading.map(|reading| reading.supply_in_usd),
+        debt_usd: reading.map(|reading| reading.borrowed_in_usd),
+        tokens,
+    }
+}
+
+/// Structured view of `chain`'s tracked position for the generic webhook. Without a
+/// reading (e.g. during a price outage) only the tokens are known, not their value.
+pub fn alert_position(chain: &ChainState, reading: Option<&HealthFactorReading>) -> AlertPosition {
+    let mut tokens = Vec::new();
+    match chain.position() {
+        Ok(position) => {
+            for (side, balances) in [
+                ("supplied", &position.supplied),
+                ("borrowed", &position.borrowed),
+            ] {
+                let mut balances: Vec<_> = balances.iter().collect();
+                balances.sort_by_key(|(reserve, _)| **reserve);
+                for (reserve, amount) in balances {
+                    tokens.push(AlertToken {
+                        side,
+                        address: format!("{:?}", reserve),
+                        symbol: get_token_symbol(reserve),
+                        amount: amount.to_string(),
+                    });
+                }
+            }
+        }
+        Err(e) => warn!(
+            chain = chain.name,
+            "Failed to read position for alert: {}", e
+        ),
+    }
+    AlertPosition {
+        chain: chain.name.to_string(),
+        user_address: chain.user_address(),
+        health_factor: reading.map(|reading| reading.health_factor),
+        collateral_usd: reading.map(|reading| reading.supply_in_usd),
+        debt_usd: reading.map(|reading| reading.borrowed_in_usd),
+        tokens,
+    }
+}
+
+fn report_alert_failures(failures: Vec<(String, MonitorError)>) {
+    for (channel, e) in failures {
+        error!(channel = %channel, "Failed to send alert: {}", e);
+    }
+}
+
+/// Alert for a cooldown decision, if it calls for one. `alerted_health_factor` holds
+/// the health factor of the previous risk alert and is updated here.
+fn decision_alert_message(
+    position: &dyn MonitoredPosition,
+    reading: &HealthFactorReading,
+    liquidation_prices: &[LiquidationPrice],
+    decision: &AlertDecision,
+    alerted_health_factor: &mut Option<f64>,
+) -> Option<AlertMessage> {
+    let message = match decision {
+        AlertDecision::Suppress => return None,
+        AlertDecision::Fire => risk_alert_message(position, reading, liquidation_prices, None),
+        AlertDecision::Repeat { in_range_for } => {
+            risk_alert_message(position, reading, liquidation_prices, Some(*in_range_for))
+        }
+        AlertDecision::Escalate { critical_for } => {
+            risk_alert_message(position, reading, liquidation_prices, None)
+                .field("Critical For", format_duration(*critical_for))
+                .field(
+                    "Trend",
+                    health_factor_trend(*alerted_health_factor, reading.health_factor),
+                )
+        }
+        AlertDecision::Recovered => {
+            *alerted_health_factor = None;
+            return get_recovery_alerts_enabled()
+                .then(|| recovery_alert_message(position, reading));
+        }
+    };
+    *alerted_health_factor = Some(reading.health_factor);
+    Some(message)
+}
+
+/// Whether the health factor kept falling since the previous alert
+pub fn health_factor_trend(previous: Option<f64>, current: f64) -> String {
+    match previous {
+        Some(previous) if current < previous => {
+            format!("Still dropping (was {:.2} at the last alert)", previous)
+        }
+        Some(previous) => format!("Not dropping (was {:.2} at the last alert)", previous),
+        None => "Unknown".to_string(),
+    }
+}
+
+/// Build the alert sent when the position reaches a warning tier. Reminders carry how
+/// long the position has been continuously at risk.
+fn risk_alert_message(
+    position: &dyn MonitoredPosition,
+    reading: &HealthFactorReading,
+    liquidation_prices: &[LiquidationPrice],
+    in_range_for: Option<Duration>,
+) -> AlertMessage {
+    let mut message = AlertMessage::new(reading.level.emoji(), reading.level.title())
+        .severity(reading.level.severity())
+        .field("Chain", position.chain_name())
+        .field("Address", position.user_address())
+        .field("Health Factor", format!("{:.2}", reading.health_factor))
+        .field("Collateral", format_quote(reading.supply_in_usd))
+        .field("Debt", format_quote(reading.borrowed_in_usd))
+        .field("LTV", format!("{:.2}%", reading.ltv * 100.0))
+        .body(risk_alert_body(
+            reading.level,
+            reading.liquidation_threshold,
+        ))
+        .position(position.alert_position(Some(reading)));
+    // The single-pair token settings only describe the Ethereum position
+    if position.chain_name() == ETHEREUM.name {
+        message = message
+            .field(
+                "Supply Token",
+                format!(
+                    "{} (Decimals: {})",
+                    get_supply_token_address(),
+                    get_supply_token_decimals()
+                ),
+            )
+            .field(
+                "Borrow Token",
+                format!(
+                    "{} (Decimals: {})",
+                    get_borrowed_token_address(),
+                    get_borrowed_token_decimals()
+                ),
+            );
+    }
+    if get_liquidation_price_alerts_enabled() {
+        for price in liquidation_prices {
+            message = message.field(
+                &format!("{} Liquidation Price", price.symbol),
+                format!(
+                    "{} (now {}{})",
+                    format_quote(price.liquidation_price),
+                    format_quote(price.current_price),
+                    if price.is_breached() {
+                        ", already below"
+                    } else {
+                        ""
+                    }
+                ),
+            );
+        }
+    }
+    if let Some(in_range_for) = in_range_for {
+        message = message.field("In Range For", format_duration(in_range_for));
+    }
+    message
+}
+
+fn risk_alert_body(level: AlertLevel, liquidation_threshold: f64) -> String {
+    let advice = "• Repaying some debt\n\
+        • Adding more collateral\n\
+        • Closing the position";
+    match level {
+        AlertLevel::Critical => format!(
+            "Your Aave position is now in liquidation range!\n\n\
+            Please check your position immediately and consider:\n\
+            {}\n\n\
+            Health factor is below 1.0.\n\
+            (Borrowed value exceeds {}% of supply value)",
+            advice,
+            (liquidation_threshold * 100.0) as i32
+        ),
+        AlertLevel::Danger => format!(
+            "Your Aave position is close to liquidation.\n\n\
+            Borrowed value exceeds {}% of supply value. Consider acting soon:\n\
+            {}",
+            (get_alert_tier_danger_ltv() * 100.0) as i32,
+            advice
+        ),
+        AlertLevel::Warn | AlertLevel::Info => format!(
+            "Your Aave position is approaching liquidation.\n\n\
+            Borrowed value exceeds {}% of supply value. Keep an eye on it or consider:\n\
+            {}",
+            (get_alert_tier_warn_ltv() * 100.0) as i32,
+            advice
+        ),
+    }
+}
+
+/// Render a USD amount in QUOTE_CURRENCY, at the latest rate read for it
+pub fn format_quote(usd: f64) -> String {
+    let rate = quote_rate();
+    format_money(rate.convert(usd), &rate.currency)
+}
+
+/// Render an amount of `currency` with thousands separators: "€12,400.00" for
+/// currencies with a common symbol, "12,400.00 CHF" for the rest
+pub fn format_money(value: f64, currency: &str) -> String {
+    let cents = format!("{:.2}", value.abs());
+    let (whole, fraction) = cents.split_once('.').unwrap_or((&cents, "00"));
+    let mut grouped = String::new();
+    for (i, digit) in whole.chars().enumerate() {
+        if i > 0 && (whole.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    let sign = if value < 0.0 { "-" } else { "" };
+    let symbol = match currency {
+        "USD" => "$",
+        "EUR" => "€",
+        "GBP" => "£",
+        "JPY" => "¥",
+        _ => return format!("{}{}.{} {}", sign, grouped, fraction, currency),
+    };
+    format!("{}{}{}.{}", sign, symbol, grouped, fraction)
+}
+
+// Render a duration as e.g. "1h 5m 30s"
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match (secs / 3600, secs % 3600 / 60, secs % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, s) => format!("{}m {}s", m, s),
+        (h, m, s) => format!("{}h {}m {}s", h, m, s),
+    }
+}
+
+/// Build the alert sent when prices have been unavailable for a while, or are back
+fn price_outage_alert_message(
+    position: &dyn MonitoredPosition,
+    change: &PriceOutageChange,
+    error: Option<&MonitorError>,
+) -> AlertMessage {
+    match change {
+        PriceOutageChange::Started { failures, since } => {
+            let mut message = AlertMessage::new("⚠️", "PRICE FEED UNAVAILABLE")
+                .field("Chain", position.chain_name())
+                .field("Address", position.user_address())
+                .field("Failed Checks", failures)
+                .field("Failing For", format_duration(*since));
+            if let Some(error) = error {
+                message = message.field("Last Error", error);
+            }
+            message
+                .body(
+                    "The position can't be priced, so monitoring is degraded: no risk alert \
+                    can be sent until prices are available again.",
+                )
+                .position(position.alert_position(None))
+        }
+        PriceOutageChange::Recovered { lasted } => AlertMessage::new("✅", "PRICE FEED RESTORED")
+            .severity(AlertSeverity::Resolved)
+            .field("Chain", position.chain_name())
+            .field("Address", position.user_address())
+            .field("Outage", format_duration(*lasted))
+            .body("Prices are available again and the health factor is checked as usual.")
+            .position(position.alert_position(None)),
+    }
+}
+
+/// Whether an event worth `usd` crosses LARGE_EVENT_ALERT_USD, 0 meaning disabled
+pub fn is_large_event(usd: f64, threshold: f64) -> bool {
+    threshold > 0.0 && usd >= threshold
+}
+
+/// Alert on every event queued by `chain`'s listener that is worth at least
+/// LARGE_EVENT_ALERT_USD. This is about unexpected activity, not risk, so like
+/// liquidations it ignores `/pause`.
+async fn report_large_events(chain: &ChainState, alerters: &[Box<dyn Alerter>]) {
+    let notices = match chain.take_large_event_notices() {
+        Ok(notices) => notices,
+        Err(e) => {
+            error!(
+                chain = chain.name,
+                "Failed to read large event notices: {}", e
+            );
+            return;
+        }
+    };
+    let threshold = get_large_event_alert_usd();
+    for notice in notices {
+        match reserve_value_in_usd(chain, &notice.reserve, notice.amount).await {
+            Ok(usd) if is_large_event(usd, threshold) => {
+                let message = large_event_alert_message(chain, &notice, usd);
+                report_alert_failures(send_to_all(alerters, &message).await);
+            }
+            Ok(_) => {}
+            Err(e) => warn!(
+                chain = chain.name,
+                event_type = notice.event_type,
+                tx_hash = ?notice.tx_hash,
+                "Failed to value event for the large event alert: {}", e
+            ),
+        }
+    }
+}
+
+/// Build the alert sent for a single large supply, withdrawal, repay or borrow
+fn large_event_alert_message(
+    chain: &ChainState,
+    notice: &LargeEventNotice,
+    usd: f64,
+) -> AlertMessage {
+    let tx_hash = notice
+        .tx_hash
+        .map(|tx_hash| format!("{:?}", tx_hash))
+        .unwrap_or_else(|| "unknown".to_string());
+    AlertMessage::new(
+        "ℹ️",
+        &format!("LARGE {}", notice.event_type.to_ascii_uppercase()),
+    )
+    .field("Chain", chain.name)
+    .field("Address", chain.user_address())
+    .field(
+        "Amount",
+        format!("{} of {:?}", notice.amount, notice.reserve),
+    )
+    .field("Value", format_quote(usd))
+    .field("Transaction", tx_hash)
+    .body(
+        "A single event moved more than the configured LARGE_EVENT_ALERT_USD. \
+        If you didn't make this change, check your account.",
+    )
+    .position(alert_position(chain, None))
+}
+
+/// Build the alert sent when the position is back out of liquidation range
+fn recovery_alert_message(
+    position: &dyn MonitoredPosition,
+    reading: &HealthFactorReading,
+) -> AlertMessage {
+    AlertMessage::new("✅", "POSITION RECOVERED")
+        .severity(AlertSeverity::Resolved)
+        .field("Chain", position.chain_name())
+        .field("Address", position.user_address())
+        .field("Health Factor", format!("{:.2}", reading.health_factor))
+        .field(
+            "Buffer",
+            format!(
+                "{:.2}% above liquidation",
+                (reading.health_factor - 1.0) * 100.0
+            ),
+        )
+        .field("Collateral", format_quote(reading.supply_in_usd))
+        .field("Debt", format_quote(reading.borrowed_in_usd))
+        .body("Your position is back below every warning tier.")
+        .position(position.alert_position(Some(reading)))
+}
+
+/// Build the alert sent when the tracked position was actually liquidated
+fn liquidated_alert_message(chain: &ChainState, notice: &LiquidationNotice) -> AlertMessage {
+    let tx_hash = notice
+        .tx_hash
+        .map(|tx_hash| format!("{:?}", tx_hash))
+        .unwrap_or_else(|| "unknown".to_string());
+    AlertMessage::new("💥", "YOU WERE LIQUIDATED")
+        .severity(AlertSeverity::Critical)
+        .field("Chain", chain.name)
+        .field("Address", chain.user_address())
+        .field(
+            "Collateral Seized",
+            format!(
+                "{} of {:?}",
+                notice.liquidated_collateral_amount, notice.collateral_asset
+            ),
+        )
+        .field(
+            "Debt Repaid",
+            format!("{} of {:?}", notice.debt_to_cover, notice.debt_asset),
+        )
+        .field("Liquidator", format!("{:?}", notice.liquidator))
+        .field("Transaction", tx_hash)
+        .body(
+            "Part of your Aave position has been liquidated. \
+            The tracked position was updated accordingly.",
+        )
+        .position(alert_position(chain, None))
+}
 
-        bot.send_message(recipient, message)
-            .parse_mode(ParseMode::MarkdownV2)
-            .await?;
+pub type PriceCache = Mutex<HashMap<String, (PriceResult, Instant)>>;
+
+// Last fetched price per token contract, shared by all spawned tasks
+lazy_static::lazy_static! {
+    static ref PRICE_CACHE: Arc<PriceCache> = Arc::new(Mutex::new(HashMap::new()));
+    static ref PRICE_SOURCES: Vec<Box<dyn PriceSource>> = configured_price_sources();
+}
+
+pub async fn get_price(smart_contract: String) -> Result<Option<PriceResult>, MonitorError> {
+    let ttl = Duration::from_secs(get_price_cache_ttl_secs());
+    get_price_with_cache(&PRICE_CACHE, smart_contract, ttl, |id| async move {
+        fetch_price_from_sources(&PRICE_SOURCES, &id).await
+    })
+    .await
+}
+
+/// A price that is safe to value the position with: positive, finite and no older
+/// than `PRICE_MAX_AGE_SECS`. A rejected cached price is fetched afresh once
+/// before giving up, so the health factor is never computed from it.
+pub async fn get_checked_price(fungible_id: String) -> Result<PriceResult, MonitorError> {
+    let max_age_secs = get_price_max_age_secs();
+    let mut refetched = false;
+    loop {
+        let price = get_price(fungible_id.clone()).await?.ok_or_else(|| {
+            MonitorError::Price(format!("No price available for {}", fungible_id))
+        })?;
+        let now = chrono::Utc::now().timestamp() as u64;
+        match check_price(&price, now, max_age_secs) {
+            Ok(()) => return Ok(price),
+            Err(e) if !refetched => {
+                warn!("Rejected price of {}, fetching again: {}", fungible_id, e);
+                PRICE_CACHE.lock()?.remove(&fungible_id);
+                refetched = true;
+            }
+            Err(e) => {
+                warn!("Rejected price of {}: {}", fungible_id, e);
+                return Err(e);
+            }
+        }
     }
+}
 
+/// Reject a price the position must not be valued with: not in USD, zero, negative
+/// or not a number, or observed more than `max_age_secs` before `now` (0 disables the
+/// age check)
+pub fn check_price(price: &PriceResult, now: u64, max_age_secs: u64) -> Result<(), MonitorError> {
+    if price.currency != "USD" {
+        return Err(MonitorError::Price(format!(
+            "{} is priced in {}, not USD",
+            price.symbol, price.currency
+        )));
+    }
+    if !price.price.is_finite() || price.price <= 0.0 {
+        return Err(MonitorError::Price(format!(
+            "invalid {} price {}",
+            price.symbol, price.price
+        )));
+    }
+    let age = now.saturating_sub(price.fetched_at);
+    if max_age_secs > 0 && age > max_age_secs {
+        return Err(MonitorError::Price(format!(
+            "{} price is stale ({}s old, max {}s)",
+            price.symbol, age, max_age_secs
+        )));
+    }
     Ok(())
 }
 
-pub async fn get_price(smart_contract: String) -> Result<Option<PriceResult>, reqwest::Error> {
-    //todo: read api key from env var.
-    let api_key = "secret_sk_1234567890";
-    let mut url = Url::parse("https://api.simplehash.com/api/v0/fungibles/assets").unwrap();
+/// Return the cached price when it is younger than `ttl`, otherwise fetch and repopulate
+pub async fn get_price_with_cache<F, Fut>(
+    cache: &PriceCache,
+    smart_contract: String,
+    ttl: Duration,
+    fetch: F,
+) -> Result<Option<PriceResult>, MonitorError>
+where
+    F: FnOnce(String) -> Fut,
+    Fut: Future<Output = Result<Option<PriceResult>, MonitorError>>,
+{
+    {
+        let entries = cache.lock()?;
+        if let Some((price, fetched_at)) = entries.get(&smart_contract) {
+            if fetched_at.elapsed() < ttl {
+                return Ok(Some(price.clone()));
+            }
+        }
+    }
+
+    let price = fetch(smart_contract.clone()).await?;
+    if let Some(price) = &price {
+        cache
+            .lock()?
+            .insert(smart_contract, (price.clone(), Instant::now()));
+    }
+    Ok(price)
+}
+
+// One client for every price request, so connections are reused and every request
+// is bounded by the configured timeout
+lazy_static::lazy_static! {
+    pub(crate) static ref PRICE_CLIENT: Client = Client::builder()
+        .timeout(Duration::from_secs(get_price_request_timeout_secs()))
+        .build()
+        .unwrap_or_else(|e| {
+            warn!("Failed to build price client, using defaults: {}", e);
+            Client::new()
+        });
+}
+
+// Every SimpleHash request, from any chain or task, draws from the same bucket
+lazy_static::lazy_static! {
+    static ref SIMPLEHASH_LIMITER: Arc<RateLimiter> =
+        Arc::new(RateLimiter::new(get_simplehash_rps()));
+}
+
+/// GET a SimpleHash URL at no more than `SIMPLEHASH_RPS`, retrying timeouts,
+/// connection errors, server errors and rate limits with exponential backoff.
+/// Rate-limited retries wait as long as the server's `Retry-After` asks.
+async fn send_price_request(url: Url, api_key: &str) -> Result<String, MonitorError> {
+    let max_retries = get_price_max_retries();
+    let mut attempt = 0;
+    loop {
+        SIMPLEHASH_LIMITER.acquire().await;
+        let response = PRICE_CLIENT
+            .get(url.clone())
+            .header("X-API-KEY", api_key)
+            .header("Accepts", "application/json")
+            .send()
+            .await;
+        let (error, retry_after) = match response {
+            Ok(resp) if resp.status() == StatusCode::TOO_MANY_REQUESTS => {
+                let retry_after = parse_retry_after(
+                    resp.headers()
+                        .get(header::RETRY_AFTER)
+                        .and_then(|value| value.to_str().ok()),
+                );
+                (
+                    MonitorError::Price("SimpleHash rate limit exceeded".to_string()),
+                    retry_after,
+                )
+            }
+            Ok(resp) if resp.status().is_server_error() => (
+                MonitorError::Price(format!("SimpleHash returned {}", resp.status())),
+                None,
+            ),
+            Ok(resp) => {
+                return resp.text().await.map_err(|e| {
+                    MonitorError::Price(format!("Failed to read SimpleHash response: {}", e))
+                })
+            }
+            Err(e) if e.is_timeout() || e.is_connect() || e.is_request() => (
+                MonitorError::Price(format!("Failed to send SimpleHash request: {}", e)),
+                None,
+            ),
+            Err(e) => {
+                return Err(MonitorError::Price(format!(
+                    "Failed to send SimpleHash request: {}",
+                    e
+                )))
+            }
+        };
+        if attempt >= max_retries {
+            return Err(error);
+        }
+        let delay = price_retry_delay(attempt, retry_after);
+        if retry_after.is_some() {
+            // The limit is shared, so every other price request holds off as well
+            SIMPLEHASH_LIMITER.pause_for(delay).await;
+        }
+        warn!(
+            "{}; retrying in {:?} ({}/{})",
+            error,
+            delay,
+            attempt + 1,
+            max_retries
+        );
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+/// Seconds form of a `Retry-After` header; HTTP dates fall back to the normal backoff
+pub fn parse_retry_after(value: Option<&str>) -> Option<Duration> {
+    value?.trim().parse::<u64>().ok().map(Duration::from_secs)
+}
+
+/// Delay before retry number `attempt` (0-based): the server's `Retry-After` when
+/// given, otherwise 500ms doubling per attempt. Both are capped at a minute so a
+/// single price can't stall the health-factor loop for long.
+pub fn price_retry_delay(attempt: u32, retry_after: Option<Duration>) -> Duration {
+    let max_delay = Duration::from_secs(60);
+    let delay = retry_after
+        .unwrap_or_else(|| Duration::from_millis(500).saturating_mul(2u32.saturating_pow(attempt)));
+    delay.min(max_delay)
+}
+
+async fn fetch_simplehash_price(
+    smart_contract: String,
+) -> Result<Option<PriceResult>, MonitorError> {
+    let api_key = get_simplehash_api_key()?;
+    fetch_simplehash_price_from(&get_simplehash_base_url(), &api_key, smart_contract).await
+}
+
+/// Price of a token from the SimpleHash API served at `base_url`
+async fn fetch_simplehash_price_from(
+    base_url: &str,
+    api_key: &str,
+    smart_contract: String,
+) -> Result<Option<PriceResult>, MonitorError> {
+    let mut url = Url::parse(&format!(
+        "{}/api/v0/fungibles/assets",
+        base_url.trim_end_matches('/')
+    ))
+    .map_err(|e| MonitorError::Config(format!("Invalid SIMPLEHASH_BASE_URL: {}", e)))?;
 
-    //if it starts with 0x then it is eth
-    let smart_contract = if smart_contract.starts_with("0x") {
+    // Chain-qualified ids (see `fungible_id`) are used as is; a bare 0x address is
+    // an Ethereum token, anything else a Solana mint
+    let smart_contract = if smart_contract.contains('.') {
+        smart_contract
+    } else if smart_contract.starts_with("0x") {
         format!("ethereum.{}", smart_contract)
     } else {
         format!("solana.{}", smart_contract)
@@ -202,25 +2701,16 @@ pub async fn get_price(smart_contract: String) -> Result<Option<PriceResult>, re
             .join("&"),
     ));
 
-    let client = Client::new();
-    let resp = client
-        .get(url)
-        .header("X-API-KEY", api_key)
-        .header("Accepts", "application/json")
-        .send()
-        .await
-        .expect("Failed to send request")
-        .text()
-        .await?;
+    let resp = send_price_request(url, api_key).await?;
 
     let parsed: Result<SimplehashPriceResp, _> = serde_json::from_str(&resp);
 
     if parsed.is_err() {
-        log::warn!(
+        warn!(
             "Failed parsed response simplehash for address {}",
             smart_contract
         );
-        log::warn!("Response: {:?}", resp);
+        warn!("Response: {:?}", resp);
         return Ok(None);
     }
     let parsed = parsed.unwrap();
@@ -232,23 +2722,20 @@ pub async fn get_price(smart_contract: String) -> Result<Option<PriceResult>, re
         .collect();
 
     if !high_precision_values.is_empty() {
-        let mut avg = get_avg(high_precision_values.clone());
-        if avg.is_none() {
-            avg = Some(high_precision_values[0]);
-        }
-
-        if avg.is_none() {
-            log::warn!("Failed to calculate average for address {}", smart_contract);
+        let Some(avg) = get_avg(high_precision_values) else {
+            warn!("No usable prices for address {}", smart_contract);
             return Ok(None);
-        }
+        };
 
         return Ok(Some(PriceResult {
-            price: avg.expect("Should never be None"),
+            price: avg,
             decimals: parsed.decimals,
             symbol: parsed.symbol,
+            fetched_at: chrono::Utc::now().timestamp() as u64,
+            currency: "USD".to_string(),
         }));
     } else {
-        log::warn!("No prices for address {}", smart_contract);
+        warn!("No prices for address {}", smart_contract);
     }
 
     return Ok(None);
@@ -270,24 +2757,53 @@ pub struct SimplehashPriceResp {
     pub symbol: String,
 }
 
+/// Average of the prices quoted by different marketplaces, ignoring outliers.
+///
+/// Zero, negative and non-numeric quotes are dropped first. Of the rest, quotes more
+/// than 3 scaled median absolute deviations (MAD) from the median are rejected and
+/// the remaining ones averaged. Unlike a cut around the mean, a single wild quote
+/// can't drag the reference point along with it. When more than half the quotes
+/// agree exactly the MAD is 0 and only those are kept. `None` without usable quotes.
 fn get_avg(prices: Vec<f64>) -> Option<f64> {
-    let mean: f64 = prices.iter().sum::<f64>() / prices.len() as f64;
+    let prices: Vec<f64> = prices
+        .into_iter()
+        .filter(|price| price.is_finite() && *price > 0.0)
+        .collect();
+    let median = median(&prices)?;
+    let deviations: Vec<f64> = prices.iter().map(|price| (price - median).abs()).collect();
+    // 1.4826 scales the MAD to a standard deviation for normally distributed quotes
+    let cutoff = 3.0 * 1.4826 * median(&deviations)?;
 
-    let new_v: Vec<f64> = prices
+    let kept: Vec<f64> = prices
         .into_iter()
-        .filter(|&price| (price - mean).abs() <= mean)
+        .filter(|price| (price - median).abs() <= cutoff)
         .collect();
+    Some(kept.iter().sum::<f64>() / kept.len() as f64)
+}
 
-    if new_v.is_empty() {
+fn median(values: &[f64]) -> Option<f64> {
+    if values.is_empty() {
         return None;
     }
-    let avg = new_v.iter().sum::<f64>() / new_v.len() as f64;
-    Some(avg)
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let middle = sorted.len() / 2;
+    if sorted.len() % 2 == 0 {
+        Some((sorted[middle - 1] + sorted[middle]) / 2.0)
+    } else {
+        Some(sorted[middle])
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct PriceResult {
     pub symbol: String,
+    // USD per whole token
     pub price: f64,
+    // Token decimals as reported by the price source; see `valuation_decimals`
     pub decimals: u64,
+    // Unix time the price was observed at its source
+    pub fetched_at: u64,
+    // Currency `price` is quoted in; the position is only valued with USD prices
+    pub currency: String,
 }
@@ -0,0 +1,480 @@
+use ethers::prelude::{Address, H256, U256};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{header, Body, Method, Request, Response, Server, StatusCode};
+use std::collections::{BTreeMap, HashMap};
+use std::convert::Infallible;
+use std::fmt::Write;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Mutex;
+
+use crate::chains::enabled_chains;
+use crate::chains::ethereum::ethereum_chain::ChainState;
+use crate::error::MonitorError;
+use crate::probes::{is_live, readiness_blocker};
+
+const OPENMETRICS_CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+// Values of one account's latest health-factor check, which can't be read back
+// from the tracked position
+#[derive(Debug, Default)]
+struct AccountMetrics {
+    supply_usd: Option<f64>,
+    borrow_usd: Option<f64>,
+    health_factor: Option<f64>,
+    // Collateral price per reserve at which the health factor reaches 1.0
+    liquidation_prices: Vec<(Address, f64)>,
+}
+
+// Counters kept per chain, whichever account the events were applied to
+#[derive(Debug, Default)]
+struct ChainMetrics {
+    // Events processed per type, with the tx of the latest one as exemplar
+    events: BTreeMap<&'static str, (u64, Option<H256>)>,
+    // Withdraw/Repay events larger than the tracked balance, per event type
+    underflows: BTreeMap<&'static str, u64>,
+}
+
+lazy_static::lazy_static! {
+    static ref METRICS: Mutex<BTreeMap<&'static str, ChainMetrics>> = Mutex::new(BTreeMap::new());
+    // Keyed by chain and tracked account
+    static ref ACCOUNT_METRICS: Mutex<BTreeMap<(&'static str, String), AccountMetrics>> =
+        Mutex::new(BTreeMap::new());
+}
+
+/// Record the latest health-factor computation of an account on a chain
+pub fn record_health_factor(
+    chain: &'static str,
+    user: &str,
+    supply_usd: f64,
+    borrow_usd: f64,
+    health_factor: f64,
+) {
+    if let Ok(mut metrics) = ACCOUNT_METRICS.lock() {
+        let metrics = metrics.entry((chain, user.to_string())).or_default();
+        metrics.supply_usd = Some(supply_usd);
+        metrics.borrow_usd = Some(borrow_usd);
+        metrics.health_factor = Some(health_factor);
+    }
+}
+
+/// Supply USD, borrow USD and health factor of an account's latest health-factor check
+pub fn latest_valuation(
+    chain: &'static str,
+    user: &str,
+) -> (Option<f64>, Option<f64>, Option<f64>) {
+    match ACCOUNT_METRICS.lock() {
+        Ok(metrics) => metrics
+            .get(&(chain, user.to_string()))
+            .map(|recorded| {
+                (
+                    recorded.supply_usd,
+                    recorded.borrow_usd,
+                    recorded.health_factor,
+                )
+            })
+            .unwrap_or_default(),
+        Err(_) => (None, None, None),
+    }
+}
+
+/// Count an applied position event, keeping its transaction as exemplar
+pub fn record_event(chain: &'static str, event_type: &'static str, tx_hash: Option<H256>) {
+    if let Ok(mut metrics) = METRICS.lock() {
+        let (count, exemplar) = metrics
+            .entry(chain)
+            .or_default()
+            .events
+            .entry(event_type)
+            .or_default();
+        *count += 1;
+        if tx_hash.is_some() {
+            *exemplar = tx_hash;
+        }
+    }
+}
+
+/// Record the latest liquidation price of each collateral reserve of an account
+pub fn record_liquidation_prices(chain: &'static str, user: &str, prices: &[(Address, f64)]) {
+    if let Ok(mut metrics) = ACCOUNT_METRICS.lock() {
+        metrics
+            .entry((chain, user.to_string()))
+            .or_default()
+            .liquidation_prices = prices.to_vec();
+    }
+}
+
+/// Count an event whose amount exceeded the tracked balance, i.e. the tracked
+/// position had drifted from the chain
+pub fn record_underflow(chain: &'static str, event_type: &'static str) {
+    if let Ok(mut metrics) = METRICS.lock() {
+        *metrics
+            .entry(chain)
+            .or_default()
+            .underflows
+            .entry(event_type)
+            .or_default() += 1;
+    }
+}
+
+/// Render every metric of `chains` (one state per tracked account) in Prometheus
+/// text format. Position and health-factor series are labelled by chain and user,
+/// counters and the latest block by chain only. OpenMetrics output additionally
+/// carries the triggering tx hash as an exemplar on the event counters.
+pub fn render_metrics(chains: &[&ChainState], openmetrics: bool) -> String {
+    let metrics = match METRICS.lock() {
+        Ok(metrics) => metrics,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let account_metrics = match ACCOUNT_METRICS.lock() {
+        Ok(metrics) => metrics,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let positions: Vec<_> = chains
+        .iter()
+        .map(|chain| (account_labels(chain), chain.position().unwrap_or_default()))
+        .collect();
+    let recorded: Vec<_> = chains
+        .iter()
+        .map(|chain| {
+            (
+                account_labels(chain),
+                account_metrics.get(&(chain.name, chain.user_address())),
+            )
+        })
+        .collect();
+    // Chain-wide series are written once per chain, from its first account
+    let primary_chains: Vec<_> = chains
+        .iter()
+        .filter(|chain| chain.is_primary_account())
+        .collect();
+    let mut out = String::new();
+
+    write_amounts(
+        &mut out,
+        "aave_supplied_amount",
+        "Raw supplied token amount per reserve",
+        positions
+            .iter()
+            .map(|(labels, position)| (labels.as_str(), &position.supplied)),
+    );
+    write_amounts(
+        &mut out,
+        "aave_borrowed_amount",
+        "Raw borrowed token amount per reserve",
+        positions
+            .iter()
+            .map(|(labels, position)| (labels.as_str(), &position.borrowed)),
+    );
+    let variable_borrowed: Vec<_> = positions
+        .iter()
+        .map(|(labels, position)| (labels.as_str(), position.variable_borrowed()))
+        .collect();
+    write_amounts(
+        &mut out,
+        "aave_variable_borrowed_amount",
+        "Raw variable-rate borrowed token amount per reserve",
+        variable_borrowed
+            .iter()
+            .map(|(labels, amounts)| (*labels, amounts)),
+    );
+    write_amounts(
+        &mut out,
+        "aave_stable_borrowed_amount",
+        "Raw stable-rate borrowed token amount per reserve",
+        positions
+            .iter()
+            .map(|(labels, position)| (labels.as_str(), &position.stable_borrowed)),
+    );
+    write_gauge(
+        &mut out,
+        "aave_supply_usd",
+        "USD value of the supplied collateral",
+        recorded.iter().map(|(labels, recorded)| {
+            (
+                labels.as_str(),
+                recorded.and_then(|recorded| recorded.supply_usd),
+            )
+        }),
+    );
+    write_gauge(
+        &mut out,
+        "aave_borrow_usd",
+        "USD value of the borrowed debt",
+        recorded.iter().map(|(labels, recorded)| {
+            (
+                labels.as_str(),
+                recorded.and_then(|recorded| recorded.borrow_usd),
+            )
+        }),
+    );
+    write_gauge(
+        &mut out,
+        "aave_health_factor",
+        "Health factor computed from current prices",
+        recorded.iter().map(|(labels, recorded)| {
+            (
+                labels.as_str(),
+                recorded.and_then(|recorded| recorded.health_factor),
+            )
+        }),
+    );
+    let chain_labels: Vec<_> = primary_chains
+        .iter()
+        .map(|chain| format!("chain=\"{}\"", chain.name))
+        .collect();
+    write_gauge(
+        &mut out,
+        "aave_latest_block",
+        "Latest block number seen on the chain",
+        primary_chains
+            .iter()
+            .zip(&chain_labels)
+            .map(|(chain, labels)| {
+                (
+                    labels.as_str(),
+                    Some(chain.latest_block())
+                        .filter(|block| *block > 0)
+                        .map(|block| block as f64),
+                )
+            }),
+    );
+
+    let _ = writeln!(
+        out,
+        "# HELP aave_liquidation_price Collateral price (USD) at which the health factor reaches 1.0"
+    );
+    let _ = writeln!(out, "# TYPE aave_liquidation_price gauge");
+    for (labels, recorded) in &recorded {
+        let Some(recorded) = recorded else {
+            continue;
+        };
+        for (reserve, price) in &recorded.liquidation_prices {
+            let _ = writeln!(
+                out,
+                "aave_liquidation_price{{{},reserve=\"{:?}\"}} {}",
+                labels,
+                reserve,
+                format_sample(*price)
+            );
+        }
+    }
+
+    // OpenMetrics names the counter family without the _total suffix
+    let family = if openmetrics {
+        "aave_events_processed"
+    } else {
+        "aave_events_processed_total"
+    };
+    let _ = writeln!(
+        out,
+        "# HELP {} Aave Pool events applied to the position by type",
+        family
+    );
+    let _ = writeln!(out, "# TYPE {} counter", family);
+    for chain in &primary_chains {
+        let Some(chain_metrics) = metrics.get(chain.name) else {
+            continue;
+        };
+        for (event_type, (count, exemplar)) in &chain_metrics.events {
+            let _ = write!(
+                out,
+                "aave_events_processed_total{{chain=\"{}\",type=\"{}\"}} {}",
+                chain.name, event_type, count
+            );
+            if let (true, Some(tx_hash)) = (openmetrics, exemplar) {
+                let _ = write!(out, " # {{tx_hash=\"{:?}\"}} 1", tx_hash);
+            }
+            out.push('\n');
+        }
+    }
+
+    // The health-factor and position series are gauges, which OpenMetrics doesn't
+    // allow exemplars on. This per-account counter carries the transaction behind
+    // the latest change instead, so a jump in those gauges can be traced to it.
+    let family = if openmetrics {
+        "aave_position_changes"
+    } else {
+        "aave_position_changes_total"
+    };
+    let _ = writeln!(
+        out,
+        "# HELP {} Changes applied to the tracked position by events and reorg rollbacks",
+        family
+    );
+    let _ = writeln!(out, "# TYPE {} counter", family);
+    for (chain, (labels, position)) in chains.iter().zip(&positions) {
+        let _ = write!(
+            out,
+            "aave_position_changes_total{{{}}} {}",
+            labels,
+            chain.event_count()
+        );
+        if let (true, Some(tx_hash)) = (openmetrics, position.last_event_tx) {
+            let _ = write!(out, " # {{tx_hash=\"{:?}\"}} 1", tx_hash);
+        }
+        out.push('\n');
+    }
+
+    let family = if openmetrics {
+        "aave_position_underflows"
+    } else {
+        "aave_position_underflows_total"
+    };
+    let _ = writeln!(
+        out,
+        "# HELP {} Events that exceeded the tracked balance, each triggering a resync",
+        family
+    );
+    let _ = writeln!(out, "# TYPE {} counter", family);
+    for chain in &primary_chains {
+        let Some(chain_metrics) = metrics.get(chain.name) else {
+            continue;
+        };
+        for (event_type, count) in &chain_metrics.underflows {
+            let _ = writeln!(
+                out,
+                "aave_position_underflows_total{{chain=\"{}\",type=\"{}\"}} {}",
+                chain.name, event_type, count
+            );
+        }
+    }
+
+    if openmetrics {
+        out.push_str("# EOF\n");
+    }
+    out
+}
+
+// `chain="ethereum",user="0x..."`, the labels of an account's series
+fn account_labels(chain: &ChainState) -> String {
+    format!("chain=\"{}\",user=\"{}\"", chain.name, chain.user_address())
+}
+
+fn write_amounts<'a>(
+    out: &mut String,
+    name: &str,
+    help: &str,
+    accounts: impl Iterator<Item = (&'a str, &'a HashMap<Address, U256>)>,
+) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} gauge", name);
+    for (labels, amounts) in accounts {
+        let mut reserves: Vec<_> = amounts.iter().collect();
+        reserves.sort_by_key(|(reserve, _)| **reserve);
+        for (reserve, amount) in reserves {
+            let _ = writeln!(
+                out,
+                "{}{{{},reserve=\"{:?}\"}} {}",
+                name, labels, reserve, amount
+            );
+        }
+    }
+}
+
+fn write_gauge<'a>(
+    out: &mut String,
+    name: &str,
+    help: &str,
+    series: impl Iterator<Item = (&'a str, Option<f64>)>,
+) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} gauge", name);
+    for (labels, value) in series {
+        if let Some(value) = value {
+            let _ = writeln!(out, "{}{{{}}} {}", name, labels, format_sample(value));
+        }
+    }
+}
+
+// Prometheus spells non-finite values +Inf, -Inf and NaN
+fn format_sample(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "+Inf" } else { "-Inf" }.to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+fn plain_response(status: StatusCode, body: String) -> Response<Body> {
+    let mut response = Response::new(Body::from(body));
+    *response.status_mut() = status;
+    response
+}
+
+/// Liveness and readiness probes: cheap flag checks, never an external call
+pub fn probe_response(path: &str) -> Option<Response<Body>> {
+    match path {
+        "/healthz" if is_live() => Some(plain_response(StatusCode::OK, "ok".to_string())),
+        "/healthz" => Some(plain_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "starting".to_string(),
+        )),
+        "/readyz" => Some(match readiness_blocker() {
+            None => plain_response(StatusCode::OK, "ready".to_string()),
+            Some(reason) => plain_response(
+                StatusCode::SERVICE_UNAVAILABLE,
+                format!("not ready: {}", reason),
+            ),
+        }),
+        _ => None,
+    }
+}
+
+async fn handle_request(request: Request<Body>) -> Result<Response<Body>, Infallible> {
+    if request.method() == Method::GET {
+        if let Some(response) = probe_response(request.uri().path()) {
+            return Ok(response);
+        }
+    }
+    if request.method() != Method::GET || request.uri().path() != "/metrics" {
+        return Ok(plain_response(
+            StatusCode::NOT_FOUND,
+            "Not Found".to_string(),
+        ));
+    }
+
+    let openmetrics = request
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| accept.contains("application/openmetrics-text"));
+    let content_type = if openmetrics {
+        OPENMETRICS_CONTENT_TYPE
+    } else {
+        PROMETHEUS_CONTENT_TYPE
+    };
+
+    let chains = enabled_chains();
+    let mut response = Response::new(Body::from(render_metrics(&chains, openmetrics)));
+    if let Ok(value) = header::HeaderValue::from_str(content_type) {
+        response.headers_mut().insert(header::CONTENT_TYPE, value);
+    }
+    Ok(response)
+}
+
+/// Serve `/metrics`, `/healthz` and `/readyz` on `port` until `shutdown` resolves
+pub async fn serve_metrics(
+    port: u16,
+    shutdown: impl Future<Output = ()>,
+) -> Result<(), MonitorError> {
+    let addr = SocketAddr::from(([0, 0, 0, 0], port));
+    let make_service =
+        make_service_fn(|_conn| async { Ok::<_, Infallible>(service_fn(handle_request)) });
+
+    let server = Server::try_bind(&addr)
+        .map_err(|e| MonitorError::Http(format!("Failed to bind metrics port {}: {}", port, e)))?
+        .serve(make_service);
+    tracing::info!(
+        "Serving Prometheus metrics on http://{}/metrics, probes on /healthz and /readyz",
+        addr
+    );
+
+    server
+        .with_graceful_shutdown(shutdown)
+        .await
+        .map_err(|e| MonitorError::Http(format!("Metrics server failed: {}", e)))
+}
@@ -0,0 +1,512 @@
+use async_trait::async_trait;
+use ethers::prelude::Address;
+use reqwest::{StatusCode, Url};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::chains::ethereum::ethereum_chain::{
+    fetch_chainlink_answer, fetch_uniswap_twap_tick, find_deepest_uniswap_pool,
+    uniswap_tick_to_price, ChainlinkAnswer,
+};
+use crate::chains::{
+    configured_chains, ensure_token_decimals, get_chainlink_feeds, get_coingecko_api_key,
+    get_coingecko_base_url, get_coingecko_platforms, get_price_cache_ttl_secs,
+    get_price_source_order, get_quote_currency, get_quote_currency_feed, get_token_decimals,
+    get_token_symbol, get_uniswap_twap_pairs, get_uniswap_twap_window_secs, get_uniswap_v3_factory,
+    Chain, PriceSourceKind,
+};
+use crate::error::MonitorError;
+use crate::probes::record_price_fetched;
+use crate::stats::STATS;
+use crate::{fetch_simplehash_price, PriceResult, PRICE_CLIENT};
+
+/// Somewhere USD prices can be read from. Tokens are named by fungible id
+/// (`chain.0x...`, see `fungible_id`).
+#[async_trait]
+pub trait PriceSource: Send + Sync {
+    /// Source name used in logs
+    fn name(&self) -> &str;
+
+    /// `None` when the source has no price for the token
+    async fn price(&self, fungible_id: &str) -> Result<Option<PriceResult>, MonitorError>;
+}
+
+/// Build every source that has configuration, in PRICE_SOURCE order followed by
+/// the sources it doesn't name. Coingecko is only used when named or given a key.
+pub fn configured_price_sources() -> Vec<Box<dyn PriceSource>> {
+    let mut order = get_price_source_order();
+    let coingecko_named = order.contains(&PriceSourceKind::Coingecko);
+    for kind in [
+        PriceSourceKind::SimpleHash,
+        PriceSourceKind::Chainlink,
+        PriceSourceKind::Coingecko,
+        PriceSourceKind::UniswapTwap,
+    ] {
+        if !order.contains(&kind) {
+            order.push(kind);
+        }
+    }
+
+    let mut sources: Vec<Box<dyn PriceSource>> = Vec::new();
+    for kind in &order {
+        if *kind != PriceSourceKind::UniswapTwap {
+            sources.extend(price_source(*kind, coingecko_named));
+            continue;
+        }
+        match get_uniswap_twap_pairs() {
+            Ok(pairs) if !pairs.is_empty() => sources.push(Box::new(UniswapTwapSource {
+                pairs,
+                window_secs: get_uniswap_twap_window_secs(),
+                // Quote tokens are priced by every other source, in the same order
+                quote_sources: order
+                    .iter()
+                    .filter(|kind| **kind != PriceSourceKind::UniswapTwap)
+                    .filter_map(|kind| price_source(*kind, coingecko_named))
+                    .collect(),
+            })),
+            Ok(_) => tracing::debug!("UNISWAP_TWAP_PAIRS is not set, skipping Uniswap prices"),
+            Err(e) => tracing::warn!("Ignoring UNISWAP_TWAP_PAIRS: {}", e),
+        }
+    }
+
+    sources
+}
+
+// One of the sources priced directly from an API or feed, if it has configuration
+fn price_source(kind: PriceSourceKind, coingecko_named: bool) -> Option<Box<dyn PriceSource>> {
+    match kind {
+        PriceSourceKind::SimpleHash => Some(Box::new(SimpleHashSource)),
+        PriceSourceKind::Chainlink => match get_chainlink_feeds() {
+            Ok(feeds) if !feeds.is_empty() => Some(Box::new(ChainlinkSource { feeds })),
+            Ok(_) => {
+                tracing::debug!("CHAINLINK_FEEDS is not set, skipping Chainlink prices");
+                None
+            }
+            Err(e) => {
+                tracing::warn!("Ignoring CHAINLINK_FEEDS: {}", e);
+                None
+            }
+        },
+        PriceSourceKind::Coingecko => {
+            let api_key = get_coingecko_api_key();
+            if !coingecko_named && api_key.is_none() {
+                tracing::debug!("COINGECKO_API_KEY is not set, skipping Coingecko prices");
+                return None;
+            }
+            match get_coingecko_platforms() {
+                Ok(platforms) => Some(Box::new(CoingeckoSource {
+                    base_url: get_coingecko_base_url(),
+                    api_key,
+                    platforms,
+                })),
+                Err(e) => {
+                    tracing::warn!("Ignoring Coingecko prices: {}", e);
+                    None
+                }
+            }
+        }
+        // Built by `configured_price_sources`, as it prices through the others
+        PriceSourceKind::UniswapTwap => None,
+    }
+}
+
+/// Ask each source in turn until one has a price. When none does, the last
+/// error is returned, or `None` if every source simply lacked the token.
+pub async fn fetch_price_from_sources(
+    sources: &[Box<dyn PriceSource>],
+    fungible_id: &str,
+) -> Result<Option<PriceResult>, MonitorError> {
+    let mut last_error = None;
+    for source in sources {
+        match source.price(fungible_id).await {
+            Ok(Some(price)) => {
+                record_price_fetched();
+                return Ok(Some(price));
+            }
+            Ok(None) => tracing::debug!("{} has no price for {}", source.name(), fungible_id),
+            Err(e) => {
+                tracing::warn!("{} price for {} failed: {}", source.name(), fungible_id, e);
+                STATS.record_price_failure();
+                last_error = Some(e);
+            }
+        }
+    }
+    match last_error {
+        Some(e) => Err(e),
+        None => Ok(None),
+    }
+}
+
+/// Prices from the SimpleHash fungibles API
+pub struct SimpleHashSource;
+
+#[async_trait]
+impl PriceSource for SimpleHashSource {
+    fn name(&self) -> &str {
+        "SimpleHash"
+    }
+
+    async fn price(&self, fungible_id: &str) -> Result<Option<PriceResult>, MonitorError> {
+        fetch_simplehash_price(fungible_id.to_string()).await
+    }
+}
+
+/// Prices read on-chain from Chainlink aggregators, the oracles Aave itself uses
+pub struct ChainlinkSource {
+    // Aggregator per fungible id
+    feeds: HashMap<String, Address>,
+}
+
+#[async_trait]
+impl PriceSource for ChainlinkSource {
+    fn name(&self) -> &str {
+        "Chainlink"
+    }
+
+    async fn price(&self, fungible_id: &str) -> Result<Option<PriceResult>, MonitorError> {
+        let Some(aggregator) = self.feeds.get(fungible_id) else {
+            return Ok(None);
+        };
+        let (chain_name, token) = fungible_id
+            .split_once('.')
+            .unwrap_or(("ethereum", fungible_id));
+        let Some(answer) = read_chainlink_feed(chain_name, *aggregator).await? else {
+            return Ok(None);
+        };
+        let decimals = token
+            .parse::<Address>()
+            .ok()
+            .and_then(|token| get_token_decimals(&token))
+            .unwrap_or_default();
+        let (symbol, currency) = feed_pair(&answer.description);
+        Ok(Some(PriceResult {
+            symbol,
+            price: answer.price,
+            decimals,
+            // Chainlink only updates on deviation or heartbeat, so this may be well past
+            fetched_at: answer.updated_at,
+            currency,
+        }))
+    }
+}
+
+/// Prices from Coingecko's `/simple/token_price`, looked up by contract address on
+/// the chain's asset platform
+pub struct CoingeckoSource {
+    base_url: String,
+    api_key: Option<String>,
+    // Asset platform per chain name
+    platforms: HashMap<String, String>,
+}
+
+#[async_trait]
+impl PriceSource for CoingeckoSource {
+    fn name(&self) -> &str {
+        "Coingecko"
+    }
+
+    async fn price(&self, fungible_id: &str) -> Result<Option<PriceResult>, MonitorError> {
+        let (chain_name, token) = fungible_id
+            .split_once('.')
+            .unwrap_or(("ethereum", fungible_id));
+        let (Some(platform), Ok(token)) =
+            (self.platforms.get(chain_name), token.parse::<Address>())
+        else {
+            return Ok(None);
+        };
+        fetch_coingecko_price_from(&self.base_url, self.api_key.as_deref(), platform, token).await
+    }
+}
+
+/// On-chain time-weighted average prices from the deepest Uniswap V3 pool of each
+/// pair in UNISWAP_TWAP_PAIRS, for long-tail tokens the APIs don't cover. The TWAP
+/// is in the quote token, which is converted to USD through the other sources.
+pub struct UniswapTwapSource {
+    // Quote token per fungible id
+    pairs: HashMap<String, Address>,
+    window_secs: u32,
+    quote_sources: Vec<Box<dyn PriceSource>>,
+}
+
+#[async_trait]
+impl PriceSource for UniswapTwapSource {
+    fn name(&self) -> &str {
+        "Uniswap TWAP"
+    }
+
+    async fn price(&self, fungible_id: &str) -> Result<Option<PriceResult>, MonitorError> {
+        let Some(quote) = self.pairs.get(fungible_id).copied() else {
+            return Ok(None);
+        };
+        let (chain_name, token) = fungible_id
+            .split_once('.')
+            .unwrap_or(("ethereum", fungible_id));
+        let Ok(token) = token.parse::<Address>() else {
+            return Ok(None);
+        };
+        let Some(chain) = configured_chains()
+            .into_iter()
+            .find(|chain| chain.name() == chain_name)
+        else {
+            return Ok(None);
+        };
+
+        let twap = read_uniswap_twap(chain.as_ref(), token, quote, self.window_secs).await?;
+        let quote_id = format!("{}.{:?}", chain_name, quote);
+        let quote_price = fetch_price_from_sources(&self.quote_sources, &quote_id)
+            .await?
+            .ok_or_else(|| {
+                MonitorError::Price(format!(
+                    "No price for Uniswap TWAP quote token {}",
+                    quote_id
+                ))
+            })?;
+        Ok(Some(PriceResult {
+            symbol: get_token_symbol(&token).unwrap_or_else(|| format!("{:?}", token)),
+            price: twap * quote_price.price,
+            decimals: get_token_decimals(&token).unwrap_or_default(),
+            // A TWAP is current as of the block it was read at
+            fetched_at: chrono::Utc::now().timestamp() as u64,
+            currency: quote_price.currency,
+        }))
+    }
+}
+
+// Price of one `token` in `quote` over the window, from the pair's deepest pool
+async fn read_uniswap_twap(
+    chain: &dyn Chain,
+    token: Address,
+    quote: Address,
+    window_secs: u32,
+) -> Result<f64, MonitorError> {
+    let factory = get_uniswap_v3_factory(chain.name())?;
+    let rpc_url = chain.rpc_url();
+    let pool = find_deepest_uniswap_pool(&rpc_url, factory, token, quote).await;
+    chain.rpc_endpoints().record(&rpc_url, &pool);
+    let pool = pool?.ok_or_else(|| {
+        MonitorError::Price(format!(
+            "No Uniswap V3 pool for {:?}/{:?} on {}",
+            token,
+            quote,
+            chain.name()
+        ))
+    })?;
+    let tick = fetch_uniswap_twap_tick(&rpc_url, pool, window_secs).await;
+    chain.rpc_endpoints().record(&rpc_url, &tick);
+    let tick = tick?;
+
+    ensure_token_decimals(&rpc_url, token).await;
+    ensure_token_decimals(&rpc_url, quote).await;
+    let (Some(token_decimals), Some(quote_decimals)) =
+        (get_token_decimals(&token), get_token_decimals(&quote))
+    else {
+        return Err(MonitorError::Price(format!(
+            "Unknown decimals for Uniswap TWAP pair {:?}/{:?}",
+            token, quote
+        )));
+    };
+    Ok(uniswap_tick_to_price(
+        tick,
+        token,
+        quote,
+        token_decimals,
+        quote_decimals,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+struct CoingeckoTokenPrice {
+    usd: Option<f64>,
+    last_updated_at: Option<u64>,
+}
+
+/// USD price of `token` on `platform` from the Coingecko API served at `base_url`.
+/// Keys are sent as a Pro key to `pro-api` hosts and as a Demo key otherwise.
+pub async fn fetch_coingecko_price_from(
+    base_url: &str,
+    api_key: Option<&str>,
+    platform: &str,
+    token: Address,
+) -> Result<Option<PriceResult>, MonitorError> {
+    let token_id = format!("{:?}", token);
+    let mut url = Url::parse(&format!(
+        "{}/simple/token_price/{}",
+        base_url.trim_end_matches('/'),
+        platform
+    ))
+    .map_err(|e| MonitorError::Config(format!("Invalid COINGECKO_BASE_URL: {}", e)))?;
+    url.query_pairs_mut()
+        .append_pair("contract_addresses", &token_id)
+        .append_pair("vs_currencies", "usd")
+        .append_pair("include_last_updated_at", "true");
+
+    let mut request = PRICE_CLIENT.get(url);
+    if let Some(api_key) = api_key {
+        let header = if base_url.contains("pro-api.") {
+            "x-cg-pro-api-key"
+        } else {
+            "x-cg-demo-api-key"
+        };
+        request = request.header(header, api_key);
+    }
+    let response = request
+        .send()
+        .await
+        .map_err(|e| MonitorError::Price(format!("Failed to send Coingecko request: {}", e)))?;
+    if response.status() == StatusCode::TOO_MANY_REQUESTS {
+        return Err(MonitorError::Price(
+            "Coingecko rate limit exceeded".to_string(),
+        ));
+    }
+    if !response.status().is_success() {
+        return Err(MonitorError::Price(format!(
+            "Coingecko returned {}",
+            response.status()
+        )));
+    }
+    let prices: HashMap<String, CoingeckoTokenPrice> = response
+        .json()
+        .await
+        .map_err(|e| MonitorError::Price(format!("Invalid Coingecko response: {}", e)))?;
+    // Addresses come back lowercased
+    let Some(CoingeckoTokenPrice {
+        usd: Some(price),
+        last_updated_at,
+    }) = prices
+        .into_iter()
+        .find_map(|(address, price)| address.eq_ignore_ascii_case(&token_id).then_some(price))
+    else {
+        return Ok(None);
+    };
+    Ok(Some(PriceResult {
+        // The endpoint doesn't return symbols
+        symbol: get_token_symbol(&token).unwrap_or(token_id),
+        price,
+        decimals: get_token_decimals(&token).unwrap_or_default(),
+        fetched_at: last_updated_at.unwrap_or_else(|| chrono::Utc::now().timestamp() as u64),
+        currency: "USD".to_string(),
+    }))
+}
+
+// Aggregators are read through the chain they are deployed on
+async fn read_chainlink_feed(
+    chain_name: &str,
+    aggregator: Address,
+) -> Result<Option<ChainlinkAnswer>, MonitorError> {
+    let Some(chain) = configured_chains()
+        .into_iter()
+        .find(|chain| chain.name() == chain_name)
+    else {
+        return Ok(None);
+    };
+    let rpc_url = chain.rpc_url();
+    let answer = fetch_chainlink_answer(&rpc_url, aggregator).await;
+    chain.rpc_endpoints().record(&rpc_url, &answer);
+    answer.map(Some)
+}
+
+/// Base and quote of a Chainlink feed description: "ETH / USD" -> ("ETH", "USD")
+pub fn feed_pair(description: &str) -> (String, String) {
+    let (base, quote) = description.split_once('/').unwrap_or((description, ""));
+    (base.trim().to_string(), quote.trim().to_ascii_uppercase())
+}
+
+/// How USD values are shown in QUOTE_CURRENCY: `usd_per_unit` USD buy one unit
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuoteRate {
+    pub currency: String,
+    pub usd_per_unit: f64,
+}
+
+impl QuoteRate {
+    pub fn usd() -> Self {
+        Self {
+            currency: "USD".to_string(),
+            usd_per_unit: 1.0,
+        }
+    }
+
+    pub fn convert(&self, usd: f64) -> f64 {
+        usd / self.usd_per_unit
+    }
+}
+
+lazy_static::lazy_static! {
+    // Latest QUOTE_CURRENCY rate and when it was read
+    static ref QUOTE_RATE: Mutex<Option<(QuoteRate, Instant)>> = Mutex::new(None);
+}
+
+/// Rate values are displayed with: USD until a QUOTE_CURRENCY rate has been read
+pub fn quote_rate() -> QuoteRate {
+    QUOTE_RATE
+        .lock()
+        .ok()
+        .and_then(|rate| rate.as_ref().map(|(rate, _)| rate.clone()))
+        .unwrap_or_else(QuoteRate::usd)
+}
+
+/// Read the QUOTE_CURRENCY rate from QUOTE_CURRENCY_FEED, at most once per
+/// `PRICE_CACHE_TTL_SECS`. On failure the last rate stays in use, so values keep
+/// being shown in USD until one is read.
+pub async fn refresh_quote_rate() {
+    let currency = get_quote_currency();
+    if currency == "USD" {
+        return;
+    }
+    let ttl = Duration::from_secs(get_price_cache_ttl_secs());
+    if let Ok(rate) = QUOTE_RATE.lock() {
+        if let Some((rate, read_at)) = rate.as_ref() {
+            if rate.currency == currency && read_at.elapsed() < ttl {
+                return;
+            }
+        }
+    }
+    match fetch_quote_rate(&currency).await {
+        Ok(rate) => {
+            if let Ok(mut latest) = QUOTE_RATE.lock() {
+                *latest = Some((rate, Instant::now()));
+            }
+        }
+        Err(e) => tracing::warn!("Failed to read the {} rate: {}", currency, e),
+    }
+}
+
+async fn fetch_quote_rate(currency: &str) -> Result<QuoteRate, MonitorError> {
+    let feed = get_quote_currency_feed()?.ok_or_else(|| {
+        MonitorError::Config(format!(
+            "QUOTE_CURRENCY is {} but QUOTE_CURRENCY_FEED is not set",
+            currency
+        ))
+    })?;
+    let answer = read_chainlink_feed("ethereum", feed)
+        .await?
+        .ok_or_else(|| MonitorError::Config("Ethereum is not configured".to_string()))?;
+    quote_rate_from_answer(currency, &answer)
+}
+
+/// Check that a feed answer is the `<currency> / USD` rate and turn it into a
+/// `QuoteRate`. FX feeds only update daily, so the answer's age isn't checked.
+pub fn quote_rate_from_answer(
+    currency: &str,
+    answer: &ChainlinkAnswer,
+) -> Result<QuoteRate, MonitorError> {
+    let (base, quote) = feed_pair(&answer.description);
+    if !base.eq_ignore_ascii_case(currency) || quote != "USD" {
+        return Err(MonitorError::Config(format!(
+            "QUOTE_CURRENCY_FEED is {}, expected {} / USD",
+            answer.description, currency
+        )));
+    }
+    if !answer.price.is_finite() || answer.price <= 0.0 {
+        return Err(MonitorError::Price(format!(
+            "invalid {} rate {}",
+            currency, answer.price
+        )));
+    }
+    Ok(QuoteRate {
+        currency: currency.to_string(),
+        usd_per_unit: answer.price,
+    })
+}
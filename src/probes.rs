@@ -0,0 +1,56 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::chains::ethereum::ethereum_chain::ChainState;
+
+// Liveness and readiness flags behind /healthz and /readyz. Probes only read them,
+// so answering never waits on an RPC or price source.
+static MAIN_LOOPS_RUNNING: AtomicBool = AtomicBool::new(false);
+static PRICE_FETCHED: AtomicBool = AtomicBool::new(false);
+
+/// Called once every monitoring task has been spawned
+pub fn mark_main_loops_running() {
+    MAIN_LOOPS_RUNNING.store(true, Ordering::SeqCst);
+}
+
+/// Called on every successful price fetch; the first one counts toward readiness
+pub fn record_price_fetched() {
+    PRICE_FETCHED.store(true, Ordering::Relaxed);
+}
+
+/// Whether `/healthz` answers 200
+pub fn is_live() -> bool {
+    MAIN_LOOPS_RUNNING.load(Ordering::SeqCst)
+}
+
+/// Why `/readyz` still answers 503, `None` once it can answer 200
+pub fn readiness_blocker() -> Option<String> {
+    let chains = crate::chains::enabled_chains();
+    not_ready_reason(is_live(), &chains, PRICE_FETCHED.load(Ordering::Relaxed))
+}
+
+/// Ready means the loops are running, every tracked account has replayed the
+/// events it missed, and at least one price has been fetched
+pub fn not_ready_reason(
+    main_loops_running: bool,
+    chains: &[&ChainState],
+    price_fetched: bool,
+) -> Option<String> {
+    if !main_loops_running {
+        return Some("monitoring loops have not started".to_string());
+    }
+    let unsynced: Vec<_> = chains
+        .iter()
+        .filter(|chain| !chain.is_initial_sync_complete())
+        .map(|chain| format!("{} {}", chain.name, chain.user_address()))
+        .collect();
+    if !unsynced.is_empty() {
+        return Some(format!(
+            "initial on-chain sync pending for {}",
+            unsynced.join(", ")
+        ));
+    }
+    if !price_fetched {
+        return Some("no price has been fetched yet".to_string());
+    }
+    None
+}
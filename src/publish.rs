@@ -0,0 +1,324 @@
+use async_trait::async_trait;
+use ethers::prelude::{Address, H256, U256};
+use reqwest::Url;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufStream};
+use tokio::net::TcpStream;
+use tokio::sync::Mutex;
+
+use crate::chains::ethereum::ethereum_chain::ChainState;
+use crate::chains::{get_publish_channel, get_publish_url};
+use crate::error::MonitorError;
+use crate::metrics::latest_valuation;
+
+// A broker slower than this drops the event rather than piling up publishes
+const PUBLISH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Position or health-factor change streamed to downstream consumers as JSON
+#[derive(Debug, Clone, Serialize)]
+pub struct PositionEvent {
+    // Aave event that changed the position (`supply`, `borrow`, ...) or `health_factor`
+    pub event_type: String,
+    pub chain: String,
+    pub user_address: String,
+    // Raw token amounts per reserve, as decimal strings
+    pub supplied: BTreeMap<String, String>,
+    pub borrowed: BTreeMap<String, String>,
+    pub supply_usd: Option<f64>,
+    pub borrow_usd: Option<f64>,
+    // `null` without debt (infinite health factor) or before the first check
+    pub health_factor: Option<f64>,
+    pub tx_hash: Option<H256>,
+    pub timestamp: u64,
+}
+
+impl PositionEvent {
+    /// Snapshot `chain`'s position, valued with the account's latest health-factor check
+    pub fn new(event_type: &str, chain: &ChainState) -> Self {
+        let position = chain.position().unwrap_or_default();
+        let (supply_usd, borrow_usd, health_factor) =
+            latest_valuation(chain.name, &chain.user_address());
+        Self {
+            event_type: event_type.to_string(),
+            chain: chain.name.to_string(),
+            user_address: chain.user_address(),
+            supplied: amounts(&position.supplied),
+            borrowed: amounts(&position.borrowed),
+            supply_usd,
+            borrow_usd,
+            health_factor: health_factor.filter(|hf| hf.is_finite()),
+            tx_hash: position.last_event_tx,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_secs())
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String, MonitorError> {
+        Ok(serde_json::to_string(self)?)
+    }
+}
+
+fn amounts(balances: &HashMap<Address, U256>) -> BTreeMap<String, String> {
+    balances
+        .iter()
+        .map(|(reserve, amount)| (format!("{:?}", reserve), amount.to_string()))
+        .collect()
+}
+
+/// Somewhere position events are streamed to
+#[async_trait]
+pub trait EventPublisher: Send + Sync {
+    /// Broker name used in logs
+    fn name(&self) -> &str;
+
+    /// Whether publishing does anything; events aren't even built otherwise
+    fn enabled(&self) -> bool {
+        true
+    }
+
+    async fn publish(&self, event: &PositionEvent) -> Result<(), MonitorError>;
+}
+
+/// Publisher used when PUBLISH_URL is not set
+pub struct NoopPublisher;
+
+#[async_trait]
+impl EventPublisher for NoopPublisher {
+    fn name(&self) -> &str {
+        "none"
+    }
+
+    fn enabled(&self) -> bool {
+        false
+    }
+
+    async fn publish(&self, _event: &PositionEvent) -> Result<(), MonitorError> {
+        Ok(())
+    }
+}
+
+/// Build the publisher for PUBLISH_URL: `redis://` or `nats://`, else the no-op one
+pub fn configured_publisher() -> Box<dyn EventPublisher> {
+    let Some(raw_url) = get_publish_url() else {
+        tracing::debug!("PUBLISH_URL is not set, skipping event publishing");
+        return Box::new(NoopPublisher);
+    };
+    match parse_publish_url(&raw_url) {
+        Ok(publisher) => publisher,
+        Err(e) => {
+            tracing::warn!("Ignoring PUBLISH_URL: {}", e);
+            Box::new(NoopPublisher)
+        }
+    }
+}
+
+pub fn parse_publish_url(raw_url: &str) -> Result<Box<dyn EventPublisher>, MonitorError> {
+    let url = Url::parse(raw_url.trim())
+        .map_err(|e| MonitorError::Config(format!("Invalid PUBLISH_URL: {}", e)))?;
+    let host = url
+        .host_str()
+        .ok_or_else(|| MonitorError::Config("PUBLISH_URL has no host".to_string()))?;
+    let channel = get_publish_channel();
+    match url.scheme() {
+        "redis" => Ok(Box::new(RedisPublisher {
+            address: format!("{}:{}", host, url.port().unwrap_or(6379)),
+            password: url.password().map(str::to_string),
+            channel,
+            connection: Mutex::new(None),
+        })),
+        "nats" => Ok(Box::new(NatsPublisher {
+            address: format!("{}:{}", host, url.port().unwrap_or(4222)),
+            subject: channel,
+            connection: Mutex::new(None),
+        })),
+        scheme => Err(MonitorError::Config(format!(
+            "Unsupported PUBLISH_URL scheme {}, expected redis or nats",
+            scheme
+        ))),
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref PUBLISHER: Box<dyn EventPublisher> = configured_publisher();
+}
+
+/// Publish `chain`'s position in the background. Never waits on the broker, so a
+/// slow or unreachable one can't hold up event handling or alerts.
+pub fn publish_position(event_type: &str, chain: &ChainState) {
+    if !PUBLISHER.enabled() {
+        return;
+    }
+    let event = PositionEvent::new(event_type, chain);
+    tokio::spawn(async move {
+        match tokio::time::timeout(PUBLISH_TIMEOUT, PUBLISHER.publish(&event)).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => tracing::warn!("Failed to publish {} event: {}", PUBLISHER.name(), e),
+            Err(_) => tracing::warn!("{} publish timed out", PUBLISHER.name()),
+        }
+    });
+}
+
+type Connection = BufStream<TcpStream>;
+
+async fn connect(address: &str) -> Result<Connection, MonitorError> {
+    TcpStream::connect(address)
+        .await
+        .map(BufStream::new)
+        .map_err(|e| MonitorError::Publish(format!("Failed to connect to {}: {}", address, e)))
+}
+
+async fn write_all(connection: &mut Connection, bytes: &[u8]) -> Result<(), MonitorError> {
+    let publish_error = |e: std::io::Error| MonitorError::Publish(e.to_string());
+    connection.write_all(bytes).await.map_err(publish_error)?;
+    connection.flush().await.map_err(publish_error)
+}
+
+async fn read_line(connection: &mut Connection) -> Result<String, MonitorError> {
+    let mut line = String::new();
+    let read = connection
+        .read_line(&mut line)
+        .await
+        .map_err(|e| MonitorError::Publish(e.to_string()))?;
+    if read == 0 {
+        return Err(MonitorError::Publish("Connection closed".to_string()));
+    }
+    Ok(line.trim_end().to_string())
+}
+
+/// Encode a Redis command in RESP, e.g. `PUBLISH channel payload`
+pub fn resp_command(args: &[&[u8]]) -> Vec<u8> {
+    let mut out = format!("*{}\r\n", args.len()).into_bytes();
+    for arg in args {
+        out.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+        out.extend_from_slice(arg);
+        out.extend_from_slice(b"\r\n");
+    }
+    out
+}
+
+/// Publishes events to a Redis pub/sub channel
+pub struct RedisPublisher {
+    address: String,
+    password: Option<String>,
+    channel: String,
+    // Opened on first use and again after any failure
+    connection: Mutex<Option<Connection>>,
+}
+
+impl RedisPublisher {
+    async fn command(connection: &mut Connection, args: &[&[u8]]) -> Result<(), MonitorError> {
+        write_all(connection, &resp_command(args)).await?;
+        let reply = read_line(connection).await?;
+        match reply.strip_prefix('-') {
+            Some(error) => Err(MonitorError::Publish(format!("Redis error: {}", error))),
+            None => Ok(()),
+        }
+    }
+
+    async fn open(&self) -> Result<Connection, MonitorError> {
+        let mut connection = connect(&self.address).await?;
+        if let Some(password) = &self.password {
+            Self::command(&mut connection, &[b"AUTH", password.as_bytes()]).await?;
+        }
+        Ok(connection)
+    }
+}
+
+#[async_trait]
+impl EventPublisher for RedisPublisher {
+    fn name(&self) -> &str {
+        "Redis"
+    }
+
+    async fn publish(&self, event: &PositionEvent) -> Result<(), MonitorError> {
+        let payload = event.to_json()?;
+        let mut connection = self.connection.lock().await;
+        let mut open = match connection.take() {
+            Some(open) => open,
+            None => self.open().await?,
+        };
+        let result = Self::command(
+            &mut open,
+            &[b"PUBLISH", self.channel.as_bytes(), payload.as_bytes()],
+        )
+        .await;
+        // A failed connection is dropped and reopened on the next event
+        if result.is_ok() {
+            *connection = Some(open);
+        }
+        result
+    }
+}
+
+/// Encode a NATS `PUB` of `payload` on `subject`
+pub fn nats_pub(subject: &str, payload: &[u8]) -> Vec<u8> {
+    let mut out = format!("PUB {} {}\r\n", subject, payload.len()).into_bytes();
+    out.extend_from_slice(payload);
+    out.extend_from_slice(b"\r\n");
+    out
+}
+
+/// Publishes events to a NATS subject
+pub struct NatsPublisher {
+    address: String,
+    subject: String,
+    connection: Mutex<Option<Connection>>,
+}
+
+impl NatsPublisher {
+    async fn open(&self) -> Result<Connection, MonitorError> {
+        let mut connection = connect(&self.address).await?;
+        // The server greets with INFO before accepting CONNECT
+        read_line(&mut connection).await?;
+        write_all(
+            &mut connection,
+            b"CONNECT {\"verbose\":false,\"pedantic\":false}\r\n",
+        )
+        .await?;
+        Ok(connection)
+    }
+
+    // NATS doesn't acknowledge a PUB, so a PING after it confirms the server got
+    // it and drains any PING the server sent in the meantime
+    async fn send(&self, connection: &mut Connection, payload: &[u8]) -> Result<(), MonitorError> {
+        let mut out = nats_pub(&self.subject, payload);
+        out.extend_from_slice(b"PING\r\n");
+        write_all(connection, &out).await?;
+        loop {
+            let line = read_line(connection).await?;
+            if line == "PONG" {
+                return Ok(());
+            }
+            if line == "PING" {
+                write_all(connection, b"PONG\r\n").await?;
+            } else if let Some(error) = line.strip_prefix("-ERR") {
+                return Err(MonitorError::Publish(format!("NATS error:{}", error)));
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl EventPublisher for NatsPublisher {
+    fn name(&self) -> &str {
+        "NATS"
+    }
+
+    async fn publish(&self, event: &PositionEvent) -> Result<(), MonitorError> {
+        let payload = event.to_json()?;
+        let mut connection = self.connection.lock().await;
+        let mut open = match connection.take() {
+            Some(open) => open,
+            None => self.open().await?,
+        };
+        let result = self.send(&mut open, payload.as_bytes()).await;
+        if result.is_ok() {
+            *connection = Some(open);
+        }
+        result
+    }
+}
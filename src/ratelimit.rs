@@ -0,0 +1,77 @@
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// Token bucket shared by every caller of one API. Holds up to a second's worth of
+/// requests, so short bursts go out at once and longer ones are spread out at
+/// `requests_per_second`.
+pub struct RateLimiter {
+    requests_per_second: f64,
+    bucket: Mutex<Bucket>,
+}
+
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+    // Set by a rate-limit response: nobody sends before then
+    paused_until: Option<Instant>,
+}
+
+impl RateLimiter {
+    /// Limiter allowing `requests_per_second` on average; 0 (or less) never waits
+    pub fn new(requests_per_second: f64) -> Self {
+        Self {
+            requests_per_second,
+            bucket: Mutex::new(Bucket {
+                tokens: requests_per_second.max(1.0),
+                refilled_at: Instant::now(),
+                paused_until: None,
+            }),
+        }
+    }
+
+    fn capacity(&self) -> f64 {
+        self.requests_per_second.max(1.0)
+    }
+
+    /// Wait until a request may be sent
+    pub async fn acquire(&self) {
+        if self.requests_per_second <= 0.0 {
+            return;
+        }
+        loop {
+            let wait = {
+                let mut bucket = self.bucket.lock().await;
+                let now = Instant::now();
+                match bucket.paused_until {
+                    Some(until) if until > now => until - now,
+                    _ => {
+                        let elapsed = now.saturating_duration_since(bucket.refilled_at);
+                        bucket.tokens = (bucket.tokens
+                            + elapsed.as_secs_f64() * self.requests_per_second)
+                            .min(self.capacity());
+                        bucket.refilled_at = now;
+                        if bucket.tokens >= 1.0 {
+                            bucket.tokens -= 1.0;
+                            return;
+                        }
+                        Duration::from_secs_f64((1.0 - bucket.tokens) / self.requests_per_second)
+                    }
+                }
+            };
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Hold back every caller for `duration`, e.g. the `Retry-After` of a 429. The
+    /// bucket is emptied so requests resume at the configured pace, not in a burst.
+    pub async fn pause_for(&self, duration: Duration) {
+        let mut bucket = self.bucket.lock().await;
+        let until = Instant::now() + duration;
+        if bucket.paused_until < Some(until) {
+            bucket.paused_until = Some(until);
+            // Tokens only start accruing again once the pause is over
+            bucket.tokens = 0.0;
+            bucket.refilled_at = until;
+        }
+    }
+}
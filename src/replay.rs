@@ -0,0 +1,206 @@
+use ethers::prelude::{Address, Http, Middleware, Provider};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::alerts::{send_to_all, AlertMessage, Alerter};
+use crate::chains::ethereum::ethereum_chain::{
+    apply_log_or_skip, fetch_pool_logs, pool_filter, refresh_liquidation_thresholds, ChainState,
+    PoolListener, BACKFILL_CHUNK_BLOCKS,
+};
+use crate::chains::*;
+use crate::error::MonitorError;
+use crate::{
+    calculate_health_factor, calculate_liquidation_prices, decision_alert_message,
+    liquidated_alert_message, report_alert_failures, AlertConfirmation, AlertCooldown, AlertLevel,
+};
+
+/// Where the replayed block sits on the alert clock: cooldowns are measured in block
+/// time, so a day of history replays with a day's worth of cooldowns
+pub fn replay_instant(start: Instant, first_timestamp: u64, timestamp: u64) -> Instant {
+    start + Duration::from_secs(timestamp.saturating_sub(first_timestamp))
+}
+
+/// Replay the Ethereum Pool's events from `from_block` to `to_block` (the chain head
+/// when `None`) on top of the configured initial position, which is taken as the
+/// position at `from_block`. After every block that changed the position the health
+/// factor is recomputed and run through the same cooldown and confirmation logic as
+/// the live loop. Alerts are only logged unless `send_alerts` is set. Returns the
+/// number of alerts the replay decided on.
+///
+/// Prices are read at replay time: neither SimpleHash nor the Chainlink source
+/// serves prices as of a past block, so only the position moves with history.
+pub async fn replay_blocks(
+    from_block: u64,
+    to_block: Option<u64>,
+    alerters: &[Box<dyn Alerter>],
+    send_alerts: bool,
+) -> Result<usize, MonitorError> {
+    let rpc_url = get_ethereum_rpc_url();
+    let provider = Provider::<Http>::try_from(rpc_url.as_str())
+        .map_err(|e| MonitorError::Config(format!("Failed to create provider: {}", e)))?;
+    let to_block = match to_block {
+        Some(to_block) => to_block,
+        None => provider
+            .get_block_number()
+            .await
+            .map_err(|e| MonitorError::Rpc(format!("Failed to get block number: {}", e)))?
+            .as_u64(),
+    };
+    if to_block < from_block {
+        return Err(MonitorError::Config(format!(
+            "--replay-to {} is before --replay-from {}",
+            to_block, from_block
+        )));
+    }
+
+    // A separate state keeps the replay away from the live position and its files.
+    // It is named after the chain so prices are looked up for the right tokens.
+    let chain = ChainState::new("ethereum", String::new, String::new);
+    chain.set_position(get_initial_position_data()?)?;
+    let pool_address = get_pool_v3_address();
+    if get_liquidation_threshold_override().is_none() {
+        refresh_liquidation_thresholds(&chain, &rpc_url, &pool_address, get_pool_version()).await?;
+    }
+    let listener = PoolListener::new(
+        &chain,
+        &rpc_url,
+        parse_address("AAVE_POOL_V3_ADDRESS", &pool_address)?,
+        parse_address("AAVE_USER_ADDRESS_TO_TRACK", &get_user_address_to_track())?,
+        get_account_field(),
+        get_pool_version(),
+    )
+    .without_publishing();
+    let filter = pool_filter(get_pool_version());
+    info!(
+        from_block,
+        to_block, send_alerts, "Replaying Aave events with current prices"
+    );
+
+    let mut replay = AlertReplay::new(alerters, send_alerts);
+    replay.evaluate(&chain, from_block).await;
+    let mut from = from_block;
+    while from <= to_block {
+        let chunk_end = (from + BACKFILL_CHUNK_BLOCKS - 1).min(to_block);
+        let logs = fetch_pool_logs(&provider, &filter, from, chunk_end).await?;
+        for block_logs in logs.chunk_by(|a, b| a.block_number == b.block_number) {
+            let event_count = chain.event_count();
+            for log in block_logs {
+                apply_log_or_skip(&listener, log).await?;
+            }
+            let block = block_logs[0].block_number.unwrap_or_default().as_u64();
+            chain.commit_block(block, block_logs[0].block_hash.unwrap_or_default())?;
+            // Blocks without a tracked event leave the health factor where it was
+            if chain.event_count() != event_count {
+                replay.evaluate(&chain, block).await;
+            }
+        }
+        from = chunk_end + 1;
+    }
+
+    info!(
+        alerts = replay.alerts,
+        "Replay finished at block {}", to_block
+    );
+    Ok(replay.alerts)
+}
+
+fn parse_address(name: &str, value: &str) -> Result<Address, MonitorError> {
+    value
+        .trim()
+        .parse::<Address>()
+        .map_err(|e| MonitorError::Config(format!("Invalid {} '{}': {}", name, value, e)))
+}
+
+// Alert state carried from one replayed block to the next
+struct AlertReplay<'a> {
+    alerters: &'a [Box<dyn Alerter>],
+    send_alerts: bool,
+    cooldown: AlertCooldown,
+    alerted_health_factor: Option<f64>,
+    confirmation: AlertConfirmation,
+    start: Instant,
+    first_timestamp: Option<u64>,
+    alerts: usize,
+}
+
+impl<'a> AlertReplay<'a> {
+    fn new(alerters: &'a [Box<dyn Alerter>], send_alerts: bool) -> Self {
+        Self {
+            alerters,
+            send_alerts,
+            cooldown: AlertCooldown::from_config(),
+            alerted_health_factor: None,
+            confirmation: AlertConfirmation::new(get_price_alert_confirmation_ticks()),
+            start: Instant::now(),
+            first_timestamp: None,
+            alerts: 0,
+        }
+    }
+
+    async fn evaluate(&mut self, chain: &ChainState, block: u64) {
+        if let Ok(notices) = chain.take_liquidation_notices() {
+            for notice in notices {
+                let message = liquidated_alert_message(chain, &notice);
+                self.deliver(block, message).await;
+            }
+        }
+
+        let reading = match calculate_health_factor(chain).await {
+            Ok(reading) => reading,
+            Err(e) => {
+                warn!(block, "Failed to compute replayed health factor: {}", e);
+                return;
+            }
+        };
+        let liquidation_prices = calculate_liquidation_prices(
+            chain,
+            reading.supply_in_usd,
+            reading.borrowed_in_usd,
+            reading.liquidation_threshold,
+        )
+        .await
+        .unwrap_or_default();
+
+        let timestamp = chain
+            .position()
+            .ok()
+            .and_then(|position| position.last_event_timestamp);
+        // Until the first replayed event the clock sits at the start
+        let now = match timestamp {
+            Some(timestamp) => {
+                let first_timestamp = *self.first_timestamp.get_or_insert(timestamp);
+                replay_instant(self.start, first_timestamp, timestamp)
+            }
+            None => self.start,
+        };
+
+        let is_at_risk = reading.level > AlertLevel::Info;
+        let confirmed = self.confirmation.observe(is_at_risk, chain.event_count());
+        let decision = self.cooldown.observe(now, reading.level, confirmed);
+        info!(
+            block,
+            health_factor = reading.health_factor,
+            level = ?reading.level,
+            decision = ?decision,
+            "Replayed health factor"
+        );
+        if let Some(message) = decision_alert_message(
+            chain,
+            &reading,
+            &liquidation_prices,
+            &decision,
+            &mut self.alerted_health_factor,
+        ) {
+            self.deliver(block, message).await;
+        }
+    }
+
+    async fn deliver(&mut self, block: u64, message: AlertMessage) {
+        if self.send_alerts {
+            report_alert_failures(send_to_all(self.alerters, &message).await);
+        } else {
+            info!(block, title = %message.title, "Would send alert");
+        }
+        self.alerts += 1;
+    }
+}
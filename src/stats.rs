@@ -0,0 +1,143 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::format_duration;
+
+/// Work counters behind the periodic stats line. Cheap enough to bump on every log:
+/// the totals are atomics, only the per-type event counts take a lock.
+pub struct Stats {
+    started_at: Instant,
+    // Blocks with Pool logs, committed by a listener
+    blocks: AtomicU64,
+    logs: AtomicU64,
+    alerts: AtomicU64,
+    price_failures: AtomicU64,
+    events: Mutex<BTreeMap<&'static str, u64>>,
+}
+
+/// Counter values at one moment, cumulative since start
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StatsSnapshot {
+    pub blocks: u64,
+    pub logs: u64,
+    pub alerts: u64,
+    pub price_failures: u64,
+    pub events: BTreeMap<&'static str, u64>,
+}
+
+impl Stats {
+    pub fn new(started_at: Instant) -> Self {
+        Self {
+            started_at,
+            blocks: AtomicU64::new(0),
+            logs: AtomicU64::new(0),
+            alerts: AtomicU64::new(0),
+            price_failures: AtomicU64::new(0),
+            events: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn record_block(&self) {
+        self.blocks.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_log(&self) {
+        self.logs.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_event(&self, event_type: &'static str) {
+        if let Ok(mut events) = self.events.lock() {
+            *events.entry(event_type).or_default() += 1;
+        }
+    }
+
+    pub fn record_alert(&self) {
+        self.alerts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_price_failure(&self) {
+        self.price_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn uptime(&self, now: Instant) -> Duration {
+        now.saturating_duration_since(self.started_at)
+    }
+
+    pub fn snapshot(&self) -> StatsSnapshot {
+        StatsSnapshot {
+            blocks: self.blocks.load(Ordering::Relaxed),
+            logs: self.logs.load(Ordering::Relaxed),
+            alerts: self.alerts.load(Ordering::Relaxed),
+            price_failures: self.price_failures.load(Ordering::Relaxed),
+            events: self
+                .events
+                .lock()
+                .map(|events| events.clone())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl StatsSnapshot {
+    /// Counts accumulated since `previous` was taken
+    pub fn since(&self, previous: &StatsSnapshot) -> StatsSnapshot {
+        StatsSnapshot {
+            blocks: self.blocks.saturating_sub(previous.blocks),
+            logs: self.logs.saturating_sub(previous.logs),
+            alerts: self.alerts.saturating_sub(previous.alerts),
+            price_failures: self.price_failures.saturating_sub(previous.price_failures),
+            events: self
+                .events
+                .iter()
+                .map(|(event_type, count)| {
+                    let before = previous.events.get(event_type).copied().unwrap_or(0);
+                    (*event_type, count.saturating_sub(before))
+                })
+                .filter(|(_, count)| *count > 0)
+                .collect(),
+        }
+    }
+
+    /// Events by type, e.g. "supply=2 borrow=1", or "none"
+    pub fn events_summary(&self) -> String {
+        if self.events.is_empty() {
+            return "none".to_string();
+        }
+        self.events
+            .iter()
+            .map(|(event_type, count)| format!("{}={}", event_type, count))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+lazy_static::lazy_static! {
+    pub static ref STATS: Stats = Stats::new(Instant::now());
+}
+
+/// Log what happened since the previous line every `interval`, with uptime and the
+/// totals since start. On a busy chain an interval without blocks means a stuck listener.
+pub async fn log_stats_periodically(interval: Duration) {
+    let mut previous = STATS.snapshot();
+    loop {
+        tokio::time::sleep(interval).await;
+        let current = STATS.snapshot();
+        let recent = current.since(&previous);
+        tracing::info!(
+            uptime = %format_duration(STATS.uptime(Instant::now())),
+            blocks = recent.blocks,
+            logs = recent.logs,
+            events = %recent.events_summary(),
+            alerts = recent.alerts,
+            price_failures = recent.price_failures,
+            total_blocks = current.blocks,
+            total_logs = current.logs,
+            total_alerts = current.alerts,
+            "Stats for the last {}s",
+            interval.as_secs()
+        );
+        previous = current;
+    }
+}
@@ -1 +1,4210 @@
+use crate::chains::reconcile_token_decimals;
 
+// Log output of the subscriber a test installs, to assert on what was logged
+#[derive(Clone, Default)]
+struct CapturedLogs(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+impl CapturedLogs {
+    fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.lock().unwrap()).into_owned()
+    }
+}
+
+impl std::io::Write for CapturedLogs {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn decimals_override_wins_over_on_chain_value_and_warns() {
+    use crate::chains::{apply_token_decimals, get_token_decimals, AssetConfig};
+    use ethers::prelude::{H160, U256};
+    use std::str::FromStr;
+
+    let wbtc = H160::from_str("0x2260fac5e5542a773aa44fbcfedf7c193bc2c599").unwrap();
+    let asset = AssetConfig {
+        address: wbtc,
+        decimals: 8,
+        initial_amount: U256::zero(),
+        decimals_overridden: true,
+    };
+    let logs = CapturedLogs::default();
+    let writer = logs.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .with_ansi(false)
+        .with_writer(move || writer.clone())
+        .finish();
+    tracing::subscriber::with_default(subscriber, || apply_token_decimals(&asset, 18));
+
+    assert_eq!(get_token_decimals(&wbtc), Some(8));
+    let logs = logs.contents();
+    // Logged at warn level, not only returned
+    assert!(logs.contains(" WARN "));
+    assert!(logs.contains("0x2260fac5e5542a773aa44fbcfedf7c193bc2c599"));
+    assert!(logs.contains("are 8"));
+    assert!(logs.contains("reports 18"));
+}
+
+#[test]
+fn decimals_match_produces_no_warning() {
+    let (decimals, warning) =
+        reconcile_token_decimals("0xdac17f958d2ee523a2206206994597c13d831ec7", Some(6), 6);
+
+    assert_eq!(decimals, 6);
+    assert!(warning.is_none());
+}
+
+#[test]
+fn decimals_without_override_use_on_chain_value() {
+    let (decimals, warning) =
+        reconcile_token_decimals("0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2", None, 18);
+
+    assert_eq!(decimals, 18);
+    assert!(warning.is_none());
+}
+
+#[tokio::test]
+async fn on_chain_decimals_are_fetched_once_and_cached() {
+    use crate::chains::ethereum::ethereum_chain::{
+        fetch_token_decimals, get_cached_token_decimals,
+    };
+    use ethers::prelude::{Address, Bytes, Provider, H256};
+    use std::sync::Arc;
+
+    let token = Address::repeat_byte(0xaa);
+    let (provider, mock) = Provider::mocked();
+    mock.push::<Bytes, _>(Bytes::from(H256::from_low_u64_be(18).as_bytes().to_vec()))
+        .unwrap();
+    let provider = Arc::new(provider);
+
+    assert_eq!(
+        fetch_token_decimals(provider.clone(), token).await.unwrap(),
+        18
+    );
+    assert_eq!(get_cached_token_decimals(&token), Some(18));
+    // The mock has no more responses queued, so this can only be served from the cache
+    assert_eq!(fetch_token_decimals(provider, token).await.unwrap(), 18);
+}
+
+#[test]
+fn alert_warmup_suppresses_until_period_and_reconciled() {
+    use crate::AlertWarmup;
+    use std::time::{Duration, Instant};
+
+    let start = Instant::now();
+    let mut warmup = AlertWarmup::new(start, Duration::from_secs(30));
+
+    assert!(!warmup.is_complete(start + Duration::from_secs(60)));
+
+    warmup.mark_reconciled();
+    assert!(!warmup.is_complete(start + Duration::from_secs(10)));
+    assert!(warmup.is_complete(start + Duration::from_secs(30)));
+}
+
+#[test]
+fn alert_warmup_waits_for_a_pending_backfill() {
+    use crate::AlertWarmup;
+    use std::time::{Duration, Instant};
+
+    let start = Instant::now();
+    let mut warmup = AlertWarmup::new(start, Duration::from_secs(30));
+
+    // Prices are in, but the backfill outlasts the warm-up period
+    warmup.observe_priced_check(false);
+    assert!(!warmup.is_complete(start + Duration::from_secs(300)));
+
+    warmup.observe_priced_check(true);
+    assert!(warmup.is_complete(start + Duration::from_secs(300)));
+}
+
+fn padded_word(hex_value: &str) -> String {
+    format!("{:0>64}", hex_value.trim_start_matches("0x"))
+}
+
+// Raw log data from concatenated hex words
+fn log_data(hex_words: &str) -> Vec<u8> {
+    alloy_primitives::hex::decode(hex_words).unwrap()
+}
+
+#[test]
+fn smart_account_supply_via_router_is_tracked() {
+    use crate::chains::ethereum::ethereum_chain::{
+        decode_event, is_tracked_account, to_h160, Supply, SUPPLY_EVENT_TOPIC,
+    };
+    use ethers::prelude::{H160, H256};
+    use std::str::FromStr;
+
+    // ERC-4337 smart account (a contract) credited with the position
+    let smart_account = "0xa1b2c3d4e5f60718293a4b5c6d7e8f9012345678";
+    // Router/bundler-side contract that actually called the pool
+    let router = "0x5ff137d4b0fdcd49dca30c7cf57e578a026d2789";
+    let usdt = "0xdac17f958d2ee523a2206206994597c13d831ec7";
+
+    let topics = vec![
+        H256::from_str(SUPPLY_EVENT_TOPIC).unwrap(),
+        H256::from_str(&padded_word(usdt)).unwrap(),
+        H256::from_str(&padded_word(smart_account)).unwrap(),
+        H256::zero(),
+    ];
+    let data = log_data(&format!("{}{}", padded_word(router), padded_word("f4240")));
+
+    let event = decode_event::<Supply>(&topics, &data)
+        .expect("decoding should not fail")
+        .expect("log should decode as a supply event");
+
+    let tracked = H160::from_str(smart_account).unwrap();
+    assert_eq!(to_h160(event.onBehalfOf), tracked);
+    assert_eq!(event.amount.to_string(), "1000000");
+    assert!(is_tracked_account(
+        tracked,
+        &[to_h160(event.user), to_h160(event.onBehalfOf)]
+    ));
+    assert!(!is_tracked_account(tracked, &[to_h160(event.user)]));
+}
+
+#[test]
+fn delegated_borrow_and_withdraw_match_the_configured_field() {
+    use crate::chains::ethereum::ethereum_chain::{
+        decode_event, event_parties, is_tracked_account, to_h160, Borrow, Withdraw,
+        BORROW_EVENT_TOPIC, WITHDRAW_EVENT_TOPIC,
+    };
+    use crate::chains::AccountField;
+    use ethers::prelude::{H160, H256};
+    use std::str::FromStr;
+
+    let tracked_account = "0x1111111111111111111111111111111111111111";
+    let delegate = "0x2222222222222222222222222222222222222222";
+    let wbtc = "0x2260fac5e5542a773aa44fbcfedf7c193bc2c599";
+    let tracked = H160::from_str(tracked_account).unwrap();
+
+    // The delegate borrows against the tracked account's credit line
+    let topics = vec![
+        H256::from_str(BORROW_EVENT_TOPIC).unwrap(),
+        H256::from_str(&padded_word(wbtc)).unwrap(),
+        H256::from_str(&padded_word(tracked_account)).unwrap(),
+        H256::zero(),
+    ];
+    let data = log_data(&format!(
+        "{}{}{}{}",
+        padded_word(delegate),
+        padded_word("5f5e100"),
+        padded_word("2"),
+        padded_word("0")
+    ));
+    let borrow = decode_event::<Borrow>(&topics, &data).unwrap().unwrap();
+    assert_ne!(borrow.user, borrow.onBehalfOf);
+    let borrow_matches = |field| {
+        is_tracked_account(
+            tracked,
+            &event_parties(field, to_h160(borrow.user), to_h160(borrow.onBehalfOf)),
+        )
+    };
+    assert!(borrow_matches(AccountField::Any));
+    assert!(borrow_matches(AccountField::OnBehalfOf));
+    assert!(!borrow_matches(AccountField::User));
+
+    // The delegate withdraws its own collateral to the tracked account
+    let topics = vec![
+        H256::from_str(WITHDRAW_EVENT_TOPIC).unwrap(),
+        H256::from_str(&padded_word(wbtc)).unwrap(),
+        H256::from_str(&padded_word(delegate)).unwrap(),
+        H256::from_str(&padded_word(tracked_account)).unwrap(),
+    ];
+    let withdraw = decode_event::<Withdraw>(&topics, &log_data(&padded_word("f4240")))
+        .unwrap()
+        .unwrap();
+    let withdraw_matches = |field| {
+        is_tracked_account(
+            tracked,
+            &event_parties(field, to_h160(withdraw.user), to_h160(withdraw.to)),
+        )
+    };
+    assert!(withdraw_matches(AccountField::Any));
+    assert!(withdraw_matches(AccountField::OnBehalfOf));
+    assert!(!withdraw_matches(AccountField::User));
+}
+
+#[test]
+fn v3_repay_log_with_indexed_user_decodes() {
+    use crate::chains::ethereum::ethereum_chain::{
+        decode_event, is_tracked_account, to_h160, Repay, REPAY_EVENT_TOPIC,
+    };
+    use ethers::prelude::{H160, H256};
+    use std::str::FromStr;
+
+    let smart_account = "0xa1b2c3d4e5f60718293a4b5c6d7e8f9012345678";
+    let router = "0x5ff137d4b0fdcd49dca30c7cf57e578a026d2789";
+    let usdc = "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48";
+
+    // The deployed V3 Pool indexes reserve, user and repayer: four topics, and
+    // only the amount and useATokens in the data
+    let topics = vec![
+        H256::from_str(REPAY_EVENT_TOPIC).unwrap(),
+        H256::from_str(&padded_word(usdc)).unwrap(),
+        H256::from_str(&padded_word(smart_account)).unwrap(),
+        H256::from_str(&padded_word(router)).unwrap(),
+    ];
+    let data = log_data(&format!("{}{}", padded_word("5f5e100"), padded_word("1")));
+    assert_eq!(data.len(), 64);
+
+    let repay = decode_event::<Repay>(&topics, &data)
+        .expect("decoding should not fail")
+        .expect("log should decode as a repay event");
+    let tracked = H160::from_str(smart_account).unwrap();
+    assert_eq!(to_h160(repay.user), tracked);
+    assert_eq!(to_h160(repay.repayer), H160::from_str(router).unwrap());
+    assert_eq!(repay.amount.to_string(), "100000000");
+    assert!(repay.useATokens);
+    assert!(is_tracked_account(tracked, &[to_h160(repay.user)]));
+}
+
+#[test]
+fn malformed_log_data_is_skipped_instead_of_failing() {
+    use crate::chains::ethereum::ethereum_chain::{decode_event, Supply, SUPPLY_EVENT_TOPIC};
+    use crate::error::MonitorError;
+    use ethers::prelude::H256;
+    use std::str::FromStr;
+
+    // Data cut short, including an odd number of bytes: an error the caller skips,
+    // never a panic
+    let topics = vec![
+        H256::from_str(SUPPLY_EVENT_TOPIC).unwrap(),
+        H256::zero(),
+        H256::zero(),
+        H256::zero(),
+    ];
+    for data in [&[][..], &[0x12, 0x34, 0x5][..], &[0; 33][..]] {
+        let decoded = decode_event::<Supply>(&topics, data);
+        assert!(
+            matches!(decoded, Err(MonitorError::Decode(_))),
+            "{:?}",
+            data
+        );
+    }
+
+    // More topics than a log can carry
+    let topics = vec![H256::from_str(SUPPLY_EVENT_TOPIC).unwrap(); 5];
+    let decoded = decode_event::<Supply>(&topics, &[]);
+    assert!(matches!(decoded, Ok(None)));
+}
+
+#[test]
+fn alert_digest_coalesces_alerts_within_window() {
+    use crate::alerts::AlertMessage;
+    use crate::AlertDigest;
+    use std::time::{Duration, Instant};
+
+    let start = Instant::now();
+    let mut digest = AlertDigest::new(Duration::from_secs(10));
+
+    digest.push(start, AlertMessage::new("🚨", "ALERT").body("first"));
+    digest.push(
+        start + Duration::from_secs(2),
+        AlertMessage::new("🚨", "ALERT").body("second"),
+    );
+    digest.push(
+        start + Duration::from_secs(4),
+        AlertMessage::new("🚨", "ALERT").body("final"),
+    );
+    assert!(digest.take_due(start + Duration::from_secs(5)).is_none());
+
+    let message = digest
+        .take_due(start + Duration::from_secs(10))
+        .expect("digest should be due after the window");
+    assert_eq!(message.title, "ALERT DIGEST");
+    assert!(message.body.contains("3 alerts"));
+    assert!(message.body.ends_with("final"));
+    assert!(digest.take_due(start + Duration::from_secs(20)).is_none());
+}
+
+#[test]
+fn alert_digest_with_zero_window_sends_immediately() {
+    use crate::alerts::AlertMessage;
+    use crate::AlertDigest;
+    use std::time::{Duration, Instant};
+
+    let now = Instant::now();
+    let mut digest = AlertDigest::new(Duration::ZERO);
+
+    let alert = AlertMessage::new("🚨", "ALERT").body("alert");
+    digest.push(now, alert.clone());
+    assert_eq!(digest.take_due(now), Some(alert));
+}
+
+#[test]
+fn alert_message_formats_for_telegram_and_discord() {
+    use crate::alerts::AlertMessage;
+
+    let message = AlertMessage::new("🚨", "LIQUIDATION ALERT")
+        .field("Address", "0xBDD3B59416Fc0263354953aeeFC51Ba3A94E134e")
+        .field("Supply Token", "0xdac1 (Decimals: 6)")
+        .body("Health factor is below 1.0!");
+
+    let telegram = message.to_markdown_v2();
+    assert!(telegram.starts_with("🚨 *LIQUIDATION ALERT* 🚨"));
+    assert!(telegram.contains("*Address:* `0xBDD3B59416Fc0263354953aeeFC51Ba3A94E134e`"));
+    // Code spans keep parentheses as-is, plain text escapes them
+    assert!(telegram.contains("*Supply Token:* `0xdac1 (Decimals: 6)`"));
+    assert!(telegram.ends_with("Health factor is below 1\\.0\\!"));
+
+    let discord = message.to_discord_payload();
+    let embed = &discord["embeds"][0];
+    assert_eq!(embed["title"], "🚨 LIQUIDATION ALERT 🚨");
+    assert_eq!(embed["description"], "Health factor is below 1.0!");
+    assert_eq!(embed["fields"][1]["name"], "Supply Token");
+    assert_eq!(embed["fields"][1]["value"], "`0xdac1 (Decimals: 6)`");
+}
+
+#[test]
+fn alert_message_formats_for_slack() {
+    use crate::alerts::{AlertMessage, AlertSeverity};
+    use crate::AlertLevel;
+    use serde_json::json;
+
+    let message = AlertMessage::new("🚨", "LIQUIDATION ALERT")
+        .severity(AlertLevel::Critical.severity())
+        .field("Health Factor", "0.98")
+        .field("Debt", "<$1,000 & more>")
+        .body("Health factor is below 1.0!");
+
+    assert_eq!(
+        message.to_slack_payload(),
+        json!({
+            "text": "🚨 LIQUIDATION ALERT 🚨",
+            "attachments": [{
+                "color": "#E01E5A",
+                "blocks": [
+                    {
+                        "type": "header",
+                        "text": {
+                            "type": "plain_text",
+                            "text": "🚨 LIQUIDATION ALERT 🚨",
+                            "emoji": true,
+                        },
+                    },
+                    {
+                        "type": "section",
+                        "fields": [
+                            { "type": "mrkdwn", "text": "*Health Factor:*\n`0.98`" },
+                            { "type": "mrkdwn", "text": "*Debt:*\n`&lt;$1,000 &amp; more&gt;`" },
+                        ],
+                    },
+                    {
+                        "type": "section",
+                        "text": { "type": "mrkdwn", "text": "Health factor is below 1.0!" },
+                    },
+                ],
+            }],
+        })
+    );
+
+    // Tiers map to green, yellow and red; a message without fields or body has
+    // only its header
+    assert_eq!(AlertLevel::Warn.severity(), AlertSeverity::Warning);
+    let resolved = AlertMessage::new("✅", "POSITION RECOVERED")
+        .severity(AlertSeverity::Resolved)
+        .to_slack_payload();
+    assert_eq!(resolved["attachments"][0]["color"], "#2EB67D");
+    assert_eq!(
+        resolved["attachments"][0]["blocks"]
+            .as_array()
+            .unwrap()
+            .len(),
+        1
+    );
+}
+
+#[test]
+fn test_alert_report_lists_each_channel() {
+    use crate::alerts::{test_alert_message, test_alert_report, AlertSeverity};
+    use crate::error::MonitorError;
+
+    let message = test_alert_message();
+    assert_eq!(message.title, "MONITORING STARTED");
+    assert_eq!(message.severity, AlertSeverity::Resolved);
+
+    let results = vec![
+        ("Telegram".to_string(), Ok(())),
+        (
+            "Webhook".to_string(),
+            Err(MonitorError::Webhook("HTTP 404".to_string())),
+        ),
+    ];
+    let report = test_alert_report(&results);
+    assert_eq!(report[0], "Telegram: ok");
+    assert!(report[1].starts_with("Webhook: failed ("));
+    assert!(report[1].contains("HTTP 404"));
+    assert_eq!(test_alert_report(&[]), vec!["No alert channels configured"]);
+}
+
+#[test]
+fn alert_message_formats_for_generic_webhook() {
+    use crate::alerts::{
+        webhook_signature, AlertMessage, AlertPosition, AlertSeverity, AlertToken,
+    };
+    use serde_json::json;
+
+    let message = AlertMessage::new("✅", "POSITION RECOVERED")
+        .severity(AlertSeverity::Resolved)
+        .field("Health Factor", "1.80")
+        .body("Back below every warning tier.")
+        .position(AlertPosition {
+            chain: "Ethereum".to_string(),
+            user_address: "0x00000000000000000000000000000000000000aa".to_string(),
+            health_factor: Some(1.8),
+            collateral_usd: Some(2000.0),
+            debt_usd: Some(900.0),
+            tokens: vec![AlertToken {
+                side: "borrowed",
+                address: "0x00000000000000000000000000000000000000bb".to_string(),
+                symbol: Some("USDC".to_string()),
+                amount: "900000000".to_string(),
+            }],
+        });
+
+    assert_eq!(
+        message.to_webhook_payload(1_700_000_000),
+        json!({
+            "schema_version": 1,
+            "timestamp": 1_700_000_000u64,
+            "severity": "resolved",
+            "title": "POSITION RECOVERED",
+            "chain": "Ethereum",
+            "user_address": "0x00000000000000000000000000000000000000aa",
+            "health_factor": 1.8,
+            "collateral_usd": 2000.0,
+            "debt_usd": 900.0,
+            "tokens": [{
+                "side": "borrowed",
+                "address": "0x00000000000000000000000000000000000000bb",
+                "symbol": "USDC",
+                "amount": "900000000",
+            }],
+            "fields": { "Health Factor": "1.80" },
+            "body": "Back below every warning tier.",
+        })
+    );
+
+    // Without a position, or with no debt, the position keys are null
+    let bare = AlertMessage::new("⚠️", "SELF-TEST").position(AlertPosition {
+        health_factor: Some(f64::INFINITY),
+        ..AlertPosition::default()
+    });
+    let payload = bare.to_webhook_payload(0);
+    assert_eq!(payload["severity"], "warning");
+    assert_eq!(payload["chain"], serde_json::Value::Null);
+    assert_eq!(payload["health_factor"], serde_json::Value::Null);
+    assert_eq!(payload["tokens"], json!([]));
+
+    // RFC 4231 test case 2
+    assert_eq!(
+        webhook_signature("Jefe", b"what do ya want for nothing?"),
+        "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+    );
+}
+
+#[test]
+fn price_triggered_alert_requires_consecutive_ticks() {
+    use crate::AlertConfirmation;
+
+    let mut confirmation = AlertConfirmation::new(3);
+
+    assert!(!confirmation.observe(true, 0));
+    assert!(!confirmation.observe(true, 0));
+    assert!(confirmation.observe(true, 0));
+
+    // A single healthy tick resets the streak
+    assert!(!confirmation.observe(false, 0));
+    assert!(!confirmation.observe(true, 0));
+}
+
+#[test]
+fn event_triggered_alert_fires_immediately() {
+    use crate::AlertConfirmation;
+
+    let mut confirmation = AlertConfirmation::new(3);
+
+    assert!(!confirmation.observe(false, 0));
+    assert!(confirmation.observe(true, 1));
+    // Event-driven change that leaves the position healthy does not alert
+    assert!(!confirmation.observe(false, 2));
+}
+
+#[test]
+fn position_changes_carry_the_triggering_tx_as_exemplar() {
+    use crate::chains::ethereum::ethereum_chain::ChainState;
+    use crate::metrics::render_metrics;
+    use ethers::prelude::{H160, H256, U256};
+
+    let chain = ChainState::new("exemplar", String::new, String::new);
+    let tx_hash = H256::repeat_byte(0xab);
+    chain
+        .update_supplied_amount(H160::repeat_byte(0x01), U256::from(1_000))
+        .unwrap();
+    chain.record_event_tx(Some(tx_hash), None).unwrap();
+
+    let series = format!(
+        "aave_position_changes_total{{chain=\"exemplar\",user=\"{}\"}} 1",
+        chain.user_address()
+    );
+    let text = render_metrics(&[&chain], true);
+    assert!(text.contains("# TYPE aave_position_changes counter\n"));
+    assert!(text.contains(&format!("{} # {{tx_hash=\"{:?}\"}} 1\n", series, tx_hash)));
+    // Prometheus text format has no exemplars
+    assert!(render_metrics(&[&chain], false).contains(&format!("{}\n", series)));
+}
+
+#[test]
+fn self_test_ignores_drift_within_tolerance() {
+    use crate::health_factor_drift_report;
+
+    assert!(health_factor_drift_report(1.50, 1.53, false, 0.05).is_none());
+    // Two debt-free accounts agree
+    assert!(health_factor_drift_report(f64::INFINITY, f64::INFINITY, false, 0.05).is_none());
+}
+
+#[test]
+fn self_test_reports_likely_cause_of_drift() {
+    use crate::health_factor_drift_report;
+
+    let prices = health_factor_drift_report(1.80, 1.50, false, 0.05).unwrap();
+    assert!(prices.contains("price discrepancy"));
+
+    let decimals = health_factor_drift_report(15.0, 1.50, false, 0.05).unwrap();
+    assert!(decimals.contains("decimals"));
+
+    let events = health_factor_drift_report(2.70, 1.50, false, 0.05).unwrap();
+    assert!(events.contains("stale or missed"));
+
+    // Same debt/collateral ratio but another health factor: the threshold differs,
+    // e.g. an eMode category we don't apply
+    let threshold = health_factor_drift_report(1.06, 1.17, true, 0.05).unwrap();
+    assert!(threshold.contains("liquidation threshold"));
+
+    // Only one side sees debt
+    let missed = health_factor_drift_report(f64::INFINITY, 1.50, false, 0.05).unwrap();
+    assert!(missed.contains("stale or missed"));
+    let missed = health_factor_drift_report(1.50, f64::INFINITY, true, 0.05).unwrap();
+    assert!(missed.contains("stale or missed"));
+}
+
+#[test]
+fn simplehash_api_key_must_be_present_and_non_empty() {
+    use crate::chains::get_simplehash_api_key;
+    use std::env;
+
+    env::set_var("SIMPLEHASH_API_KEY", "  ");
+    assert!(get_simplehash_api_key()
+        .unwrap_err()
+        .to_string()
+        .contains("empty"));
+
+    env::remove_var("SIMPLEHASH_API_KEY");
+    assert!(get_simplehash_api_key()
+        .unwrap_err()
+        .to_string()
+        .contains("not set"));
+
+    env::set_var("SIMPLEHASH_API_KEY", "sk_test");
+    assert_eq!(get_simplehash_api_key().unwrap(), "sk_test");
+}
+
+#[tokio::test]
+async fn cached_price_is_reused_within_ttl() {
+    use crate::{get_price_with_cache, PriceCache, PriceResult};
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    let cache: PriceCache = Mutex::new(HashMap::new());
+    let requests = AtomicUsize::new(0);
+    let fetch = |_contract: String| {
+        requests.fetch_add(1, Ordering::SeqCst);
+        async {
+            Ok(Some(PriceResult {
+                symbol: "USDT".to_string(),
+                price: 1.0,
+                decimals: 6,
+                fetched_at: 0,
+                currency: "USD".to_string(),
+            }))
+        }
+    };
+
+    let ttl = Duration::from_secs(30);
+    let first = get_price_with_cache(&cache, "0xusdt".to_string(), ttl, fetch)
+        .await
+        .unwrap();
+    let second = get_price_with_cache(&cache, "0xusdt".to_string(), ttl, fetch)
+        .await
+        .unwrap();
+
+    assert_eq!(first.unwrap().price, 1.0);
+    assert_eq!(second.unwrap().price, 1.0);
+    assert_eq!(requests.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn average_price_ignores_outliers() {
+    use crate::get_avg;
+
+    // One marketplace reporting 0 and one wildly high don't move the average
+    let avg = get_avg(vec![2_001.0, 1_999.0, 2_000.5, 0.0, 1_999.5, 20_000.0]).unwrap();
+    assert!(
+        (avg - 2_000.0).abs() < 1.0,
+        "average {} pulled by outliers",
+        avg
+    );
+
+    let avg = get_avg(vec![1.0, 1.0, 1.0, 0.2]).unwrap();
+    assert_eq!(avg, 1.0);
+
+    assert_eq!(get_avg(vec![1_999.0]), Some(1_999.0));
+    assert_eq!(get_avg(vec![0.0, -5.0, f64::NAN]), None);
+    assert_eq!(get_avg(Vec::new()), None);
+}
+
+#[test]
+fn stale_and_invalid_prices_are_rejected() {
+    use crate::{check_price, PriceResult};
+
+    let price = |price: f64, fetched_at: u64| PriceResult {
+        symbol: "WETH".to_string(),
+        price,
+        decimals: 18,
+        fetched_at,
+        currency: "USD".to_string(),
+    };
+    let now = 1_700_000_000;
+    assert!(check_price(&price(2_000.0, now - 60), now, 120).is_ok());
+    assert!(check_price(&price(2_000.0, now - 121), now, 120).is_err());
+    // 0 disables the age check, never the value check
+    assert!(check_price(&price(2_000.0, 0), now, 0).is_ok());
+    assert!(check_price(&price(0.0, now), now, 0).is_err());
+    assert!(check_price(&price(-1.0, now), now, 120).is_err());
+    assert!(check_price(&price(f64::NAN, now), now, 120).is_err());
+}
+
+#[tokio::test]
+async fn expired_price_is_fetched_again() {
+    use crate::{get_price_with_cache, PriceCache};
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    let cache: PriceCache = Mutex::new(HashMap::new());
+    let requests = AtomicUsize::new(0);
+    let fetch = |_contract: String| {
+        requests.fetch_add(1, Ordering::SeqCst);
+        async {
+            Ok(Some(crate::PriceResult {
+                symbol: "WBTC".to_string(),
+                price: 60_000.0,
+                decimals: 8,
+                fetched_at: 0,
+                currency: "USD".to_string(),
+            }))
+        }
+    };
+
+    get_price_with_cache(&cache, "0xwbtc".to_string(), Duration::ZERO, fetch)
+        .await
+        .unwrap();
+    get_price_with_cache(&cache, "0xwbtc".to_string(), Duration::ZERO, fetch)
+        .await
+        .unwrap();
+
+    assert_eq!(requests.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn healthy_position_does_not_alert() {
+    use crate::{compute_health_factor, is_in_liquidation_range};
+
+    // $1000 collateral, $100 debt: far from liquidation
+    let health_factor = compute_health_factor(1000.0, 100.0, 0.89);
+    assert!((health_factor - 8.9).abs() < 1e-9);
+    assert!(!is_in_liquidation_range(health_factor));
+
+    // No debt at all can never be liquidated
+    assert!(!is_in_liquidation_range(compute_health_factor(
+        1000.0, 0.0, 0.89
+    )));
+}
+
+#[test]
+fn position_near_liquidation_alerts() {
+    use crate::{compute_health_factor, compute_ltv, is_in_liquidation_range};
+
+    // $1000 collateral, $900 debt: borrowed value above the 89% threshold
+    let health_factor = compute_health_factor(1000.0, 900.0, 0.89);
+    assert!(health_factor < 1.0);
+    assert!(is_in_liquidation_range(health_factor));
+    assert!((compute_ltv(1000.0, 900.0) - 0.9).abs() < 1e-9);
+
+    // Exactly at 85% LTV stays healthy with an 89% threshold
+    assert!(!is_in_liquidation_range(compute_health_factor(
+        1000.0, 850.0, 0.89
+    )));
+}
+
+#[test]
+fn health_factor_boundaries() {
+    use crate::{compute_health_factor, is_in_liquidation_range};
+
+    // (collateral, debt, threshold, health factor, liquidatable)
+    let cases = [
+        // No debt: never liquidatable, even without collateral
+        (1000.0, 0.0, 0.8, f64::INFINITY, false),
+        (0.0, 0.0, 0.8, f64::INFINITY, false),
+        // Debt without collateral
+        (0.0, 100.0, 0.8, 0.0, true),
+        // Exactly at the threshold is still safe, just below it is not
+        (1000.0, 800.0, 0.8, 1.0, false),
+        (1000.0, 800.01, 0.8, 800.0 / 800.01, true),
+        (1000.0, 400.0, 0.8, 2.0, false),
+    ];
+    for (collateral, debt, threshold, expected, liquidatable) in cases {
+        let health_factor = compute_health_factor(collateral, debt, threshold);
+        if expected.is_infinite() {
+            assert!(health_factor.is_infinite(), "{} / {}", collateral, debt);
+        } else {
+            assert!(
+                (health_factor - expected).abs() < 1e-9,
+                "{} / {}: {}",
+                collateral,
+                debt,
+                health_factor
+            );
+        }
+        assert_eq!(
+            is_in_liquidation_range(health_factor),
+            liquidatable,
+            "{} / {}",
+            collateral,
+            debt
+        );
+    }
+}
+
+#[test]
+fn sustained_price_outage_is_reported_once_and_its_end_too() {
+    use crate::{PriceOutage, PriceOutageChange};
+    use std::time::{Duration, Instant};
+
+    let start = Instant::now();
+    let mut outage = PriceOutage::new(3, Duration::from_secs(60));
+    // A failure followed by success is a blip, not an outage
+    assert_eq!(outage.observe_failure(start), None);
+    assert_eq!(outage.observe_success(start), None);
+
+    // Enough failures, but not for long enough yet
+    for secs in [0, 15, 30] {
+        assert_eq!(
+            outage.observe_failure(start + Duration::from_secs(secs)),
+            None
+        );
+    }
+    assert_eq!(
+        outage.observe_failure(start + Duration::from_secs(60)),
+        Some(PriceOutageChange::Started {
+            failures: 4,
+            since: Duration::from_secs(60)
+        })
+    );
+    assert_eq!(
+        outage.observe_failure(start + Duration::from_secs(75)),
+        None
+    );
+    assert_eq!(
+        outage.observe_success(start + Duration::from_secs(90)),
+        Some(PriceOutageChange::Recovered {
+            lasted: Duration::from_secs(90)
+        })
+    );
+    assert_eq!(
+        outage.observe_success(start + Duration::from_secs(105)),
+        None
+    );
+
+    let mut disabled = PriceOutage::new(0, Duration::ZERO);
+    for _ in 0..10 {
+        assert_eq!(disabled.observe_failure(start), None);
+    }
+}
+
+#[test]
+fn parses_multi_asset_position_config() {
+    use crate::chains::parse_asset_positions;
+    use ethers::prelude::U256;
+
+    let assets = parse_asset_positions(
+        "0xdac17f958d2ee523a2206206994597c13d831ec7:6:1000000, \
+         0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2:18:20000000000000000000",
+    )
+    .unwrap();
+
+    assert_eq!(assets.len(), 2);
+    assert_eq!(assets[0].decimals, 6);
+    assert_eq!(assets[0].initial_amount, U256::from(1_000_000u64));
+    assert_eq!(assets[1].decimals, 18);
+    assert_eq!(
+        assets[1].initial_amount,
+        U256::from_dec_str("20000000000000000000").unwrap()
+    );
+
+    assert!(parse_asset_positions("0xdac17f958d2ee523a2206206994597c13d831ec7:6").is_err());
+}
+
+#[test]
+fn known_assets_seed_the_reserve_registry() {
+    use crate::chains::ethereum::ethereum_chain::ChainState;
+    use crate::chains::{
+        get_reserve_metadata, get_token_decimals, get_token_symbol, parse_known_assets,
+        update_reserve_metadata,
+    };
+
+    let assets = parse_known_assets(
+        "0x00000000000000000000000000000000000a55e1:TKA:6:0.78, \
+         0x00000000000000000000000000000000000a55e2:TKB:18",
+    )
+    .unwrap();
+    assert_eq!(assets.len(), 2);
+    let (collateral, metadata) = assets[0].clone();
+    assert_eq!(metadata.decimals, Some(6));
+    assert_eq!(metadata.symbol.as_deref(), Some("TKA"));
+    assert_eq!(metadata.is_collateral, Some(true));
+    assert_eq!(metadata.liquidation_threshold, Some(0.78));
+    assert_eq!(assets[1].1.liquidation_threshold, None);
+    assert_eq!(assets[1].1.is_collateral, None);
+
+    for (reserve, metadata) in assets {
+        update_reserve_metadata(reserve, |known| *known = metadata);
+    }
+    assert_eq!(get_token_decimals(&collateral), Some(6));
+    assert_eq!(get_token_symbol(&collateral).as_deref(), Some("TKA"));
+    // Seeded thresholds stand in until the Pool's own value is read
+    let chain = ChainState::new("known-assets", String::new, String::new);
+    assert_eq!(chain.liquidation_threshold(&collateral), 0.78);
+    chain.set_liquidation_threshold(collateral, 0.8).unwrap();
+    assert_eq!(chain.liquidation_threshold(&collateral), 0.8);
+    assert!(get_reserve_metadata(&collateral).is_some());
+
+    assert!(parse_known_assets("0x00000000000000000000000000000000000a55e1:TKA").is_err());
+    assert!(parse_known_assets("0x00000000000000000000000000000000000a55e1:TKA:6:1.5").is_err());
+}
+
+#[test]
+fn emode_collateral_uses_the_category_threshold() {
+    use crate::chains::ethereum::ethereum_chain::{
+        emode_category_from_configuration, is_isolated_from_configuration,
+        liquidation_threshold_from_configuration, AccountMode, ChainState, EModeCategory,
+    };
+    use ethers::prelude::{Address, U256};
+
+    // LT 80.5%, eMode category 1, debt ceiling 1,000,000.00
+    let configuration = (U256::from(8_050u64) << 16)
+        | (U256::from(1u64) << 168)
+        | (U256::from(100_000_000u64) << 212);
+    assert_eq!(
+        liquidation_threshold_from_configuration(configuration),
+        0.805
+    );
+    assert_eq!(emode_category_from_configuration(configuration), 1);
+    assert!(is_isolated_from_configuration(configuration));
+    assert!(!is_isolated_from_configuration(U256::from(8_050u64) << 16));
+
+    let wsteth: Address = "0x7f39C581F595B53c5cb19bD0b3f8dA6c935E2Ca0"
+        .parse()
+        .unwrap();
+    let usdc: Address = "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48"
+        .parse()
+        .unwrap();
+    let chain = ChainState::new("emode", String::new, String::new);
+    chain.set_liquidation_threshold(wsteth, 0.81).unwrap();
+    chain.set_liquidation_threshold(usdc, 0.78).unwrap();
+    chain
+        .set_account_mode(AccountMode {
+            emode: Some(EModeCategory {
+                id: 1,
+                label: "ETH correlated".to_string(),
+                ltv: 0.93,
+                liquidation_threshold: 0.95,
+            }),
+            emode_reserves: [wsteth].into_iter().collect(),
+            ..AccountMode::default()
+        })
+        .unwrap();
+    // Only collateral in the category moves to its threshold
+    assert_eq!(chain.liquidation_threshold(&wsteth), 0.95);
+    assert_eq!(chain.liquidation_threshold(&usdc), 0.78);
+
+    chain.set_account_mode(AccountMode::default()).unwrap();
+    assert_eq!(chain.liquidation_threshold(&wsteth), 0.81);
+}
+
+struct FixedPriceSource(Result<Option<f64>, &'static str>);
+
+#[async_trait::async_trait]
+impl crate::prices::PriceSource for FixedPriceSource {
+    fn name(&self) -> &str {
+        "fixed"
+    }
+
+    async fn price(
+        &self,
+        _fungible_id: &str,
+    ) -> Result<Option<crate::PriceResult>, crate::error::MonitorError> {
+        self.0
+            .map(|price| {
+                price.map(|price| crate::PriceResult {
+                    symbol: "TKN".to_string(),
+                    price,
+                    decimals: 18,
+                    fetched_at: 0,
+                    currency: "USD".to_string(),
+                })
+            })
+            .map_err(|e| crate::error::MonitorError::Price(e.to_string()))
+    }
+}
+
+#[tokio::test]
+async fn price_sources_fall_back_in_order() {
+    use crate::prices::{fetch_price_from_sources, PriceSource};
+
+    let sources: Vec<Box<dyn PriceSource>> = vec![
+        Box::new(FixedPriceSource(Err("down"))),
+        Box::new(FixedPriceSource(Ok(None))),
+        Box::new(FixedPriceSource(Ok(Some(2_000.0)))),
+    ];
+    let price = fetch_price_from_sources(&sources, "ethereum.0x01")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(price.price, 2_000.0);
+
+    // Without any price the error of the failing source is surfaced
+    assert!(fetch_price_from_sources(&sources[..2], "ethereum.0x01")
+        .await
+        .is_err());
+    assert!(fetch_price_from_sources(&sources[1..2], "ethereum.0x01")
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn parses_chainlink_feeds() {
+    use crate::chains::parse_chainlink_feeds;
+
+    let feeds = parse_chainlink_feeds(
+        "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2:0x5f4eC3Df9cbd43714FE2740f5E3616155c5b8419, \
+         polygon.0xc2132D05D31c914a87C6611C10748AEb04B58e8F:0x0A6513e40db6EB1b165753AD52E80663aeA50545",
+    )
+    .unwrap();
+    assert_eq!(feeds.len(), 2);
+    assert!(feeds.contains_key("ethereum.0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2"));
+    assert!(feeds.contains_key("polygon.0xc2132d05d31c914a87c6611c10748aeb04b58e8f"));
+
+    assert!(parse_chainlink_feeds("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2").is_err());
+}
+
+#[test]
+fn start_block_resolves_against_the_head() {
+    use crate::chains::{parse_start_block, StartBlock};
+
+    assert_eq!(parse_start_block("").unwrap(), None);
+    assert_eq!(
+        parse_start_block("latest").unwrap(),
+        Some(StartBlock::Latest)
+    );
+    assert_eq!(
+        parse_start_block(" Latest-100 ").unwrap(),
+        Some(StartBlock::BeforeLatest(100))
+    );
+    assert_eq!(
+        parse_start_block("19000000").unwrap(),
+        Some(StartBlock::Number(19_000_000))
+    );
+    assert!(parse_start_block("latest-").is_err());
+    assert!(parse_start_block("-5").is_err());
+
+    // Newest processed block 990 (head 1000 less a confirmation depth of 10)
+    assert_eq!(StartBlock::Latest.first_block(990, 1000).unwrap(), 991);
+    assert_eq!(
+        StartBlock::BeforeLatest(100)
+            .first_block(990, 1000)
+            .unwrap(),
+        891
+    );
+    assert_eq!(
+        StartBlock::BeforeLatest(5_000)
+            .first_block(990, 1000)
+            .unwrap(),
+        1
+    );
+    assert_eq!(StartBlock::Number(500).first_block(990, 1000).unwrap(), 500);
+    assert_eq!(
+        StartBlock::Number(1000).first_block(990, 1000).unwrap(),
+        1000
+    );
+    assert!(StartBlock::Number(1001).first_block(990, 1000).is_err());
+}
+
+#[test]
+fn uniswap_twap_ticks_convert_to_prices() {
+    use crate::chains::ethereum::ethereum_chain::{twap_tick, uniswap_tick_to_price};
+    use crate::chains::parse_uniswap_twap_pairs;
+    use ethers::prelude::Address;
+
+    // The mean tick rounds towards negative infinity, like OracleLibrary.consult
+    assert_eq!(twap_tick(0, 1_800 * 200_311, 1_800), 200_311);
+    assert_eq!(twap_tick(0, -7, 2), -4);
+    assert_eq!(twap_tick(10, 4, 2), -3);
+
+    // USDC sorts before WETH, so the pool's tick prices USDC in WETH
+    let weth: Address = "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2"
+        .parse()
+        .unwrap();
+    let usdc: Address = "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48"
+        .parse()
+        .unwrap();
+    let weth_in_usdc = uniswap_tick_to_price(200_311, weth, usdc, 18, 6);
+    assert!((weth_in_usdc - 2000.0).abs() < 0.1, "{}", weth_in_usdc);
+    let usdc_in_weth = uniswap_tick_to_price(200_311, usdc, weth, 6, 18);
+    assert!((usdc_in_weth * weth_in_usdc - 1.0).abs() < 1e-9);
+
+    let pairs = parse_uniswap_twap_pairs(
+        "0x1f9840a85d5aF5bf1D1762F925BDADdC4201F984:0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2, \
+         polygon.0xc2132D05D31c914a87C6611C10748AEb04B58e8F:0x7ceB23fD6bC0adD59E62ac25578270cFf1b9f619",
+    )
+    .unwrap();
+    assert_eq!(
+        pairs.get("ethereum.0x1f9840a85d5af5bf1d1762f925bdaddc4201f984"),
+        Some(&weth)
+    );
+    assert!(pairs.contains_key("polygon.0xc2132d05d31c914a87c6611c10748aeb04b58e8f"));
+    assert!(parse_uniswap_twap_pairs("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2").is_err());
+    assert!(parse_uniswap_twap_pairs(
+        "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2:0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2"
+    )
+    .is_err());
+}
+
+#[test]
+fn cli_flags_parse_into_overrides() {
+    use crate::cli::Cli;
+    use clap::Parser;
+
+    let cli = Cli::try_parse_from([
+        "aave-monitoring-tool",
+        "--user-address",
+        "0x0000000000000000000000000000000000000001",
+        "--liquidation-threshold",
+        "0.8",
+        "--print-config",
+    ])
+    .unwrap();
+    assert_eq!(
+        cli.user_address.as_deref(),
+        Some("0x0000000000000000000000000000000000000001")
+    );
+    assert_eq!(cli.liquidation_threshold, Some(0.8));
+    assert_eq!(cli.alert_cooldown_secs, None);
+    assert!(cli.print_config);
+    assert!(!cli.test_alerts);
+    assert!(
+        Cli::try_parse_from(["aave-monitoring-tool", "--test-alerts"])
+            .unwrap()
+            .test_alerts
+    );
+
+    assert!(
+        Cli::try_parse_from(["aave-monitoring-tool", "--supply-token-decimals", "six"]).is_err()
+    );
+}
+
+#[test]
+fn replay_flags_parse_and_block_time_drives_the_alert_clock() {
+    use crate::cli::Cli;
+    use crate::replay::replay_instant;
+    use clap::Parser;
+    use std::time::{Duration, Instant};
+
+    let cli = Cli::try_parse_from([
+        "aave-monitoring-tool",
+        "--replay-from",
+        "19000000",
+        "--replay-to",
+        "19000100",
+    ])
+    .unwrap();
+    assert_eq!(cli.replay_from, Some(19_000_000));
+    assert_eq!(cli.replay_to, Some(19_000_100));
+    assert!(!cli.replay_send_alerts);
+
+    // The end block and sending only make sense with a start block
+    assert!(Cli::try_parse_from(["aave-monitoring-tool", "--replay-to", "19000100"]).is_err());
+    assert!(Cli::try_parse_from(["aave-monitoring-tool", "--replay-send-alerts"]).is_err());
+
+    let start = Instant::now();
+    assert_eq!(replay_instant(start, 1_700_000_000, 1_700_000_000), start);
+    assert_eq!(
+        replay_instant(start, 1_700_000_000, 1_700_003_600),
+        start + Duration::from_secs(3_600)
+    );
+    // Out-of-order timestamps never move the clock before the start
+    assert_eq!(replay_instant(start, 1_700_000_000, 1_699_999_000), start);
+}
+
+fn test_endpoints() -> Vec<String> {
+    vec![
+        "https://primary.example".to_string(),
+        "https://backup.example".to_string(),
+    ]
+}
+
+#[test]
+fn endpoint_pool_fails_over_after_repeated_failures() {
+    use crate::chains::endpoints::{parse_endpoint_list, EndpointPool};
+    use crate::error::MonitorError;
+
+    assert_eq!(
+        parse_endpoint_list(" https://primary.example, ,https://backup.example"),
+        test_endpoints()
+    );
+
+    // Default threshold: three failures in a row
+    let pool = EndpointPool::new("test RPC", test_endpoints);
+    pool.report_failure("https://primary.example");
+    pool.report_failure("https://primary.example");
+    pool.report_success("https://primary.example");
+    pool.report_failure("https://primary.example");
+    pool.report_failure("https://primary.example");
+    assert_eq!(pool.current(), "https://primary.example");
+
+    // Only RPC errors count against an endpoint
+    pool.record::<()>(
+        "https://primary.example",
+        &Err(MonitorError::Config("bad".to_string())),
+    );
+    assert_eq!(pool.current(), "https://primary.example");
+    pool.record::<()>(
+        "https://primary.example",
+        &Err(MonitorError::Rpc("down".to_string())),
+    );
+    assert_eq!(pool.current(), "https://backup.example");
+
+    // Wraps around to the first endpoint
+    for _ in 0..3 {
+        pool.report_failure("https://backup.example");
+    }
+    assert_eq!(pool.current(), "https://primary.example");
+}
+
+#[test]
+fn parses_initial_amounts_above_u64_max() {
+    use crate::chains::parse_initial_amount;
+    use ethers::prelude::U256;
+
+    // 20 ETH in wei is above u64::MAX
+    assert_eq!(
+        parse_initial_amount("INITIAL_SUPPLIED_AMOUNT", "20000000000000000000"),
+        U256::from(20u64) * U256::exp10(18)
+    );
+    assert_eq!(
+        parse_initial_amount("INITIAL_SUPPLIED_AMOUNT", " 1000000 "),
+        U256::from(1_000_000u64)
+    );
+    assert_eq!(
+        parse_initial_amount("INITIAL_SUPPLIED_AMOUNT", "20 ETH"),
+        U256::zero()
+    );
+}
+
+#[test]
+fn events_update_the_reserve_they_reference() {
+    use crate::chains::ethereum::ethereum_chain::{
+        refresh_position_after_borrow, refresh_position_after_supply, to_h160, Borrow, Supply,
+        ETHEREUM,
+    };
+    use ethers::prelude::U256;
+
+    let first_reserve = alloy_primitives::Address::repeat_byte(0x11);
+    let second_reserve = alloy_primitives::Address::repeat_byte(0x22);
+    let user = alloy_primitives::Address::repeat_byte(0x33);
+
+    for (reserve, amount) in [(first_reserve, 100u64), (second_reserve, 250u64)] {
+        refresh_position_after_supply(
+            &ETHEREUM,
+            Supply {
+                reserve,
+                user,
+                onBehalfOf: user,
+                amount: alloy_primitives::U256::from(amount),
+                referralCode: 0,
+            },
+        )
+        .unwrap();
+    }
+    refresh_position_after_borrow(
+        &ETHEREUM,
+        Borrow {
+            reserve: second_reserve,
+            user,
+            onBehalfOf: user,
+            amount: alloy_primitives::U256::from(40u64),
+            interestRateMode: 2,
+            borrowRate: alloy_primitives::U256::from(0u64),
+            referralCode: 0,
+        },
+    )
+    .unwrap();
+
+    let position = ETHEREUM.position().unwrap();
+    assert_eq!(
+        position.supplied_amount(&to_h160(first_reserve)),
+        U256::from(100)
+    );
+    assert_eq!(
+        position.supplied_amount(&to_h160(second_reserve)),
+        U256::from(250)
+    );
+    assert_eq!(
+        position.borrowed_amount(&to_h160(second_reserve)),
+        U256::from(40)
+    );
+    assert_eq!(
+        position.borrowed_amount(&to_h160(first_reserve)),
+        U256::zero()
+    );
+}
+
+#[test]
+fn usd_values_sum_across_reserves() {
+    use crate::{compute_health_factor, to_usd};
+    use ethers::prelude::U256;
+
+    // 1000 USDT + 1 WETH at $2000 as collateral, 0.02 WBTC at $60000 as debt
+    let collateral = to_usd(U256::from(1_000_000_000u64), 6, 1.0).unwrap()
+        + to_usd(
+            U256::from_dec_str("1000000000000000000").unwrap(),
+            18,
+            2000.0,
+        )
+        .unwrap();
+    let debt = to_usd(U256::from(2_000_000u64), 8, 60_000.0).unwrap();
+
+    assert!((collateral - 3000.0).abs() < 1e-6);
+    assert!((debt - 1200.0).abs() < 1e-6);
+    assert!((compute_health_factor(collateral, debt, 0.8) - 2.0).abs() < 1e-9);
+}
+
+#[tokio::test]
+async fn reconnect_delay_grows_while_connect_keeps_failing() {
+    use crate::ReconnectBackoff;
+    use std::time::{Duration, Instant};
+
+    let mut backoff = ReconnectBackoff::new(
+        Duration::from_secs(1),
+        Duration::from_secs(60),
+        Duration::from_secs(60),
+    );
+    let connect = || async { Err::<(), String>("connection refused".to_string()) };
+
+    let mut delays = Vec::new();
+    for _ in 0..8 {
+        let connected_at = Instant::now();
+        assert!(connect().await.is_err());
+        delays.push(backoff.next_delay(connected_at.elapsed()).as_secs());
+    }
+
+    assert_eq!(delays, vec![1, 2, 4, 8, 16, 32, 60, 60]);
+}
+
+#[test]
+fn reconnect_delay_resets_after_stable_connection() {
+    use crate::ReconnectBackoff;
+    use std::time::Duration;
+
+    let mut backoff = ReconnectBackoff::new(
+        Duration::from_secs(1),
+        Duration::from_secs(60),
+        Duration::from_secs(60),
+    );
+    backoff.next_delay(Duration::ZERO);
+    backoff.next_delay(Duration::ZERO);
+    assert_eq!(backoff.next_delay(Duration::ZERO), Duration::from_secs(4));
+
+    assert_eq!(
+        backoff.next_delay(Duration::from_secs(120)),
+        Duration::from_secs(1)
+    );
+}
+
+#[test]
+fn last_processed_block_round_trips_through_disk() {
+    use crate::chains::ethereum::ethereum_chain::ETHEREUM;
+    use std::env;
+
+    let path = env::temp_dir().join(format!("aave-last-block-{}.json", std::process::id()));
+    env::set_var("LAST_PROCESSED_BLOCK_FILE", &path);
+
+    assert_eq!(ETHEREUM.last_processed_block(), None);
+    ETHEREUM.set_last_processed_block(19_000_000).unwrap();
+    assert_eq!(ETHEREUM.last_processed_block(), Some(19_000_000));
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn state_files_survive_a_crash_mid_write() {
+    use crate::chains::ethereum::ethereum_chain::{write_atomically, ChainState};
+
+    let block_file = || {
+        std::env::temp_dir()
+            .join(format!("aave-atomic-block-{}.json", std::process::id()))
+            .to_string_lossy()
+            .into_owned()
+    };
+    let chain = ChainState::new("atomic", block_file, String::new);
+    chain.set_last_processed_block(19_000_000).unwrap();
+    let written = std::fs::read_to_string(block_file()).unwrap();
+    assert!(written.contains("\"version\":1"));
+    assert!(!std::path::Path::new(&format!("{}.tmp", block_file())).exists());
+
+    // A crash while writing the next state leaves a truncated temp file behind,
+    // which is never read: the previous good state is
+    std::fs::write(format!("{}.tmp", block_file()), "{\"version\":1,\"last_pro").unwrap();
+    assert_eq!(chain.last_processed_block(), Some(19_000_000));
+    // And the next write replaces it
+    chain.set_last_processed_block(19_000_001).unwrap();
+    assert_eq!(chain.last_processed_block(), Some(19_000_001));
+
+    // Files from before versioning still load
+    write_atomically(&block_file(), "{\"last_processed_block\":18000000}").unwrap();
+    assert_eq!(chain.last_processed_block(), Some(18_000_000));
+
+    std::fs::remove_file(block_file()).unwrap();
+}
+
+#[test]
+fn reorged_blocks_are_detected_and_reverted() {
+    use crate::chains::ethereum::ethereum_chain::{
+        revert_blocks, AppliedBlock, BlockHistory, PositionDelta, PositionSide,
+    };
+    use crate::chains::PositionData;
+    use ethers::prelude::{Address, H256, U256};
+
+    let reserve = Address::repeat_byte(0x44);
+    let mut position = PositionData::new();
+    let mut history = BlockHistory::default();
+
+    // Block 100 supplies 500, block 101 borrows 200
+    position.update_supplied_amount(reserve, U256::from(500));
+    history.commit(AppliedBlock {
+        number: 100,
+        hash: H256::repeat_byte(0x01),
+        deltas: vec![PositionDelta {
+            side: PositionSide::Supplied,
+            reserve,
+            before: U256::zero(),
+            after: U256::from(500),
+        }],
+    });
+    position.update_borrowed_amount(reserve, U256::from(200));
+    history.commit(AppliedBlock {
+        number: 101,
+        hash: H256::repeat_byte(0x02),
+        deltas: vec![PositionDelta {
+            side: PositionSide::Borrowed,
+            reserve,
+            before: U256::zero(),
+            after: U256::from(200),
+        }],
+    });
+
+    // A different block 101 shows up: it conflicts, block 100 does not
+    assert!(history.conflicts(101, H256::repeat_byte(0x03)));
+    assert!(!history.conflicts(100, H256::repeat_byte(0x01)));
+    assert_eq!(history.before(101), vec![(100, H256::repeat_byte(0x01))]);
+
+    let reverted = history.rollback_to(100);
+    assert_eq!(reverted.len(), 1);
+    revert_blocks(&mut position, &reverted);
+
+    assert_eq!(position.supplied_amount(&reserve), U256::from(500));
+    assert_eq!(position.borrowed_amount(&reserve), U256::zero());
+    assert!(!history.conflicts(101, H256::repeat_byte(0x03)));
+}
+
+#[test]
+fn event_topic_constants_match_signatures() {
+    use crate::chains::ethereum::ethereum_chain::*;
+    use alloy_primitives::hex;
+    use alloy_sol_types::SolEvent;
+
+    assert_eq!(hex::encode(Supply::SIGNATURE_HASH), SUPPLY_EVENT_TOPIC);
+    assert_eq!(hex::encode(Withdraw::SIGNATURE_HASH), WITHDRAW_EVENT_TOPIC);
+    assert_eq!(hex::encode(Repay::SIGNATURE_HASH), REPAY_EVENT_TOPIC);
+    assert_eq!(hex::encode(Borrow::SIGNATURE_HASH), BORROW_EVENT_TOPIC);
+    assert_eq!(
+        hex::encode(LiquidationCall::SIGNATURE_HASH),
+        LIQUIDATION_CALL_EVENT_TOPIC
+    );
+    assert_eq!(
+        hex::encode(pool_v2::Deposit::SIGNATURE_HASH),
+        DEPOSIT_V2_EVENT_TOPIC
+    );
+    assert_eq!(
+        hex::encode(pool_v2::Repay::SIGNATURE_HASH),
+        REPAY_V2_EVENT_TOPIC
+    );
+    assert_eq!(
+        hex::encode(pool_v2::Borrow::SIGNATURE_HASH),
+        BORROW_V2_EVENT_TOPIC
+    );
+    assert_eq!(
+        hex::encode(ReserveUsedAsCollateralEnabled::SIGNATURE_HASH),
+        RESERVE_USED_AS_COLLATERAL_ENABLED_EVENT_TOPIC
+    );
+    assert_eq!(
+        hex::encode(ReserveUsedAsCollateralDisabled::SIGNATURE_HASH),
+        RESERVE_USED_AS_COLLATERAL_DISABLED_EVENT_TOPIC
+    );
+}
+
+#[test]
+fn liquidation_reduces_both_legs_and_queues_notice() {
+    use crate::chains::ethereum::ethereum_chain::{
+        refresh_position_after_liquidation, to_h160, LiquidationCall, ETHEREUM,
+    };
+    use ethers::prelude::{H256, U256};
+
+    let collateral = alloy_primitives::Address::repeat_byte(0x55);
+    let debt = alloy_primitives::Address::repeat_byte(0x66);
+    ETHEREUM
+        .update_supplied_amount(to_h160(collateral), U256::from(1_000))
+        .unwrap();
+    ETHEREUM
+        .update_borrowed_amount(to_h160(debt), U256::from(500))
+        .unwrap();
+
+    refresh_position_after_liquidation(
+        &ETHEREUM,
+        LiquidationCall {
+            collateralAsset: collateral,
+            debtAsset: debt,
+            user: alloy_primitives::Address::repeat_byte(0x77),
+            debtToCover: alloy_primitives::U256::from(250u64),
+            liquidatedCollateralAmount: alloy_primitives::U256::from(300u64),
+            liquidator: alloy_primitives::Address::repeat_byte(0x88),
+            receiveAToken: false,
+        },
+        Some(H256::repeat_byte(0x99)),
+    )
+    .unwrap();
+
+    let position = ETHEREUM.position().unwrap();
+    assert_eq!(
+        position.supplied_amount(&to_h160(collateral)),
+        U256::from(700)
+    );
+    assert_eq!(position.borrowed_amount(&to_h160(debt)), U256::from(250));
+
+    let notices = ETHEREUM.take_liquidation_notices().unwrap();
+    assert_eq!(notices.len(), 1);
+    assert_eq!(notices[0].debt_to_cover, U256::from(250));
+    assert_eq!(notices[0].tx_hash, Some(H256::repeat_byte(0x99)));
+    assert!(ETHEREUM.take_liquidation_notices().unwrap().is_empty());
+}
+
+#[test]
+fn scaled_totals_convert_to_token_amounts() {
+    use crate::usd_to_token_amount;
+    use ethers::prelude::U256;
+
+    // $1000 of USDT collateral
+    assert_eq!(
+        usd_to_token_amount(1_000.0, 1.0, 6).unwrap(),
+        U256::from(1_000_000_000u64)
+    );
+    // $600 of WBTC debt at $60000
+    assert_eq!(
+        usd_to_token_amount(600.0, 60_000.0, 8).unwrap(),
+        U256::from(1_000_000u64)
+    );
+    assert!(usd_to_token_amount(1.0, 0.0, 6).is_err());
+}
+
+#[test]
+fn user_account_data_is_scaled_field_by_field() {
+    use crate::chains::ethereum::ethereum_chain::UserAccountData;
+    use crate::chains::PoolVersion;
+    use ethers::prelude::U256;
+
+    // Raw values in the shape Pool V3 returns them: base currency with 8 decimals,
+    // basis points and a wad health factor
+    let raw = UserAccountData {
+        total_collateral_base: U256::from(1_234_567_890_123u64),
+        total_debt_base: U256::from(500_000_000_000u64),
+        current_liquidation_threshold: U256::from(8_250u64),
+        ltv: U256::from(8_000u64),
+        health_factor: U256::from_dec_str("2037037018702999900").unwrap(),
+    };
+    let account = raw.scaled(PoolVersion::V3, 1.0).unwrap();
+    assert!((account.total_collateral_usd - 12_345.678_901_23).abs() < 1e-8);
+    assert_eq!(account.total_debt_usd, 5_000.0);
+    assert_eq!(account.liquidation_threshold, 0.825);
+    assert_eq!(account.ltv, 0.8);
+    assert!((account.health_factor - 2.037_037_018_703).abs() < 1e-12);
+    let ratio = account.debt_to_collateral_ratio().unwrap();
+    assert!((ratio - 5_000.0 / 12_345.678_901_23).abs() < 1e-12);
+
+    // No debt: Aave reports the largest uint256 as health factor
+    let debt_free = UserAccountData {
+        total_debt_base: U256::zero(),
+        health_factor: U256::MAX,
+        ..raw.clone()
+    }
+    .scaled(PoolVersion::V3, 1.0)
+    .unwrap();
+    assert_eq!(debt_free.health_factor, f64::INFINITY);
+
+    // Nothing supplied leaves no ratio to compare
+    let empty = UserAccountData {
+        total_collateral_base: U256::zero(),
+        ..raw
+    }
+    .scaled(PoolVersion::V3, 1.0)
+    .unwrap();
+    assert_eq!(empty.debt_to_collateral_ratio(), None);
+}
+
+#[test]
+fn v2_user_account_data_is_scaled_from_eth() {
+    use crate::chains::ethereum::ethereum_chain::UserAccountData;
+    use crate::chains::PoolVersion;
+    use ethers::prelude::U256;
+
+    // A V2 LendingPool reports 2 ETH of collateral and 0.8 ETH of debt in wei
+    let raw = UserAccountData {
+        total_collateral_base: U256::from_dec_str("2000000000000000000").unwrap(),
+        total_debt_base: U256::from_dec_str("800000000000000000").unwrap(),
+        current_liquidation_threshold: U256::from(8_250u64),
+        ltv: U256::from(8_000u64),
+        health_factor: U256::from_dec_str("2062500000000000000").unwrap(),
+    };
+    let account = raw.scaled(PoolVersion::V2, 2_000.0).unwrap();
+    assert!((account.total_collateral_usd - 4_000.0).abs() < 1e-9);
+    assert!((account.total_debt_usd - 1_600.0).abs() < 1e-9);
+    assert_eq!(account.liquidation_threshold, 0.825);
+    assert!((account.health_factor - 2.0625).abs() < 1e-12);
+
+    // Read as V3's 8-decimal USD, 2 ETH would show as $20 billion
+    let misread = raw.scaled(PoolVersion::V3, 1.0).unwrap();
+    assert_eq!(misread.total_collateral_usd, 2e10);
+}
+
+#[test]
+fn resync_reports_only_drift_beyond_tolerance() {
+    use crate::chains::PositionData;
+    use crate::position_drift;
+    use ethers::prelude::{Address, U256};
+
+    let usdt = Address::repeat_byte(0x01);
+    let wbtc = Address::repeat_byte(0x02);
+
+    let mut local = PositionData::new();
+    local.update_supplied_amount(usdt, U256::from(1_000_000));
+    local.update_borrowed_amount(wbtc, U256::from(50_000));
+
+    let mut on_chain = PositionData::new();
+    on_chain.update_supplied_amount(usdt, U256::from(1_005_000));
+    on_chain.update_borrowed_amount(wbtc, U256::from(60_000));
+
+    let warnings = position_drift(&local, &on_chain, 0.01);
+    assert_eq!(warnings.len(), 1);
+    assert!(warnings[0].contains("borrowed"));
+    assert!(warnings[0].contains("tracked 50000 vs on-chain 60000"));
+}
+
+#[test]
+fn monitor_errors_display_readably_and_classify_fatal() {
+    use crate::error::MonitorError;
+    use std::sync::{Arc, Mutex};
+
+    let config = MonitorError::Config("Invalid asset entry 'x'".to_string());
+    assert_eq!(
+        config.to_string(),
+        "Configuration error: Invalid asset entry 'x'"
+    );
+    assert!(config.is_fatal());
+
+    let rpc = MonitorError::Rpc("Failed to subscribe to blocks: closed".to_string());
+    assert!(!rpc.is_fatal());
+
+    let lock = Arc::new(Mutex::new(0));
+    let poisoner = Arc::clone(&lock);
+    let _ = std::thread::spawn(move || {
+        let _guard = poisoner.lock().unwrap();
+        panic!("poison the lock");
+    })
+    .join();
+    let poisoned: MonitorError = lock.lock().unwrap_err().into();
+    assert!(matches!(poisoned, MonitorError::Lock(_)));
+}
+
+#[tokio::test]
+async fn alert_reaches_every_channel_despite_failures() {
+    use crate::alerts::{send_to_all, AlertMessage, Alerter};
+    use crate::error::MonitorError;
+    use async_trait::async_trait;
+    use std::sync::{Arc, Mutex};
+
+    struct RecordingAlerter {
+        name: &'static str,
+        fail: bool,
+        sent: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[async_trait]
+    impl Alerter for RecordingAlerter {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        async fn send(&self, message: &AlertMessage) -> Result<(), MonitorError> {
+            if self.fail {
+                return Err(MonitorError::Webhook("endpoint unavailable".to_string()));
+            }
+            self.sent
+                .lock()
+                .unwrap()
+                .push(format!("{}: {}", self.name, message.title));
+            Ok(())
+        }
+    }
+
+    let sent = Arc::new(Mutex::new(Vec::new()));
+    let alerters: Vec<Box<dyn Alerter>> = vec![
+        Box::new(RecordingAlerter {
+            name: "first",
+            fail: false,
+            sent: Arc::clone(&sent),
+        }),
+        Box::new(RecordingAlerter {
+            name: "broken",
+            fail: true,
+            sent: Arc::clone(&sent),
+        }),
+        Box::new(RecordingAlerter {
+            name: "last",
+            fail: false,
+            sent: Arc::clone(&sent),
+        }),
+    ];
+
+    let failures = send_to_all(&alerters, &AlertMessage::new("🚨", "LIQUIDATION ALERT")).await;
+
+    assert_eq!(
+        *sent.lock().unwrap(),
+        vec!["first: LIQUIDATION ALERT", "last: LIQUIDATION ALERT"]
+    );
+    assert_eq!(failures.len(), 1);
+    assert_eq!(failures[0].0, "broken");
+}
+
+#[tokio::test]
+async fn health_check_completes_without_telegram_configured() {
+    use crate::alerts::configured_alerters;
+    use crate::chains::ethereum::ethereum_chain::ChainState;
+    use crate::{AlertLevel, HealthCheck};
+    use std::env;
+    use std::time::Instant;
+
+    env::remove_var("TELEGRAM_BOT_TOKEN");
+    env::remove_var("TELEGRAM_CHAT_ID");
+    let alerters = configured_alerters();
+    assert!(alerters.iter().all(|alerter| alerter.name() != "Telegram"));
+
+    // An empty position is valued without any price request
+    let chain = ChainState::new("no-telegram", String::new, String::new);
+    let mut check = HealthCheck::from_config(Instant::now());
+    let reading = check.run(&chain, &alerters).await.unwrap();
+    assert!(reading.health_factor.is_infinite());
+    assert_eq!(reading.level, AlertLevel::Info);
+}
+
+#[tokio::test]
+async fn solana_is_checked_like_the_aave_chains() {
+    use crate::chains::solana::solana_chain::SOLANA;
+    use crate::HealthCheck;
+    use std::time::Instant;
+
+    // Without configured reserves the position is valued without any price request
+    let history = SOLANA.health_factor_history().unwrap().len();
+    let mut check = HealthCheck::from_config(Instant::now());
+    let reading = check.run(&*SOLANA, &[]).await.unwrap();
+    assert!(reading.health_factor.is_infinite());
+    // Recorded in the history like every other chain's checks
+    assert_eq!(SOLANA.health_factor_history().unwrap().len(), history + 1);
+}
+
+#[test]
+fn alert_cooldown_suppresses_repeats_until_elapsed() {
+    use crate::{AlertCooldown, AlertDecision, AlertLevel};
+    use std::time::{Duration, Instant};
+
+    let start = Instant::now();
+    let mut cooldown = AlertCooldown::new(Duration::from_secs(300));
+
+    assert_eq!(
+        cooldown.observe(start, AlertLevel::Critical, true),
+        AlertDecision::Fire
+    );
+    assert_eq!(
+        cooldown.observe(start + Duration::from_secs(2), AlertLevel::Critical, true),
+        AlertDecision::Suppress
+    );
+    assert_eq!(
+        cooldown.observe(start + Duration::from_secs(300), AlertLevel::Critical, true),
+        AlertDecision::Repeat {
+            in_range_for: Duration::from_secs(300)
+        }
+    );
+}
+
+#[test]
+fn critical_alerts_escalate_on_a_shrinking_schedule_up_to_the_cap() {
+    use crate::{health_factor_trend, AlertCooldown, AlertDecision, AlertLevel};
+    use std::time::{Duration, Instant};
+
+    let start = Instant::now();
+    let at = |secs| start + Duration::from_secs(secs);
+    let mut cooldown = AlertCooldown::new(Duration::from_secs(600))
+        .with_escalation(vec![Duration::from_secs(300), Duration::from_secs(120)], 3);
+
+    assert_eq!(
+        cooldown.observe(start, AlertLevel::Critical, true),
+        AlertDecision::Fire
+    );
+    assert_eq!(
+        cooldown.observe(at(299), AlertLevel::Critical, true),
+        AlertDecision::Suppress
+    );
+    assert_eq!(
+        cooldown.observe(at(300), AlertLevel::Critical, true),
+        AlertDecision::Escalate {
+            critical_for: Duration::from_secs(300)
+        }
+    );
+    // The last interval repeats until the cap
+    assert_eq!(
+        cooldown.observe(at(420), AlertLevel::Critical, true),
+        AlertDecision::Escalate {
+            critical_for: Duration::from_secs(420)
+        }
+    );
+    assert_eq!(
+        cooldown.observe(at(540), AlertLevel::Critical, true),
+        AlertDecision::Escalate {
+            critical_for: Duration::from_secs(540)
+        }
+    );
+    // Past the cap the regular cooldown takes over
+    assert_eq!(
+        cooldown.observe(at(660), AlertLevel::Critical, true),
+        AlertDecision::Suppress
+    );
+    assert_eq!(
+        cooldown.observe(at(1140), AlertLevel::Critical, true),
+        AlertDecision::Repeat {
+            in_range_for: Duration::from_secs(1140)
+        }
+    );
+
+    // Dropping out of the critical tier restarts the schedule
+    assert_eq!(
+        cooldown.observe(at(1200), AlertLevel::Danger, true),
+        AlertDecision::Suppress
+    );
+    assert_eq!(
+        cooldown.observe(at(1210), AlertLevel::Critical, true),
+        AlertDecision::Fire
+    );
+    assert_eq!(
+        cooldown.observe(at(1510), AlertLevel::Critical, true),
+        AlertDecision::Escalate {
+            critical_for: Duration::from_secs(300)
+        }
+    );
+
+    // Without a schedule critical reminders stay on the cooldown
+    let mut cooldown = AlertCooldown::new(Duration::from_secs(600)).with_escalation(Vec::new(), 3);
+    cooldown.observe(start, AlertLevel::Critical, true);
+    assert_eq!(
+        cooldown.observe(at(300), AlertLevel::Critical, true),
+        AlertDecision::Suppress
+    );
+
+    assert!(health_factor_trend(Some(0.98), 0.95).starts_with("Still dropping"));
+    assert!(health_factor_trend(Some(0.95), 0.97).starts_with("Not dropping"));
+}
+
+#[test]
+fn alert_cooldown_resets_when_leaving_range() {
+    use crate::{AlertCooldown, AlertDecision, AlertLevel};
+    use std::time::{Duration, Instant};
+
+    let start = Instant::now();
+    let mut cooldown = AlertCooldown::new(Duration::from_secs(300));
+
+    assert_eq!(
+        cooldown.observe(start, AlertLevel::Critical, true),
+        AlertDecision::Fire
+    );
+    assert_eq!(
+        cooldown.observe(start + Duration::from_secs(4), AlertLevel::Info, false),
+        AlertDecision::Recovered
+    );
+    // Recovery is reported once, not on every healthy tick
+    assert_eq!(
+        cooldown.observe(start + Duration::from_secs(5), AlertLevel::Info, false),
+        AlertDecision::Suppress
+    );
+    assert_eq!(
+        cooldown.observe(start + Duration::from_secs(6), AlertLevel::Critical, true),
+        AlertDecision::Fire
+    );
+}
+
+#[test]
+fn leaving_range_without_an_alert_is_not_a_recovery() {
+    use crate::{AlertCooldown, AlertDecision, AlertLevel};
+    use std::time::{Duration, Instant};
+
+    let start = Instant::now();
+    let mut cooldown = AlertCooldown::new(Duration::from_secs(300));
+
+    // At risk but not yet confirmed, so nothing was sent
+    assert_eq!(
+        cooldown.observe(start, AlertLevel::Critical, false),
+        AlertDecision::Suppress
+    );
+    assert_eq!(
+        cooldown.observe(start + Duration::from_secs(2), AlertLevel::Info, false),
+        AlertDecision::Suppress
+    );
+}
+
+#[test]
+fn telegram_chat_ids_resolve_users_and_groups() {
+    use crate::alerts::telegram_recipient;
+    use teloxide::types::{ChatId, Recipient, UserId};
+
+    assert_eq!(
+        telegram_recipient("123456789").unwrap(),
+        Recipient::from(UserId(123456789))
+    );
+    assert_eq!(
+        telegram_recipient("-1001234567890").unwrap(),
+        Recipient::from(ChatId(-1001234567890))
+    );
+    assert!(telegram_recipient("@channel").is_err());
+}
+
+#[test]
+fn usd_values_render_with_thousands_separators() {
+    use crate::format_money;
+
+    let format_usd = |value: f64| format_money(value, "USD");
+    assert_eq!(format_usd(12_400.0), "$12,400.00");
+    assert_eq!(format_usd(11_899.556), "$11,899.56");
+    assert_eq!(format_usd(999.5), "$999.50");
+    assert_eq!(format_usd(1_234_567.891), "$1,234,567.89");
+    assert_eq!(format_usd(0.0), "$0.00");
+}
+
+#[test]
+fn quote_currency_converts_displayed_values_at_the_feed_rate() {
+    use crate::chains::ethereum::ethereum_chain::ChainlinkAnswer;
+    use crate::prices::{feed_pair, quote_rate_from_answer};
+    use crate::{check_price, format_money, PriceResult};
+
+    assert_eq!(
+        feed_pair("ETH / USD"),
+        ("ETH".to_string(), "USD".to_string())
+    );
+    let answer = |description: &str, price: f64| ChainlinkAnswer {
+        description: description.to_string(),
+        price,
+        updated_at: 0,
+    };
+
+    // 1 EUR buys 1.25 USD, so $12,500 shows as €10,000
+    let rate = quote_rate_from_answer("EUR", &answer("EUR / USD", 1.25)).unwrap();
+    assert_eq!(
+        format_money(rate.convert(12_500.0), &rate.currency),
+        "€10,000.00"
+    );
+    assert_eq!(format_money(-1_500.0, "CHF"), "-1,500.00 CHF");
+    assert!(quote_rate_from_answer("EUR", &answer("GBP / USD", 1.25)).is_err());
+    assert!(quote_rate_from_answer("EUR", &answer("EUR / USD", 0.0)).is_err());
+
+    // A feed quoted in anything but USD never values the position
+    let price = PriceResult {
+        symbol: "ETH".to_string(),
+        price: 0.05,
+        decimals: 18,
+        fetched_at: 0,
+        currency: "BTC".to_string(),
+    };
+    assert!(check_price(&price, 0, 0).is_err());
+}
+
+#[test]
+fn markdown_v2_escapes_every_dynamic_value() {
+    use crate::alerts::{escape_markdown_v2, AlertMessage};
+
+    assert_eq!(
+        escape_markdown_v2("_*[]()~`>#+-=|{}.!\\"),
+        "\\_\\*\\[\\]\\(\\)\\~\\`\\>\\#\\+\\-\\=\\|\\{\\}\\.\\!\\\\"
+    );
+
+    let message = AlertMessage::new("🚨", "HF-1.0 (test)!")
+        .field("Supply_Token", "wst`ETH\\v2")
+        .body("LTV 89.5% > limit [warn]");
+    let telegram = message.to_markdown_v2();
+
+    assert!(telegram.starts_with("🚨 *HF\\-1\\.0 \\(test\\)\\!* 🚨"));
+    // Inside code spans only backticks and backslashes are escaped
+    assert!(telegram.contains("*Supply\\_Token:* `wst\\`ETH\\\\v2`"));
+    assert!(telegram.ends_with("LTV 89\\.5% \\> limit \\[warn\\]"));
+}
+
+#[test]
+fn alert_level_maps_ltv_to_tiers() {
+    use crate::{alert_level, AlertLevel, AlertTiers};
+
+    let tiers = AlertTiers {
+        warn: 0.80,
+        danger: 0.85,
+        critical: 0.89,
+    };
+
+    assert_eq!(tiers.level(0.50), AlertLevel::Info);
+    assert_eq!(tiers.level(0.80), AlertLevel::Warn);
+    assert_eq!(tiers.level(0.86), AlertLevel::Danger);
+    assert_eq!(tiers.level(0.95), AlertLevel::Critical);
+    // A liquidatable position is critical even below the configured tier
+    assert_eq!(alert_level(0.99, 0.70, &tiers), AlertLevel::Critical);
+    assert_eq!(alert_level(1.20, 0.70, &tiers), AlertLevel::Info);
+}
+
+#[test]
+fn dust_positions_are_not_alerted_on() {
+    use crate::{is_dust_position, AlertLevel, HealthFactorReading};
+
+    let reading = |supply_in_usd: f64, borrowed_in_usd: f64| HealthFactorReading {
+        health_factor: 1.05,
+        supply_in_usd,
+        borrowed_in_usd,
+        ltv: 0.86,
+        liquidation_threshold: 0.9,
+        level: AlertLevel::Danger,
+    };
+    // A $2 leftover position stays quiet, a real one doesn't
+    assert!(is_dust_position(&reading(2.0, 1.7), 50.0));
+    assert!(!is_dust_position(&reading(2_000.0, 1_720.0), 50.0));
+    // Either side reaching the floor is enough to alert
+    assert!(!is_dust_position(&reading(60.0, 40.0), 50.0));
+    // No floor by default
+    assert!(!is_dust_position(&reading(2.0, 1.7), 0.0));
+}
+
+#[test]
+fn alert_cooldown_realerts_on_escalation() {
+    use crate::{AlertCooldown, AlertDecision, AlertLevel};
+    use std::time::{Duration, Instant};
+
+    let start = Instant::now();
+    let mut cooldown = AlertCooldown::new(Duration::from_secs(300));
+
+    assert_eq!(
+        cooldown.observe(start, AlertLevel::Warn, true),
+        AlertDecision::Fire
+    );
+    assert_eq!(
+        cooldown.observe(start + Duration::from_secs(2), AlertLevel::Warn, true),
+        AlertDecision::Suppress
+    );
+    // Escalating ignores the cooldown
+    assert_eq!(
+        cooldown.observe(start + Duration::from_secs(4), AlertLevel::Danger, true),
+        AlertDecision::Fire
+    );
+    // Dropping back a tier stays quiet, climbing again alerts
+    assert_eq!(
+        cooldown.observe(start + Duration::from_secs(6), AlertLevel::Warn, true),
+        AlertDecision::Suppress
+    );
+    assert_eq!(
+        cooldown.observe(start + Duration::from_secs(8), AlertLevel::Danger, true),
+        AlertDecision::Fire
+    );
+}
+
+#[test]
+fn metrics_render_in_prometheus_and_openmetrics_format() {
+    use crate::chains::ethereum::ethereum_chain::ETHEREUM;
+    use crate::metrics::{record_event, record_health_factor, render_metrics};
+    use ethers::prelude::{H160, H256};
+
+    let user = ETHEREUM.user_address();
+    let other = ETHEREUM.for_account(H160::repeat_byte(0x5e));
+    record_health_factor("ethereum", &user, 12_400.0, 11_900.0, f64::INFINITY);
+    record_health_factor("ethereum", &other.user_address(), 800.0, 700.0, 1.05);
+    record_event("ethereum", "supply", Some(H256::repeat_byte(0xab)));
+    record_event("ethereum", "supply", None);
+
+    let text = render_metrics(&[&*ETHEREUM, &other], false);
+    assert!(text.contains(&format!(
+        "# TYPE aave_health_factor gauge\naave_health_factor{{chain=\"ethereum\",user=\"{}\"}} +Inf\n",
+        user
+    )));
+    assert!(text.contains(&format!(
+        "aave_health_factor{{chain=\"ethereum\",user=\"{}\"}} 1.05\n",
+        other.user_address()
+    )));
+    assert!(text.contains(&format!(
+        "aave_supply_usd{{chain=\"ethereum\",user=\"{}\"}} 12400\n",
+        user
+    )));
+    assert!(text.contains("# TYPE aave_events_processed_total counter\n"));
+    // Chain-wide counters are written once, not per account
+    assert_eq!(
+        text.matches("aave_events_processed_total{chain=\"ethereum\",type=\"supply\"} 2\n")
+            .count(),
+        1
+    );
+    assert!(!text.contains("# EOF"));
+
+    // Exemplars only appear in OpenMetrics output
+    let text = render_metrics(&[&*ETHEREUM], true);
+    assert!(text.contains("# TYPE aave_events_processed counter\n"));
+    assert!(text.contains(&format!(
+        "aave_events_processed_total{{chain=\"ethereum\",type=\"supply\"}} 2 # {{tx_hash=\"{:?}\"}} 1\n",
+        H256::repeat_byte(0xab)
+    )));
+    assert!(text.ends_with("# EOF\n"));
+}
+
+#[tokio::test]
+async fn position_api_is_unavailable_until_initial_sync() {
+    use crate::api::handle_request;
+    use hyper::{Body, Request, StatusCode};
+
+    let request = Request::get("/position").body(Body::empty()).unwrap();
+    let response = handle_request(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+    let request = Request::get("/unknown").body(Body::empty()).unwrap();
+    let response = handle_request(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn readiness_waits_for_loops_sync_and_a_price() {
+    use crate::chains::ethereum::ethereum_chain::ChainState;
+    use crate::metrics::probe_response;
+    use crate::probes::not_ready_reason;
+    use hyper::StatusCode;
+
+    let chain = ChainState::new("probe", String::new, String::new);
+    assert!(not_ready_reason(false, &[], true)
+        .unwrap()
+        .contains("have not started"));
+    assert!(not_ready_reason(true, &[&chain], true)
+        .unwrap()
+        .contains("initial on-chain sync pending for probe"));
+    assert!(not_ready_reason(true, &[], false)
+        .unwrap()
+        .contains("no price"));
+    assert_eq!(not_ready_reason(true, &[], true), None);
+
+    // Only `main` starts the loops, so the probes report the bot as starting here
+    let live = probe_response("/healthz").unwrap();
+    assert_eq!(live.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let ready = probe_response("/readyz").unwrap();
+    assert_eq!(ready.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert!(probe_response("/metrics").is_none());
+}
+
+#[test]
+fn position_snapshot_round_trips_and_rejects_corrupt_files() {
+    use crate::chains::ethereum::ethereum_chain::{PositionSnapshot, ETHEREUM, STATE_VERSION};
+    use crate::chains::PositionData;
+    use ethers::prelude::{Address, U256};
+    use std::env;
+
+    let path = env::temp_dir().join(format!("aave-position-{}.json", std::process::id()));
+    env::set_var("POSITION_FILE", &path);
+    assert!(ETHEREUM.load_snapshot().unwrap().is_none());
+
+    let reserve = Address::repeat_byte(0x11);
+    let mut position = PositionData::new();
+    // Larger than u128 to make sure nothing is squeezed through a JSON number
+    position.update_supplied_amount(reserve, U256::MAX);
+    position.update_borrowed_amount(reserve, U256::from(42u64));
+    let last_updated = position.last_updated;
+    ETHEREUM
+        .save_snapshot(&PositionSnapshot {
+            version: STATE_VERSION,
+            user_address: "0xabc".to_string(),
+            last_processed_block: 19_000_000,
+            position,
+        })
+        .unwrap();
+
+    let raw = std::fs::read_to_string(&path).unwrap();
+    assert!(raw.contains(&format!("\"{}\"", U256::MAX)));
+    let restored = ETHEREUM.load_snapshot().unwrap().unwrap();
+    assert_eq!(restored.last_processed_block, 19_000_000);
+    assert_eq!(restored.position.supplied_amount(&reserve), U256::MAX);
+    assert_eq!(
+        restored.position.borrowed_amount(&reserve),
+        U256::from(42u64)
+    );
+    // Every update stamps the position, and the stamp survives the round trip
+    assert!(last_updated.is_some());
+    assert_eq!(restored.position.last_updated, last_updated);
+
+    // A partially written file is reported instead of loading zeroes
+    std::fs::write(&path, &raw[..raw.len() / 2]).unwrap();
+    assert!(ETHEREUM.load_snapshot().is_err());
+    // So is one written by a newer build
+    std::fs::write(
+        &path,
+        raw.replacen(
+            &format!("\"version\": {}", STATE_VERSION),
+            &format!("\"version\": {}", STATE_VERSION + 1),
+            1,
+        ),
+    )
+    .unwrap();
+    assert!(ETHEREUM.load_snapshot().is_err());
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn poisoned_position_lock_is_recovered() {
+    use crate::chains::{lock_recovering, PositionData};
+    use ethers::prelude::{Address, U256};
+    use std::sync::{Arc, Mutex};
+
+    let reserve = Address::repeat_byte(0x42);
+    let position = Arc::new(Mutex::new(PositionData::new()));
+    lock_recovering(&position).update_supplied_amount(reserve, U256::from(1_000));
+
+    // A thread panicking mid-update poisons the lock
+    let held = Arc::clone(&position);
+    let panicked = std::thread::spawn(move || {
+        let mut position = held.lock().unwrap();
+        position.update_borrowed_amount(reserve, U256::from(400));
+        panic!("simulated panic while holding the position lock");
+    })
+    .join();
+    assert!(panicked.is_err());
+    assert!(position.is_poisoned());
+
+    // Reads and writes keep working, and the lock is usable again afterwards
+    assert_eq!(
+        lock_recovering(&position).supplied_amount(&reserve),
+        U256::from(1_000)
+    );
+    lock_recovering(&position).update_borrowed_amount(reserve, U256::from(500));
+    assert!(!position.is_poisoned());
+    assert_eq!(
+        position.lock().unwrap().borrowed_amount(&reserve),
+        U256::from(500)
+    );
+}
+
+#[test]
+fn chains_track_their_positions_separately() {
+    use crate::chains::ethereum::ethereum_chain::{
+        refresh_position_after_supply, to_h160, Supply, ETHEREUM,
+    };
+    use crate::chains::polygon::polygon_chain::POLYGON;
+    use ethers::prelude::U256;
+
+    let reserve = alloy_primitives::Address::repeat_byte(0x44);
+    let user = alloy_primitives::Address::repeat_byte(0x33);
+    refresh_position_after_supply(
+        &POLYGON,
+        Supply {
+            reserve,
+            user,
+            onBehalfOf: user,
+            amount: alloy_primitives::U256::from(500u64),
+            referralCode: 0,
+        },
+    )
+    .unwrap();
+
+    assert_eq!(
+        POLYGON
+            .position()
+            .unwrap()
+            .supplied_amount(&to_h160(reserve)),
+        U256::from(500)
+    );
+    assert_eq!(
+        ETHEREUM
+            .position()
+            .unwrap()
+            .supplied_amount(&to_h160(reserve)),
+        U256::zero()
+    );
+    assert_eq!(POLYGON.event_count(), 1);
+}
+
+#[test]
+fn chain_impls_point_at_their_own_state_and_pool() {
+    use crate::chains::arbitrum::arbitrum_chain::Arbitrum;
+    use crate::chains::base::base_chain::Base;
+    use crate::chains::ethereum::ethereum_chain::Ethereum;
+    use crate::chains::optimism::optimism_chain::Optimism;
+    use crate::chains::polygon::polygon_chain::Polygon;
+    use crate::chains::Chain;
+
+    let chains: Vec<Box<dyn Chain>> = vec![
+        Box::new(Ethereum),
+        Box::new(Polygon),
+        Box::new(Arbitrum),
+        Box::new(Optimism),
+        Box::new(Base),
+    ];
+    let names: Vec<&str> = chains.iter().map(|chain| chain.name()).collect();
+    assert_eq!(
+        names,
+        vec!["ethereum", "polygon", "arbitrum", "optimism", "base"]
+    );
+    assert_ne!(chains[0].pool_address(), chains[1].pool_address());
+    assert!(!std::ptr::eq(chains[0].state(), chains[1].state()));
+    assert!(!std::ptr::eq(chains[1].state(), chains[2].state()));
+    assert!(!std::ptr::eq(
+        chains[1].ws_endpoints(),
+        chains[2].ws_endpoints()
+    ));
+    assert_ne!(chains[3].pool_address(), chains[4].pool_address());
+    assert!(!std::ptr::eq(chains[3].state(), chains[4].state()));
+
+    // Each chain keeps its own head block
+    chains[2].state().set_latest_block(250_000_000);
+    assert_eq!(chains[2].state().latest_block(), 250_000_000);
+    assert_ne!(chains[1].state().latest_block(), 250_000_000);
+}
+
+#[test]
+fn interest_accrues_by_index_growth_after_first_reading() {
+    use crate::chains::ethereum::ethereum_chain::{accrue, ChainState, ReserveIndexes};
+    use ethers::prelude::{Address, U256};
+    use std::collections::HashMap;
+
+    let ray = U256::exp10(27);
+    assert_eq!(
+        accrue(U256::from(1_000), ray, ray * 11 / 10),
+        U256::from(1_100)
+    );
+    assert_eq!(
+        accrue(U256::from(1_000), U256::zero(), ray),
+        U256::from(1_000)
+    );
+
+    let chain = ChainState::new("interest", String::new, String::new);
+    let collateral = Address::repeat_byte(0x51);
+    let debt = Address::repeat_byte(0x52);
+    let mut position = chain.position().unwrap();
+    position.update_supplied_amount(collateral, U256::from(1_000_000));
+    position.update_borrowed_amount(debt, U256::from(500_000));
+    chain.set_position(position).unwrap();
+
+    let reading = |liquidity: U256, variable_debt: U256| {
+        let indexes = ReserveIndexes {
+            liquidity,
+            variable_debt,
+        };
+        HashMap::from([(collateral, indexes), (debt, indexes)])
+    };
+    // The first reading only sets the base
+    chain.accrue_interest(&reading(ray, ray)).unwrap();
+    assert_eq!(
+        chain.position().unwrap().borrowed_amount(&debt),
+        U256::from(500_000)
+    );
+
+    chain
+        .accrue_interest(&reading(ray * 101 / 100, ray * 102 / 100))
+        .unwrap();
+    let position = chain.position().unwrap();
+    assert_eq!(position.supplied_amount(&collateral), U256::from(1_010_000));
+    assert_eq!(position.borrowed_amount(&debt), U256::from(510_000));
+}
+
+#[test]
+fn stable_and_variable_debt_are_tracked_separately() {
+    use crate::chains::ethereum::ethereum_chain::{
+        refresh_position_after_borrow, refresh_position_after_repay, to_h160, Borrow, ChainState,
+        Repay, ReserveIndexes,
+    };
+    use crate::metrics::render_metrics;
+    use ethers::prelude::U256;
+    use std::collections::HashMap;
+
+    let chain = ChainState::new("rate-modes", String::new, String::new);
+    let reserve = alloy_primitives::Address::repeat_byte(0x71);
+    let user = alloy_primitives::Address::repeat_byte(0x72);
+    for (mode, amount) in [(1u8, 300u64), (2, 1_000)] {
+        refresh_position_after_borrow(
+            &chain,
+            Borrow {
+                reserve,
+                user,
+                onBehalfOf: user,
+                amount: alloy_primitives::U256::from(amount),
+                interestRateMode: mode,
+                borrowRate: alloy_primitives::U256::from(0u64),
+                referralCode: 0,
+            },
+        )
+        .unwrap();
+    }
+    let debt = to_h160(reserve);
+    let position = chain.position().unwrap();
+    assert_eq!(position.borrowed_amount(&debt), U256::from(1_300));
+    assert_eq!(position.stable_borrowed_amount(&debt), U256::from(300));
+    assert_eq!(position.variable_borrowed_amount(&debt), U256::from(1_000));
+    let metrics = render_metrics(&[&chain], false);
+    assert!(metrics.contains(&format!(
+        "aave_stable_borrowed_amount{{chain=\"rate-modes\",user=\"{}\",reserve=\"{:?}\"}} 300\n",
+        chain.user_address(),
+        debt
+    )));
+    assert!(metrics.contains(&format!(
+        "aave_variable_borrowed_amount{{chain=\"rate-modes\",user=\"{}\",reserve=\"{:?}\"}} 1000\n",
+        chain.user_address(),
+        debt
+    )));
+
+    // The variable index only grows the variable part
+    let ray = U256::exp10(27);
+    let reading = |variable_debt: U256| {
+        HashMap::from([(
+            debt,
+            ReserveIndexes {
+                liquidity: ray,
+                variable_debt,
+            },
+        )])
+    };
+    chain.accrue_interest(&reading(ray)).unwrap();
+    chain.accrue_interest(&reading(ray * 11 / 10)).unwrap();
+    let position = chain.position().unwrap();
+    assert_eq!(position.borrowed_amount(&debt), U256::from(1_400));
+    assert_eq!(position.stable_borrowed_amount(&debt), U256::from(300));
+
+    // Repays pay off variable debt before stable debt
+    refresh_position_after_repay(
+        &chain,
+        Repay {
+            reserve,
+            user,
+            repayer: user,
+            amount: alloy_primitives::U256::from(1_200u64),
+            useATokens: false,
+        },
+    )
+    .unwrap();
+    let position = chain.position().unwrap();
+    assert_eq!(position.borrowed_amount(&debt), U256::from(200));
+    assert_eq!(position.stable_borrowed_amount(&debt), U256::from(200));
+    assert_eq!(position.variable_borrowed_amount(&debt), U256::zero());
+}
+
+#[tokio::test]
+async fn tracked_events_wake_balance_polling() {
+    use crate::chains::ethereum::ethereum_chain::ChainState;
+    use crate::chains::{get_position_source, PositionSource};
+    use std::time::Duration;
+
+    assert_eq!(get_position_source(), PositionSource::Events);
+
+    let chain = ChainState::new("balances", String::new, String::new);
+    chain.record_event_tx(None, None).unwrap();
+    // The request is remembered even though nobody was waiting yet
+    tokio::time::timeout(Duration::from_secs(1), chain.balance_refresh_requested())
+        .await
+        .expect("event should request a balance refresh");
+}
+
+#[test]
+fn price_retries_back_off_and_honor_retry_after() {
+    use crate::{parse_retry_after, price_retry_delay};
+    use std::time::Duration;
+
+    assert_eq!(price_retry_delay(0, None), Duration::from_millis(500));
+    assert_eq!(price_retry_delay(2, None), Duration::from_secs(2));
+    assert_eq!(price_retry_delay(20, None), Duration::from_secs(60));
+
+    let retry_after = parse_retry_after(Some(" 7 "));
+    assert_eq!(retry_after, Some(Duration::from_secs(7)));
+    assert_eq!(price_retry_delay(0, retry_after), Duration::from_secs(7));
+    assert_eq!(
+        price_retry_delay(0, parse_retry_after(Some("3600"))),
+        Duration::from_secs(60)
+    );
+    // HTTP-date values fall back to the normal backoff
+    assert_eq!(
+        parse_retry_after(Some("Wed, 21 Oct 2026 07:28:00 GMT")),
+        None
+    );
+    assert_eq!(parse_retry_after(None), None);
+}
+
+#[tokio::test]
+async fn repay_beyond_tracked_debt_requests_resync() {
+    use crate::chains::ethereum::ethereum_chain::{
+        refresh_position_after_repay, to_h160, ChainState, Repay,
+    };
+    use crate::metrics::render_metrics;
+    use ethers::prelude::U256;
+    use std::time::Duration;
+
+    let chain = ChainState::new("underflow", String::new, String::new);
+    let reserve = alloy_primitives::Address::repeat_byte(0x61);
+    let user = alloy_primitives::Address::repeat_byte(0x62);
+    chain
+        .update_borrowed_amount(to_h160(reserve), U256::from(100))
+        .unwrap();
+
+    refresh_position_after_repay(
+        &chain,
+        Repay {
+            reserve,
+            user,
+            repayer: user,
+            amount: alloy_primitives::U256::from(250u64),
+            useATokens: false,
+        },
+    )
+    .unwrap();
+
+    assert_eq!(
+        chain.position().unwrap().borrowed_amount(&to_h160(reserve)),
+        U256::zero()
+    );
+    tokio::time::timeout(Duration::from_secs(1), chain.resync_requested())
+        .await
+        .expect("underflow should request a resync");
+    assert!(render_metrics(&[&chain], false)
+        .contains("aave_position_underflows_total{chain=\"underflow\",type=\"repay\"} 1\n"));
+}
+
+#[tokio::test]
+async fn supply_overflowing_tracked_amount_is_ignored_and_resyncs() {
+    use crate::chains::ethereum::ethereum_chain::{
+        refresh_position_after_supply, to_h160, to_u256, ChainState, Supply,
+    };
+    use ethers::prelude::U256;
+    use std::time::Duration;
+
+    let amount = alloy_primitives::U256::from(123_456_789u64);
+    assert_eq!(to_u256(amount), U256::from(123_456_789u64));
+    assert_eq!(to_u256(alloy_primitives::U256::MAX), U256::MAX);
+
+    let chain = ChainState::new("overflow", String::new, String::new);
+    let reserve = alloy_primitives::Address::repeat_byte(0x63);
+    let user = alloy_primitives::Address::repeat_byte(0x64);
+    chain
+        .update_supplied_amount(to_h160(reserve), U256::from(100))
+        .unwrap();
+
+    refresh_position_after_supply(
+        &chain,
+        Supply {
+            reserve,
+            user,
+            onBehalfOf: user,
+            amount: alloy_primitives::U256::MAX,
+            referralCode: 0,
+        },
+    )
+    .unwrap();
+
+    assert_eq!(
+        chain.position().unwrap().supplied_amount(&to_h160(reserve)),
+        U256::from(100)
+    );
+    tokio::time::timeout(Duration::from_secs(1), chain.resync_requested())
+        .await
+        .expect("overflow should request a resync");
+}
+
+#[test]
+fn event_block_time_becomes_the_last_update() {
+    use crate::chains::ethereum::ethereum_chain::{format_block_time, ChainState};
+    use ethers::prelude::H256;
+
+    let chain = ChainState::new("block-time", String::new, String::new);
+    // A backfilled event from well before "now"
+    chain
+        .record_event_tx(Some(H256::repeat_byte(0x07)), Some(1_700_000_000))
+        .unwrap();
+    assert_eq!(chain.updated_at(), Some(1_700_000_000));
+    assert_eq!(
+        chain.position().unwrap().last_event_timestamp,
+        Some(1_700_000_000)
+    );
+    assert_eq!(format_block_time(1_700_000_000), "2023-11-14 22:13:20 UTC");
+}
+
+#[test]
+fn liquidation_price_brings_health_factor_to_one() {
+    use crate::{compute_health_factor, compute_liquidation_price};
+
+    // 10 ETH at $2000 plus $5000 of USDC against $15000 of debt, threshold 0.8
+    let price = compute_liquidation_price(10.0, 5_000.0, 15_000.0, 0.8).unwrap();
+    assert!((price - 1_375.0).abs() < 1e-9);
+    let health_factor = compute_health_factor(10.0 * price + 5_000.0, 15_000.0, 0.8);
+    assert!((health_factor - 1.0).abs() < 1e-9);
+
+    // Without debt, or when the other collateral covers the debt, there is no such price
+    assert_eq!(compute_liquidation_price(10.0, 5_000.0, 0.0, 0.8), None);
+    assert_eq!(
+        compute_liquidation_price(10.0, 20_000.0, 15_000.0, 0.8),
+        None
+    );
+    assert_eq!(compute_liquidation_price(0.0, 5_000.0, 15_000.0, 0.8), None);
+
+    // Already underwater: the liquidation price is above the current price
+    let price = compute_liquidation_price(10.0, 0.0, 18_000.0, 0.8).unwrap();
+    assert!(price > 2_000.0);
+}
+
+#[test]
+fn liquidation_threshold_read_from_reserve_configuration() {
+    use crate::chains::ethereum::ethereum_chain::liquidation_threshold_from_configuration;
+    use crate::weighted_liquidation_threshold;
+    use ethers::prelude::U256;
+
+    // WETH on mainnet: LTV 8050, threshold 8300, bonus 10500 in the low bits
+    let configuration =
+        U256::from(8050u64) | U256::from(8300u64) << 16 | U256::from(10500u64) << 32;
+    assert!((liquidation_threshold_from_configuration(configuration) - 0.83).abs() < 1e-9);
+    assert_eq!(liquidation_threshold_from_configuration(U256::zero()), 0.0);
+
+    // $3000 at 0.83 and $1000 at 0.78 average to 0.8175
+    let threshold = weighted_liquidation_threshold(&[(3_000.0, 0.83), (1_000.0, 0.78)], 0.89);
+    assert!((threshold - 0.8175).abs() < 1e-9);
+    assert_eq!(weighted_liquidation_threshold(&[], 0.89), 0.89);
+}
+
+#[test]
+fn price_shock_recomputes_health_factor() {
+    use crate::simulate_price_shock;
+
+    // Health factor 1.2 today: $15000 collateral at 0.8 against $10000 debt
+    let shock = simulate_price_shock(15_000.0, 10_000.0, 0.8, -20.0, 0.0);
+    assert!((shock.supply_in_usd - 12_000.0).abs() < 1e-9);
+    assert!((shock.health_factor - 0.96).abs() < 1e-9);
+    assert!(shock.in_liquidation_range);
+
+    let shock = simulate_price_shock(15_000.0, 10_000.0, 0.8, -10.0, -10.0);
+    assert!((shock.health_factor - 1.2).abs() < 1e-9);
+    assert!(!shock.in_liquidation_range);
+
+    // A collateral wipe-out can't go negative
+    let shock = simulate_price_shock(15_000.0, 10_000.0, 0.8, -150.0, 0.0);
+    assert_eq!(shock.supply_in_usd, 0.0);
+    assert!(shock.in_liquidation_range);
+}
+
+#[tokio::test]
+async fn simulate_endpoint_rejects_invalid_shocks() {
+    use crate::api::handle_request;
+    use hyper::{Body, Request, StatusCode};
+
+    let request = Request::get("/simulate?collateral_change_pct=lots")
+        .body(Body::empty())
+        .unwrap();
+    let response = handle_request(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn health_factor_history_keeps_the_latest_samples() {
+    use crate::api::handle_request;
+    use crate::chains::ethereum::ethereum_chain::{ChainState, HealthFactorSample};
+    use hyper::{Body, Request, StatusCode};
+
+    let chain = ChainState::new("ethereum", String::new, String::new);
+    for (timestamp, health_factor) in [(1, Some(1.8)), (2, Some(1.4)), (3, None)] {
+        let sample = HealthFactorSample {
+            timestamp,
+            health_factor,
+            collateral_usd: 10_000.0,
+            debt_usd: 5_000.0,
+        };
+        chain.record_health_factor_sample(sample, 2).unwrap();
+    }
+    let history = chain.health_factor_history().unwrap();
+    let timestamps: Vec<_> = history.iter().map(|sample| sample.timestamp).collect();
+    assert_eq!(timestamps, vec![2, 3]);
+    let json = serde_json::to_string(&history[1]).unwrap();
+    assert!(json.contains("\"health_factor\":null"));
+
+    // Served without waiting for the initial sync
+    let request = Request::get("/history").body(Body::empty()).unwrap();
+    let response = handle_request(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+// Aave Pool log as delivered by a provider, for driving the listener offline
+fn aave_log(
+    pool: ethers::prelude::Address,
+    topics: Vec<ethers::prelude::H256>,
+    data: String,
+    block: u64,
+    index: u64,
+) -> ethers::prelude::Log {
+    use ethers::prelude::{Bytes, Log, H256, U256, U64};
+
+    Log {
+        address: pool,
+        topics,
+        data: Bytes::from(alloy_primitives::hex::decode(data).unwrap()),
+        block_number: Some(U64::from(block)),
+        block_hash: Some(H256::from_low_u64_be(block)),
+        transaction_hash: Some(H256::from_low_u64_be(block * 1_000 + index)),
+        log_index: Some(U256::from(index)),
+        ..Default::default()
+    }
+}
+
+// Per-process temp file for the last processed block of the harness chain `name`
+fn harness_block_file(name: &str) -> String {
+    std::env::temp_dir()
+        .join(format!("aave-{}-block-{}.json", name, std::process::id()))
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[tokio::test]
+async fn collateral_toggles_exclude_supplies_from_the_collateral() {
+    use crate::chains::ethereum::ethereum_chain::{
+        backfill_missed_events, revert_blocks, AppliedBlock, ChainState, PoolListener,
+        PositionDelta, PositionSide, RESERVE_USED_AS_COLLATERAL_DISABLED_EVENT_TOPIC,
+        RESERVE_USED_AS_COLLATERAL_ENABLED_EVENT_TOPIC, SUPPLY_EVENT_TOPIC,
+    };
+    use crate::chains::{set_token_decimals, AccountField, PoolVersion};
+    use ethers::prelude::{Filter, Log, Provider, H160, H256, U256};
+    use std::str::FromStr;
+
+    let pool = H160::from_str("0x87870Bca3F3fD6335C3F4ce8392D69350B4fA4E2").unwrap();
+    let user = "0x1111111111111111111111111111111111111111";
+    let stranger = "0x3333333333333333333333333333333333333333";
+    let usdt = "0xdac17f958d2ee523a2206206994597c13d831ec7";
+    let weth = "0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2";
+    let topic = |value: &str| H256::from_str(&padded_word(value)).unwrap();
+    set_token_decimals(H160::from_str(usdt).unwrap(), 6);
+    set_token_decimals(H160::from_str(weth).unwrap(), 18);
+    let supply = |reserve: &str, block: u64, index: u64| {
+        aave_log(
+            pool,
+            vec![
+                topic(SUPPLY_EVENT_TOPIC),
+                topic(reserve),
+                topic(user),
+                H256::zero(),
+            ],
+            format!("{}{}", padded_word(user), padded_word("f4240")),
+            block,
+            index,
+        )
+    };
+    // Both fields are indexed, so the data is empty
+    let toggle = |event_topic: &str, account: &str, block: u64, index: u64| {
+        aave_log(
+            pool,
+            vec![topic(event_topic), topic(weth), topic(account)],
+            String::new(),
+            block,
+            index,
+        )
+    };
+
+    let logs: Vec<Log> = vec![
+        supply(usdt, 100, 0),
+        supply(weth, 100, 1),
+        toggle(
+            RESERVE_USED_AS_COLLATERAL_DISABLED_EVENT_TOPIC,
+            user,
+            101,
+            0,
+        ),
+        // Someone else's switch leaves the tracked position alone
+        toggle(
+            RESERVE_USED_AS_COLLATERAL_ENABLED_EVENT_TOPIC,
+            stranger,
+            101,
+            1,
+        ),
+    ];
+
+    let chain = ChainState::new(
+        "harness-collateral",
+        || harness_block_file("harness-collateral"),
+        String::new,
+    );
+    chain.set_last_processed_block(99).unwrap();
+    let (provider, mock) = Provider::mocked();
+    mock.push::<Vec<Log>, _>(logs).unwrap();
+    let listener = PoolListener::new(
+        &chain,
+        "",
+        pool,
+        H160::from_str(user).unwrap(),
+        AccountField::Any,
+        PoolVersion::V3,
+    );
+    backfill_missed_events(&listener, &provider, &Filter::new(), 101)
+        .await
+        .unwrap();
+
+    let weth = H160::from_str(weth).unwrap();
+    let usdt = H160::from_str(usdt).unwrap();
+    let position = chain.position().unwrap();
+    assert_eq!(position.supplied_amount(&weth), U256::from(1_000_000u64));
+    assert!(!position.is_collateral(&weth));
+    assert!(position.is_collateral(&usdt));
+    assert_eq!(
+        position.collateral().keys().collect::<Vec<_>>(),
+        vec![&usdt]
+    );
+    assert_eq!(chain.event_count(), 3);
+
+    // Turning it back on counts the supply again
+    let (provider, mock) = Provider::mocked();
+    mock.push::<Vec<Log>, _>(vec![toggle(
+        RESERVE_USED_AS_COLLATERAL_ENABLED_EVENT_TOPIC,
+        user,
+        102,
+        0,
+    )])
+    .unwrap();
+    backfill_missed_events(&listener, &provider, &Filter::new(), 102)
+        .await
+        .unwrap();
+    let mut position = chain.position().unwrap();
+    assert!(position.is_collateral(&weth));
+    assert_eq!(position.collateral().len(), 2);
+
+    // A reorged-out switch is undone like any other change
+    revert_blocks(
+        &mut position,
+        &[AppliedBlock {
+            number: 102,
+            hash: H256::from_low_u64_be(102),
+            deltas: vec![PositionDelta {
+                side: PositionSide::Collateral,
+                reserve: weth,
+                before: U256::zero(),
+                after: U256::one(),
+            }],
+        }],
+    );
+    assert!(!position.is_collateral(&weth));
+
+    std::fs::remove_file(harness_block_file("harness-collateral")).unwrap();
+}
+
+#[tokio::test]
+async fn one_listener_applies_each_tracked_accounts_events_to_its_own_position() {
+    use crate::chains::ethereum::ethereum_chain::{
+        backfill_missed_events, ChainState, PoolListener, SUPPLY_EVENT_TOPIC,
+    };
+    use crate::chains::{set_token_decimals, AccountField, PoolVersion};
+    use ethers::prelude::{Filter, Log, Provider, H160, H256, U256};
+    use std::str::FromStr;
+
+    let pool = H160::from_str("0x87870Bca3F3fD6335C3F4ce8392D69350B4fA4E2").unwrap();
+    let first = "0x1111111111111111111111111111111111111111";
+    let second = "0x2222222222222222222222222222222222222222";
+    let stranger = "0x3333333333333333333333333333333333333333";
+    let usdt = "0xdac17f958d2ee523a2206206994597c13d831ec7";
+    let topic = |value: &str| H256::from_str(&padded_word(value)).unwrap();
+    set_token_decimals(H160::from_str(usdt).unwrap(), 6);
+    let supply = |account: &str, amount: &str, index: u64| {
+        aave_log(
+            pool,
+            vec![
+                topic(SUPPLY_EVENT_TOPIC),
+                topic(usdt),
+                topic(account),
+                H256::zero(),
+            ],
+            format!("{}{}", padded_word(account), padded_word(amount)),
+            100,
+            index,
+        )
+    };
+
+    let chain = ChainState::new(
+        "harness-accounts",
+        || harness_block_file("harness-accounts"),
+        String::new,
+    );
+    let other = chain.for_account(H160::from_str(second).unwrap());
+    assert!(chain.is_primary_account());
+    assert!(!other.is_primary_account());
+    assert!(other.user_address().eq_ignore_ascii_case(second));
+    chain.set_last_processed_block(99).unwrap();
+
+    let (provider, mock) = Provider::mocked();
+    mock.push::<Vec<Log>, _>(vec![
+        supply(first, "f4240", 0),
+        supply(second, "1e8480", 1),
+        supply(stranger, "2dc6c0", 2),
+    ])
+    .unwrap();
+    let listener = PoolListener::new(
+        &chain,
+        "",
+        pool,
+        H160::from_str(first).unwrap(),
+        AccountField::Any,
+        PoolVersion::V3,
+    )
+    .track_account(H160::from_str(second).unwrap(), &other);
+    backfill_missed_events(&listener, &provider, &Filter::new(), 100)
+        .await
+        .unwrap();
+
+    let usdt = H160::from_str(usdt).unwrap();
+    assert_eq!(
+        chain.position().unwrap().supplied_amount(&usdt),
+        U256::from(1_000_000u64)
+    );
+    assert_eq!(
+        other.position().unwrap().supplied_amount(&usdt),
+        U256::from(2_000_000u64)
+    );
+    assert_eq!(chain.event_count(), 1);
+    assert_eq!(other.event_count(), 1);
+    // Both accounts advance block by block, each in its own file
+    assert_eq!(chain.last_processed_block(), Some(100));
+    assert_eq!(other.last_processed_block(), Some(100));
+
+    std::fs::remove_file(harness_block_file("harness-accounts")).unwrap();
+    std::fs::remove_file(format!(
+        "{}.{:?}",
+        harness_block_file("harness-accounts"),
+        H160::from_str(second).unwrap()
+    ))
+    .unwrap();
+}
+
+#[tokio::test]
+async fn logs_fetched_twice_are_applied_once() {
+    use crate::chains::ethereum::ethereum_chain::{
+        apply_log_or_skip, backfill_missed_events, ChainState, PoolListener, BORROW_EVENT_TOPIC,
+    };
+    use crate::chains::{set_token_decimals, AccountField, PoolVersion};
+    use ethers::prelude::{Filter, Log, Provider, H160, H256, U256};
+    use std::str::FromStr;
+
+    let pool = H160::from_str("0x87870Bca3F3fD6335C3F4ce8392D69350B4fA4E2").unwrap();
+    let user = "0x1111111111111111111111111111111111111111";
+    let wbtc = "0x2260fac5e5542a773aa44fbcfedf7c193bc2c599";
+    let topic = |value: &str| H256::from_str(&padded_word(value)).unwrap();
+    set_token_decimals(H160::from_str(wbtc).unwrap(), 8);
+    let borrow = |index: u64| {
+        aave_log(
+            pool,
+            vec![
+                topic(BORROW_EVENT_TOPIC),
+                topic(wbtc),
+                topic(user),
+                H256::zero(),
+            ],
+            format!(
+                "{}{}{}{}",
+                padded_word(user),
+                padded_word("4c4b40"),
+                padded_word("2"),
+                padded_word("0")
+            ),
+            100,
+            index,
+        )
+    };
+
+    let chain = ChainState::new(
+        "harness-dedup",
+        || harness_block_file("harness-dedup"),
+        String::new,
+    );
+    chain.set_last_processed_block(99).unwrap();
+    let (provider, mock) = Provider::mocked();
+    // The same Borrow twice, as overlapping ranges return it, next to a second one
+    mock.push::<Vec<Log>, _>(vec![borrow(0), borrow(0), borrow(1)])
+        .unwrap();
+    let listener = PoolListener::new(
+        &chain,
+        "",
+        pool,
+        H160::from_str(user).unwrap(),
+        AccountField::Any,
+        PoolVersion::V3,
+    );
+    backfill_missed_events(&listener, &provider, &Filter::new(), 100)
+        .await
+        .unwrap();
+    // And once more from the live subscription after a reconnection
+    apply_log_or_skip(&listener, &borrow(1)).await.unwrap();
+
+    assert_eq!(
+        chain
+            .position()
+            .unwrap()
+            .borrowed_amount(&H160::from_str(wbtc).unwrap()),
+        U256::from(10_000_000u64)
+    );
+    assert_eq!(chain.event_count(), 2);
+
+    std::fs::remove_file(harness_block_file("harness-dedup")).unwrap();
+}
+
+#[tokio::test]
+async fn blocks_skipped_by_the_subscription_are_backfilled() {
+    use crate::chains::ethereum::ethereum_chain::{
+        apply_confirmed_block, ChainState, PoolListener, BORROW_EVENT_TOPIC,
+    };
+    use crate::chains::{set_token_decimals, AccountField, PoolVersion};
+    use ethers::prelude::{Filter, Log, Provider, H160, H256, U256};
+    use std::str::FromStr;
+
+    let pool = H160::from_str("0x87870Bca3F3fD6335C3F4ce8392D69350B4fA4E2").unwrap();
+    let user = "0x1111111111111111111111111111111111111111";
+    let wbtc = "0x2260fac5e5542a773aa44fbcfedf7c193bc2c599";
+    let topic = |value: &str| H256::from_str(&padded_word(value)).unwrap();
+    set_token_decimals(H160::from_str(wbtc).unwrap(), 8);
+    let borrow = |block: u64| {
+        aave_log(
+            pool,
+            vec![
+                topic(BORROW_EVENT_TOPIC),
+                topic(wbtc),
+                topic(user),
+                H256::zero(),
+            ],
+            format!(
+                "{}{}{}{}",
+                padded_word(user),
+                padded_word("4c4b40"),
+                padded_word("2"),
+                padded_word("0")
+            ),
+            block,
+            0,
+        )
+    };
+
+    let chain = ChainState::new(
+        "harness-gap",
+        || harness_block_file("harness-gap"),
+        String::new,
+    );
+    chain.set_last_processed_block(100).unwrap();
+    let listener = PoolListener::new(
+        &chain,
+        "",
+        pool,
+        H160::from_str(user).unwrap(),
+        AccountField::Any,
+        PoolVersion::V3,
+    );
+    // Heads 101 and 102 never arrived; the mock answers the last pushed request first
+    let (provider, mock) = Provider::mocked();
+    mock.push::<Vec<Log>, _>(vec![borrow(103)]).unwrap();
+    mock.push::<Vec<Log>, _>(vec![borrow(101), borrow(102)])
+        .unwrap();
+    apply_confirmed_block(
+        &listener,
+        &provider,
+        &Filter::new(),
+        103,
+        H256::from_low_u64_be(103),
+        H256::from_low_u64_be(102),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+        chain
+            .position()
+            .unwrap()
+            .borrowed_amount(&H160::from_str(wbtc).unwrap()),
+        U256::from(15_000_000u64)
+    );
+    assert_eq!(chain.event_count(), 3);
+    assert_eq!(chain.last_processed_block(), Some(103));
+
+    std::fs::remove_file(harness_block_file("harness-gap")).unwrap();
+}
+
+#[tokio::test]
+async fn blocks_orphaned_before_the_confirmation_depth_are_never_applied() {
+    use crate::chains::ethereum::ethereum_chain::{
+        apply_head, ChainState, PoolListener, BORROW_EVENT_TOPIC,
+    };
+    use crate::chains::{set_token_decimals, AccountField, PoolVersion};
+    use ethers::prelude::{Block, Filter, Log, Provider, H160, H256, U256, U64};
+    use std::str::FromStr;
+
+    let pool = H160::from_str("0x87870Bca3F3fD6335C3F4ce8392D69350B4fA4E2").unwrap();
+    let user = "0x1111111111111111111111111111111111111111";
+    let wbtc = "0x2260fac5e5542a773aa44fbcfedf7c193bc2c599";
+    let topic = |value: &str| H256::from_str(&padded_word(value)).unwrap();
+    set_token_decimals(H160::from_str(wbtc).unwrap(), 8);
+    let borrow = |block: u64, amount: &str| {
+        aave_log(
+            pool,
+            vec![
+                topic(BORROW_EVENT_TOPIC),
+                topic(wbtc),
+                topic(user),
+                H256::zero(),
+            ],
+            format!(
+                "{}{}{}{}",
+                padded_word(user),
+                padded_word(amount),
+                padded_word("2"),
+                padded_word("0")
+            ),
+            block,
+            0,
+        )
+    };
+    let block = |number: u64, hash: H256| Block::<H256> {
+        number: Some(U64::from(number)),
+        hash: Some(hash),
+        parent_hash: H256::from_low_u64_be(number - 1),
+        ..Default::default()
+    };
+
+    let chain = ChainState::new(
+        "harness-depth",
+        || harness_block_file("harness-depth"),
+        String::new,
+    );
+    chain.set_last_processed_block(100).unwrap();
+    let listener = PoolListener::new(
+        &chain,
+        "",
+        pool,
+        H160::from_str(user).unwrap(),
+        AccountField::Any,
+        PoolVersion::V3,
+    );
+    let (provider, mock) = Provider::mocked();
+
+    // Head 101, with a 5 WBTC borrow, is only pending at depth 5: the block it
+    // confirms, 96, was already processed
+    mock.push(block(96, H256::from_low_u64_be(96))).unwrap();
+    apply_head(
+        &listener,
+        &provider,
+        &Filter::new(),
+        block(101, H256::repeat_byte(0xaa)),
+        5,
+    )
+    .await
+    .unwrap();
+    mock.assert_request(
+        "eth_getBlockByNumber",
+        [serde_json::json!("0x60"), serde_json::json!(false)],
+    )
+    .unwrap();
+    assert_eq!(chain.last_processed_block(), Some(100));
+
+    // Block 101 is then replaced by one with a 1 WBTC borrow; once head 106 buries
+    // it, the replacement is looked up and its logs are read by its hash
+    let canonical = H256::repeat_byte(0xbb);
+    mock.push::<Vec<Log>, _>(vec![borrow(101, "5f5e100")])
+        .unwrap();
+    mock.push(block(101, canonical)).unwrap();
+    apply_head(
+        &listener,
+        &provider,
+        &Filter::new(),
+        block(106, H256::repeat_byte(0xcc)),
+        5,
+    )
+    .await
+    .unwrap();
+    mock.assert_request(
+        "eth_getBlockByNumber",
+        [serde_json::json!("0x65"), serde_json::json!(false)],
+    )
+    .unwrap();
+    mock.assert_request("eth_getLogs", [Filter::new().at_block_hash(canonical)])
+        .unwrap();
+
+    assert_eq!(
+        chain
+            .position()
+            .unwrap()
+            .borrowed_amount(&H160::from_str(wbtc).unwrap()),
+        U256::from(100_000_000u64)
+    );
+    assert_eq!(chain.last_processed_block(), Some(101));
+
+    std::fs::remove_file(harness_block_file("harness-depth")).unwrap();
+}
+
+#[tokio::test]
+async fn live_events_are_queued_for_the_large_event_alert() {
+    use crate::chains::ethereum::ethereum_chain::{
+        apply_log_or_skip, ChainState, PoolListener, BORROW_EVENT_TOPIC,
+    };
+    use crate::chains::{set_token_decimals, AccountField, PoolVersion};
+    use crate::is_large_event;
+    use ethers::prelude::{H160, H256, U256};
+    use std::str::FromStr;
+
+    std::env::set_var("LARGE_EVENT_ALERT_USD", "50000");
+    let pool = H160::from_str("0x87870Bca3F3fD6335C3F4ce8392D69350B4fA4E2").unwrap();
+    let user = "0x1111111111111111111111111111111111111111";
+    let wbtc = "0x2260fac5e5542a773aa44fbcfedf7c193bc2c599";
+    let topic = |value: &str| H256::from_str(&padded_word(value)).unwrap();
+    set_token_decimals(H160::from_str(wbtc).unwrap(), 8);
+    let borrow = aave_log(
+        pool,
+        vec![
+            topic(BORROW_EVENT_TOPIC),
+            topic(wbtc),
+            topic(user),
+            H256::zero(),
+        ],
+        format!(
+            "{}{}{}{}",
+            padded_word(user),
+            padded_word("4c4b40"),
+            padded_word("2"),
+            padded_word("0")
+        ),
+        200,
+        0,
+    );
+    let chain = ChainState::new("large-events", String::new, String::new);
+    let listener = PoolListener::new(
+        &chain,
+        "",
+        pool,
+        H160::from_str(user).unwrap(),
+        AccountField::Any,
+        PoolVersion::V3,
+    );
+    apply_log_or_skip(&listener, &borrow).await.unwrap();
+    let notices = chain.take_large_event_notices().unwrap();
+    assert_eq!(notices.len(), 1);
+    assert_eq!(notices[0].event_type, "borrow");
+    assert_eq!(notices[0].amount, U256::from(5_000_000u64));
+    assert_eq!(notices[0].tx_hash, borrow.transaction_hash);
+    assert!(chain.take_large_event_notices().unwrap().is_empty());
+
+    // A replay goes through past activity, which isn't news
+    let replayed = ChainState::new("large-events-replay", String::new, String::new);
+    let listener = PoolListener::new(
+        &replayed,
+        "",
+        pool,
+        H160::from_str(user).unwrap(),
+        AccountField::Any,
+        PoolVersion::V3,
+    )
+    .without_publishing();
+    apply_log_or_skip(&listener, &borrow).await.unwrap();
+    assert!(replayed.take_large_event_notices().unwrap().is_empty());
+    std::env::remove_var("LARGE_EVENT_ALERT_USD");
+
+    assert!(is_large_event(50_000.0, 50_000.0));
+    assert!(!is_large_event(49_999.0, 50_000.0));
+    assert!(!is_large_event(1e12, 0.0));
+}
+
+#[test]
+fn applied_logs_are_bounded_and_forgotten_on_rollback() {
+    use crate::chains::ethereum::ethereum_chain::AppliedLogs;
+    use ethers::prelude::{H256, U256};
+
+    let key = |n: u64| (H256::from_low_u64_be(n), U256::from(n % 7));
+    let mut logs = AppliedLogs::default();
+    for n in 0..10_005u64 {
+        logs.insert(n, key(n));
+    }
+    // The oldest logs fall out once the cap is reached
+    assert!(!logs.contains(&key(4)));
+    assert!(logs.contains(&key(5)));
+    assert!(logs.contains(&key(10_004)));
+
+    // Reorged-out blocks' logs must apply again when the new blocks carry them
+    logs.rollback_to(10_000);
+    assert!(logs.contains(&key(10_000)));
+    assert!(!logs.contains(&key(10_001)));
+    logs.insert(10_001, key(10_001));
+    assert!(logs.contains(&key(10_001)));
+}
+
+#[test]
+fn block_history_stays_bounded_over_thousands_of_blocks() {
+    use crate::chains::ethereum::ethereum_chain::{push_notice, AppliedLogs, ChainState};
+    use ethers::prelude::{H256, U256};
+
+    fn no_file() -> String {
+        "/nonexistent/history_test.json".to_string()
+    }
+
+    let chain = ChainState::new("history_test", no_file, no_file);
+    let mut logs = AppliedLogs::default();
+    let (window, confirmation_depth) = (128, 12);
+    for block in 1..=20_000u64 {
+        chain.set_latest_block(block + confirmation_depth);
+        chain
+            .commit_block(block, H256::from_low_u64_be(block))
+            .unwrap();
+        for index in 0..3u64 {
+            logs.insert(block, (H256::from_low_u64_be(block), U256::from(index)));
+        }
+        // The background step runs every minute: about five Ethereum blocks
+        if block % 5 == 0 {
+            chain.prune_history(window, confirmation_depth).unwrap();
+            logs.prune_below(block.saturating_sub(window));
+        }
+        let (blocks, _) = chain.history_sizes().unwrap();
+        assert!(blocks <= window as usize + 5, "{} blocks kept", blocks);
+        assert!(logs.len() <= 3 * (window as usize + 5));
+    }
+    // Recent blocks are still there for reorg detection, old ones are gone
+    chain.prune_history(window, confirmation_depth).unwrap();
+    assert_eq!(chain.history_sizes().unwrap().0, window as usize + 1);
+    assert!(logs.contains(&(H256::from_low_u64_be(20_000), U256::from(2))));
+    assert!(!logs.contains(&(H256::from_low_u64_be(100), U256::zero())));
+
+    // Without pruning the hard caps still hold
+    let unpruned = ChainState::new("history_test", no_file, no_file);
+    for block in 1..=5_000u64 {
+        unpruned
+            .commit_block(block, H256::from_low_u64_be(block))
+            .unwrap();
+    }
+    assert_eq!(unpruned.history_sizes().unwrap().0, 1024);
+
+    let mut notices = Vec::new();
+    for notice in 0..5_000 {
+        push_notice(&mut notices, notice);
+    }
+    assert_eq!(notices.len(), 1_000);
+    assert_eq!(notices[0], 4_000);
+}
+
+#[tokio::test]
+async fn backfill_applies_mocked_pool_logs_to_the_position() {
+    use crate::chains::ethereum::ethereum_chain::{
+        backfill_missed_events, ChainState, PoolListener,
+    };
+    use crate::chains::{set_token_decimals, AccountField, PoolVersion};
+    use ethers::prelude::{Filter, Log, Provider, H160, H256, U256};
+    use std::str::FromStr;
+
+    // Raw topics and data laid out as the mainnet V3 Pool emits them (indexing as in
+    // Aave's IPool), written out by hand rather than encoded with our own `sol!`
+    // definitions, so a wrong declaration there can't hide behind them
+    // Supply(reserve, user, onBehalfOf, amount, referralCode): reserve, onBehalfOf and
+    // referralCode are indexed, user and amount are the data
+    let supply_topics = [
+        "0x2b627736bca15cd5381dcf80b0bf11fd197d01a037c52b927a881a10fb73ba61",
+        "0x000000000000000000000000dac17f958d2ee523a2206206994597c13d831ec7",
+        "0x0000000000000000000000001111111111111111111111111111111111111111",
+        "0x0000000000000000000000000000000000000000000000000000000000000000",
+    ];
+    let supply_data = concat!(
+        "0000000000000000000000001111111111111111111111111111111111111111",
+        "00000000000000000000000000000000000000000000000000000000000f4240",
+    );
+    // Borrow(reserve, user, onBehalfOf, amount, interestRateMode, borrowRate,
+    // referralCode): a variable-rate borrow
+    let borrow_topics = [
+        "0xb3d084820fb1a9decffb176436bd02558d15fac9b0ddfed8c465bc7359d7dce0",
+        "0x0000000000000000000000002260fac5e5542a773aa44fbcfedf7c193bc2c599",
+        "0x0000000000000000000000001111111111111111111111111111111111111111",
+        "0x0000000000000000000000000000000000000000000000000000000000000000",
+    ];
+    let borrow_data = concat!(
+        "0000000000000000000000001111111111111111111111111111111111111111",
+        "00000000000000000000000000000000000000000000000000000000004c4b40",
+        "0000000000000000000000000000000000000000000000000000000000000002",
+        "0000000000000000000000000000000000000000000000000000000000000000",
+    );
+    // Someone else's supply
+    let stranger_topics = [
+        "0x2b627736bca15cd5381dcf80b0bf11fd197d01a037c52b927a881a10fb73ba61",
+        "0x000000000000000000000000dac17f958d2ee523a2206206994597c13d831ec7",
+        "0x0000000000000000000000003333333333333333333333333333333333333333",
+        "0x0000000000000000000000000000000000000000000000000000000000000000",
+    ];
+    let stranger_data = concat!(
+        "0000000000000000000000003333333333333333333333333333333333333333",
+        "00000000000000000000000000000000000000000000000000000000000f4240",
+    );
+    // Repay(reserve, user, repayer, amount, useATokens): three indexed addresses
+    let repay_topics = [
+        "0xa534c8dbe71f871f9f3530e97a74601fea17b426cae02e1c5aee42c96c784051",
+        "0x0000000000000000000000002260fac5e5542a773aa44fbcfedf7c193bc2c599",
+        "0x0000000000000000000000001111111111111111111111111111111111111111",
+        "0x0000000000000000000000001111111111111111111111111111111111111111",
+    ];
+    let repay_data = concat!(
+        "00000000000000000000000000000000000000000000000000000000001e8480",
+        "0000000000000000000000000000000000000000000000000000000000000000",
+    );
+    // Withdraw(reserve, user, to, amount): everything but the amount is indexed
+    let withdraw_topics = [
+        "0x3115d1449a7b732c986cba18244e897a450f61e1bb8d589cd2e69e6c8924f9f7",
+        "0x000000000000000000000000dac17f958d2ee523a2206206994597c13d831ec7",
+        "0x0000000000000000000000001111111111111111111111111111111111111111",
+        "0x0000000000000000000000001111111111111111111111111111111111111111",
+    ];
+    let withdraw_data =
+        concat!("0000000000000000000000000000000000000000000000000000000000061a80",);
+    // LiquidationCall(collateralAsset, debtAsset, user, debtToCover,
+    // liquidatedCollateralAmount, liquidator, receiveAToken)
+    let liquidation_topics = [
+        "0xe413a321e8681d831f4dbccbca790d2952b56f977908e45be37335533e005286",
+        "0x000000000000000000000000dac17f958d2ee523a2206206994597c13d831ec7",
+        "0x0000000000000000000000002260fac5e5542a773aa44fbcfedf7c193bc2c599",
+        "0x0000000000000000000000001111111111111111111111111111111111111111",
+    ];
+    let liquidation_data = concat!(
+        "00000000000000000000000000000000000000000000000000000000000f4240",
+        "00000000000000000000000000000000000000000000000000000000000186a0",
+        "0000000000000000000000004444444444444444444444444444444444444444",
+        "0000000000000000000000000000000000000000000000000000000000000000",
+    );
+
+    let pool = H160::from_str("0x87870Bca3F3fD6335C3F4ce8392D69350B4fA4E2").unwrap();
+    let user = H160::from_str("0x1111111111111111111111111111111111111111").unwrap();
+    let usdt = H160::from_str("0xdac17f958d2ee523a2206206994597c13d831ec7").unwrap();
+    let wbtc = H160::from_str("0x2260fac5e5542a773aa44fbcfedf7c193bc2c599").unwrap();
+    let topics = |raw: [&str; 4]| -> Vec<H256> {
+        raw.iter()
+            .map(|topic| H256::from_str(topic).unwrap())
+            .collect()
+    };
+    // Known decimals keep the listener from asking the chain for them
+    set_token_decimals(usdt, 6);
+    set_token_decimals(wbtc, 8);
+
+    let logs: Vec<Log> = vec![
+        aave_log(pool, topics(supply_topics), supply_data.to_string(), 100, 0),
+        aave_log(pool, topics(borrow_topics), borrow_data.to_string(), 100, 1),
+        aave_log(
+            pool,
+            topics(stranger_topics),
+            stranger_data.to_string(),
+            101,
+            0,
+        ),
+        // The same supply from another contract
+        aave_log(
+            H160::repeat_byte(0x99),
+            topics(supply_topics),
+            supply_data.to_string(),
+            101,
+            1,
+        ),
+        aave_log(pool, topics(repay_topics), repay_data.to_string(), 102, 0),
+        aave_log(
+            pool,
+            topics(withdraw_topics),
+            withdraw_data.to_string(),
+            102,
+            1,
+        ),
+        aave_log(
+            pool,
+            topics(liquidation_topics),
+            liquidation_data.to_string(),
+            103,
+            0,
+        ),
+    ];
+
+    let chain = ChainState::new("harness", || harness_block_file("harness"), String::new);
+    chain.set_last_processed_block(99).unwrap();
+    let (provider, mock) = Provider::mocked();
+    mock.push::<Vec<Log>, _>(logs).unwrap();
+    let listener = PoolListener::new(&chain, "", pool, user, AccountField::Any, PoolVersion::V3);
+
+    backfill_missed_events(&listener, &provider, &Filter::new(), 103)
+        .await
+        .unwrap();
+
+    // 1 USDT supplied, 0.4 withdrawn, 0.1 liquidated
+    let position = chain.position().unwrap();
+    assert_eq!(position.supplied_amount(&usdt), U256::from(500_000u64));
+    // 0.05 WBTC borrowed, 0.02 repaid, 0.01 covered by the liquidator
+    assert_eq!(position.borrowed_amount(&wbtc), U256::from(2_000_000u64));
+    // Supply, borrow, repay, withdraw, and both sides of the liquidation
+    assert_eq!(chain.event_count(), 6);
+    let notices = chain.take_liquidation_notices().unwrap();
+    assert_eq!(notices.len(), 1);
+    assert_eq!(notices[0].debt_to_cover, U256::from(1_000_000u64));
+    assert_eq!(
+        notices[0].liquidator,
+        H160::from_str("0x4444444444444444444444444444444444444444").unwrap()
+    );
+    assert_eq!(chain.last_processed_block(), Some(103));
+
+    std::fs::remove_file(harness_block_file("harness")).unwrap();
+}
+
+#[test]
+fn pool_logs_dispatch_on_their_topic() {
+    use crate::chains::ethereum::ethereum_chain::{
+        pool_event_map, PoolEvent, DEPOSIT_V2_EVENT_TOPIC, LIQUIDATION_CALL_EVENT_TOPIC,
+        SUPPLY_EVENT_TOPIC,
+    };
+    use crate::chains::PoolVersion;
+    use ethers::prelude::H256;
+    use std::str::FromStr;
+
+    let topic = |value: &str| H256::from_str(value).unwrap();
+    let v3 = pool_event_map(PoolVersion::V3);
+    let v2 = pool_event_map(PoolVersion::V2);
+    assert_eq!(v3.len(), 7);
+    assert_eq!(v3.get(&topic(SUPPLY_EVENT_TOPIC)), Some(&PoolEvent::Supply));
+    assert_eq!(
+        v3.get(&topic(LIQUIDATION_CALL_EVENT_TOPIC)),
+        Some(&PoolEvent::LiquidationCall)
+    );
+    // V2's Deposit is handled as a Supply, and V3's Supply isn't a V2 event
+    assert_eq!(
+        v2.get(&topic(DEPOSIT_V2_EVENT_TOPIC)),
+        Some(&PoolEvent::Supply)
+    );
+    assert_eq!(v2.get(&topic(SUPPLY_EVENT_TOPIC)), None);
+    assert_eq!(v3.get(&H256::repeat_byte(0x42)), None);
+}
+
+#[tokio::test]
+async fn backfill_applies_v2_pool_logs_to_the_position() {
+    use crate::chains::ethereum::ethereum_chain::{
+        backfill_missed_events, pool_event_topics, ChainState, PoolListener, BORROW_V2_EVENT_TOPIC,
+        DEPOSIT_V2_EVENT_TOPIC, REPAY_V2_EVENT_TOPIC, SUPPLY_EVENT_TOPIC,
+    };
+    use crate::chains::{set_token_decimals, AccountField, PoolVersion};
+    use ethers::prelude::{Filter, Log, Provider, H160, H256, U256};
+    use std::str::FromStr;
+
+    assert!(pool_event_topics(PoolVersion::V2).contains(&DEPOSIT_V2_EVENT_TOPIC));
+    assert!(!pool_event_topics(PoolVersion::V2).contains(&SUPPLY_EVENT_TOPIC));
+
+    let pool = H160::from_str("0x7d2768dE32b0b80b7a3454c06BdAc94A69DDc7A9").unwrap();
+    let user = "0x1111111111111111111111111111111111111111";
+    let usdt = "0xdac17f958d2ee523a2206206994597c13d831ec7";
+    let wbtc = "0x2260fac5e5542a773aa44fbcfedf7c193bc2c599";
+    let topic = |value: &str| H256::from_str(&padded_word(value)).unwrap();
+    set_token_decimals(H160::from_str(usdt).unwrap(), 6);
+    set_token_decimals(H160::from_str(wbtc).unwrap(), 8);
+
+    let logs: Vec<Log> = vec![
+        aave_log(
+            pool,
+            vec![
+                topic(DEPOSIT_V2_EVENT_TOPIC),
+                topic(usdt),
+                topic(user),
+                H256::zero(),
+            ],
+            format!("{}{}", padded_word(user), padded_word("f4240")),
+            100,
+            0,
+        ),
+        aave_log(
+            pool,
+            vec![
+                topic(BORROW_V2_EVENT_TOPIC),
+                topic(wbtc),
+                topic(user),
+                H256::zero(),
+            ],
+            format!(
+                "{}{}{}{}",
+                padded_word(user),
+                padded_word("4c4b40"),
+                padded_word("2"),
+                padded_word("0")
+            ),
+            100,
+            1,
+        ),
+        // V2 Repay indexes the user, so only the amount is in the data
+        aave_log(
+            pool,
+            vec![
+                topic(REPAY_V2_EVENT_TOPIC),
+                topic(wbtc),
+                topic(user),
+                topic(user),
+            ],
+            padded_word("1e8480"),
+            101,
+            0,
+        ),
+    ];
+
+    let chain = ChainState::new(
+        "harness-v2",
+        || harness_block_file("harness-v2"),
+        String::new,
+    );
+    chain.set_last_processed_block(99).unwrap();
+    let (provider, mock) = Provider::mocked();
+    mock.push::<Vec<Log>, _>(logs).unwrap();
+    let listener = PoolListener::new(
+        &chain,
+        "",
+        pool,
+        H160::from_str(user).unwrap(),
+        AccountField::Any,
+        PoolVersion::V2,
+    );
+
+    backfill_missed_events(&listener, &provider, &Filter::new(), 101)
+        .await
+        .unwrap();
+
+    let position = chain.position().unwrap();
+    assert_eq!(
+        position.supplied_amount(&H160::from_str(usdt).unwrap()),
+        U256::from(1_000_000u64)
+    );
+    assert_eq!(
+        position.borrowed_amount(&H160::from_str(wbtc).unwrap()),
+        U256::from(3_000_000u64)
+    );
+    assert_eq!(chain.event_count(), 3);
+
+    std::fs::remove_file(harness_block_file("harness-v2")).unwrap();
+}
+
+#[test]
+fn publish_commands_are_encoded_for_the_broker() {
+    use crate::publish::{nats_pub, parse_publish_url, resp_command, EventPublisher};
+
+    assert_eq!(
+        resp_command(&[b"PUBLISH", b"aave", b"{}"]),
+        b"*3\r\n$7\r\nPUBLISH\r\n$4\r\naave\r\n$2\r\n{}\r\n".to_vec()
+    );
+    assert_eq!(nats_pub("aave", b"{}"), b"PUB aave 2\r\n{}\r\n".to_vec());
+
+    assert_eq!(
+        parse_publish_url("redis://localhost").unwrap().name(),
+        "Redis"
+    );
+    assert_eq!(
+        parse_publish_url("nats://localhost:4222").unwrap().name(),
+        "NATS"
+    );
+    assert!(parse_publish_url("kafka://localhost").is_err());
+}
+
+#[test]
+fn health_factor_checks_encode_as_line_protocol() {
+    use crate::influx::{health_factor_points, influx_write_url};
+
+    assert_eq!(
+        health_factor_points("ethereum", "0xabc", 1.5, 2000.0, 1066.5, 1_700_000_000),
+        vec![
+            "aave_health_factor,chain=ethereum,user=0xabc value=1.5 1700000000".to_string(),
+            "aave_collateral_usd,chain=ethereum,user=0xabc value=2000 1700000000".to_string(),
+            "aave_debt_usd,chain=ethereum,user=0xabc value=1066.5 1700000000".to_string(),
+        ]
+    );
+    // No debt: the infinite health factor is left out, tags are escaped
+    assert_eq!(
+        health_factor_points("my chain", "a,b=c", f64::INFINITY, 10.0, 0.0, 1),
+        vec![
+            "aave_collateral_usd,chain=my\\ chain,user=a\\,b\\=c value=10 1".to_string(),
+            "aave_debt_usd,chain=my\\ chain,user=a\\,b\\=c value=0 1".to_string(),
+        ]
+    );
+
+    assert_eq!(
+        influx_write_url("http://localhost:8086", "my-org", "aave monitor")
+            .unwrap()
+            .as_str(),
+        "http://localhost:8086/api/v2/write?org=my-org&bucket=aave+monitor&precision=s"
+    );
+    assert!(influx_write_url("not a url", "org", "bucket").is_err());
+}
+
+#[tokio::test]
+async fn position_events_are_published_to_redis() {
+    use crate::chains::ethereum::ethereum_chain::ChainState;
+    use crate::publish::{parse_publish_url, EventPublisher, PositionEvent};
+    use ethers::prelude::{Address, U256};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = tokio::spawn(async move {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut received = Vec::new();
+        let mut buf = [0u8; 1024];
+        // Read until the payload's closing CRLF
+        while !received.ends_with(b"}\r\n") {
+            let read = socket.read(&mut buf).await.unwrap();
+            received.extend_from_slice(&buf[..read]);
+        }
+        socket.write_all(b":1\r\n").await.unwrap();
+        String::from_utf8(received).unwrap()
+    });
+
+    let chain = ChainState::new("publish", String::new, String::new);
+    chain
+        .update_supplied_amount(Address::repeat_byte(0x81), U256::from(500))
+        .unwrap();
+    let publisher = parse_publish_url(&format!("redis://127.0.0.1:{}", port)).unwrap();
+    publisher
+        .publish(&PositionEvent::new("supply", &chain))
+        .await
+        .unwrap();
+
+    let command = server.await.unwrap();
+    assert!(command.starts_with("*3\r\n$7\r\nPUBLISH\r\n"));
+    let payload: serde_json::Value = serde_json::from_str(command.lines().last().unwrap()).unwrap();
+    assert_eq!(payload["event_type"], "supply");
+    assert_eq!(payload["chain"], "publish");
+    assert_eq!(
+        payload["supplied"][format!("{:?}", Address::repeat_byte(0x81))],
+        "500"
+    );
+}
+
+#[tokio::test]
+async fn health_factor_loop_stops_on_shutdown() {
+    use crate::chains::polygon::polygon_chain::POLYGON;
+    use crate::monitor_health_factor;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    let (shutdown, shutdown_signal) = tokio::sync::watch::channel(false);
+    let task = tokio::spawn(monitor_health_factor(
+        &POLYGON,
+        Arc::new(Vec::new()),
+        shutdown_signal,
+    ));
+    shutdown.send(true).unwrap();
+
+    tokio::time::timeout(Duration::from_secs(1), task)
+        .await
+        .expect("health-factor loop should stop on shutdown")
+        .unwrap();
+}
+
+#[test]
+fn ens_names_resolve_to_the_cached_address() {
+    use crate::chains::{is_ens_name, resolved_account, set_ens_address};
+    use ethers::prelude::Address;
+
+    assert!(is_ens_name("vitalik.eth"));
+    assert!(is_ens_name(" aave-watcher.eth "));
+    assert!(!is_ens_name("0xBDD3B59416Fc0263354953aeeFC51Ba3A94E134e"));
+    assert!(!is_ens_name("not-an-address"));
+
+    let hex = "0xBDD3B59416Fc0263354953aeeFC51Ba3A94E134e";
+    assert_eq!(resolved_account(hex), hex);
+    // Unresolved names are left for the address parser to reject
+    assert_eq!(resolved_account("unresolved.eth"), "unresolved.eth");
+
+    set_ens_address("Tracked.eth", Address::repeat_byte(0x91));
+    assert_eq!(
+        resolved_account("tracked.eth"),
+        format!("{:?}", Address::repeat_byte(0x91))
+    );
+}
+
+#[tokio::test]
+async fn simplehash_price_is_read_from_the_configured_base_url() {
+    use crate::fetch_simplehash_price_from;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base_url = format!("http://{}/", listener.local_addr().unwrap());
+    let server = tokio::spawn(async move {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut request = Vec::new();
+        let mut buf = [0u8; 1024];
+        while !request.ends_with(b"\r\n\r\n") {
+            let read = socket.read(&mut buf).await.unwrap();
+            request.extend_from_slice(&buf[..read]);
+        }
+        let body = serde_json::json!({
+            "decimals": 6,
+            "symbol": "USDT",
+            "prices": [{
+                "marketplace_id": "uniswap_v3",
+                "marketplace_name": "Uniswap V3",
+                "value_usd_cents": 100,
+                "value_usd_string": "1.00",
+                "value_usd_string_high_precision": "1.0002",
+            }],
+        })
+        .to_string();
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        socket.write_all(response.as_bytes()).await.unwrap();
+        String::from_utf8(request).unwrap()
+    });
+
+    let price = fetch_simplehash_price_from(
+        &base_url,
+        "sk_test",
+        "0xdac17f958d2ee523a2206206994597c13d831ec7".to_string(),
+    )
+    .await
+    .unwrap()
+    .expect("the canned response has a price");
+    assert_eq!(price.symbol, "USDT");
+    assert_eq!(price.decimals, 6);
+    assert_eq!(price.price, 1.0002);
+
+    let request = server.await.unwrap();
+    assert!(request.starts_with("GET /api/v0/fungibles/assets?"));
+    assert!(request.contains("fungible_ids=ethereum.0xdac17f958d2ee523a2206206994597c13d831ec7"));
+    assert!(request.to_lowercase().contains("x-api-key: sk_test"));
+}
+
+#[tokio::test]
+async fn coingecko_price_is_read_by_contract_address() {
+    use crate::chains::parse_coingecko_platforms;
+    use crate::prices::fetch_coingecko_price_from;
+    use ethers::prelude::Address;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let platforms = parse_coingecko_platforms("base:base-mainnet").unwrap();
+    assert_eq!(platforms["polygon"], "polygon-pos");
+    assert_eq!(platforms["base"], "base-mainnet");
+    assert!(parse_coingecko_platforms("base").is_err());
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base_url = format!("http://{}/api/v3", listener.local_addr().unwrap());
+    let server = tokio::spawn(async move {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut request = Vec::new();
+        let mut buf = [0u8; 1024];
+        while !request.ends_with(b"\r\n\r\n") {
+            let read = socket.read(&mut buf).await.unwrap();
+            request.extend_from_slice(&buf[..read]);
+        }
+        let body = serde_json::json!({
+            "0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2": {
+                "usd": 3120.45,
+                "last_updated_at": 1_700_000_000u64,
+            },
+        })
+        .to_string();
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        socket.write_all(response.as_bytes()).await.unwrap();
+        String::from_utf8(request).unwrap()
+    });
+
+    let weth: Address = "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2"
+        .parse()
+        .unwrap();
+    let price = fetch_coingecko_price_from(&base_url, Some("CG-test"), "ethereum", weth)
+        .await
+        .unwrap()
+        .expect("the canned response has a price");
+    assert_eq!(price.price, 3120.45);
+    assert_eq!(price.fetched_at, 1_700_000_000);
+    assert_eq!(price.currency, "USD");
+
+    let request = server.await.unwrap();
+    assert!(request.starts_with("GET /api/v3/simple/token_price/ethereum?"));
+    assert!(request.contains("contract_addresses=0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2"));
+    assert!(request.contains("vs_currencies=usd"));
+    assert!(request
+        .to_lowercase()
+        .contains("x-cg-demo-api-key: cg-test"));
+}
+
+#[test]
+fn token_decimals_win_over_price_source_decimals() {
+    use crate::{to_usd, valuation_decimals, PriceResult};
+    use ethers::prelude::{Address, U256};
+
+    let reserve = Address::repeat_byte(0xa1);
+    // SimpleHash claims 18 decimals for a 6-decimal token
+    let price = PriceResult {
+        symbol: "USDT".to_string(),
+        price: 1.0,
+        decimals: 18,
+        fetched_at: 0,
+        currency: "USD".to_string(),
+    };
+
+    let (decimals, warning) = valuation_decimals(&reserve, Some(6), &price).unwrap();
+    assert_eq!(decimals, 6);
+    assert!(warning.unwrap().contains("reports 18 decimals"));
+    // 1,000 USDT is worth 1,000 USD, not 1e-9
+    assert_eq!(
+        to_usd(U256::from(1_000_000_000u64), decimals, price.price).unwrap(),
+        1_000.0
+    );
+
+    let (decimals, warning) = valuation_decimals(&reserve, Some(18), &price).unwrap();
+    assert_eq!(decimals, 18);
+    assert!(warning.is_none());
+
+    // Without the token's own decimals the source's are the only hint
+    let (decimals, warning) = valuation_decimals(&reserve, None, &price).unwrap();
+    assert_eq!(decimals, 18);
+    assert!(warning.is_some());
+    let unreported = PriceResult {
+        decimals: 0,
+        ..price
+    };
+    assert!(valuation_decimals(&reserve, None, &unreported).is_err());
+}
+
+#[test]
+fn telegram_commands_pause_and_resume_alerts() {
+    use crate::commands::{parse_command, AlertPause, Command};
+    use std::time::{Duration, Instant};
+
+    assert_eq!(parse_command("/pause"), Ok(Some(Command::Pause(None))));
+    assert_eq!(
+        parse_command("/pause@aave_monitor_bot 30"),
+        Ok(Some(Command::Pause(Some(Duration::from_secs(30 * 60)))))
+    );
+    assert!(parse_command("/pause soon").is_err());
+    assert!(parse_command("/pause 0").is_err());
+    assert_eq!(parse_command("/resume"), Ok(Some(Command::Resume)));
+    assert_eq!(parse_command("/status"), Ok(Some(Command::Status)));
+    assert_eq!(
+        parse_command("/healthfactor"),
+        Ok(Some(Command::HealthFactor))
+    );
+    assert_eq!(parse_command("/Position"), Ok(Some(Command::Position)));
+    assert_eq!(parse_command("/testalert"), Ok(Some(Command::TestAlert)));
+    assert_eq!(parse_command("hello"), Ok(None));
+    assert_eq!(parse_command("/start"), Ok(None));
+
+    let pause = AlertPause::new();
+    let start = Instant::now();
+    assert!(pause.alerts_enabled(start));
+    pause.pause(start, Duration::from_secs(600));
+    assert!(!pause.alerts_enabled(start + Duration::from_secs(599)));
+    // Alerts come back on their own once the pause runs out
+    assert!(pause.alerts_enabled(start + Duration::from_secs(600)));
+    assert!(pause.alerts_enabled(start));
+
+    pause.pause(start, Duration::from_secs(600));
+    pause.resume();
+    assert!(pause.alerts_enabled(start));
+}
+
+#[test]
+fn stats_summarise_the_work_since_the_previous_line() {
+    use crate::stats::Stats;
+    use std::time::{Duration, Instant};
+
+    let start = Instant::now();
+    let stats = Stats::new(start);
+    stats.record_block();
+    stats.record_log();
+    stats.record_log();
+    stats.record_event("supply");
+    let previous = stats.snapshot();
+
+    stats.record_block();
+    stats.record_log();
+    stats.record_event("borrow");
+    stats.record_event("borrow");
+    stats.record_alert();
+    stats.record_price_failure();
+    let current = stats.snapshot();
+
+    let recent = current.since(&previous);
+    assert_eq!(recent.blocks, 1);
+    assert_eq!(recent.logs, 1);
+    assert_eq!(recent.alerts, 1);
+    assert_eq!(recent.price_failures, 1);
+    // Types without new events are left out of the interval
+    assert_eq!(recent.events_summary(), "borrow=2");
+    assert_eq!(current.events_summary(), "borrow=2 supply=1");
+    assert_eq!(current.logs, 3);
+    assert_eq!(current.since(&current).events_summary(), "none");
+    assert_eq!(
+        stats.uptime(start + Duration::from_secs(90)),
+        Duration::from_secs(90)
+    );
+}
+
+#[test]
+fn config_validation_lists_every_problem_at_once() {
+    use crate::chains::validate_config;
+    use std::env;
+
+    env::set_var("AAVE_SUPPLY_POSITIONS", "0xnot-an-address:6:1000");
+    env::set_var("ETHEREUM_WS_URL", "https://mainnet.example.org");
+    env::set_var("QUOTE_CURRENCY_FEED", "eur-usd");
+    let error = validate_config().unwrap_err().to_string();
+    env::remove_var("AAVE_SUPPLY_POSITIONS");
+    env::remove_var("ETHEREUM_WS_URL");
+    env::remove_var("QUOTE_CURRENCY_FEED");
+
+    assert!(error.contains("Invalid asset address '0xnot-an-address'"));
+    assert!(error.contains("ETHEREUM_WS_URL 'https://mainnet.example.org' has scheme https"));
+    assert!(error.contains("Invalid QUOTE_CURRENCY_FEED"));
+    assert!(validate_config().is_ok());
+}
+
+#[tokio::test]
+async fn ended_or_silent_block_subscription_is_an_error() {
+    use crate::chains::ethereum::ethereum_chain::next_block;
+    use crate::error::MonitorError;
+    use ethers::prelude::{Block, H256};
+    use futures::stream;
+    use std::time::Duration;
+
+    let mut live = stream::iter(vec![Block::<H256>::default()]);
+    assert!(next_block(&mut live, Duration::from_secs(1)).await.is_ok());
+    // The provider dropped the subscription
+    let ended = next_block(&mut live, Duration::from_secs(1)).await;
+    assert!(matches!(ended, Err(MonitorError::Rpc(e)) if e.contains("ended")));
+
+    // Connected but no block arrives any more
+    let mut silent = stream::pending::<Block<H256>>();
+    let stalled = next_block(&mut silent, Duration::from_millis(20)).await;
+    assert!(matches!(stalled, Err(MonitorError::Rpc(e)) if e.contains("No new block")));
+}
+
+#[tokio::test]
+async fn rate_limiter_spreads_bursts_and_honors_pauses() {
+    use crate::ratelimit::RateLimiter;
+    use std::time::{Duration, Instant};
+
+    // A second's worth of requests goes out at once, the next one waits its turn
+    let limiter = RateLimiter::new(20.0);
+    let start = Instant::now();
+    for _ in 0..20 {
+        limiter.acquire().await;
+    }
+    assert!(start.elapsed() < Duration::from_millis(40));
+    limiter.acquire().await;
+    assert!(start.elapsed() >= Duration::from_millis(40));
+
+    // A 429 holds everyone back for its Retry-After
+    limiter.pause_for(Duration::from_millis(100)).await;
+    let paused = Instant::now();
+    limiter.acquire().await;
+    assert!(paused.elapsed() >= Duration::from_millis(90));
+
+    // No limit configured
+    let unlimited = RateLimiter::new(0.0);
+    let start = Instant::now();
+    for _ in 0..1_000 {
+        unlimited.acquire().await;
+    }
+    assert!(start.elapsed() < Duration::from_millis(100));
+}
+
+#[test]
+fn config_file_maps_to_env_vars_without_overriding_the_environment() {
+    use crate::config::Config;
+    use std::env;
+
+    let config = Config::parse(
+        r#"
+        user_addresses = ["0x1", "vitalik.eth"]
+        liquidation_threshold = 0.8
+
+        [alerts]
+        cooldown_secs = 600
+
+        [[chains.ethereum.supply]]
+        address = "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2"
+        decimals = 18
+        initial_amount = "1000000000000000000"
+
+        [chains.polygon]
+        enabled = true
+        rpc_urls = ["https://a", "https://b"]
+        pool_address = "0x794a61358D6845594F94dc1DB02A252b5b4814aD"
+
+        [env]
+        PRICE_CACHE_TTL_SECS = 30
+        "#,
+    )
+    .unwrap();
+    let vars = config.env_vars().unwrap();
+    let value = |name: &str| {
+        vars.iter()
+            .find(|(var, _)| var == name)
+            .map(|(_, value)| value.as_str())
+    };
+    assert_eq!(value("AAVE_USER_ADDRESS_TO_TRACK"), Some("0x1,vitalik.eth"));
+    assert_eq!(value("LIQUIDATION_THRESHOLD"), Some("0.8"));
+    assert_eq!(value("ALERT_COOLDOWN_SECS"), Some("600"));
+    assert_eq!(
+        value("AAVE_SUPPLY_POSITIONS"),
+        Some("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2:18:1000000000000000000")
+    );
+    assert_eq!(value("POLYGON_ENABLED"), Some("true"));
+    assert_eq!(value("POLYGON_RPC_URL"), Some("https://a,https://b"));
+    assert!(value("POLYGON_AAVE_POOL_V3_ADDRESS").is_some());
+    assert_eq!(value("ETHEREUM_ENABLED"), None);
+    assert_eq!(value("PRICE_CACHE_TTL_SECS"), Some("30"));
+
+    // The environment wins over the file. Only made-up variables are applied here,
+    // so other tests keep the configuration they expect.
+    let config = Config::parse(
+        r#"
+        [env]
+        CONFIG_TEST_FROM_FILE = 5
+        CONFIG_TEST_FROM_ENV = "file"
+        "#,
+    )
+    .unwrap();
+    env::set_var("CONFIG_TEST_FROM_ENV", "env");
+    config.apply().unwrap();
+    assert_eq!(env::var("CONFIG_TEST_FROM_ENV").unwrap(), "env");
+    assert_eq!(env::var("CONFIG_TEST_FROM_FILE").unwrap(), "5");
+
+    // Typos are reported rather than silently ignored
+    assert!(Config::parse("[alerts]\ncooldown = 600").is_err());
+}
+
+#[test]
+fn solana_positions_parse_and_logs_trigger_refreshes() {
+    use crate::chains::solana::solana_chain::{
+        balance_amount, is_solana_address, parse_solana_positions, relevant_log_slot, rpc_result,
+        solana_fungible_id, NATIVE_MINT,
+    };
+    use serde_json::json;
+
+    let usdc = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+    assert!(is_solana_address(usdc));
+    assert!(is_solana_address(NATIVE_MINT));
+    assert!(is_solana_address("11111111111111111111111111111111"));
+    assert!(!is_solana_address(
+        "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48"
+    ));
+    assert!(!is_solana_address("EPjFWdd5Auf"));
+    assert!(!is_solana_address(""));
+
+    let reserves = parse_solana_positions(&format!(
+        "{}:6:1500000000:{}, {}:9:2000000000",
+        usdc, "Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB", NATIVE_MINT
+    ))
+    .unwrap();
+    assert_eq!(reserves.len(), 2);
+    assert_eq!(reserves[0].decimals, 6);
+    assert_eq!(reserves[0].amount, 1_500_000_000);
+    assert_eq!(
+        reserves[0].token_account.as_deref(),
+        Some("Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB")
+    );
+    assert_eq!(reserves[1].token_account, None);
+    assert!(parse_solana_positions("").unwrap().is_empty());
+    assert!(parse_solana_positions(&format!("{}:6", usdc)).is_err());
+    assert!(parse_solana_positions("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48:6:1").is_err());
+    assert_eq!(solana_fungible_id(usdc), format!("solana.{}", usdc));
+
+    // Token balances come back as strings, lamports as numbers
+    let token_balance =
+        json!({"context": {"slot": 1}, "value": {"amount": "1234567", "decimals": 6}});
+    assert_eq!(balance_amount(&token_balance).unwrap(), 1_234_567);
+    assert_eq!(balance_amount(&json!({"value": 42})).unwrap(), 42);
+    assert!(balance_amount(&json!({"value": null})).is_err());
+    assert!(rpc_result("getBalance", json!({"error": {"code": -32602}})).is_err());
+    assert_eq!(
+        rpc_result("getBalance", json!({"result": {"value": 7}})).unwrap(),
+        json!({"value": 7})
+    );
+
+    let program = "So1endDq2YkqhipRh3WViPa8hdiSpxWy6z3Z6tMCpAo";
+    let notification = |err: serde_json::Value, logs: Vec<String>| {
+        json!({
+            "jsonrpc": "2.0",
+            "method": "logsNotification",
+            "params": {
+                "result": {
+                    "context": {"slot": 250_000_000},
+                    "value": {"signature": "5h6x", "err": err, "logs": logs},
+                },
+                "subscription": 24040,
+            },
+        })
+    };
+    let lending = notification(
+        serde_json::Value::Null,
+        vec![format!("Program {} invoke [1]", program)],
+    );
+    assert_eq!(
+        relevant_log_slot(&lending, Some(program)),
+        Some(250_000_000)
+    );
+    assert_eq!(relevant_log_slot(&lending, None), Some(250_000_000));
+    // A transfer that doesn't touch the lending program, a failed transaction and
+    // the subscription confirmation are ignored
+    let transfer = notification(
+        serde_json::Value::Null,
+        vec!["Program 11111111111111111111111111111111 invoke [1]".to_string()],
+    );
+    assert_eq!(relevant_log_slot(&transfer, Some(program)), None);
+    let failed = notification(
+        json!({"InstructionError": [0, "Custom"]}),
+        vec![format!("Program {} invoke [1]", program)],
+    );
+    assert_eq!(relevant_log_slot(&failed, Some(program)), None);
+    assert_eq!(
+        relevant_log_slot(&json!({"jsonrpc": "2.0", "result": 24040, "id": 1}), None),
+        None
+    );
+}